@@ -1,21 +1,36 @@
 use clap::{builder::ArgAction, Parser};
+use colored::Colorize;
 use diffenator3::{
+    compare::{compare_fonts, CompareOptions},
     dfont::DFont,
+    diff::DiffOptions,
     render::{
-        encodedglyphs::{modified_encoded_glyphs, new_missing_glyphs},
-        test_font_words,
+        shape_string, CustomWordlists, DifferenceMetric, HintingMode, Progress, RenderOptions,
+        VERTICAL_SCRIPTS,
+    },
+    reporters::{
+        self,
+        baseline::{compare_to_baseline, read_baseline},
+        html::{template_engine, IndexEntry, PngStyle},
+        three_way::three_way,
+        ReportFormat, SortBy, Summary,
+    },
+    setting::{parse_comparisons, parse_location, parse_sweep, Setting},
+    utils::{
+        check_font_size, die, error_message, fail, init_logging, read_font_file, set_ascii_only,
+        terminal_safe, DEFAULT_MAX_FONT_SIZE_MB,
     },
-    reporters::{self, html::template_engine, LocationResult, Report},
-    setting::{parse_location, Setting},
-    ttj::{jsondiff::Substantial, table_diff},
 };
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
+use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use skrifa::{MetadataProvider, Tag};
+use rustybuzz::{Feature, Language, Script};
+use skrifa::{setting::VariationSetting, MetadataProvider, Tag};
 use std::{
-    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    str::FromStr,
 };
+use tera::Tera;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -28,6 +43,20 @@ struct Cli {
     #[clap(long = "tables", overrides_with = "tables", help_heading = Some("Tests to run"))]
     _no_tables: bool,
 
+    /// Only show diffs in these font tables (comma-separated, e.g. OS/2,hhea,head)
+    #[clap(long = "only-tables", value_delimiter = ',', value_parser = parse_tag, help_heading = Some("Tests to run"))]
+    only_tables: Vec<Tag>,
+
+    /// Leave these name IDs out of the table diff (comma-separated)
+    #[clap(long = "ignore-name-ids", value_delimiter = ',', default_values_t = [3, 5], help_heading = Some("Tests to run"))]
+    ignore_name_ids: Vec<u16>,
+
+    /// Compare all name records and the head table's modified and
+    /// fontRevision fields, which are ignored by default as they change
+    /// with every build
+    #[clap(long = "no-ignore", conflicts_with = "ignore_name_ids", help_heading = Some("Tests to run"))]
+    no_ignore: bool,
+
     /// Don't show diffs in glyph images
     #[clap(long = "no-glyphs", action = ArgAction::SetFalse, help_heading = Some("Tests to run"))]
     glyphs: bool,
@@ -44,12 +73,249 @@ struct Cli {
     #[clap(long = "words", overrides_with = "words", help_heading = Some("Tests to run"))]
     _no_words: bool,
 
+    /// Compare glyph outlines point-by-point
+    #[clap(long = "compare-outlines", help_heading = Some("Tests to run"))]
+    compare_outlines: bool,
+
+    /// Compare the outlines of all glyphs, matched by name, including
+    /// unencoded glyphs
+    #[clap(long = "glyphs-by-name", help_heading = Some("Tests to run"))]
+    glyphs_by_name: bool,
+
+    /// Show differences in GPOS kerning pairs
+    #[clap(long = "kerning", help_heading = Some("Tests to run"))]
+    kerning: bool,
+
+    /// Show differences in GPOS mark attachment (anchor) positions
+    #[clap(long = "anchors", help_heading = Some("Tests to run"))]
+    anchors: bool,
+
+    /// Show glyphs whose advance or side bearing changed in hmtx or vmtx
+    #[clap(long = "metrics", help_heading = Some("Tests to run"))]
+    metrics: bool,
+
+    /// Only show metrics which changed by more than this many font units
+    #[clap(long = "metrics-tolerance", default_value = "0", requires = "metrics", help_heading = Some("Tests to run"))]
+    metrics_tolerance: u16,
+
+    /// Show metrics adjusted by MVAR (e.g. x-height, underline position)
+    /// which differ at each location tested
+    #[clap(long = "mvar", help_heading = Some("Tests to run"))]
+    mvar: bool,
+
+    /// Show glyphs whose GDEF glyph class or mark attachment class changed
+    #[clap(long = "gdef", help_heading = Some("Tests to run"))]
+    gdef: bool,
+
+    /// Show glyphs whose name in the post table changed, by glyph ID, and
+    /// glyph names added or removed
+    #[clap(long = "post-names", help_heading = Some("Tests to run"))]
+    post_names: bool,
+
+    /// Show changes to the paint graphs of COLR color glyphs (layers,
+    /// transforms, gradients and composite modes), instead of including
+    /// COLR in the table diff
+    #[clap(long = "colr", help_heading = Some("Tests to run"))]
+    colr: bool,
+
+    /// Show glyphs whose single substitution under a GSUB feature (e.g.
+    /// smcp) now substitutes a different glyph, or none
+    #[clap(long = "substitutions", help_heading = Some("Tests to run"))]
+    substitutions: bool,
+
+    /// Show OpenType features added or removed for each script and language
+    #[clap(long = "features", help_heading = Some("Tests to run"))]
+    features: bool,
+
+    /// Show semantic differences in the name table, resolving typographic
+    /// and WWS family names as applications do
+    #[clap(long = "diff-names", help_heading = Some("Tests to run"))]
+    names: bool,
+
+    /// Check the OS/2 Unicode range bits against the characters each font
+    /// maps, and compare them between the fonts
+    #[clap(long = "include-unicode-ranges", help_heading = Some("Tests to run"))]
+    unicode_ranges: bool,
+
+    /// Check that the bold and italic bits of head.macStyle and
+    /// OS/2.fsSelection agree with the subfamily name, and compare them
+    /// between the fonts
+    #[clap(long = "include-style-bits", help_heading = Some("Tests to run"))]
+    style_bits: bool,
+
+    /// Only report glyphs which differ by at least this percentage of pixels
+    #[clap(long = "glyph-threshold", default_value = "0.0", help_heading = Some("Tests to run"))]
+    glyph_threshold: f32,
+
+    /// Only report words which differ by at least this percentage of pixels
+    #[clap(long = "word-threshold", default_value = "0.0", help_heading = Some("Tests to run"))]
+    word_threshold: f32,
+
+    /// Wordlist file or directory (one word per line, optionally followed by a tab and script name)
+    #[clap(long = "wordlist", help_heading = Some("Tests to run"))]
+    wordlist: Option<PathBuf>,
+
+    /// Use only the custom wordlist, not the built-in wordlists
+    #[clap(long = "replace-wordlists", requires = "wordlist", help_heading = Some("Tests to run"))]
+    replace_wordlists: bool,
+
+    /// Only test the first N words (in sorted order) of each script's wordlist
+    #[clap(long = "max-words-per-script", value_name = "N", help_heading = Some("Tests to run"))]
+    max_words_per_script: Option<usize>,
+
+    /// Only test words in these scripts (comma-separated Unicode script
+    /// names, e.g. Latin,Greek)
+    #[clap(long = "scripts", value_name = "SCRIPTS", value_delimiter = ',', help_heading = Some("Tests to run"))]
+    scripts: Vec<String>,
+
+    /// Don't test words in these scripts (comma-separated, e.g. Arabic)
+    #[clap(long = "skip-scripts", value_name = "SCRIPTS", value_delimiter = ',', help_heading = Some("Tests to run"))]
+    skip_scripts: Vec<String>,
+
+    /// Only test these encoded glyphs (comma-separated glyph names,
+    /// characters or codepoints, e.g. A,aacute,U+0041)
+    #[clap(long = "only-glyphs", value_name = "GLYPHS", value_delimiter = ',', help_heading = Some("Tests to run"))]
+    only_glyphs: Vec<String>,
+
+    /// Group each script's word differences by whether glyph substitution,
+    /// glyph positioning or outlines changed
+    #[clap(long = "group-words-by-kind", help_heading = Some("Tests to run"))]
+    group_words_by_kind: bool,
+
+    /// Binarize glyph coverage instead of comparing anti-aliased renderings
+    #[clap(long = "no-antialias", action = ArgAction::SetFalse, help_heading = Some("Tests to run"))]
+    antialias: bool,
+
+    /// Coverage (from 0 to 1) above which a pixel counts as inked when
+    /// binarizing; lower values catch fainter hairlines
+    #[clap(long = "coverage-threshold", default_value = "0.5", value_parser = parse_coverage, requires = "antialias", help_heading = Some("Tests to run"))]
+    coverage_threshold: f32,
+
+    /// Exit with status 2 if any differences were found
+    #[clap(long = "fail-on-diff")]
+    fail_on_diff: bool,
+
+    /// Face to compare within font collection (TTC) files
+    #[clap(long = "face-index")]
+    face_index: Option<u32>,
+
+    /// Refuse to load fonts larger than this many megabytes, or WOFF and
+    /// WOFF2 fonts which decompress to more
+    #[clap(long = "max-font-size", value_name = "MB", default_value_t = DEFAULT_MAX_FONT_SIZE_MB)]
+    max_font_size: u64,
+
+    /// Number of threads to use for rendering (defaults to the number of CPUs)
+    #[clap(long = "jobs", short = 'j')]
+    jobs: Option<usize>,
+
+    /// Don't show a progress bar while testing words (it is only shown
+    /// when standard error is a terminal)
+    #[clap(long = "no-progress", action = ArgAction::SetFalse)]
+    progress: bool,
+
+    /// Log more detail to stderr (repeat for even more)
+    #[clap(long = "verbose", short = 'v', action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log errors to stderr
+    #[clap(long = "quiet", short = 'q')]
+    quiet: bool,
+
+    /// Instead of diffing, print each font's shaped buffer (glyph IDs and
+    /// offsets) for this string at each location to test
+    #[clap(long = "shape", value_name = "STRING", conflicts_with_all = ["html", "json"])]
+    shape: Option<String>,
+
+    /// ISO 15924 script tag (e.g. Arab) to shape with; guessed if omitted
+    #[clap(long = "shape-script", value_name = "SCRIPT", requires = "shape")]
+    shape_script: Option<Script>,
+
+    /// Print the JSON Schema of the report format and exit
+    #[clap(long = "print-schema", exclusive = true, hide = true)]
+    print_schema: bool,
+
+    /// How to measure differences between renderings: pixel (the
+    /// percentage of differing pixels) or ssim (structural dissimilarity,
+    /// less sensitive to small shifts). Thresholds are in this metric.
+    #[clap(long = "metric", default_value = "pixel", help_heading = Some("Tests to run"))]
+    metric: DifferenceMetric,
+
+    /// Hinting and rasterization mode: none, grayscale or subpixel
+    #[clap(long = "hinting", default_value = "none", help_heading = Some("Tests to run"))]
+    hinting: HintingMode,
+
+    /// Size in pixels per em at which to render glyphs and words
+    #[clap(long = "font-size", help_heading = Some("Tests to run"))]
+    font_size: Option<f32>,
+
+    /// Rasterize glyphs and words at this multiple of the font size when
+    /// comparing them, to catch sub-pixel differences; images in reports
+    /// stay at the font size
+    #[clap(long = "render-scale", value_name = "FACTOR", default_value = "1", value_parser = parse_render_scale, help_heading = Some("Tests to run"))]
+    render_scale: f32,
+
+    /// Also compare encoded glyphs hinted at each of these sizes in pixels
+    /// per em (comma-separated, e.g. 12,16,24), reporting the sizes at
+    /// which each glyph differs. Hinting is grayscale unless --hinting
+    /// chooses subpixel.
+    #[clap(long = "ppem", value_name = "SIZES", value_delimiter = ',', value_parser = clap::value_parser!(u16).range(1..), help_heading = Some("Tests to run"))]
+    ppems: Vec<u16>,
+
+    /// Skip any glyph or word which takes longer than this many seconds to
+    /// shape or render, reporting it as timed out
+    #[clap(long = "timeout", value_name = "SECONDS", value_parser = parse_timeout, help_heading = Some("Tests to run"))]
+    timeout: Option<f32>,
+
+    /// Also test words set vertically, for these scripts (e.g.
+    /// '--vertical=Han,Hangul') or by default those commonly set vertically
+    #[clap(
+        long = "vertical",
+        value_name = "SCRIPTS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        help_heading = Some("Tests to run")
+    )]
+    vertical: Option<Vec<String>>,
+
+    /// OpenType features to apply when shaping words, e.g. 'ss01,+dlig,-liga'
+    #[clap(
+        long = "shaping-features",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        help_heading = Some("Tests to run")
+    )]
+    shaping_features: Vec<Feature>,
+
+    /// Shape in this language (a BCP 47 tag, e.g. sr), or in a language
+    /// for one script's words (e.g. Cyrillic=sr,Latin=tr), to test
+    /// language-specific shaping such as locl features
+    #[clap(long = "language", value_name = "LANGUAGES", value_delimiter = ',', value_parser = parse_language, help_heading = Some("Tests to run"))]
+    languages: Vec<(Option<String>, String)>,
+
+    /// Normalize words to Unicode NFC before shaping them
+    #[clap(long = "normalize", help_heading = Some("Tests to run"))]
+    normalize: bool,
+
     /// Show diffs as JSON
     #[clap(long = "json", help_heading = Some("Report format"))]
     json: bool,
     /// Show diffs as HTML
     #[clap(long = "html", help_heading = Some("Report format"))]
     html: bool,
+    /// Format of the terminal report: text, or unified for a unified diff
+    /// of the fonts' tables serialized as JSON (no rendering is done)
+    #[clap(
+        long = "format",
+        default_value = "text",
+        conflicts_with_all = ["html", "json", "shape"],
+        help_heading = Some("Report format")
+    )]
+    format: ReportFormat,
+    /// Write only ASCII to the terminal, without colors, escaping other
+    /// characters as \u{...}; for logs and consoles which garble them
+    #[clap(long = "ascii-only", help_heading = Some("Report format"))]
+    ascii_only: bool,
     /// If an entry is absent in one font, show the data anyway
     #[clap(long = "no-succinct", action = ArgAction::SetFalse, help_heading = Some("Report format"))]
     succinct: bool,
@@ -58,24 +324,153 @@ struct Cli {
     #[clap(long = "succinct", overrides_with = "succinct", help_heading = Some("Report format"))]
     _no_succinct: bool,
 
+    /// Order of glyph and word differences: percent (most different
+    /// first), codepoint, name (Unicode character name) or string (shortest
+    /// first)
+    #[clap(long = "sort-by", default_value = "percent", help_heading = Some("Report format"))]
+    sort_by: SortBy,
+
     /// Indent JSON
     #[clap(long = "pretty", requires = "json", help_heading = Some("Report format"))]
     pretty: bool,
 
-    /// Output directory for HTML
-    #[clap(long = "output", default_value = "out", requires = "html", help_heading = Some("Report format"))]
+    /// Write the JSON report to this file rather than standard output
+    #[clap(long = "json-output", value_name = "FILE", requires = "json", help_heading = Some("Report format"))]
+    json_output: Option<PathBuf>,
+
+    /// Compare with a JSON report saved from an earlier run, and report
+    /// only the differences which are new and those which were fixed
+    #[clap(long = "baseline", value_name = "FILE", requires = "json", help_heading = Some("Report format"))]
+    baseline: Option<PathBuf>,
+
+    /// Compare both fonts with a font they descend from, and report what
+    /// each changed and where their changes conflict
+    #[clap(long = "base", alias = "baseline-font", value_name = "FONT", requires = "json", conflicts_with = "baseline", help_heading = Some("Report format"))]
+    base: Option<PathBuf>,
+
+    /// Treat FONT1 and FONT2 as directories, and compare each font in the
+    /// first with the font of the same file name, or failing that the
+    /// same family and style names, in the second. With --html, each
+    /// pair's report is written to its own subdirectory of the output
+    /// directory, with an index linking them. A pair which can't be read
+    /// is reported as an error without stopping the others, and
+    /// diffenator3 then exits with status 1.
+    #[clap(long = "dir", conflicts_with_all = ["shape", "base", "baseline", "format"], help_heading = Some("Report format"))]
+    dir: bool,
+
+    /// Output directory for HTML and contact sheets
+    #[clap(long = "output", default_value = "out", help_heading = Some("Report format"))]
     output: String,
 
-    /// Directory for custom templates
-    #[clap(long = "templates", requires = "html", help_heading = Some("Report format"))]
+    /// Write the rendered images of differing glyphs and words as PNGs
+    /// and show them in the HTML report
+    #[clap(long = "render-png", requires = "html", help_heading = Some("Report format"))]
+    render_png: bool,
+
+    /// Embed the fonts in the HTML report, so that it is a single
+    /// standalone file
+    #[clap(long = "embed", requires = "html", conflicts_with = "render_png", help_heading = Some("Report format"))]
+    embed: bool,
+
+    /// Write a PNG contact sheet of each script's differing words, old
+    /// font beside new, to the output directory; optionally only the N
+    /// most different words (e.g. '--contact-sheet=20')
+    #[clap(
+        long = "contact-sheet",
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        help_heading = Some("Report format")
+    )]
+    contact_sheet: Option<Option<usize>>,
+
+    /// Pixels of blank space to leave around each PNG rendering
+    #[clap(long = "png-padding", default_value = "0", requires = "render_png", help_heading = Some("Report format"))]
+    png_padding: u32,
+
+    /// Draw the PNG renderings as black on white, rather than white on black
+    #[clap(long = "png-black-on-white", requires = "render_png", help_heading = Some("Report format"))]
+    png_black_on_white: bool,
+
+    /// Also write an animated GIF flickering between the old and new
+    /// renderings of each differing glyph and word, and show it in the
+    /// HTML report
+    #[clap(long = "flicker-gif", requires = "render_png", help_heading = Some("Report format"))]
+    flicker_gif: bool,
+
+    /// Also write a PNG of each differing glyph and word with the old
+    /// rendering in red over the new in blue, so that movement shows in
+    /// colour, and show it in the HTML report
+    #[clap(long = "png-overlay", requires = "render_png", help_heading = Some("Report format"))]
+    png_overlay: bool,
+
+    /// Directory for custom templates, which override the default templates
+    #[clap(long = "templates", visible_alias = "template-dir", requires = "html", help_heading = Some("Report format"))]
     templates: Option<String>,
 
-    /// Location in user space, in the form axis=123,other=456 (may be repeated)
+    /// Use the built-in templates rather than those in ~/.diffenator3/templates,
+    /// and don't create that directory
+    #[clap(long = "no-home-templates", requires = "html", help_heading = Some("Report format"))]
+    no_home_templates: bool,
+
+    /// Location in user space, in the form axis=123,other=456 (may be repeated;
+    /// an empty string is the default location)
     #[clap(long = "location", help_heading = "Locations to test")]
     location: Vec<String>,
-    /// Instance to compare (may be repeated; use * for all instances)
+    /// Location for the old font, compared against --location-b in the new
+    /// font (e.g. to compare a font against itself at two locations)
+    #[clap(
+        long = "location-a",
+        requires = "location_b",
+        help_heading = "Locations to test"
+    )]
+    location_a: Option<String>,
+    /// Location for the new font, compared against --location-a in the old font
+    #[clap(
+        long = "location-b",
+        requires = "location_a",
+        help_heading = "Locations to test"
+    )]
+    location_b: Option<String>,
+    /// Sweep across an axis in user space, in the form wght=100:900:100
+    /// (start:end:step); several axes, separated by commas, are swept in
+    /// every combination (may be repeated)
+    #[clap(long = "location-sweep", help_heading = "Locations to test")]
+    location_sweep: Vec<String>,
+    /// Instance to compare (may be repeated); * compares every named
+    /// instance, reporting instances present in only one font
     #[clap(long = "instances", help_heading = "Locations to test")]
     instances: Vec<String>,
+    /// Compare every named instance; the same as --instances '*'
+    #[clap(long = "all-instances", help_heading = "Locations to test")]
+    all_instances: bool,
+    /// With --instances '*', match instances across fonts by their
+    /// location rather than their name, reporting renamed instances
+    #[clap(
+        long = "match-instances-by-location",
+        help_heading = "Locations to test"
+    )]
+    match_instances_by_location: bool,
+    /// With --match-instances-by-location, treat instance coordinates which
+    /// differ by no more than this on every axis as the same location
+    #[clap(
+        long = "instance-tolerance",
+        default_value = "0.001",
+        requires = "match_instances_by_location",
+        help_heading = "Locations to test"
+    )]
+    instance_tolerance: f32,
+    /// Run the comparisons listed in a JSON file, an array of objects with
+    /// a name, a location_a for the old font and a location_b for the new
+    /// font (e.g. {"name": "Bold", "location_a": "wght=700", "location_b":
+    /// "wght=700"}), reporting each under its name
+    #[clap(
+        long = "config",
+        value_name = "FILE",
+        conflicts_with = "shape",
+        help_heading = "Locations to test"
+    )]
+    config: Option<PathBuf>,
     /// Cross-product (use min/default/max of all axes)
     #[clap(long = "cross-product", help_heading = "Locations to test")]
     cross_product: bool,
@@ -87,116 +482,630 @@ struct Cli {
     )]
     splits: usize,
 
-    /// The first font file to compare
-    font1: PathBuf,
-    /// The second font file to compare
-    font2: PathBuf,
+    /// The first font file to compare (or, with the network feature, an
+    /// http(s) URL)
+    #[clap(required_unless_present = "print_schema")]
+    font1: Option<PathBuf>,
+    /// The second font file to compare (or URL)
+    #[clap(required_unless_present = "print_schema")]
+    font2: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Whether every named instance is compared, by --all-instances or
+    /// --instances '*'
+    fn all_instances(&self) -> bool {
+        self.all_instances || self.instances.iter().any(|instance| instance == "*")
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_ascii_only(cli.ascii_only);
+    init_logging(cli.verbose, cli.quiet);
+    if cli.match_instances_by_location && !cli.all_instances() {
+        fail("--match-instances-by-location needs --instances '*'");
+    }
+    if cli.print_schema {
+        let schema = serde_json::to_string_pretty(&reporters::schema())
+            .unwrap_or_else(|e| die("serializing schema", e));
+        println!("{}", schema);
+        return;
+    }
+    let (Some(font1), Some(font2)) = (&cli.font1, &cli.font2) else {
+        unreachable!("clap requires both fonts");
+    };
+    if let Some(jobs) = cli.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .unwrap_or_else(|e| die("setting up thread pool", e));
+    }
 
-    let font_binary_a = std::fs::read(&cli.font1).expect("Couldn't open file");
-    let font_binary_b = std::fs::read(&cli.font2).expect("Couldn't open file");
-
-    let tera = cli.html.then(|| template_engine(cli.templates.as_ref()));
+    let max_font_size = cli.max_font_size.saturating_mul(1024 * 1024);
+    let tera = cli
+        .html
+        .then(|| template_engine(cli.templates.as_ref(), !cli.no_home_templates));
+    let wordlists = cli.wordlist.as_ref().map(|path| {
+        CustomWordlists::from_path(path, cli.replace_wordlists)
+            .unwrap_or_else(|e| die("reading wordlist", e))
+    });
+    if cli.dir {
+        compare_directories(&cli, (font1, font2), max_font_size, tera, wordlists);
+    }
 
-    let mut font_a = DFont::new(&font_binary_a);
-    let mut font_b = DFont::new(&font_binary_b);
+    let font_binary_a = read_font(font1, max_font_size);
+    let font_binary_b = read_font(font2, max_font_size);
+    let base_binary = cli.base.as_ref().map(|path| read_font(path, max_font_size));
 
-    let mut result = Report::default();
+    let mut font_a = load_font(&font_binary_a, font1, cli.face_index, max_font_size);
+    let mut font_b = load_font(&font_binary_b, font2, cli.face_index, max_font_size);
+    let render_options = render_options(&cli);
 
-    // Location-independent tests
-    if cli.tables {
-        let table_diff = table_diff(&font_a.fontref(), &font_b.fontref());
-        if table_diff.is_something() {
-            result.tables = Some(table_diff);
-        }
+    if let Some(string) = &cli.shape {
+        show_shaping(&cli, string, &mut font_a, &mut font_b, &render_options);
+        return;
     }
-    if cli.glyphs {
-        result.cmap_diff = Some(new_missing_glyphs(&font_a, &font_b));
+
+    if cli.format == ReportFormat::Unified {
+        let differs = reporters::unified::report(
+            (&font1.display().to_string(), &font_a),
+            (&font2.display().to_string(), &font_b),
+            (!cli.only_tables.is_empty()).then_some(cli.only_tables.as_slice()),
+        );
+        std::process::exit(if cli.fail_on_diff && differs { 2 } else { 0 });
     }
 
-    // Location-specific tests
-    let settings: Vec<Setting> = generate_settings(&cli, &font_a, &font_b);
+    let options = compare_options(
+        &cli,
+        &font_a,
+        &font_b,
+        wordlists,
+        render_options,
+        Path::new(&cli.output),
+    );
+    // Read the baseline first, so a bad path fails before the comparison
+    let baseline = cli.baseline.as_deref().map(read_baseline);
+    let mut result = with_progress(&cli, |report_progress| {
+        compare_fonts(&mut font_a, &mut font_b, &options, report_progress)
+    });
 
-    result.locations = settings
-        .into_iter()
-        .map(|setting| {
-            println!("Testing {}", setting.name());
-            if let Err(e) = setting.set_on_fonts(&mut font_a, &mut font_b) {
-                LocationResult::from_error(setting.name(), e)
-            } else {
-                test_at_location(&font_a, setting.name(), &cli, &font_b)
-            }
-        })
-        .collect();
+    result.sort(cli.sort_by);
 
-    // If there's more than one, filter out the boring ones
-    if result.locations.len() > 1 {
-        result.locations.retain(|l| l.is_some());
+    if let Some(baseline) = baseline {
+        let changes = compare_to_baseline(&result, &baseline);
+        let exit_code = if cli.fail_on_diff && changes.is_some() {
+            2
+        } else {
+            0
+        };
+        reporters::json::report(changes, cli.pretty, cli.json_output.as_deref());
+        std::process::exit(exit_code);
+    }
+
+    if let (Some(path), Some(data)) = (&cli.base, &base_binary) {
+        let mut base = load_font(data, path, cli.face_index, max_font_size);
+        let base_options = CompareOptions {
+            images: None,
+            contact_sheets: None,
+            ..options
+        };
+        let [a_changes, b_changes] = [&mut font_a, &mut font_b].map(|font| {
+            let mut changes = compare_fonts(&mut base, font, &base_options, None);
+            changes.sort(cli.sort_by);
+            changes.summary = Some(changes.summarize());
+            changes
+        });
+        let report = three_way(a_changes, b_changes, &result);
+        let exit_code = if cli.fail_on_diff && report.is_some() {
+            2
+        } else {
+            0
+        };
+        reporters::json::report(report, cli.pretty, cli.json_output.as_deref());
+        std::process::exit(exit_code);
     }
 
+    let exit_code = if cli.fail_on_diff && result.is_some() {
+        2
+    } else {
+        0
+    };
+
     // Report back
     if cli.html {
         reporters::html::report(
-            &cli.font1,
-            &cli.font2,
+            (font1, &font_binary_a),
+            (font2, &font_binary_b),
             Path::new(&cli.output),
             result,
             tera.unwrap(),
+            cli.font_size.unwrap_or(40.0),
+            cli.embed,
         );
     } else if cli.json {
-        reporters::json::report(result, cli.pretty);
+        result.summary = Some(result.summarize());
+        reporters::json::report(result, cli.pretty, cli.json_output.as_deref());
     } else {
         reporters::text::report(result, cli.succinct);
     }
+    std::process::exit(exit_code);
+}
+
+/// The rendering options chosen on the command line
+fn render_options(cli: &Cli) -> RenderOptions {
+    RenderOptions {
+        antialias: cli.antialias,
+        coverage_threshold: cli.coverage_threshold,
+        hinting: cli.hinting,
+        font_size: cli.font_size,
+        render_scale: cli.render_scale,
+        ppems: cli.ppems.clone(),
+        features: cli.shaping_features.clone(),
+        normalize: cli.normalize,
+        metric: cli.metric,
+        timeout: cli.timeout,
+        vertical_scripts: match &cli.vertical {
+            Some(scripts) if scripts.is_empty() => {
+                VERTICAL_SCRIPTS.iter().map(|s| s.to_string()).collect()
+            }
+            Some(scripts) => scripts.clone(),
+            None => vec![],
+        },
+        scripts: cli.scripts.clone(),
+        skip_scripts: cli.skip_scripts.clone(),
+        only_glyphs: cli.only_glyphs.clone(),
+        language: cli
+            .languages
+            .iter()
+            .rev()
+            .find(|(script, _)| script.is_none())
+            .map(|(_, language)| language.clone()),
+        script_languages: cli
+            .languages
+            .iter()
+            .filter_map(|(script, language)| Some((script.clone()?, language.clone())))
+            .collect(),
+    }
+}
+
+/// The comparison options chosen on the command line, for a pair of fonts,
+/// writing any images and contact sheets to `output`
+fn compare_options(
+    cli: &Cli,
+    font_a: &DFont,
+    font_b: &DFont,
+    wordlists: Option<CustomWordlists>,
+    render_options: RenderOptions,
+    output: &Path,
+) -> CompareOptions {
+    let settings: Vec<Setting> = generate_settings(cli, font_a, font_b);
+    log::debug!(
+        "Testing {} location(s): {}",
+        settings.len(),
+        settings.iter().map(|s| s.name()).join(", ")
+    );
+    let comparisons = cli
+        .config
+        .as_deref()
+        .map(read_comparisons)
+        .unwrap_or_default();
+    CompareOptions {
+        diff: DiffOptions {
+            tables: cli.tables,
+            only_tables: (!cli.only_tables.is_empty()).then(|| cli.only_tables.clone()),
+            ignore_name_ids: if cli.no_ignore {
+                vec![]
+            } else {
+                cli.ignore_name_ids.clone()
+            },
+            ignore_head_revision: !cli.no_ignore,
+            cmap: cli.glyphs,
+            glyphs: cli.glyphs,
+            glyph_threshold: cli.glyph_threshold,
+            outlines: cli.compare_outlines,
+            glyph_names: cli.glyphs_by_name,
+            words: cli.words,
+            word_threshold: cli.word_threshold,
+            wordlists,
+            max_words_per_script: cli.max_words_per_script,
+            group_words_by_kind: cli.group_words_by_kind,
+            kerning: cli.kerning,
+            anchors: cli.anchors,
+            metrics: cli.metrics,
+            metrics_tolerance: cli.metrics_tolerance,
+            mvar: cli.mvar,
+            upm: true,
+            gdef: cli.gdef,
+            post_names: cli.post_names,
+            colr: cli.colr,
+            substitutions: cli.substitutions,
+            features: cli.features,
+            names: cli.names,
+            unicode_ranges: cli.unicode_ranges,
+            style_bits: cli.style_bits,
+            render: render_options,
+        },
+        locations: settings,
+        all_instances: cli.all_instances(),
+        match_instances_by_location: cli.match_instances_by_location,
+        instance_tolerance: cli.instance_tolerance,
+        comparisons,
+        face_index: cli.face_index.unwrap_or(0),
+        images: cli.render_png.then(|| {
+            (
+                output.to_path_buf(),
+                PngStyle {
+                    padding: cli.png_padding,
+                    black_on_white: cli.png_black_on_white,
+                    flicker: cli.flicker_gif,
+                    overlay: cli.png_overlay,
+                },
+            )
+        }),
+        contact_sheets: cli.contact_sheet.map(|limit| (output.to_path_buf(), limit)),
+    }
+}
+
+/// Font file extensions looked for when comparing directories
+const FONT_EXTENSIONS: [&str; 6] = ["ttf", "otf", "ttc", "otc", "woff", "woff2"];
+
+/// The font files in a directory, sorted by name
+fn font_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| die(&format!("reading directory {}", dir.display()), e));
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| FONT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Pair up the fonts in two directories by file name, and then the
+/// remaining fonts by family and style name
+///
+/// Returns the pairs, and the fonts in either directory left unmatched.
+fn pair_fonts(
+    old_dir: &Path,
+    new_dir: &Path,
+    max_size: u64,
+    face_index: Option<u32>,
+) -> (Vec<(PathBuf, PathBuf)>, Vec<PathBuf>) {
+    let mut new_files = font_files(new_dir);
+    let mut pairs = vec![];
+    let mut unmatched_old = vec![];
+    for old in font_files(old_dir) {
+        match new_files
+            .iter()
+            .position(|new| new.file_name() == old.file_name())
+        {
+            Some(index) => pairs.push((old, new_files.remove(index))),
+            None => unmatched_old.push(old),
+        }
+    }
+    let style = |path: &Path| {
+        let data = read_font_file(path, max_size).ok()?;
+        let font = DFont::with_max_size(&data, face_index.unwrap_or(0), max_size).ok()?;
+        Some((font.family_name(), font.style_name()))
+    };
+    let mut new_styles: Vec<_> = new_files.iter().map(|path| style(path)).collect();
+    let mut unmatched = vec![];
+    for old in unmatched_old {
+        let old_style = style(&old);
+        match new_styles
+            .iter()
+            .position(|new_style| new_style.is_some() && *new_style == old_style)
+        {
+            Some(index) => {
+                new_styles.remove(index);
+                pairs.push((old, new_files.remove(index)));
+            }
+            None => unmatched.push(old),
+        }
+    }
+    unmatched.extend(new_files);
+    pairs.sort();
+    (pairs, unmatched)
 }
 
-fn test_at_location(font_a: &DFont, loc_name: String, cli: &Cli, font_b: &DFont) -> LocationResult {
-    let mut this_location_value = LocationResult::default();
-    let loc_coords: HashMap<String, f32> = font_a
-        .location
+/// Compare each pair of fonts in two directories, and report on them all
+fn compare_directories(
+    cli: &Cli,
+    (old_dir, new_dir): (&Path, &Path),
+    max_font_size: u64,
+    tera: Option<Tera>,
+    wordlists: Option<CustomWordlists>,
+) -> ! {
+    let (pairs, unmatched) = pair_fonts(old_dir, new_dir, max_font_size, cli.face_index);
+    for path in &unmatched {
+        log::warn!("Skipping {}, which has no counterpart", path.display());
+    }
+    let output = Path::new(&cli.output);
+    let mut index = vec![];
+    let mut json_reports = IndexMap::new();
+    let mut errors = IndexMap::new();
+    let mut differs = false;
+    for (old, new) in pairs {
+        // Name each pair after the old font's file
+        let name = old.file_name().unwrap().to_string_lossy().to_string();
+        log::info!("Comparing {} with {}", old.display(), new.display());
+        let loaded = try_read_and_load_font(&old, cli.face_index, max_font_size).and_then(|a| {
+            Ok((
+                a,
+                try_read_and_load_font(&new, cli.face_index, max_font_size)?,
+            ))
+        });
+        let ((font_binary_a, mut font_a), (font_binary_b, mut font_b)) = match loaded {
+            Ok(fonts) => fonts,
+            Err(message) => {
+                // Carry on with the other pairs, and report this one as failed
+                log::warn!(
+                    "Skipping {} => {}: {}",
+                    old.display(),
+                    new.display(),
+                    message
+                );
+                if tera.is_some() {
+                    index.push(IndexEntry {
+                        name: name.clone(),
+                        old: old.display().to_string(),
+                        new: new.display().to_string(),
+                        differs: false,
+                        summary: Summary::default(),
+                        error: Some(message.clone()),
+                    });
+                } else if !cli.json {
+                    println!(
+                        "{}",
+                        terminal_safe(&format!("## {} => {}", old.display(), new.display())).bold()
+                    );
+                    println!("{}\n", terminal_safe(&message).red());
+                }
+                errors.insert(name, message);
+                continue;
+            }
+        };
+        let options = compare_options(
+            cli,
+            &font_a,
+            &font_b,
+            wordlists.clone(),
+            render_options(cli),
+            &output.join(&name),
+        );
+        let mut result = with_progress(cli, |report_progress| {
+            compare_fonts(&mut font_a, &mut font_b, &options, report_progress)
+        });
+        result.sort(cli.sort_by);
+        differs |= result.is_some();
+        if let Some(tera) = &tera {
+            index.push(IndexEntry {
+                name: name.clone(),
+                old: old.display().to_string(),
+                new: new.display().to_string(),
+                differs: result.is_some(),
+                summary: result.summarize(),
+                error: None,
+            });
+            reporters::html::report(
+                (&old, &font_binary_a),
+                (&new, &font_binary_b),
+                &output.join(&name),
+                result,
+                tera.clone(),
+                cli.font_size.unwrap_or(40.0),
+                cli.embed,
+            );
+        } else if cli.json {
+            result.summary = Some(result.summarize());
+            json_reports.insert(name, result);
+        } else {
+            println!(
+                "{}",
+                terminal_safe(&format!("## {} => {}", old.display(), new.display())).bold()
+            );
+            reporters::text::report(result, cli.succinct);
+            println!();
+        }
+    }
+    let unmatched: Vec<String> = unmatched
         .iter()
-        .map(|v| (v.selector.to_string(), v.value))
+        .map(|path| path.display().to_string())
         .collect();
-    this_location_value.location = loc_name;
-    this_location_value.coords = loc_coords;
+    if let Some(tera) = &tera {
+        reporters::html::write_index(output, &index, &unmatched, tera);
+    } else if cli.json {
+        reporters::json::report(
+            serde_json::json!({ "reports": json_reports, "unmatched": unmatched, "errors": errors }),
+            cli.pretty,
+            cli.json_output.as_deref(),
+        );
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+    std::process::exit(if cli.fail_on_diff && differs { 2 } else { 0 });
+}
+
+/// Read a font file, or fetch it if the path is an http(s) URL, refusing
+/// fonts larger than `max_size` bytes
+fn read_font(path: &Path, max_size: u64) -> Vec<u8> {
+    try_read_font(path, max_size).unwrap_or_else(|message| fail(&message))
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Like [read_font], but returning a description of any error
+fn try_read_font(path: &Path, max_size: u64) -> Result<Vec<u8>, String> {
+    let location = path.to_string_lossy();
+    if is_url(&location) {
+        log::debug!("Fetching {}", location);
+        return fetch_url(&location, max_size)
+            .and_then(|data| check_font_size(&data, max_size).map(|_| data))
+            .map_err(|e| error_message(&format!("fetching {}", location), e));
+    }
+    log::debug!("Reading {}", path.display());
+    read_font_file(path, max_size)
+        .map_err(|e| error_message(&format!("reading {}", path.display()), e))
+}
+
+#[cfg(feature = "network")]
+fn fetch_url(url: &str, max_size: u64) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    // Redirects are followed; HTTP error statuses are errors
+    let response = reqwest::blocking::get(url)
+        .and_then(|response| response.error_for_status())
+        .map_err(std::io::Error::other)?;
+    // Read one byte more than allowed, so an oversized font is caught by
+    // check_font_size rather than silently truncated
+    let mut data = vec![];
+    response.take(max_size + 1).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(not(feature = "network"))]
+fn fetch_url(_url: &str, _max_size: u64) -> std::io::Result<Vec<u8>> {
+    Err(std::io::Error::other(
+        "diffenator3 was built without the network feature",
+    ))
+}
+
+fn load_font(data: &[u8], path: &Path, face_index: Option<u32>, max_size: u64) -> DFont {
+    try_load_font(data, path, face_index, max_size).unwrap_or_else(|message| fail(&message))
+}
+
+/// Like [load_font], but returning a description of any error
+fn try_load_font(
+    data: &[u8],
+    path: &Path,
+    face_index: Option<u32>,
+    max_size: u64,
+) -> Result<DFont, String> {
+    let face_count = DFont::face_count(data);
+    if face_index.is_none() && face_count > 1 {
+        return Err(format!(
+            "{} is a collection of {} fonts; use --face-index to choose one",
+            path.display(),
+            face_count
+        ));
+    }
+    DFont::with_max_size(data, face_index.unwrap_or(0), max_size)
+        .map_err(|e| error_message(&format!("loading {}", path.display()), e))
+}
+
+/// Read and load a font, returning its binary as well
+fn try_read_and_load_font(
+    path: &Path,
+    face_index: Option<u32>,
+    max_size: u64,
+) -> Result<(Vec<u8>, DFont), String> {
+    let data = try_read_font(path, max_size)?;
+    let font = try_load_font(&data, path, face_index, max_size)?;
+    Ok((data, font))
+}
 
-    if cli.glyphs {
-        this_location_value.glyphs = modified_encoded_glyphs(font_a, font_b);
+/// Print the shaped buffer of a string in each font, at each location
+fn show_shaping(
+    cli: &Cli,
+    string: &str,
+    font_a: &mut DFont,
+    font_b: &mut DFont,
+    render_options: &RenderOptions,
+) {
+    for setting in generate_settings(cli, font_a, font_b) {
+        let heading = format!("# Shaping '{}' at location {}", string, setting.name());
+        println!("{}", terminal_safe(&heading));
+        if let Err(e) = setting.set_on_fonts(font_a, font_b) {
+            println!("{}", e.red());
+            continue;
+        }
+        let old = shape_string(font_a, string, cli.shape_script, render_options);
+        let new = shape_string(font_b, string, cli.shape_script, render_options);
+        if old == new {
+            println!("  old: {}", old);
+            println!("  new: {} (identical)", new);
+        } else {
+            println!("  old: {}", old.green());
+            println!("  new: {}", new.red());
+        }
     }
-    if cli.words {
-        this_location_value.words = Some(test_font_words(font_a, font_b));
+}
+
+/// Run a comparison, with a progress bar for the word tests if one was
+/// asked for
+fn with_progress<T>(cli: &Cli, compare: impl FnOnce(Option<&(dyn Fn(Progress) + Sync)>) -> T) -> T {
+    let progress_bar = cli.progress.then(|| word_progress_bar(cli.ascii_only));
+    let report_progress = |progress: Progress| {
+        if let Some(bar) = &progress_bar {
+            bar.set_prefix(progress.script);
+            bar.set_length(progress.words_total as u64);
+            bar.set_position(progress.words_done as u64);
+        }
+    };
+    let result = compare(cli.progress.then_some(&report_progress));
+    if let Some(bar) = progress_bar {
+        bar.finish_and_clear();
     }
-    this_location_value
+    result
+}
+
+/// A progress bar for the word tests, showing the script being tested
+fn word_progress_bar(ascii_only: bool) -> ProgressBar {
+    let bar = ProgressBar::new(0);
+    let style = ProgressStyle::with_template("{prefix:>12} {wide_bar} {pos}/{len} words")
+        .expect("progress bar template is valid");
+    bar.set_style(if ascii_only {
+        style.progress_chars("#>-")
+    } else {
+        style
+    });
+    bar
 }
 
 fn generate_settings(args: &Cli, font_a: &DFont, font_b: &DFont) -> Vec<Setting> {
     let mut settings = vec![];
-    for instance in &args.instances {
-        if instance == "*" {
-            // Add the union of instances from both fonts
-            let mut instances: IndexSet<String> = font_a.instances().into_iter().collect();
-            instances.extend(font_b.instances().into_iter());
-            settings.extend(instances.into_iter().map(Setting::from_instance));
-        } else {
-            settings.push(Setting::from_instance(instance.clone()));
-        }
+    // Every instance, for "*", is compared by compare_fonts itself
+    for instance in args.instances.iter().filter(|instance| *instance != "*") {
+        settings.push(Setting::from_instance(instance.clone()));
     }
 
     for location in &args.location {
-        let loc = parse_location(location).expect("Couldn't parse location");
-        settings.push(Setting::from_setting(loc));
+        let loc = parse_location(location)
+            .unwrap_or_else(|e| fail(&format!("Error in location '{}': {}", location, e)));
+        if loc.is_empty() {
+            settings.push(Setting::Default);
+        } else {
+            settings.push(Setting::from_setting(loc));
+        }
+    }
+    for sweep in &args.location_sweep {
+        let locations = parse_sweep(sweep)
+            .unwrap_or_else(|e| fail(&format!("Error in location sweep '{}': {}", sweep, e)));
+        settings.extend(locations.into_iter().map(Setting::from_setting));
+    }
+    if let (Some(location_a), Some(location_b)) = (&args.location_a, &args.location_b) {
+        let parse = |location: &String| {
+            parse_location(location)
+                .unwrap_or_else(|e| fail(&format!("Error in location '{}': {}", location, e)))
+        };
+        settings.push(Setting::from_settings(parse(location_a), parse(location_b)));
     }
     if args.cross_product {
-        let mut axes: HashSet<Tag> = font_a.fontref().axes().iter().map(|a| a.tag()).collect();
-        axes.extend(font_b.fontref().axes().iter().map(|a| a.tag()));
+        let (fontref_a, fontref_b) = (font_a.fontref(), font_b.fontref());
+        let mut axes: IndexSet<Tag> = fontref_a.axes().iter().map(|a| a.tag()).collect();
+        axes.extend(fontref_b.axes().iter().map(|a| a.tag()));
         let axes_min_max = axes
             .iter()
             .map(|tag| {
-                let a = font_a.fontref().axes().iter().find(|a| a.tag() == *tag);
-                let b = font_b.fontref().axes().iter().find(|a| a.tag() == *tag);
+                let a = fontref_a.axes().iter().find(|a| a.tag() == *tag);
+                let b = fontref_b.axes().iter().find(|a| a.tag() == *tag);
                 let a_extents = a.map(|a| (a.min_value(), a.default_value(), a.max_value()));
                 let b_extents = b.map(|a| (a.min_value(), a.default_value(), a.max_value()));
                 match (a_extents, b_extents) {
@@ -208,7 +1117,7 @@ fn generate_settings(args: &Cli, font_a: &DFont, font_b: &DFont) -> Vec<Setting>
                     (None, None) => panic!("Couldn't find axis"),
                 }
             })
-            .collect::<HashMap<Tag, (f32, f32, f32)>>();
+            .collect::<Vec<(Tag, (f32, f32, f32))>>();
         let mut per_axis_splits: Vec<Vec<(Tag, f32)>> = vec![];
 
         for (axis, tuple) in axes_min_max.into_iter() {
@@ -217,21 +1126,54 @@ fn generate_settings(args: &Cli, font_a: &DFont, font_b: &DFont) -> Vec<Setting>
         per_axis_splits.dedup();
         // Find the cartesian product of all axis/value iterators
         for locations in per_axis_splits.into_iter().multi_cartesian_product() {
-            settings.push(Setting::from_setting(
-                locations
-                    .into_iter()
-                    .map(|(a, v)| skrifa::setting::Setting::new(a, v))
-                    .collect(),
-            ));
+            let location: Vec<VariationSetting> = locations
+                .into_iter()
+                .map(|(a, v)| skrifa::setting::Setting::new(a, v))
+                .collect();
+            // The splits span both fonts' axes, so fit the location to
+            // each font where it falls outside one of them
+            if font_a.check_location(&location).is_ok() && font_b.check_location(&location).is_ok()
+            {
+                settings.push(Setting::from_setting(location));
+            } else {
+                settings.push(Setting::from_settings(
+                    fit_location(font_a, &location),
+                    fit_location(font_b, &location),
+                ));
+            }
         }
     }
-    if settings.is_empty() {
+    if settings.is_empty() && !args.all_instances() && args.config.is_none() {
         // Add default setting
         settings.push(Setting::Default);
     }
     settings
 }
 
+/// Drop the settings for axes the font doesn't have, and clamp the rest
+/// to the font's axis ranges
+fn fit_location(font: &DFont, location: &[VariationSetting]) -> Vec<VariationSetting> {
+    let axes = font.axis_info();
+    location
+        .iter()
+        .filter_map(|setting| {
+            let (min, _default, max) = axes.get(&setting.selector.to_string())?;
+            Some(VariationSetting::new(
+                setting.selector,
+                setting.value.clamp(*min, *max),
+            ))
+        })
+        .collect()
+}
+
+/// Read a comparisons file
+fn read_comparisons(path: &Path) -> Vec<(String, Setting)> {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| die(&format!("reading {}", path.display()), e));
+    parse_comparisons(&contents)
+        .unwrap_or_else(|e| fail(&format!("Error in {}: {}", path.display(), e)))
+}
+
 fn split_axis(axis: &Tag, tuple: (f32, f32, f32), split_count: usize) -> Vec<(Tag, f32)> {
     let (min, default, max) = tuple;
     let step = (default - min) / split_count as f32;
@@ -248,3 +1190,47 @@ fn split_axis(axis: &Tag, tuple: (f32, f32, f32), split_count: usize) -> Vec<(Ta
     splits.dedup();
     splits.into_iter().map(|v| (*axis, v)).collect()
 }
+
+fn parse_coverage(coverage: &str) -> Result<f32, String> {
+    match coverage.parse::<f32>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!(
+            "Invalid coverage threshold '{}' (expected a number from 0 to 1)",
+            coverage
+        )),
+    }
+}
+
+fn parse_render_scale(scale: &str) -> Result<f32, String> {
+    match scale.parse::<f32>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!(
+            "Invalid render scale '{}' (expected a positive number)",
+            scale
+        )),
+    }
+}
+
+fn parse_timeout(timeout: &str) -> Result<f32, String> {
+    match timeout.parse::<f32>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!(
+            "Invalid timeout '{}' (expected a positive number of seconds)",
+            timeout
+        )),
+    }
+}
+
+/// Parse a language tag, optionally preceded by a script name and `=`
+fn parse_language(language: &str) -> Result<(Option<String>, String), String> {
+    let (script, tag) = match language.split_once('=') {
+        Some((script, tag)) => (Some(script.trim().to_string()), tag.trim()),
+        None => (None, language.trim()),
+    };
+    Language::from_str(tag).map_err(|e| format!("Invalid language '{}': {}", tag, e))?;
+    Ok((script, tag.to_string()))
+}
+
+fn parse_tag(tag: &str) -> Result<Tag, String> {
+    Tag::new_checked(tag.as_bytes()).map_err(|e| format!("Invalid table tag '{}': {}", tag, e))
+}
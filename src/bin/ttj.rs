@@ -1,17 +1,158 @@
-use clap::{Arg, Command};
-use diffenator3::ttj::font_to_json;
+use clap::{Arg, ArgAction, Command};
+use diffenator3::{
+    dfont::DFont,
+    ttj::font_to_json,
+    utils::{die, init_logging, read_font_file, terminal_safe, DEFAULT_MAX_FONT_SIZE_MB},
+    woff,
+};
 use read_fonts::FontRef;
+use serde_json::{json, Map, Value};
+use skrifa::setting::VariationSetting;
+use std::io::Write;
 
 fn main() {
     let matches = Command::new("ttj")
         .about("dump a font file to json")
         .arg_required_else_help(true)
         .arg(Arg::new("font").help("Font file to dump"))
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .action(ArgAction::Count)
+                .conflicts_with("quiet")
+                .help("Log more detail to stderr (repeat for even more)"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .short('q')
+                .action(ArgAction::SetTrue)
+                .help("Only log errors to stderr"),
+        )
+        .arg(
+            Arg::new("max-font-size")
+                .long("max-font-size")
+                .value_name("MB")
+                .value_parser(clap::value_parser!(u64))
+                .help(format!(
+                    "Refuse to load fonts larger than this many megabytes, or WOFF and WOFF2 fonts which decompress to more [default: {}]",
+                    DEFAULT_MAX_FONT_SIZE_MB
+                )),
+        )
+        .arg(
+            Arg::new("info")
+                .long("info")
+                .action(ArgAction::SetTrue)
+                .help("Instead of dumping the font, list its axes and named instances"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .requires("info")
+                .help("Write the axes and instances as JSON"),
+        )
         .get_matches();
+    init_logging(matches.get_count("verbose"), matches.get_flag("quiet"));
 
     let name = matches.get_one::<String>("font").expect("No font name?");
-    let font_binary = std::fs::read(name).expect("Couldn't open file");
-    let font = FontRef::new(&font_binary).expect("Can't parse");
-    let json = font_to_json(&font);
-    println!("{:}", serde_json::to_string_pretty(&json).unwrap());
+    let max_font_size = matches
+        .get_one::<u64>("max-font-size")
+        .copied()
+        .unwrap_or(DEFAULT_MAX_FONT_SIZE_MB)
+        .saturating_mul(1024 * 1024);
+    let font_binary = read_font_file(name.as_ref(), max_font_size)
+        .unwrap_or_else(|e| die(&format!("reading {}", name), e));
+    if matches.get_flag("info") {
+        let font = DFont::with_max_size(&font_binary, 0, max_font_size)
+            .unwrap_or_else(|e| die(&format!("parsing {}", name), e));
+        if matches.get_flag("json") {
+            write_json(&info_json(&font));
+        } else {
+            show_info(&font);
+        }
+        return;
+    }
+    let font_binary = woff::decompress(&font_binary, max_font_size)
+        .unwrap_or_else(|e| die(&format!("decompressing {}", name), e));
+    let font = FontRef::new(&font_binary).unwrap_or_else(|e| die(&format!("parsing {}", name), e));
+    write_json(&font_to_json(&font));
+}
+
+fn write_json(json: &Value) {
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    serde_json::to_writer_pretty(&mut stdout, json)
+        .map_err(std::io::Error::from)
+        .and_then(|_| writeln!(stdout))
+        .and_then(|_| stdout.flush())
+        .unwrap_or_else(|e| die("writing JSON", e));
+}
+
+/// A location as `--location` takes it, e.g. `wght=700,wdth=100`
+fn location_string(location: &[VariationSetting]) -> String {
+    location
+        .iter()
+        .map(|setting| format!("{}={}", setting.selector, setting.value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Print the font's axes and named instances
+fn show_info(font: &DFont) {
+    println!(
+        "{}",
+        terminal_safe(&format!("{} {}", font.family_name(), font.style_name()))
+    );
+    let axes = font.axis_info();
+    if axes.is_empty() {
+        println!("Not a variable font");
+        return;
+    }
+    println!("\nAxes:");
+    for (tag, (min, default, max)) in axes {
+        println!("  {}: {} to {}, default {}", tag, min, max, default);
+    }
+    let instances = font.instance_locations();
+    if !instances.is_empty() {
+        println!("\nNamed instances:");
+        for (name, location) in instances {
+            println!(
+                "  {}",
+                terminal_safe(&format!("{}: {}", name, location_string(&location)))
+            );
+        }
+    }
+}
+
+/// The font's axes and named instances, as JSON
+fn info_json(font: &DFont) -> Value {
+    let axes: Map<String, Value> = font
+        .axis_info()
+        .into_iter()
+        .map(|(tag, (min, default, max))| {
+            (tag, json!({"min": min, "default": default, "max": max}))
+        })
+        .collect();
+    let instances: Vec<Value> = font
+        .instance_locations()
+        .into_iter()
+        .map(|(name, location)| {
+            let coordinates: Map<String, Value> = location
+                .iter()
+                .map(|setting| (setting.selector.to_string(), json!(setting.value)))
+                .collect();
+            json!({
+                "name": name,
+                "location": location_string(&location),
+                "coordinates": coordinates,
+            })
+        })
+        .collect();
+    json!({
+        "family": font.family_name(),
+        "style": font.style_name(),
+        "axes": axes,
+        "instances": instances,
+    })
 }
@@ -0,0 +1,424 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use indexmap::IndexSet;
+use itertools::Itertools;
+use serde_json::{json, Value};
+use skrifa::setting::VariationSetting;
+
+use crate::{
+    dfont::DFont,
+    diff::{diff_fonts, diff_fonts_with_progress, DiffOptions},
+    render::Progress,
+    reporters::{
+        contact_sheet::write_contact_sheets,
+        html::{write_images, PngStyle},
+        LocationResult, Report,
+    },
+    setting::Setting,
+};
+
+/// Everything needed to compare two fonts from start to finish
+#[derive(Debug, Clone, Default)]
+pub struct CompareOptions {
+    /// Which tests to run
+    pub diff: DiffOptions,
+    /// The locations at which to compare glyphs and words; if empty, the
+    /// default location is used unless `all_instances` is set or there are
+    /// `comparisons`
+    pub locations: Vec<Setting>,
+    /// Also compare glyphs and words at each named instance
+    pub all_instances: bool,
+    /// Pair up named instances by their location rather than their name
+    pub match_instances_by_location: bool,
+    /// When pairing up named instances by location, treat coordinates
+    /// which differ by no more than this on every axis as the same
+    pub instance_tolerance: f32,
+    /// Named settings at which to compare glyphs and words, reported
+    /// under their names
+    pub comparisons: Vec<(String, Setting)>,
+    /// Which font to compare, if the files are collections
+    pub face_index: u32,
+    /// Write PNG renderings of the differences to this directory, for the
+    /// HTML report
+    pub images: Option<(PathBuf, PngStyle)>,
+    /// Write a contact sheet of each script's differing words at each
+    /// location to this directory, with at most this many words per script
+    pub contact_sheets: Option<(PathBuf, Option<usize>)>,
+}
+
+/// Compare two fonts, given as the contents of their files, and return
+/// the report as JSON
+///
+/// If either font can't be loaded, the result is an object with an
+/// `error` key.
+pub fn compare(a: &[u8], b: &[u8], options: &CompareOptions) -> Value {
+    let load = |data: &[u8], which: &str| {
+        DFont::new_with_index(data, options.face_index)
+            .map_err(|e| format!("{} font: {}", which, e))
+    };
+    let (mut font_a, mut font_b) = match (load(a, "old"), load(b, "new")) {
+        (Ok(font_a), Ok(font_b)) => (font_a, font_b),
+        (Err(e), _) | (_, Err(e)) => return json!({"error": e}),
+    };
+    let mut report = compare_fonts(&mut font_a, &mut font_b, options, None);
+    report.summary = Some(report.summarize());
+    serde_json::to_value(report).unwrap_or_else(|e| json!({"error": e.to_string()}))
+}
+
+/// Compare two fonts, running the location-independent tests once and the
+/// glyph and word tests at each location, reporting the progress of the
+/// word tests to a callback
+///
+/// The fonts are left at the last location tested.
+pub fn compare_fonts(
+    font_a: &mut DFont,
+    font_b: &mut DFont,
+    options: &CompareOptions,
+    progress: Option<&(dyn Fn(Progress) + Sync)>,
+) -> Report {
+    let mut result = Report::default();
+
+    // Location-independent tests
+    let diff = diff_fonts(
+        font_a,
+        font_b,
+        &DiffOptions {
+            glyphs: false,
+            outlines: false,
+            glyph_names: false,
+            words: false,
+            wordlists: None,
+            // MVAR values are only reported at each location, but still
+            // decide whether a change to the units per em is flagged
+            ..options.diff.clone()
+        },
+    );
+    result.global = diff.global;
+
+    // Location-specific tests
+    let location_options = options.diff.without_global_tests();
+    let mut settings = options.locations.clone();
+    if settings.is_empty() && !options.all_instances && options.comparisons.is_empty() {
+        settings.push(Setting::Default);
+    }
+    result.locations = settings
+        .into_iter()
+        .map(|setting| {
+            log::info!("Testing {}", setting.name());
+            if let Err(e) = setting.set_on_fonts(font_a, font_b) {
+                LocationResult::from_error(setting.name(), e)
+            } else {
+                test_at_location(
+                    font_a,
+                    font_b,
+                    setting.name(),
+                    &location_options,
+                    options.images.as_ref(),
+                    options.contact_sheets.as_ref(),
+                    progress,
+                )
+            }
+        })
+        .collect();
+
+    // If there's more than one, filter out the boring ones
+    if result.locations.len() > 1 {
+        result.locations.retain(|l| l.is_some());
+    }
+
+    for (name, setting) in &options.comparisons {
+        log::info!("Testing {}", name);
+        let location_result = if let Err(e) = setting.set_on_fonts(font_a, font_b) {
+            LocationResult::from_error(name.clone(), e)
+        } else {
+            test_at_location(
+                font_a,
+                font_b,
+                name.clone(),
+                &location_options,
+                options.images.as_ref(),
+                options.contact_sheets.as_ref(),
+                progress,
+            )
+        };
+        result.comparisons.insert(name.clone(), location_result);
+    }
+
+    if options.all_instances {
+        let instances = if options.match_instances_by_location {
+            match_instances_by_location(font_a, font_b, options.instance_tolerance, &mut result)
+        } else {
+            let instances_a: IndexSet<String> = font_a.instances().into_iter().collect();
+            let instances_b: IndexSet<String> = font_b.instances().into_iter().collect();
+            result.missing_instances = instances_a.difference(&instances_b).cloned().collect();
+            result.new_instances = instances_b.difference(&instances_a).cloned().collect();
+            instances_a
+                .intersection(&instances_b)
+                .map(|instance| (instance.clone(), Setting::from_instance(instance.clone())))
+                .collect()
+        };
+        for (instance, setting) in instances {
+            log::info!("Testing instance {}", instance);
+            let location_result = if let Err(e) = setting.set_on_fonts(font_a, font_b) {
+                LocationResult::from_error(setting.name(), e)
+            } else {
+                test_at_location(
+                    font_a,
+                    font_b,
+                    instance.clone(),
+                    &location_options,
+                    options.images.as_ref(),
+                    options.contact_sheets.as_ref(),
+                    progress,
+                )
+            };
+            result.instances.insert(instance, location_result);
+        }
+    }
+    result
+}
+
+/// Pair up the named instances of two fonts which are at the same
+/// location, recording unpaired instances as missing or new and paired
+/// instances whose names differ as renamed
+///
+/// Locations match if each axis's coordinates differ by no more than
+/// `tolerance`; each instance is paired with the closest match. Pairs
+/// whose locations aren't exactly equal are recorded as near matches.
+/// Returns the setting for each pair, with the old font's instance name.
+fn match_instances_by_location(
+    font_a: &DFont,
+    font_b: &DFont,
+    tolerance: f32,
+    result: &mut Report,
+) -> Vec<(String, Setting)> {
+    let as_map = |location: &[VariationSetting]| -> BTreeMap<String, f32> {
+        location
+            .iter()
+            .map(|setting| (setting.selector.to_string(), setting.value))
+            .collect()
+    };
+    // The largest difference on any axis, if the locations are on the
+    // same axes
+    let distance = |a: &BTreeMap<String, f32>, b: &BTreeMap<String, f32>| {
+        (a.len() == b.len() && a.keys().eq(b.keys())).then(|| {
+            a.values()
+                .zip(b.values())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max)
+        })
+    };
+    let describe = |coords: &BTreeMap<String, f32>| {
+        coords
+            .iter()
+            .map(|(tag, value)| format!("{}={}", tag, value))
+            .join(",")
+    };
+    let mut instances_b = font_b.instance_locations();
+    let mut pairs = vec![];
+    for (name_a, location_a) in font_a.instance_locations() {
+        let coords_a = as_map(&location_a);
+        let Some((ix, coords_b, _)) = instances_b
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, (_, location_b))| {
+                let coords_b = as_map(location_b);
+                let distance = distance(&coords_a, &coords_b).filter(|d| *d <= tolerance)?;
+                Some((ix, coords_b, distance))
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        else {
+            result.missing_instances.push(name_a);
+            continue;
+        };
+        let (name_b, location_b) = instances_b.remove(ix);
+        if coords_a != coords_b {
+            result.near_matched_instances.push(format!(
+                "{} is at {} in the old font and {} in the new",
+                name_a,
+                describe(&coords_a),
+                describe(&coords_b)
+            ));
+        }
+        if name_a != name_b {
+            result.renamed_instances.insert(name_a.clone(), name_b);
+        }
+        pairs.push((name_a, Setting::from_settings(location_a, location_b)));
+    }
+    result.new_instances = instances_b.into_iter().map(|(name, _)| name).collect();
+    pairs
+}
+
+fn test_at_location(
+    font_a: &DFont,
+    font_b: &DFont,
+    loc_name: String,
+    options: &DiffOptions,
+    images: Option<&(PathBuf, PngStyle)>,
+    contact_sheets: Option<&(PathBuf, Option<usize>)>,
+    progress: Option<&(dyn Fn(Progress) + Sync)>,
+) -> LocationResult {
+    let mut this_location_value = LocationResult::default();
+    let loc_coords: BTreeMap<String, f32> = font_a
+        .location
+        .iter()
+        .map(|v| (v.selector.to_string(), v.value))
+        .collect();
+    let loc_coords_b: BTreeMap<String, f32> = font_b
+        .location
+        .iter()
+        .map(|v| (v.selector.to_string(), v.value))
+        .collect();
+    this_location_value.location = loc_name;
+    if loc_coords_b != loc_coords {
+        this_location_value.coords_b = loc_coords_b;
+    }
+    this_location_value.coords = loc_coords;
+
+    let diff = diff_fonts_with_progress(font_a, font_b, options, progress);
+    this_location_value.glyphs = diff.glyphs;
+    this_location_value.glyphs_timed_out = diff.glyphs_timed_out;
+    this_location_value.outlines = diff.outlines;
+    this_location_value.glyph_names = diff.glyph_names;
+    this_location_value.words = diff.words;
+    this_location_value.renderability = diff.renderability;
+    this_location_value.mvar = diff.mvar;
+    if let Some((image_dir, png_style)) = images {
+        write_images(
+            &mut this_location_value,
+            font_a,
+            font_b,
+            image_dir,
+            &options.render,
+            *png_style,
+        );
+    }
+    if let Some((output_dir, limit)) = contact_sheets {
+        write_contact_sheets(
+            &this_location_value,
+            font_a,
+            font_b,
+            output_dir,
+            &options.render,
+            *limit,
+        );
+    }
+    this_location_value
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::test_fonts::{be16, be32, cmap, font, name};
+
+    /// Build a font with a `wght` axis and a named instance at each
+    /// (name, weight), with an empty `cmap`
+    fn instance_font(instances: &[(&str, i32)]) -> Vec<u8> {
+        let mut fvar = be16(&[1, 0, 16, 2, 1, 20, instances.len() as i32, 8]);
+        fvar.extend(b"wght");
+        fvar.extend(be32(&[100 << 16, 400 << 16, 900 << 16]));
+        fvar.extend(be16(&[0, 256]));
+        let mut names = vec![];
+        for (ix, (instance, weight)) in instances.iter().enumerate() {
+            fvar.extend(be16(&[257 + ix as i32, 0]));
+            fvar.extend(be32(&[(*weight as u32) << 16]));
+            names.push((257 + ix as u16, *instance));
+        }
+        font([
+            (b"cmap", cmap(&[])),
+            (b"fvar", fvar),
+            (b"name", name(&names)),
+        ])
+    }
+
+    fn fonts() -> (DFont, DFont) {
+        let old = instance_font(&[
+            ("Light", 300),
+            ("Regular", 400),
+            ("Bold", 700),
+            ("Black", 900),
+        ]);
+        let new = instance_font(&[
+            ("ExtraBold", 800),
+            ("Bold", 705),
+            ("Book", 400),
+            ("Light", 300),
+        ]);
+        (DFont::new(&old).unwrap(), DFont::new(&new).unwrap())
+    }
+
+    /// The instance names paired up, with each font's weight
+    fn pairs(settings: Vec<(String, Setting)>) -> Vec<(String, f32, f32)> {
+        settings
+            .into_iter()
+            .map(|(name, setting)| match setting {
+                Setting::LocationPair(a, b) => (name, a[0].value, b[0].value),
+                _ => panic!("instances should be paired by location"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_match_instances_by_location() {
+        let (font_a, font_b) = fonts();
+        let mut report = Report::default();
+        let matched = match_instances_by_location(&font_a, &font_b, 10.0, &mut report);
+        assert_eq!(
+            pairs(matched),
+            [
+                ("Light".to_string(), 300.0, 300.0),
+                ("Regular".to_string(), 400.0, 400.0),
+                ("Bold".to_string(), 700.0, 705.0),
+            ]
+        );
+        assert_eq!(report.missing_instances, ["Black"]);
+        assert_eq!(report.new_instances, ["ExtraBold"]);
+        assert_eq!(
+            report.renamed_instances,
+            IndexMap::from([("Regular".to_string(), "Book".to_string())])
+        );
+        assert_eq!(
+            report.near_matched_instances,
+            ["Bold is at wght=700 in the old font and wght=705 in the new"]
+        );
+    }
+
+    #[test]
+    fn test_match_instances_exactly() {
+        let (font_a, font_b) = fonts();
+        let mut report = Report::default();
+        let matched = match_instances_by_location(&font_a, &font_b, 0.0, &mut report);
+        assert_eq!(
+            pairs(matched),
+            [
+                ("Light".to_string(), 300.0, 300.0),
+                ("Regular".to_string(), 400.0, 400.0),
+            ]
+        );
+        assert_eq!(report.missing_instances, ["Bold", "Black"]);
+        assert_eq!(report.new_instances, ["ExtraBold", "Bold"]);
+        assert!(report.near_matched_instances.is_empty());
+    }
+
+    #[test]
+    fn test_set_on_fonts_checks_location() {
+        let (mut font_a, mut font_b) = fonts();
+        let location = |value: f32| vec![("wght", value).into()];
+        assert_eq!(
+            Setting::from_setting(location(950.0)).set_on_fonts(&mut font_a, &mut font_b),
+            Err("Old font: Value 950 for axis 'wght' is out of range (100 to 900)".to_string())
+        );
+        assert_eq!(
+            Setting::from_settings(location(900.0), vec![("opsz", 12.0).into()])
+                .set_on_fonts(&mut font_a, &mut font_b),
+            Err("New font: Unknown axis 'opsz' (font has axes wght)".to_string())
+        );
+        Setting::from_setting(location(700.0))
+            .set_on_fonts(&mut font_a, &mut font_b)
+            .unwrap();
+        assert_eq!(font_a.location[0].value, 700.0);
+        assert_eq!(font_b.location[0].value, 700.0);
+    }
+}
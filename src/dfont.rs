@@ -1,118 +1,359 @@
-use crate::setting::parse_location;
+use crate::{render::wordlists::script_tag, setting::parse_location, woff};
 use font_types::NameId;
-use read_fonts::FontRef;
-use skrifa::{instance::Location, setting::VariationSetting, MetadataProvider};
+use read_fonts::{
+    tables::{
+        avar::SegmentMaps,
+        variations::{DeltaSetIndex, DeltaSetIndexMap, ItemVariationStore},
+    },
+    types::F2Dot14,
+    FileRef, FontRead, FontRef, ReadError, TableProvider,
+};
+use skrifa::{
+    instance::{Location, NormalizedCoord},
+    setting::VariationSetting,
+    MetadataProvider, Tag,
+};
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
+    error::Error,
+    fmt::Display,
 };
-use ucd::Codepoint;
+use ucd::{Codepoint, Script};
+
+/// A Unicode script supported by a font, along with the OpenType script
+/// tag used to shape text in it
+///
+/// Scripts are compared and ordered by tag.
+#[derive(Debug, Clone, Copy)]
+pub struct SupportedScript {
+    pub script: Script,
+    pub tag: rustybuzz::Script,
+}
+
+impl SupportedScript {
+    /// Returns `None` if the script has no OpenType tag
+    pub fn new(script: Script) -> Option<Self> {
+        Some(SupportedScript {
+            script,
+            tag: script_tag(script)?,
+        })
+    }
+
+    /// The Unicode script name (e.g. `Latin`), as used to key word
+    /// differences and custom wordlists
+    pub fn name(&self) -> String {
+        format!("{:?}", self.script)
+    }
+}
+
+impl PartialEq for SupportedScript {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Eq for SupportedScript {}
+
+impl PartialOrd for SupportedScript {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SupportedScript {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.tag.cmp(&other.tag)
+    }
+}
+
+/// Errors which can occur when loading a font
+#[derive(Debug)]
+pub enum DFontError {
+    /// The data could not be parsed as a font, or the collection has no
+    /// face at the requested index
+    Parse(ReadError),
+    /// The font has no usable `cmap` table, so nothing can be compared
+    MissingCmap(ReadError),
+}
+
+impl Display for DFontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DFontError::Parse(_) => write!(f, "couldn't parse font"),
+            DFontError::MissingCmap(_) => write!(f, "font has no usable cmap table"),
+        }
+    }
+}
 
+impl Error for DFontError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DFontError::Parse(e) | DFontError::MissingCmap(e) => Some(e),
+        }
+    }
+}
+
+/// The largest font, in megabytes, which is loaded by default
+pub const DEFAULT_MAX_FONT_SIZE_MB: u64 = 256;
+
+#[derive(Clone)]
 pub struct DFont {
     pub backing: Vec<u8>,
+    /// Index of the face within a font collection (0 for a single font)
+    pub face_index: u32,
     pub location: Vec<VariationSetting>,
     pub normalized_location: Location,
     pub codepoints: HashSet<u32>,
+    // Metadata which doesn't change with the location, read once on load
+    family_name: String,
+    style_name: String,
+    is_color: bool,
+    is_variable: bool,
+    supported_scripts: BTreeSet<SupportedScript>,
 }
 
 impl DFont {
-    pub fn new(string: &[u8]) -> Self {
-        let backing: Vec<u8> = string.to_vec();
+    pub fn new(string: &[u8]) -> Result<Self, DFontError> {
+        Self::new_with_index(string, 0)
+    }
+
+    /// Load a face from a font or font collection (TTC) file
+    ///
+    /// WOFF and WOFF2 fonts are decompressed, and stored as SFNT data. A
+    /// font whose cmap maps no characters (for instance because none of
+    /// its subtables can be read) is still loaded, with a warning, as its
+    /// tables can be compared even though its glyphs and words cannot.
+    ///
+    /// WOFF and WOFF2 fonts may decompress to at most
+    /// [`DEFAULT_MAX_FONT_SIZE_MB`] megabytes; see [`DFont::with_max_size`].
+    pub fn new_with_index(string: &[u8], face_index: u32) -> Result<Self, DFontError> {
+        Self::with_max_size(string, face_index, DEFAULT_MAX_FONT_SIZE_MB * 1024 * 1024)
+    }
 
-        let mut fnt = DFont {
-            backing,
-            codepoints: HashSet::new(),
+    /// Load a face from a font or font collection file, as
+    /// [`DFont::new_with_index`] does, refusing WOFF and WOFF2 fonts which
+    /// decompress to more than `max_size` bytes
+    pub fn with_max_size(
+        string: &[u8],
+        face_index: u32,
+        max_size: u64,
+    ) -> Result<Self, DFontError> {
+        let string = woff::decompress(string, max_size).map_err(DFontError::Parse)?;
+        let fontref = FontRef::from_index(&string, face_index).map_err(DFontError::Parse)?;
+        fontref.cmap().map_err(DFontError::MissingCmap)?;
+        let codepoints: HashSet<u32> = fontref.charmap().mappings().map(|(cp, _)| cp).collect();
+        let name = |id: NameId, default: &str| {
+            fontref
+                .localized_strings(id)
+                .english_or_first()
+                .map_or_else(|| default.to_string(), |s| s.chars().collect())
+        };
+        let has_table = |tags: &[&[u8; 4]]| {
+            fontref
+                .table_directory
+                .table_records()
+                .iter()
+                .any(|tr| tags.iter().any(|tag| tr.tag() == **tag))
+        };
+        let family_name = name(NameId::FAMILY_NAME, "Unknown");
+        let style_name = name(NameId::SUBFAMILY_NAME, "Regular");
+        if codepoints.is_empty() {
+            log::warn!(
+                "{} {} has a cmap table which maps no characters, so no glyphs or words will be compared",
+                family_name,
+                style_name
+            );
+        }
+        let supported_scripts = codepoints
+            .iter()
+            .filter_map(|cp| char::from_u32(*cp).and_then(|c| c.script()))
+            .filter_map(SupportedScript::new)
+            .collect();
+        Ok(DFont {
+            family_name,
+            style_name,
+            is_color: has_table(&[b"SVG ", b"COLR", b"CBDT"]),
+            is_variable: has_table(&[b"fvar"]),
+            supported_scripts,
+            backing: string.to_vec(),
+            face_index,
+            codepoints,
             normalized_location: Location::default(),
             location: vec![],
-        };
-        let cmap = fnt.fontref().charmap();
-        fnt.codepoints = cmap.mappings().map(|(cp, _)| cp).collect();
-        fnt
+        })
+    }
+
+    /// The number of faces in a font or font collection file
+    pub fn face_count(string: &[u8]) -> u32 {
+        match FileRef::new(string) {
+            Ok(FileRef::Collection(collection)) => collection.len(),
+            _ => 1,
+        }
     }
 
     /// Must be called after the location is set
     pub fn normalize_location(&mut self) {
-        self.normalized_location = self.fontref().axes().location(&self.location);
+        let fontref = self.fontref();
+        let mut location = fontref.axes().location(&self.location);
+        apply_avar2(&fontref, &mut location);
+        self.normalized_location = location;
     }
 
     pub fn set_location(&mut self, variations: &str) -> Result<(), String> {
-        self.location = parse_location(variations)?;
+        let location = parse_location(variations)?;
+        self.check_location(&location)?;
+        self.location = location;
         self.normalize_location();
         Ok(())
     }
+
+    /// Check that each setting names an axis of the font and is within
+    /// that axis's range
+    pub fn check_location(&self, location: &[VariationSetting]) -> Result<(), String> {
+        let axes = self.axis_info();
+        for setting in location {
+            let tag = setting.selector.to_string();
+            let Some((min, _default, max)) = axes.get(&tag) else {
+                return Err(if axes.is_empty() {
+                    format!("Unknown axis '{}' (font is not variable)", tag)
+                } else {
+                    format!(
+                        "Unknown axis '{}' (font has axes {})",
+                        tag,
+                        axes.keys().cloned().collect::<Vec<_>>().join(", ")
+                    )
+                });
+            };
+            if setting.value < *min || setting.value > *max {
+                return Err(format!(
+                    "Value {} for axis '{}' is out of range ({} to {})",
+                    setting.value, tag, min, max
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the location from normalized (-1..1) axis coordinates
+    ///
+    /// Axes not mentioned are left at their default. The user-space
+    /// `location` is back-computed by inverting the `avar` segment maps
+    /// and the `fvar` normalization; this is exact for `avar` version 1
+    /// but ignores any `avar2` mappings.
+    pub fn set_normalized_location(&mut self, coords: &[(Tag, f32)]) {
+        let fontref = self.fontref();
+        let axes = fontref.axes();
+        let avar = fontref.avar().ok();
+        let mut normalized = Location::new(axes.len());
+        let mut location = vec![];
+        for (tag, value) in coords {
+            let Some(axis) = axes.iter().find(|a| a.tag() == *tag) else {
+                continue;
+            };
+            let value = value.clamp(-1.0, 1.0);
+            normalized.coords_mut()[axis.index()] = NormalizedCoord::from_f32(value);
+            let unmapped = avar
+                .as_ref()
+                .and_then(|avar| avar.axis_segment_maps().get(axis.index()))
+                .and_then(|maps| maps.ok())
+                .map_or(value, |maps| unmap_avar(&maps, value));
+            let user = if unmapped < 0.0 {
+                axis.default_value() + unmapped * (axis.default_value() - axis.min_value())
+            } else {
+                axis.default_value() + unmapped * (axis.max_value() - axis.default_value())
+            };
+            location.push((*tag, user).into());
+        }
+        self.normalized_location = normalized;
+        self.location = location;
+    }
+
     pub fn instances(&self) -> Vec<String> {
-        self.fontref()
+        let fontref = self.fontref();
+        fontref
             .named_instances()
             .iter()
             .flat_map(|ni| {
-                self.fontref()
+                fontref
                     .localized_strings(ni.subfamily_name_id())
                     .english_or_first()
             })
             .map(|s| s.to_string())
             .collect()
     }
+    /// The name of each named instance, with its location in user space
+    pub fn instance_locations(&self) -> Vec<(String, Vec<VariationSetting>)> {
+        let fontref = self.fontref();
+        fontref
+            .named_instances()
+            .iter()
+            .filter_map(|ni| {
+                let name = fontref
+                    .localized_strings(ni.subfamily_name_id())
+                    .english_or_first()?
+                    .to_string();
+                let location = fontref
+                    .axes()
+                    .iter()
+                    .zip(ni.user_coords())
+                    .map(|(a, v)| (a.tag(), v).into())
+                    .collect();
+                Some((name, location))
+            })
+            .collect()
+    }
+
     pub fn set_instance(&mut self, instance: &str) -> Result<(), String> {
-        let instance = self
-            .fontref()
+        let fontref = self.fontref();
+        let instance = fontref
             .named_instances()
             .iter()
             .find(|ni| {
-                self.fontref()
+                fontref
                     .localized_strings(ni.subfamily_name_id())
                     .any(|s| instance == s.chars().collect::<Cow<str>>())
             })
             .ok_or_else(|| format!("No instance named {}", instance))?;
-        let user_coords = instance.user_coords();
-        let location = instance.location();
-        self.location = self
-            .fontref()
+        let mut location = instance.location();
+        apply_avar2(&fontref, &mut location);
+        let user_location = fontref
             .axes()
             .iter()
-            .zip(user_coords)
+            .zip(instance.user_coords())
             .map(|(a, v)| (a.tag(), v).into())
             .collect();
+        self.location = user_location;
         self.normalized_location = location;
         Ok(())
     }
 
-    pub fn fontref(&self) -> FontRef {
-        FontRef::new(&self.backing).expect("Couldn't parse font")
+    /// A view of the font's tables
+    ///
+    /// Each call parses the table directory again, so a comparison should
+    /// call this once per font and pass the result down rather than call it
+    /// for each glyph or table. It cannot fail, as the font was checked
+    /// when the `DFont` was created.
+    pub fn fontref(&self) -> FontRef<'_> {
+        FontRef::from_index(&self.backing, self.face_index).expect("Couldn't parse font")
     }
     pub fn family_name(&self) -> String {
-        self.fontref()
-            .localized_strings(NameId::FAMILY_NAME)
-            .english_or_first()
-            .map_or_else(|| "Unknown".to_string(), |s| s.chars().collect())
+        self.family_name.clone()
     }
 
     pub fn style_name(&self) -> String {
-        self.fontref()
-            .localized_strings(NameId::SUBFAMILY_NAME)
-            .english_or_first()
-            .map_or_else(|| "Regular".to_string(), |s| s.chars().collect())
+        self.style_name.clone()
     }
 
     pub fn is_color(&self) -> bool {
-        self.fontref()
-            .table_directory
-            .table_records()
-            .iter()
-            .any(|tr| {
-                let tag = tr.tag();
-                tag == "SVG " || tag == "COLR" || tag == "CBDT"
-            })
+        self.is_color
     }
 
     pub fn is_variable(&self) -> bool {
-        self.fontref()
-            .table_directory
-            .table_records()
-            .iter()
-            .any(|tr| tr.tag() == "fvar")
+        self.is_variable
     }
 
-    pub fn axis_info(&self) -> HashMap<String, (f32, f32, f32)> {
+    pub fn axis_info(&self) -> BTreeMap<String, (f32, f32, f32)> {
         self.fontref()
             .axes()
             .iter()
@@ -125,14 +366,132 @@ impl DFont {
             .collect()
     }
 
-    pub fn supported_scripts(&self) -> HashSet<String> {
-        let cmap = self.fontref().charmap();
-        let mut strings = HashSet::new();
-        for (codepoint, _glyphid) in cmap.mappings() {
-            if let Some(script) = char::from_u32(codepoint).and_then(|c| c.script()) {
-                strings.insert(format!("{:?}", script));
+    pub fn supported_scripts(&self) -> &BTreeSet<SupportedScript> {
+        &self.supported_scripts
+    }
+}
+
+/// Apply the mappings of an `avar` version 2 table to normalized coordinates
+///
+/// skrifa applies the per-axis segment maps, but version 2 tables also
+/// adjust each axis through an item variation store, based on the
+/// segment-mapped values of all axes.
+fn apply_avar2(font: &FontRef, location: &mut Location) {
+    let (Some(data), Ok(avar)) = (font.table_data(Tag::new(b"avar")), font.avar()) else {
+        return;
+    };
+    if avar.version().major < 2 {
+        return;
+    }
+    // The version 2 offsets follow the variable-length segment maps
+    let mut offset = 8;
+    for maps in avar
+        .axis_segment_maps()
+        .iter()
+        .take(avar.axis_count() as usize)
+    {
+        let Ok(maps) = maps else {
+            return;
+        };
+        offset += 2 + 4 * maps.position_map_count() as usize;
+    }
+    let (Ok(index_map_offset), Ok(var_store_offset)) =
+        (data.read_at::<u32>(offset), data.read_at::<u32>(offset + 4))
+    else {
+        return;
+    };
+    let Some(Ok(var_store)) = (var_store_offset != 0)
+        .then(|| data.split_off(var_store_offset as usize))
+        .flatten()
+        .map(ItemVariationStore::read)
+    else {
+        return;
+    };
+    let index_map = (index_map_offset != 0)
+        .then(|| data.split_off(index_map_offset as usize))
+        .flatten()
+        .and_then(|data| DeltaSetIndexMap::read(data).ok());
+
+    let coords: Vec<F2Dot14> = location.coords().to_vec();
+    for (axis, coord) in location.coords_mut().iter_mut().enumerate() {
+        let index = match &index_map {
+            Some(map) => map.get(axis as u32),
+            None => Ok(DeltaSetIndex {
+                outer: 0,
+                inner: axis as u16,
+            }),
+        };
+        let Ok(delta) = index.and_then(|index| var_store.compute_delta(index, &coords)) else {
+            continue;
+        };
+        let value = (coord.to_bits() as i32 + delta).clamp(-16384, 16384);
+        *coord = F2Dot14::from_bits(value as i16);
+    }
+}
+
+/// Invert an avar segment map, taking a mapped coordinate back to the
+/// default normalized coordinate
+fn unmap_avar(maps: &SegmentMaps, value: f32) -> f32 {
+    let pairs: Vec<(f32, f32)> = maps
+        .axis_value_maps()
+        .iter()
+        .map(|m| (m.from_coordinate().to_f32(), m.to_coordinate().to_f32()))
+        .collect();
+    for window in pairs.windows(2) {
+        let ((from_lo, to_lo), (from_hi, to_hi)) = (window[0], window[1]);
+        if value >= to_lo && value <= to_hi {
+            if to_hi == to_lo {
+                return from_lo;
             }
+            return from_lo + (value - to_lo) / (to_hi - to_lo) * (from_hi - from_lo);
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, be32, cmap, font};
+
+    /// Build a font with an empty `cmap`, a single `wght` axis (100-400-900) and an `avar`
+    /// version 2 table which moves the axis by -0.25 at its maximum
+    fn avar2_font() -> Vec<u8> {
+        let mut avar = be16(&[2, 0, 0, 1]);
+        // Identity segment map
+        avar.extend(be16(&[3, -16384, -16384, 0, 0, 16384, 16384]));
+        // No axis index map; variation store follows
+        avar.extend(be32(&[0, 30]));
+        // ItemVariationStore: region list at 12, one data subtable at 22
+        avar.extend(be16(&[1]));
+        avar.extend(be32(&[12]));
+        avar.extend(be16(&[1]));
+        avar.extend(be32(&[22]));
+        // One region, peaking at wght=1.0
+        avar.extend(be16(&[1, 1, 0, 16384, 16384]));
+        // One item with a single word delta of -0.25
+        avar.extend(be16(&[1, 1, 1, 0, -4096]));
+
+        let mut fvar = be16(&[1, 0, 16, 2, 1, 20, 0, 8]);
+        fvar.extend(b"wght");
+        fvar.extend(be32(&[100 << 16, 400 << 16, 900 << 16]));
+        fvar.extend(be16(&[0, 256]));
+
+        font([(b"avar", avar), (b"cmap", cmap(&[])), (b"fvar", fvar)])
+    }
+
+    #[test]
+    fn test_avar2_normalization() {
+        let data = avar2_font();
+        let mut font = DFont::new(&data).unwrap();
+        for (user, expected) in [(900.0, 0.75), (650.0, 0.375), (400.0, 0.0), (250.0, -0.5)] {
+            font.set_location(&format!("wght={}", user)).unwrap();
+            assert_eq!(
+                font.normalized_location.coords()[0].to_f32(),
+                expected,
+                "wght={}",
+                user
+            );
         }
-        strings
     }
 }
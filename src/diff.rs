@@ -0,0 +1,540 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::{
+    dfont::DFont,
+    outlines::{compare_glyphs_by_name, compare_outlines, GlyphNameDiff, OutlineDiff},
+    render::{
+        encodedglyphs::{modified_encoded_glyphs, new_missing_glyphs, CmapDiff},
+        test_font_words, CustomWordlists, GlyphDiff, Progress, RenderOptions, RenderabilityDiffs,
+        SharedFonts, WordDiffs, WordTestOptions,
+    },
+    ttj::{
+        anchors::{anchor_diff, AnchorDiff},
+        build_name_ids,
+        colr::{colr_diff, ColrDiff},
+        features::{feature_diff, FeatureDiff},
+        fvar::default_instance_diff,
+        gdef::{gdef_diff, GdefDiff},
+        glyph_count::{glyph_count_diff, GlyphCountDiff},
+        jsondiff::Substantial,
+        kerning::{kern_diff, KernDiff},
+        metrics::{metrics_diff, MetricsDiff},
+        mvar::{mvar_diff, MvarDiff},
+        names::name_diff,
+        post_names::{post_name_diff, PostNameDiff},
+        stat::stat_diff,
+        style_bits::style_bits_diff,
+        substitutions::{substitution_diff, SubstitutionDiff},
+        table_diff_filtered,
+        unicode_ranges::unicode_range_diff,
+        upm::{upm_diff, UpmDiff},
+        Ignored,
+    },
+};
+use skrifa::Tag;
+
+/// Which tests to run when diffing two fonts
+///
+/// When deserialized, missing fields take their default values, and
+/// unknown fields are an error.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffOptions {
+    /// Compare the font tables
+    pub tables: bool,
+    /// Only compare these tables (all tables if `None`)
+    #[serde(deserialize_with = "deserialize_tags")]
+    pub only_tables: Option<Vec<Tag>>,
+    /// Name IDs left out of the table diff (by default the unique ID and
+    /// version string, which change with every build)
+    pub ignore_name_ids: Vec<u16>,
+    /// Leave the `head` table's `modified` and `font_revision` fields out
+    /// of the table diff
+    pub ignore_head_revision: bool,
+    /// Compare the set of encoded codepoints
+    pub cmap: bool,
+    /// Compare the rendering of encoded glyphs
+    pub glyphs: bool,
+    /// Only report glyphs which differ by at least this percentage
+    pub glyph_threshold: f32,
+    /// Compare glyph outlines point-by-point (off by default)
+    pub outlines: bool,
+    /// Compare the outlines of all glyphs, matched by glyph name rather
+    /// than by encoding (off by default)
+    pub glyph_names: bool,
+    /// Compare the rendering of words
+    pub words: bool,
+    /// Only report words which differ by at least this percentage
+    pub word_threshold: f32,
+    /// Additional or replacement wordlists for the word tests
+    pub wordlists: Option<CustomWordlists>,
+    /// Only test this many words from each script's wordlist (all words
+    /// if `None`)
+    pub max_words_per_script: Option<usize>,
+    /// Group each script's word differences by the kind of difference,
+    /// rather than listing them together (off by default)
+    pub group_words_by_kind: bool,
+    /// Compare GPOS kerning pairs (off by default)
+    pub kerning: bool,
+    /// Compare GPOS mark-to-base, mark-to-ligature and mark-to-mark
+    /// attachment positions (off by default)
+    pub anchors: bool,
+    /// Compare glyph advances and side bearings in `hmtx` and `vmtx`,
+    /// instead of including those tables in the table diff (off by default)
+    pub metrics: bool,
+    /// Only report metrics which differ by more than this many font units
+    pub metrics_tolerance: u16,
+    /// Compare the metrics which MVAR adjusts, resolved at the fonts'
+    /// current location (off by default)
+    pub mvar: bool,
+    /// Flag a change to the units per em when the `head` table, metrics,
+    /// kerning, anchors or MVAR values are compared
+    pub upm: bool,
+    /// Compare GDEF glyph classes and mark attachment classes (off by
+    /// default)
+    pub gdef: bool,
+    /// Compare the glyph names in the `post` table by glyph ID (off by
+    /// default)
+    pub post_names: bool,
+    /// Compare the paint graphs of color glyphs, instead of including
+    /// `COLR` in the table diff (off by default)
+    pub colr: bool,
+    /// Compare the glyphs which each GSUB feature's single substitutions
+    /// substitute (off by default)
+    pub substitutions: bool,
+    /// Compare the GSUB/GPOS features registered for each script and
+    /// language system (off by default)
+    pub features: bool,
+    /// Compare family and subfamily names as applications resolve them,
+    /// and the other name table entries (off by default)
+    pub names: bool,
+    /// Check the OS/2 Unicode range bits against each font's cmap, and
+    /// compare them between the fonts (off by default)
+    pub unicode_ranges: bool,
+    /// Check that the bold and italic bits of head.macStyle and
+    /// OS/2.fsSelection agree with the subfamily name, and compare them
+    /// between the fonts (off by default)
+    pub style_bits: bool,
+    /// How glyphs and words are rasterized for comparison
+    pub render: RenderOptions,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            tables: true,
+            only_tables: None,
+            ignore_name_ids: build_name_ids(),
+            ignore_head_revision: true,
+            cmap: true,
+            glyphs: true,
+            glyph_threshold: 0.0,
+            outlines: false,
+            glyph_names: false,
+            words: true,
+            word_threshold: 0.0,
+            wordlists: None,
+            max_words_per_script: None,
+            group_words_by_kind: false,
+            kerning: false,
+            anchors: false,
+            metrics: false,
+            metrics_tolerance: 0,
+            mvar: false,
+            upm: true,
+            gdef: false,
+            post_names: false,
+            colr: false,
+            substitutions: false,
+            features: false,
+            names: false,
+            unicode_ranges: false,
+            style_bits: false,
+            render: RenderOptions::default(),
+        }
+    }
+}
+
+impl DiffOptions {
+    /// The switches for the tests which fill a [GlobalDiff]
+    fn global_tests(&mut self) -> [&mut bool; 14] {
+        [
+            &mut self.tables,
+            &mut self.upm,
+            &mut self.cmap,
+            &mut self.kerning,
+            &mut self.anchors,
+            &mut self.metrics,
+            &mut self.gdef,
+            &mut self.post_names,
+            &mut self.colr,
+            &mut self.substitutions,
+            &mut self.features,
+            &mut self.names,
+            &mut self.unicode_ranges,
+            &mut self.style_bits,
+        ]
+    }
+
+    /// These options with the tests whose results don't depend on the
+    /// fonts' location switched off
+    pub fn without_global_tests(&self) -> DiffOptions {
+        let mut options = self.clone();
+        for test in options.global_tests() {
+            *test = false;
+        }
+        options
+    }
+}
+
+fn deserialize_tags<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Tag>>, D::Error> {
+    let tags: Option<Vec<String>> = Option::deserialize(deserializer)?;
+    tags.map(|tags| {
+        tags.iter()
+            .map(|tag| Tag::new_checked(tag.as_bytes()).map_err(serde::de::Error::custom))
+            .collect()
+    })
+    .transpose()
+}
+
+/// The differences between two fonts which don't depend on their location
+#[derive(Serialize, Default, JsonSchema)]
+pub struct GlobalDiff {
+    /// Differences in font tables, keyed by table tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tables: Option<Value>,
+    /// Semantic changes to the STAT table, which is left out of `tables`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stat: Vec<String>,
+    /// Changes to the default location of a variable font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_instance: Vec<String>,
+    /// Changes to the number or order of glyphs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glyph_count: Option<GlyphCountDiff>,
+    /// A change to the units per em
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upm: Option<UpmDiff>,
+    /// Semantic changes to the name table
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    /// Disagreements between the OS/2 Unicode range bits and the cmap,
+    /// and changes to the bits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unicode_ranges: Vec<String>,
+    /// Disagreements between head.macStyle, OS/2.fsSelection and the
+    /// subfamily name, and changes to them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub style_bits: Vec<String>,
+    /// Codepoints encoded in only one of the fonts, and glyphs mapped from
+    /// different codepoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmap_diff: Option<CmapDiff>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kerning: Vec<KernDiff>,
+    /// Mark attachments whose position differs, matched by glyph name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anchors: Vec<AnchorDiff>,
+    /// Glyphs whose advance or side bearing differs, matched by glyph name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metrics: Vec<MetricsDiff>,
+    /// Glyphs whose GDEF classification differs, matched by glyph name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gdef: Vec<GdefDiff>,
+    /// Changes to the glyph names in the post table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_names: Option<PostNameDiff>,
+    /// Changes to the paint graphs of COLR color glyphs, matched by glyph
+    /// name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colr: Option<ColrDiff>,
+    /// Glyphs whose single substitution under a GSUB feature differs,
+    /// matched by glyph name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub substitutions: Vec<SubstitutionDiff>,
+    /// OpenType features registered in only one of the fonts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<FeatureDiff>,
+}
+
+impl GlobalDiff {
+    pub fn is_some(&self) -> bool {
+        self.tables.as_ref().is_some_and(|t| t.is_something())
+            || !self.stat.is_empty()
+            || !self.default_instance.is_empty()
+            || self.glyph_count.is_some()
+            || self.upm.is_some()
+            || !self.names.is_empty()
+            || !self.unicode_ranges.is_empty()
+            || !self.style_bits.is_empty()
+            || self.cmap_diff.as_ref().is_some_and(|c| c.is_some())
+            || !self.kerning.is_empty()
+            || !self.anchors.is_empty()
+            || !self.metrics.is_empty()
+            || !self.gdef.is_empty()
+            || self.post_names.as_ref().is_some_and(|p| p.is_some())
+            || self.colr.as_ref().is_some_and(|c| c.is_some())
+            || !self.substitutions.is_empty()
+            || self.features.as_ref().is_some_and(|f| f.is_some())
+    }
+}
+
+/// The differences between two fonts
+///
+/// Glyph and word differences are computed at the fonts' current location.
+#[derive(Serialize, Default, JsonSchema)]
+pub struct Diff {
+    #[serde(flatten)]
+    pub global: GlobalDiff,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub glyphs: Vec<GlyphDiff>,
+    /// Encoded glyphs which took too long to render to be compared
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub glyphs_timed_out: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outlines: Vec<OutlineDiff>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyph_names: Option<GlyphNameDiff>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<WordDiffs>,
+    /// Words which only one of the fonts can render, keyed by script
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub renderability: RenderabilityDiffs,
+    /// Metrics adjusted by MVAR which differ at the fonts' current location
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mvar: Vec<MvarDiff>,
+}
+
+impl Diff {
+    pub fn is_some(&self) -> bool {
+        self.global.is_some()
+            || !self.glyphs.is_empty()
+            || !self.glyphs_timed_out.is_empty()
+            || !self.outlines.is_empty()
+            || self.glyph_names.as_ref().is_some_and(|g| g.is_some())
+            || self.words.as_ref().is_some_and(|w| !w.is_empty())
+            || !self.renderability.is_empty()
+            || !self.mvar.is_empty()
+    }
+}
+
+/// Run the tests selected in `options` on two fonts
+pub fn diff_fonts(font_a: &DFont, font_b: &DFont, options: &DiffOptions) -> Diff {
+    diff_fonts_with_progress(font_a, font_b, options, None)
+}
+
+/// Run the tests selected in `options` on two fonts, reporting the
+/// progress of the word tests to a callback
+pub fn diff_fonts_with_progress(
+    font_a: &DFont,
+    font_b: &DFont,
+    options: &DiffOptions,
+    progress: Option<&(dyn Fn(Progress) + Sync)>,
+) -> Diff {
+    let mut result = Diff::default();
+    let (fontref_a, fontref_b) = (font_a.fontref(), font_b.fontref());
+    if options.tables {
+        let mut table_diff = table_diff_filtered(
+            &fontref_a,
+            &fontref_b,
+            options.only_tables.as_deref(),
+            &Ignored {
+                name_ids: &options.ignore_name_ids,
+                head_revision: options.ignore_head_revision,
+            },
+        );
+        // The JSON diff of STAT is sensitive to record order, so compare it
+        // semantically instead
+        if let Some(tables) = table_diff.as_object_mut() {
+            tables.remove("STAT");
+            // Metrics are compared glyph by glyph instead
+            if options.metrics {
+                tables.remove("hmtx");
+                tables.remove("vmtx");
+            }
+            // Color glyphs are compared by their paint graphs instead
+            if options.colr {
+                tables.remove("COLR");
+            }
+        }
+        if table_diff.is_something() {
+            result.global.tables = Some(table_diff);
+        }
+        let wanted = |tag: &[u8; 4]| {
+            options
+                .only_tables
+                .as_ref()
+                .is_none_or(|tables| tables.contains(&Tag::new(tag)))
+        };
+        if wanted(b"STAT") {
+            result.global.stat = stat_diff(&fontref_a, &fontref_b);
+        }
+        if wanted(b"fvar") {
+            result.global.default_instance = default_instance_diff(&fontref_a, &fontref_b);
+        }
+        if wanted(b"maxp") {
+            result.global.glyph_count = glyph_count_diff(&fontref_a, &fontref_b);
+            if let Some(warning) = result
+                .global
+                .glyph_count
+                .as_ref()
+                .and_then(|g| g.warning.as_ref())
+            {
+                log::warn!("{}", warning);
+            }
+        }
+    }
+    // Metrics, kerning, anchors and MVAR values are scaled if the units per em
+    // differ, so the change is flagged even when the tables aren't compared
+    if options.upm
+        && (options.tables
+            && options
+                .only_tables
+                .as_ref()
+                .is_none_or(|tables| tables.contains(&Tag::new(b"head")))
+            || options.metrics
+            || options.kerning
+            || options.anchors
+            || options.mvar)
+    {
+        result.global.upm = upm_diff(&fontref_a, &fontref_b);
+        if let Some(upm) = &result.global.upm {
+            log::warn!("{}", upm.warning);
+        }
+    }
+    if options.cmap {
+        result.global.cmap_diff = Some(new_missing_glyphs(font_a, font_b));
+    }
+    // Workers rendering with a timeout need their own copies of the fonts
+    let shared_fonts = options
+        .render
+        .timeout
+        .is_some()
+        .then(|| SharedFonts::new(font_a, font_b));
+    if options.glyphs {
+        (result.glyphs, result.glyphs_timed_out) = modified_encoded_glyphs(
+            font_a,
+            font_b,
+            options.glyph_threshold,
+            &options.render,
+            shared_fonts.as_ref(),
+        );
+    }
+    if options.outlines {
+        result.outlines = compare_outlines(font_a, font_b);
+    }
+    if options.glyph_names {
+        result.glyph_names = Some(compare_glyphs_by_name(font_a, font_b)).filter(|g| g.is_some());
+    }
+    if options.words {
+        let (words, renderability) = test_font_words(
+            font_a,
+            font_b,
+            &WordTestOptions {
+                threshold: options.word_threshold,
+                custom_wordlists: options.wordlists.as_ref(),
+                max_words: options.max_words_per_script,
+                render: &options.render,
+                progress,
+                shared_fonts: shared_fonts.as_ref(),
+            },
+        );
+        result.words = Some(if options.group_words_by_kind {
+            words
+                .into_iter()
+                .map(|(script, differences)| (script, differences.group_by_kind()))
+                .collect()
+        } else {
+            words
+        });
+        result.renderability = renderability;
+    }
+    if options.kerning {
+        result.global.kerning = kern_diff(&fontref_a, &fontref_b);
+    }
+    if options.anchors {
+        result.global.anchors = anchor_diff(&fontref_a, &fontref_b);
+    }
+    if options.metrics {
+        result.global.metrics = metrics_diff(&fontref_a, &fontref_b, options.metrics_tolerance);
+    }
+    if options.mvar {
+        result.mvar = mvar_diff(
+            &fontref_a,
+            font_a.normalized_location.coords(),
+            &fontref_b,
+            font_b.normalized_location.coords(),
+        );
+    }
+    if options.gdef {
+        result.global.gdef = gdef_diff(&fontref_a, &fontref_b);
+    }
+    if options.colr {
+        let colr = colr_diff(&fontref_a, &fontref_b);
+        if colr.is_some() {
+            result.global.colr = Some(colr);
+        }
+    }
+    if options.post_names {
+        let post_names = post_name_diff(&fontref_a, &fontref_b);
+        if post_names.is_some() {
+            result.global.post_names = Some(post_names);
+        }
+    }
+    if options.substitutions {
+        result.global.substitutions = substitution_diff(&fontref_a, &fontref_b);
+    }
+    if options.features {
+        let features = feature_diff(&fontref_a, &fontref_b);
+        if features.is_some() {
+            result.global.features = Some(features);
+        }
+    }
+    if options.names {
+        result.global.names = name_diff(&fontref_a, &fontref_b);
+    }
+    if options.unicode_ranges {
+        result.global.unicode_ranges = unicode_range_diff(&fontref_a, &fontref_b);
+    }
+    if options.style_bits {
+        result.global.style_bits = style_bits_diff(&fontref_a, &fontref_b);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_options() {
+        let options: DiffOptions =
+            serde_json::from_str(r#"{"kerning": true, "render": {"antialias": false}}"#).unwrap();
+        assert!(options.kerning && options.tables && !options.render.antialias);
+        // Misspelt options are rejected rather than ignored
+        assert!(serde_json::from_str::<DiffOptions>(r#"{"kernng": true}"#).is_err());
+        assert!(serde_json::from_str::<DiffOptions>(r#"{"render": {"antialis": false}}"#).is_err());
+    }
+
+    #[test]
+    fn test_global_differences_are_flattened() {
+        let diff = Diff {
+            global: GlobalDiff {
+                stat: vec!["Axis value added".to_string()],
+                ..Default::default()
+            },
+            glyphs_timed_out: vec!["a".to_string()],
+            ..Default::default()
+        };
+        assert!(diff.is_some());
+        assert_eq!(
+            serde_json::to_value(&diff).unwrap(),
+            serde_json::json!({ "stat": ["Axis value added"], "glyphs_timed_out": ["a"] })
+        );
+    }
+}
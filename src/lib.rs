@@ -1,12 +1,20 @@
 use cfg_if::cfg_if;
 
 pub mod dfont;
+pub mod diff;
+pub mod outlines;
 pub mod render;
 pub mod setting;
+#[cfg(test)]
+mod test_fonts;
 pub mod ttj;
+pub mod woff;
 
 cfg_if! {
     if #[cfg(not(target_family = "wasm"))] {
+        pub mod compare;
+        #[cfg(feature = "python")]
+        mod python;
         pub mod reporters;
         pub mod utils;
     }
@@ -14,9 +22,9 @@ cfg_if! {
 
 cfg_if! {
     if #[cfg(target_family = "wasm")] {
-        use std::collections::HashMap;
+        use std::collections::BTreeMap;
         use dfont::DFont;
-        use render::{test_font_glyphs, test_font_words};
+        use render::{encodedglyphs::modified_encoded_glyphs, test_font_words, RenderOptions, WordTestOptions};
         use serde_json::json;
         use ttj::table_diff;
         use skrifa::MetadataProvider;
@@ -30,10 +38,22 @@ cfg_if! {
             panic::set_hook(Box::new(console_error_panic_hook::hook));
         }
 
+        fn load_fonts(font_a: &[u8], font_b: &[u8]) -> Result<(DFont, DFont), String> {
+            let f_a = DFont::new(font_a).map_err(|e| format!("old font: {}", e))?;
+            let f_b = DFont::new(font_b).map_err(|e| format!("new font: {}", e))?;
+            Ok((f_a, f_b))
+        }
+
+        fn error_json(error: String) -> String {
+            json!({"error": error}).to_string()
+        }
+
         #[wasm_bindgen]
         pub fn axes(font_a: &[u8], font_b: &[u8]) -> String {
-            let f_a = DFont::new(font_a);
-            let f_b = DFont::new(font_b);
+            let (f_a, f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => return error_json(e),
+            };
             let mut axes = f_a.axis_info();
             let b_axes = f_b.axis_info();
             let a_axes_names: Vec<String> = axes.keys().cloned().collect();
@@ -54,26 +74,25 @@ cfg_if! {
                     }
                 );
             }
-            let axis_names: Vec<String> = f_a.fontref()
+            let fontref_a = f_a.fontref();
+            let axis_names: Vec<String> = fontref_a
             .axes()
             .iter()
             .map(|axis| {
                     axis.tag().to_string()
             }).collect();
-            let instances = f_a
-                .fontref()
+            let instances = fontref_a
                 .named_instances()
                 .iter()
                 .map(|ni| {
-                    let name = f_a
-                        .fontref()
+                    let name = fontref_a
                         .localized_strings(ni.subfamily_name_id())
                         .english_or_first()
                         .map_or_else(|| "Unknown".to_string(), |s| s.chars().collect());
                     let location_map = axis_names.iter().cloned().zip(ni.user_coords()).collect();
                     (name, location_map)
                 })
-                .collect::<Vec<(String, HashMap<String, f32>)>>();
+                .collect::<Vec<(String, BTreeMap<String, f32>)>>();
             return serde_json::to_string(&json!({
                 "axes": &axes,
                 "instances": instances
@@ -82,21 +101,54 @@ cfg_if! {
 
         #[wasm_bindgen]
         pub fn diff(font_a: &[u8], font_b: &[u8]) -> String {
-            let f_a = DFont::new(font_a);
-            let f_b = DFont::new(font_b);
-            let val = json!({
-                "tables": table_diff(&f_a.fontref(), &f_b.fontref()),
-                "glyphs": test_font_glyphs(&f_a, &f_b),
-                "words": test_font_words(&f_a, &f_b),
-            });
+            let (f_a, f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => return error_json(e),
+            };
+            let val = diff::diff_fonts(&f_a, &f_b, &diff::DiffOptions::default());
+            serde_json::to_string(&val)
+                .unwrap_or("Couldn't do it".to_string())
+        }
+
+        #[cfg(feature = "wasm")]
+        #[wasm_bindgen]
+        pub fn diff_fonts_wasm(font_a: &[u8], font_b: &[u8], options_json: &str) -> String {
+            let options: diff::DiffOptions = if options_json.trim().is_empty() {
+                diff::DiffOptions::default()
+            } else {
+                match serde_json::from_str(options_json) {
+                    Ok(options) => options,
+                    Err(e) => return error_json(e.to_string()),
+                }
+            };
+            let (f_a, f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => return error_json(e),
+            };
+            let val = diff::diff_fonts(&f_a, &f_b, &options);
             serde_json::to_string(&val)
                 .unwrap_or("Couldn't do it".to_string())
         }
 
+        #[cfg(feature = "wasm")]
+        #[wasm_bindgen]
+        pub fn render_word(font: &[u8], location: &str, word: &str, font_size: f32) -> String {
+            let Ok(mut f) = DFont::new(font) else {
+                return String::new();
+            };
+            let _hack = f.set_location(location);
+            render::render_png_base64(&f, word, font_size).unwrap_or_default()
+        }
+
         #[wasm_bindgen]
         pub fn diff_tables(font_a: &[u8], font_b: &[u8], f: &js_sys::Function) {
-            let f_a = DFont::new(font_a);
-            let f_b = DFont::new(font_b);
+            let (f_a, f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => {
+                    f.call1(&JsValue::NULL, &JsValue::from_str(&error_json(e))).unwrap();
+                    return;
+                }
+            };
 
             let val = json!({
                 "tables": table_diff(&f_a.fontref(), &f_b.fontref())
@@ -106,27 +158,39 @@ cfg_if! {
 
         #[wasm_bindgen]
         pub fn diff_glyphs(font_a: &[u8], font_b: &[u8], location: &str, f: &js_sys::Function) {
-            let mut f_a = DFont::new(font_a);
-            let mut f_b = DFont::new(font_b);
+            let (mut f_a, mut f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => {
+                    f.call1(&JsValue::NULL, &JsValue::from_str(&error_json(e))).unwrap();
+                    return;
+                }
+            };
             let _hack = f_a.set_location(location);
             let _hack = f_b.set_location(location);
 
             let val = json!({
-                "glyphs": test_font_glyphs(&f_a, &f_b)
+                "glyphs": modified_encoded_glyphs(&f_a, &f_b, 0.0, &RenderOptions::default(), None).0
             });
             f.call1(&JsValue::NULL, &JsValue::from_str(&serde_json::to_string(&val).unwrap_or("Couldn't do it".to_string()))).unwrap();
         }
 
         #[wasm_bindgen]
         pub fn diff_words(font_a: &[u8], font_b: &[u8], location: &str, f: &js_sys::Function) {
-            let mut f_a = DFont::new(font_a);
-            let mut f_b = DFont::new(font_b);
+            let (mut f_a, mut f_b) = match load_fonts(font_a, font_b) {
+                Ok(fonts) => fonts,
+                Err(e) => {
+                    f.call1(&JsValue::NULL, &JsValue::from_str(&error_json(e))).unwrap();
+                    return;
+                }
+            };
             let _hack = f_a.set_location(location);
             let _hack = f_b.set_location(location);
 
 
+            let (words, renderability) = test_font_words(&f_a, &f_b, &WordTestOptions::new(&RenderOptions::default()));
             let val = json!({
-                "words": test_font_words(&f_a, &f_b)
+                "words": words,
+                "renderability": renderability,
             });
             f.call1(&JsValue::NULL, &JsValue::from_str(&serde_json::to_string(&val).unwrap_or("Couldn't do it".to_string()))).unwrap();
         }
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+
+use read_fonts::FontRef;
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::{
+    instance::{LocationRef, Size},
+    outline::{DrawSettings, OutlineGlyphCollection, OutlinePen},
+    GlyphId, MetadataProvider,
+};
+
+use crate::{dfont::DFont, ttj::glyph_names};
+
+/// A pen which records the points of each contour, in font units
+#[derive(Default)]
+struct PointPen {
+    contours: Vec<Vec<(f32, f32)>>,
+}
+
+impl PointPen {
+    fn push(&mut self, x: f32, y: f32) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push((x, y));
+        }
+    }
+
+    fn points(&self) -> usize {
+        self.contours.iter().map(|c| c.len()).sum()
+    }
+}
+
+impl OutlinePen for PointPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(vec![(x, y)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.push(cx0, cy0);
+        self.push(x, y);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        self.push(cx0, cy0);
+        self.push(cx1, cy1);
+        self.push(x, y);
+    }
+
+    fn close(&mut self) {}
+}
+
+/// How a glyph's outline changed between the old and new fonts
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutlineChange {
+    pub contours: (usize, usize),
+    pub points: (usize, usize),
+    /// The largest distance a point moved, in font units, if the two
+    /// outlines have the same structure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_delta: Option<f32>,
+}
+
+/// A difference in the outline of an encoded glyph
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutlineDiff {
+    pub string: String,
+    pub unicode: String,
+    #[serde(flatten)]
+    pub change: OutlineChange,
+}
+
+/// Glyphs compared by name rather than by encoding, so that unencoded
+/// glyphs such as alternates are included
+#[derive(Debug, Serialize, Default, JsonSchema)]
+pub struct GlyphNameDiff {
+    /// Glyph names only present in the new font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Glyph names only present in the old font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified: Vec<NamedOutlineDiff>,
+}
+
+impl GlyphNameDiff {
+    pub fn is_some(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty() || !self.modified.is_empty()
+    }
+}
+
+/// A difference in the outline of a glyph found by name
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NamedOutlineDiff {
+    pub name: String,
+    #[serde(flatten)]
+    pub change: OutlineChange,
+}
+
+fn draw_glyph(
+    font: &DFont,
+    outlines: &OutlineGlyphCollection,
+    glyph_id: GlyphId,
+) -> Option<PointPen> {
+    let outline = outlines.get(glyph_id)?;
+    let location: LocationRef = (&font.normalized_location).into();
+    let mut pen = PointPen::default();
+    outline
+        .draw(DrawSettings::unhinted(Size::unscaled(), location), &mut pen)
+        .ok()?;
+    Some(pen)
+}
+
+/// Compare two outlines, returning None if they are identical
+fn compare_pens(pen_a: &PointPen, pen_b: &PointPen) -> Option<OutlineChange> {
+    let same_structure = pen_a.contours.len() == pen_b.contours.len()
+        && pen_a
+            .contours
+            .iter()
+            .zip(pen_b.contours.iter())
+            .all(|(a, b)| a.len() == b.len());
+    let max_delta = same_structure.then(|| {
+        pen_a
+            .contours
+            .iter()
+            .flatten()
+            .zip(pen_b.contours.iter().flatten())
+            .map(|((xa, ya), (xb, yb))| ((xa - xb).powi(2) + (ya - yb).powi(2)).sqrt())
+            .fold(0.0, f32::max)
+    });
+    if same_structure && max_delta == Some(0.0) {
+        return None;
+    }
+    Some(OutlineChange {
+        contours: (pen_a.contours.len(), pen_b.contours.len()),
+        points: (pen_a.points(), pen_b.points()),
+        max_delta,
+    })
+}
+
+/// Compare the outlines of glyphs encoded in both fonts, at the fonts'
+/// current locations
+///
+/// Unlike the rendered glyph comparison, this is independent of
+/// rasterization and reports changes in structure (contour and point
+/// counts) as well as any movement of points.
+pub fn compare_outlines(font_a: &DFont, font_b: &DFont) -> Vec<OutlineDiff> {
+    let mut codepoints: Vec<u32> = font_a
+        .codepoints
+        .intersection(&font_b.codepoints)
+        .copied()
+        .collect();
+    codepoints.sort();
+    let (fontref_a, fontref_b) = (font_a.fontref(), font_b.fontref());
+    let (charmap_a, charmap_b) = (fontref_a.charmap(), fontref_b.charmap());
+    let (outlines_a, outlines_b) = (fontref_a.outline_glyphs(), fontref_b.outline_glyphs());
+    codepoints
+        .into_iter()
+        .filter_map(|codepoint| {
+            let pen_a = draw_glyph(font_a, &outlines_a, charmap_a.map(codepoint)?)?;
+            let pen_b = draw_glyph(font_b, &outlines_b, charmap_b.map(codepoint)?)?;
+            Some(OutlineDiff {
+                string: char::from_u32(codepoint)?.to_string(),
+                unicode: format!("U+{:04X}", codepoint),
+                change: compare_pens(&pen_a, &pen_b)?,
+            })
+        })
+        .collect()
+}
+
+/// Map each glyph name in a font to its glyph ID
+///
+/// Fonts without glyph names in the `post` table get names of the form
+/// `gid123`, so are effectively compared by glyph ID.
+fn glyph_ids(fontref: &FontRef) -> BTreeMap<String, GlyphId> {
+    let mut ids = BTreeMap::new();
+    for (gid, name) in glyph_names(fontref).into_iter().enumerate() {
+        ids.entry(name).or_insert(GlyphId::new(gid as u16));
+    }
+    ids
+}
+
+/// Compare all glyphs in two fonts by name, at the fonts' current
+/// locations
+///
+/// This finds changes to unencoded glyphs and to glyphs whose encoding
+/// changed, which the codepoint-based comparisons cannot see.
+pub fn compare_glyphs_by_name(font_a: &DFont, font_b: &DFont) -> GlyphNameDiff {
+    let (fontref_a, fontref_b) = (font_a.fontref(), font_b.fontref());
+    let names_a = glyph_ids(&fontref_a);
+    let names_b = glyph_ids(&fontref_b);
+    let (outlines_a, outlines_b) = (fontref_a.outline_glyphs(), fontref_b.outline_glyphs());
+    let modified = names_a
+        .iter()
+        .filter_map(|(name, gid_a)| {
+            let gid_b = names_b.get(name)?;
+            let pen_a = draw_glyph(font_a, &outlines_a, *gid_a)?;
+            let pen_b = draw_glyph(font_b, &outlines_b, *gid_b)?;
+            Some(NamedOutlineDiff {
+                name: name.clone(),
+                change: compare_pens(&pen_a, &pen_b)?,
+            })
+        })
+        .collect();
+    GlyphNameDiff {
+        added: names_b
+            .keys()
+            .filter(|name| !names_a.contains_key(*name))
+            .cloned()
+            .collect(),
+        removed: names_a
+            .keys()
+            .filter(|name| !names_b.contains_key(*name))
+            .cloned()
+            .collect(),
+        modified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, outline_tables};
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+    const MOVED: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 540), (400, 0)];
+    const TRIANGLE: &[(i32, i32)] = &[(100, 0), (250, 500), (400, 0)];
+
+    #[test]
+    fn test_compare_outlines() {
+        let old = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, SQUARE), (0x42, SQUARE), (0x43, SQUARE)],
+        )))
+        .unwrap();
+        let new = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, SQUARE), (0x42, MOVED), (0x43, TRIANGLE)],
+        )))
+        .unwrap();
+        assert!(compare_outlines(&old, &old).is_empty());
+
+        let diffs = compare_outlines(&old, &new);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            (diffs[0].string.as_str(), diffs[0].unicode.as_str()),
+            ("B", "U+0042")
+        );
+        assert_eq!(diffs[0].change.points, (4, 4));
+        assert_eq!(diffs[0].change.max_delta, Some(40.0));
+        // A change of structure has no single distance moved
+        assert_eq!(diffs[1].string, "C");
+        assert_eq!(diffs[1].change.points, (4, 3));
+        assert_eq!(diffs[1].change.max_delta, None);
+    }
+
+    #[test]
+    fn test_compare_glyphs_by_name() {
+        let old = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, SQUARE), (0x42, SQUARE)],
+        )))
+        .unwrap();
+        let new = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, MOVED), (0x43, SQUARE)],
+        )))
+        .unwrap();
+        let diff = compare_glyphs_by_name(&old, &new);
+        assert!(diff.is_some());
+        assert_eq!(diff.added, vec!["uni0043"]);
+        assert_eq!(diff.removed, vec!["uni0042"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].name, "uni0041");
+        assert!(!compare_glyphs_by_name(&old, &old).is_some());
+    }
+}
@@ -0,0 +1,157 @@
+// The code generated by pyo3's macros trips this lint
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use serde_json::Value;
+
+use crate::{
+    compare::{compare, CompareOptions},
+    dfont::DFont,
+    setting::{parse_location, Setting},
+};
+
+/// Convert JSON to the equivalent Python objects
+fn to_python(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_py(py),
+            (None, Some(u)) => u.into_py(py),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(|item| to_python(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in map {
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+/// Convert Python objects to JSON, by way of the `json` module
+fn from_python(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let json: String = value
+        .py()
+        .import_bound("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&json).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn load(font: &[u8], face_index: u32) -> PyResult<DFont> {
+    DFont::new_with_index(font, face_index).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Compare two fonts, given as the contents of their files, and return
+/// the report as a dict, as `diffenator3 --json` would print it
+///
+/// Keyword arguments set the tests to run, as in the WASM options: e.g.
+/// `kerning=True`, `words=False` or `render={"antialias": False}`. The
+/// glyphs and words are compared at the default location unless one of
+/// these is given:
+///
+/// - `location`: a location such as `"wght=700,wdth=100"`, which must be
+///   within the axes of both fonts
+/// - `instance`: the name of a named instance, or `"*"` for every named
+///   instance the fonts share (but not the default location, unless it
+///   is one of them)
+///
+/// `face_index` chooses the font to compare in font collections. An
+/// unknown keyword argument or an invalid location raises a `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (font_a, font_b, **options))]
+fn diff_fonts(
+    py: Python<'_>,
+    font_a: &[u8],
+    font_b: &[u8],
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<PyObject> {
+    let mut options = match options {
+        Some(options) => from_python(options.as_any())?,
+        None => Value::Object(Default::default()),
+    };
+    let mut take = |key: &str| options.as_object_mut().and_then(|map| map.remove(key));
+    let location = take("location");
+    let instance = take("instance");
+    let face_index = take("face_index");
+
+    let face_index = match face_index {
+        Some(face_index) => face_index
+            .as_u64()
+            .and_then(|index| u32::try_from(index).ok())
+            .ok_or_else(|| PyValueError::new_err("face_index must be a font number"))?,
+        None => 0,
+    };
+
+    let mut locations = vec![];
+    let mut all_instances = false;
+    if let Some(location) = location {
+        let location = location
+            .as_str()
+            .ok_or_else(|| PyValueError::new_err("location must be a string"))?;
+        let settings = parse_location(location).map_err(PyValueError::new_err)?;
+        for (font, which) in [(font_a, "Old"), (font_b, "New")] {
+            load(font, face_index)?
+                .check_location(&settings)
+                .map_err(|e| PyValueError::new_err(format!("{} font: {}", which, e)))?;
+        }
+        locations.push(Setting::from_setting(settings));
+    }
+    match instance.as_ref().map(|instance| instance.as_str()) {
+        Some(Some("*")) => all_instances = true,
+        Some(Some(instance)) => locations.push(Setting::from_instance(instance.to_string())),
+        Some(None) => return Err(PyValueError::new_err("instance must be a string")),
+        None => {}
+    }
+    let compare_options = CompareOptions {
+        diff: serde_json::from_value(options).map_err(|e| PyValueError::new_err(e.to_string()))?,
+        locations,
+        all_instances,
+        face_index,
+        ..Default::default()
+    };
+
+    let report = py.allow_threads(|| compare(font_a, font_b, &compare_options));
+    if let Some(error) = report.get("error").and_then(|e| e.as_str()) {
+        return Err(PyValueError::new_err(error.to_string()));
+    }
+    to_python(py, &report)
+}
+
+/// The names of a font's named instances, which can be passed to
+/// `diff_fonts` as `instance`
+#[pyfunction]
+#[pyo3(signature = (font, face_index = 0))]
+fn instances(font: &[u8], face_index: u32) -> PyResult<Vec<String>> {
+    Ok(load(font, face_index)?.instances())
+}
+
+/// A font's variation axes, as a dict of axis tags to (minimum, default,
+/// maximum) values, for building a `location` for `diff_fonts`
+#[pyfunction]
+#[pyo3(signature = (font, face_index = 0))]
+fn axes(py: Python<'_>, font: &[u8], face_index: u32) -> PyResult<PyObject> {
+    Ok(load(font, face_index)?.axis_info().into_py(py))
+}
+
+#[pymodule]
+fn diffenator3(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(diff_fonts, m)?)?;
+    m.add_function(wrap_pyfunction!(instances, m)?)?;
+    m.add_function(wrap_pyfunction!(axes, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+
+use rustybuzz::GlyphBuffer;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// How a cluster's glyphs changed between the old and new fonts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusterChange {
+    /// Different glyphs were used
+    Glyphs,
+    /// The same glyphs were used, but positioned differently
+    Position,
+}
+
+/// A cluster of characters in a word which shaped differently
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClusterDiff {
+    pub text: String,
+    /// The index of the cluster's first character in the word
+    pub start: usize,
+    /// The index after the cluster's last character in the word
+    pub end: usize,
+    pub change: ClusterChange,
+}
+
+/// A shaped glyph: its ID, offsets and advances in font units
+type Glyph = (u32, i32, i32, i32, i32);
+
+/// Group the glyphs of a buffer by segment, where `boundaries` are the
+/// byte offsets at which each segment starts
+fn segment_glyphs(buffer: &GlyphBuffer, boundaries: &[u32]) -> Vec<Vec<Glyph>> {
+    let mut segments = vec![vec![]; boundaries.len()];
+    for (info, position) in buffer.glyph_infos().iter().zip(buffer.glyph_positions()) {
+        let index = boundaries
+            .partition_point(|b| *b <= info.cluster)
+            .saturating_sub(1);
+        segments[index].push((
+            info.glyph_id,
+            position.x_offset,
+            position.y_offset,
+            position.x_advance,
+            position.y_advance,
+        ));
+    }
+    segments
+}
+
+/// Compare two shapings of the same word cluster by cluster
+///
+/// The fonts may merge characters into clusters differently, so the
+/// buffers are aligned on the cluster boundaries they have in common and
+/// each resulting segment is compared as a whole. Each glyph's advance is
+/// compared rather than its absolute position, so that a change is
+/// attributed to the cluster responsible and not to everything after it.
+pub(crate) fn cluster_diff(
+    word: &str,
+    buffer_a: &GlyphBuffer,
+    buffer_b: &GlyphBuffer,
+) -> Vec<ClusterDiff> {
+    let clusters = |buffer: &GlyphBuffer| -> BTreeSet<u32> {
+        buffer
+            .glyph_infos()
+            .iter()
+            .map(|info| info.cluster)
+            .collect()
+    };
+    let boundaries: Vec<u32> = clusters(buffer_a)
+        .intersection(&clusters(buffer_b))
+        .copied()
+        .collect();
+    if boundaries.is_empty() {
+        return vec![];
+    }
+    let segments_a = segment_glyphs(buffer_a, &boundaries);
+    let segments_b = segment_glyphs(buffer_b, &boundaries);
+    let char_index = |byte: usize| word[..byte.min(word.len())].chars().count();
+    let mut diffs = vec![];
+    for (index, (glyphs_a, glyphs_b)) in segments_a.iter().zip(segments_b.iter()).enumerate() {
+        let change = if glyphs_a.len() != glyphs_b.len()
+            || glyphs_a.iter().zip(glyphs_b).any(|(a, b)| a.0 != b.0)
+        {
+            ClusterChange::Glyphs
+        } else if glyphs_a != glyphs_b {
+            ClusterChange::Position
+        } else {
+            continue;
+        };
+        let start = if index == 0 {
+            0
+        } else {
+            boundaries[index] as usize
+        };
+        let end = boundaries
+            .get(index + 1)
+            .map_or(word.len(), |b| *b as usize);
+        let Some(text) = word.get(start..end) else {
+            continue;
+        };
+        diffs.push(ClusterDiff {
+            text: text.to_string(),
+            start: char_index(start),
+            end: char_index(end),
+            change,
+        });
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use read_fonts::{types::Tag, FontRef, TableProvider};
+    use rustybuzz::{BufferClusterLevel, Face, UnicodeBuffer};
+
+    use super::*;
+
+    const FONT: &[u8] = include_bytes!("../../www/AND-Regular.ttf");
+
+    fn shape(font: &[u8], text: &str, cluster_level: BufferClusterLevel) -> GlyphBuffer {
+        let face = Face::from_slice(font, 0).unwrap();
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.set_cluster_level(cluster_level);
+        buffer.guess_segment_properties();
+        rustybuzz::shape(&face, &[], buffer)
+    }
+
+    fn summary(diffs: &[ClusterDiff]) -> Vec<(&str, usize, usize, ClusterChange)> {
+        diffs
+            .iter()
+            .map(|d| (d.text.as_str(), d.start, d.end, d.change))
+            .collect()
+    }
+
+    #[test]
+    fn test_same_shaping() {
+        let buffer = shape(FONT, "abc", BufferClusterLevel::default());
+        assert!(cluster_diff("abc", &buffer, &buffer).is_empty());
+    }
+
+    #[test]
+    fn test_glyph_change() {
+        // Cluster positions are byte offsets, but are reported in characters
+        let buffer_a = shape(FONT, "aéb", BufferClusterLevel::default());
+        let buffer_b = shape(FONT, "aéc", BufferClusterLevel::default());
+        assert_eq!(
+            summary(&cluster_diff("aéb", &buffer_a, &buffer_b)),
+            [("b", 2, 3, ClusterChange::Glyphs)]
+        );
+    }
+
+    #[test]
+    fn test_position_change() {
+        // Every glyph in this font shares the first advance width
+        let mut wider = FONT.to_vec();
+        let hmtx = FontRef::new(FONT)
+            .unwrap()
+            .table_directory
+            .table_records()
+            .iter()
+            .find(|record| record.tag() == Tag::new(b"hmtx"))
+            .unwrap()
+            .offset() as usize;
+        let advance = FontRef::new(FONT)
+            .unwrap()
+            .hmtx()
+            .unwrap()
+            .advance(0.into());
+        wider[hmtx..hmtx + 2].copy_from_slice(&(advance.unwrap() + 100).to_be_bytes());
+
+        let buffer_a = shape(FONT, "ab", BufferClusterLevel::default());
+        let buffer_b = shape(&wider, "ab", BufferClusterLevel::default());
+        assert_eq!(
+            summary(&cluster_diff("ab", &buffer_a, &buffer_b)),
+            [
+                ("a", 0, 1, ClusterChange::Position),
+                ("b", 1, 2, ClusterChange::Position)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_clusters() {
+        // The mark has a cluster of its own in the old shaping, and is
+        // merged with its base in the new; the segments are aligned on the
+        // boundaries both have
+        let word = "ae\u{301}b";
+        let buffer_a = shape(FONT, word, BufferClusterLevel::Characters);
+        let buffer_b = shape(FONT, "ae\u{301}c", BufferClusterLevel::MonotoneGraphemes);
+        assert_eq!(
+            summary(&cluster_diff(word, &buffer_a, &buffer_b)),
+            [("b", 3, 4, ClusterChange::Glyphs)]
+        );
+    }
+}
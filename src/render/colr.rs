@@ -0,0 +1,233 @@
+use skrifa::{
+    color::{Brush, ColorPainter, CompositeMode, Transform},
+    instance::{LocationRef, Size},
+    outline::{DrawSettings, OutlinePen},
+    raw::{types::BoundingBox, TableProvider},
+    FontRef, GlyphId, OutlineGlyphCollection,
+};
+use zeno::{Command, PathBuilder};
+
+/// A set of drawing commands to be filled in a single color
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub commands: Vec<Command>,
+    /// RGBA color of the layer, or `None` for the foreground color
+    pub color: Option<[u8; 4]>,
+}
+
+impl Layer {
+    pub fn foreground(commands: Vec<Command>) -> Self {
+        Layer {
+            commands,
+            color: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// Read the first palette of the font's CPAL table
+pub(crate) fn first_palette(font: &FontRef) -> Vec<[u8; 4]> {
+    let Ok(cpal) = font.cpal() else {
+        return vec![];
+    };
+    let Some(Ok(records)) = cpal.color_records_array() else {
+        return vec![];
+    };
+    let first = cpal
+        .color_record_indices()
+        .first()
+        .map(|i| i.get() as usize)
+        .unwrap_or(0);
+    records
+        .iter()
+        .skip(first)
+        .take(cpal.num_palette_entries() as usize)
+        .map(|c| [c.red, c.green, c.blue, c.alpha])
+        .collect()
+}
+
+/// A pen which applies an affine transform, scale and offset to
+/// everything drawn through it
+struct TransformPen<'a> {
+    buffer: &'a mut Vec<Command>,
+    transform: Transform,
+    scale: f32,
+    offset_x: f32,
+    offset_y: f32,
+}
+
+impl TransformPen<'_> {
+    fn map(&self, x: f32, y: f32) -> [f32; 2] {
+        let t = &self.transform;
+        [
+            self.offset_x + (t.xx * x + t.xy * y + t.dx) * self.scale,
+            self.offset_y + (t.yx * x + t.yy * y + t.dy) * self.scale,
+        ]
+    }
+}
+
+impl OutlinePen for TransformPen<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let to = self.map(x, y);
+        self.buffer.move_to(to);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.map(x, y);
+        self.buffer.line_to(to);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        let (ctrl, to) = (self.map(cx0, cy0), self.map(x, y));
+        self.buffer.quad_to(ctrl, to);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        let (ctrl0, ctrl1, to) = (self.map(cx0, cy0), self.map(cx1, cy1), self.map(x, y));
+        self.buffer.curve_to(ctrl0, ctrl1, to);
+    }
+
+    fn close(&mut self) {
+        self.buffer.close();
+    }
+}
+
+/// A color painter which flattens a COLR glyph into solid-colored layers
+///
+/// This is an approximation: clip boxes and composite modes are ignored,
+/// and gradients are filled with the average of their color stops.
+pub(crate) struct LayerPainter<'a> {
+    outlines: &'a OutlineGlyphCollection<'a>,
+    location: LocationRef<'a>,
+    palette: &'a [[u8; 4]],
+    scale: f32,
+    offset_x: f32,
+    offset_y: f32,
+    transforms: Vec<Transform>,
+    clips: Vec<Option<GlyphId>>,
+    pub layers: Vec<Layer>,
+}
+
+impl<'a> LayerPainter<'a> {
+    pub fn new(
+        outlines: &'a OutlineGlyphCollection<'a>,
+        location: LocationRef<'a>,
+        palette: &'a [[u8; 4]],
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+    ) -> Self {
+        LayerPainter {
+            outlines,
+            location,
+            palette,
+            scale,
+            offset_x,
+            offset_y,
+            transforms: vec![],
+            clips: vec![],
+            layers: vec![],
+        }
+    }
+
+    fn current_transform(&self) -> Transform {
+        self.transforms
+            .iter()
+            .fold(Transform::default(), |acc, t| acc * *t)
+    }
+
+    fn color(&self, palette_index: u16, alpha: f32) -> Option<[u8; 4]> {
+        // 0xFFFF means "use the foreground color"
+        let [r, g, b, a] = self.palette.get(palette_index as usize)?;
+        Some([*r, *g, *b, (*a as f32 * alpha).round() as u8])
+    }
+
+    fn brush_color(&self, brush: &Brush) -> Option<[u8; 4]> {
+        let stops = match brush {
+            Brush::Solid {
+                palette_index,
+                alpha,
+            } => return self.color(*palette_index, *alpha),
+            Brush::LinearGradient { color_stops, .. } => color_stops,
+            Brush::RadialGradient { color_stops, .. } => color_stops,
+            Brush::SweepGradient { color_stops, .. } => color_stops,
+        };
+        let colors: Vec<[u8; 4]> = stops
+            .iter()
+            .flat_map(|stop| self.color(stop.palette_index, stop.alpha))
+            .collect();
+        if colors.is_empty() {
+            return None;
+        }
+        let mut average = [0u32; 4];
+        for color in colors.iter() {
+            for (channel, value) in average.iter_mut().zip(color) {
+                *channel += *value as u32;
+            }
+        }
+        Some(average.map(|c| (c / colors.len() as u32) as u8))
+    }
+
+    fn fill_with_color(&mut self, glyph_id: GlyphId, color: Option<[u8; 4]>) {
+        let Some(outline) = self.outlines.get(glyph_id) else {
+            return;
+        };
+        let mut commands = vec![];
+        let mut pen = TransformPen {
+            buffer: &mut commands,
+            transform: self.current_transform(),
+            scale: self.scale,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+        };
+        let settings = DrawSettings::unhinted(Size::unscaled(), self.location);
+        let _ = outline.draw(settings, &mut pen);
+        self.layers.push(Layer { commands, color });
+    }
+}
+
+impl ColorPainter for LayerPainter<'_> {
+    fn push_transform(&mut self, transform: Transform) {
+        self.transforms.push(transform);
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        self.clips.push(Some(glyph_id));
+    }
+
+    fn push_clip_box(&mut self, _clip_box: BoundingBox<f32>) {
+        self.clips.push(None);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn fill(&mut self, brush: Brush<'_>) {
+        if let Some(glyph_id) = self.clips.iter().rev().find_map(|c| *c) {
+            let color = self.brush_color(&brush);
+            self.fill_with_color(glyph_id, color);
+        }
+    }
+
+    fn fill_glyph(
+        &mut self,
+        glyph_id: GlyphId,
+        _brush_transform: Option<Transform>,
+        brush: Brush<'_>,
+    ) {
+        let color = self.brush_color(&brush);
+        self.fill_with_color(glyph_id, color);
+    }
+
+    fn push_layer(&mut self, _composite_mode: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+}
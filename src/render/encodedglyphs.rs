@@ -1,27 +1,37 @@
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Display,
+};
 
 use crate::{
     dfont::DFont,
-    render::{diff_many_words, GlyphDiff},
+    render::{
+        diff_many_words, GlyphDiff, RenderOptions, SharedFonts, WordComparison, GLYPH_FONT_SIZE,
+    },
+    ttj::glyph_names,
 };
+use image::DynamicImage;
 use rustybuzz::Direction;
+use schemars::JsonSchema;
 use serde::Serialize;
+use skrifa::MetadataProvider;
+use ucd::Codepoint;
 
-#[derive(Serialize)]
+/// An encoded codepoint
+#[derive(Serialize, JsonSchema)]
 pub struct EncodedGlyph {
-    pub string: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codepoint: u32,
+    pub character: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// The Unicode block containing the codepoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
 }
 
 impl Display for EncodedGlyph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} (U+{:04X})",
-            self.string,
-            self.string.chars().next().unwrap() as u32
-        )?;
+        write!(f, "{} (U+{:04X})", self.character, self.codepoint)?;
         if let Some(name) = &self.name {
             write!(f, " {}", name)
         } else {
@@ -30,63 +40,339 @@ impl Display for EncodedGlyph {
     }
 }
 
-#[derive(Serialize)]
+/// Codepoints encoded in only one of two fonts, and glyphs mapped from
+/// different codepoints
+///
+/// Codepoints are sorted, and so are grouped by Unicode block.
+#[derive(Serialize, JsonSchema)]
 pub struct CmapDiff {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    pub missing: Vec<EncodedGlyph>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    pub new: Vec<EncodedGlyph>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_codepoints: Vec<EncodedGlyph>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_codepoints: Vec<EncodedGlyph>,
+    /// Glyphs encoded in both fonts, but mapped from different codepoints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remapped_glyphs: Vec<GlyphMappingDiff>,
 }
 
 impl CmapDiff {
     pub fn is_some(&self) -> bool {
-        !self.missing.is_empty() || !self.new.is_empty()
+        !self.added_codepoints.is_empty()
+            || !self.removed_codepoints.is_empty()
+            || !self.remapped_glyphs.is_empty()
     }
 }
 
-fn chars_to_json_array(chars: impl Iterator<Item = u32>) -> impl Iterator<Item = EncodedGlyph> {
-    chars
-        .map(char::from_u32)
-        .filter(|x| x.is_some())
-        .map(|c| EncodedGlyph {
-            string: c.unwrap().to_string(),
-            name: unicode_names2::name(c.unwrap()).map(|n| n.to_string()),
+/// A glyph, matched by name, which is mapped from different codepoints
+/// in two fonts
+#[derive(Serialize, JsonSchema)]
+pub struct GlyphMappingDiff {
+    pub glyph: String,
+    /// Codepoints which map to the glyph only in the old font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<EncodedGlyph>,
+    /// Codepoints which map to the glyph only in the new font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<EncodedGlyph>,
+}
+
+fn encoded_glyphs<'a>(codepoints: impl Iterator<Item = &'a u32>) -> Vec<EncodedGlyph> {
+    let mut codepoints: Vec<u32> = codepoints.copied().collect();
+    codepoints.sort();
+    codepoints
+        .into_iter()
+        .filter_map(|cp| {
+            let c = char::from_u32(cp)?;
+            Some(EncodedGlyph {
+                codepoint: cp,
+                character: c.to_string(),
+                name: unicode_names2::name(c).map(|n| n.to_string()),
+                block: c.block().map(|b| format!("{:?}", b)),
+            })
         })
+        .collect()
+}
+
+/// The codepoints which map to each encoded glyph, by glyph name
+fn glyph_codepoints(font: &DFont) -> BTreeMap<String, BTreeSet<u32>> {
+    let fontref = font.fontref();
+    let names = glyph_names(&fontref);
+    let mut glyphs: BTreeMap<String, BTreeSet<u32>> = BTreeMap::new();
+    for (codepoint, gid) in fontref.charmap().mappings() {
+        if let Some(name) = names.get(gid.to_u16() as usize) {
+            glyphs.entry(name.clone()).or_default().insert(codepoint);
+        }
+    }
+    glyphs
+}
+
+/// Glyphs encoded in both fonts whose sets of codepoints differ
+///
+/// This catches a codepoint which is dropped from, or moved away from, a
+/// glyph it shared with other codepoints, which doesn't show up as a
+/// missing glyph.
+fn remapped_glyphs(font_a: &DFont, font_b: &DFont) -> Vec<GlyphMappingDiff> {
+    let glyphs_b = glyph_codepoints(font_b);
+    glyph_codepoints(font_a)
+        .into_iter()
+        .filter_map(|(glyph, codepoints_a)| {
+            let codepoints_b = glyphs_b.get(&glyph)?;
+            (&codepoints_a != codepoints_b).then(|| GlyphMappingDiff {
+                removed: encoded_glyphs(codepoints_a.difference(codepoints_b)),
+                added: encoded_glyphs(codepoints_b.difference(&codepoints_a)),
+                glyph,
+            })
+        })
+        .collect()
 }
 
 pub fn new_missing_glyphs(font_a: &DFont, font_b: &DFont) -> CmapDiff {
     let cmap_a = &font_a.codepoints;
     let cmap_b = &font_b.codepoints;
-    let missing_glyphs = cmap_a.difference(cmap_b).copied();
-    let new_glyphs = cmap_b.difference(cmap_a).copied();
     CmapDiff {
-        missing: chars_to_json_array(missing_glyphs).collect(),
-        new: chars_to_json_array(new_glyphs).collect(),
+        added_codepoints: encoded_glyphs(cmap_b.difference(cmap_a)),
+        removed_codepoints: encoded_glyphs(cmap_a.difference(cmap_b)),
+        remapped_glyphs: remapped_glyphs(font_a, font_b),
+    }
+}
+
+/// The codepoints selected by a list of glyph names, characters and
+/// `U+XXXX` codepoints
+///
+/// A glyph name selects the codepoints mapped to a glyph of that name in
+/// either font.
+fn selected_codepoints(font_a: &DFont, font_b: &DFont, glyphs: &[String]) -> HashSet<u32> {
+    let mut selected = HashSet::new();
+    let mut names = HashSet::new();
+    for glyph in glyphs {
+        if let Some(Ok(codepoint)) = glyph
+            .strip_prefix("U+")
+            .or_else(|| glyph.strip_prefix("u+"))
+            .map(|hex| u32::from_str_radix(hex, 16))
+        {
+            selected.insert(codepoint);
+            continue;
+        }
+        let mut chars = glyph.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            selected.insert(c as u32);
+            continue;
+        }
+        names.insert(glyph.as_str());
     }
+    let mut found = HashSet::new();
+    for font in [font_a, font_b] {
+        let fontref = font.fontref();
+        let glyph_names = glyph_names(&fontref);
+        for (codepoint, glyph_id) in fontref.charmap().mappings() {
+            let Some(name) = glyph_names.get(glyph_id.to_u16() as usize) else {
+                continue;
+            };
+            if let Some(name) = names.get(name.as_str()) {
+                selected.insert(codepoint);
+                found.insert(*name);
+            }
+        }
+    }
+    for name in names.difference(&found) {
+        log::warn!("No encoded glyph named {} in either font", name);
+    }
+    selected
+}
+
+/// Compare the rendering of glyphs encoded in both fonts
+///
+/// Glyphs whose pixel difference percentage is below `threshold`, and
+/// glyphs which render as nothing in both fonts, are not reported. If
+/// the render options name `only_glyphs`, only those glyphs are compared.
+/// If they name `ppems`, glyphs are also compared hinted at each of those
+/// sizes, and a glyph which differs at any of them is reported with the
+/// sizes at which it does, even if it doesn't differ at the usual size.
+///
+/// Also returns the glyphs which couldn't be compared within the render
+/// options' timeout. Renders with a timeout use the `shared_fonts` if
+/// given.
+pub fn modified_encoded_glyphs(
+    font_a: &DFont,
+    font_b: &DFont,
+    threshold: f32,
+    render_options: &RenderOptions,
+    shared_fonts: Option<&SharedFonts>,
+) -> (Vec<GlyphDiff>, Vec<String>) {
+    let (glyphs, timed_out) = compare_encoded_glyphs(
+        font_a,
+        font_b,
+        threshold,
+        render_options,
+        shared_fonts,
+        false,
+    );
+    (
+        glyphs.into_iter().map(|glyph| glyph.diff).collect(),
+        timed_out,
+    )
+}
+
+/// A glyph which renders differently, with the images which were compared
+///
+/// Images are grayscale, except for color glyphs, which are RGBA.
+pub struct GlyphImages {
+    pub diff: GlyphDiff,
+    /// The old and new renderings compared at the usual size, if the glyph
+    /// differs at that size
+    pub images: Option<(DynamicImage, DynamicImage)>,
+    /// The old and new hinted renderings at each size in the diff's
+    /// `ppems`
+    pub ppem_images: BTreeMap<u16, (DynamicImage, DynamicImage)>,
+}
+
+/// Compare the rendering of glyphs encoded in both fonts, as
+/// [modified_encoded_glyphs] does, keeping the renderings which were
+/// compared for each differing glyph
+pub fn modified_encoded_glyph_images(
+    font_a: &DFont,
+    font_b: &DFont,
+    threshold: f32,
+    render_options: &RenderOptions,
+    shared_fonts: Option<&SharedFonts>,
+) -> (Vec<GlyphImages>, Vec<String>) {
+    compare_encoded_glyphs(
+        font_a,
+        font_b,
+        threshold,
+        render_options,
+        shared_fonts,
+        true,
+    )
 }
 
-pub fn modified_encoded_glyphs(font_a: &DFont, font_b: &DFont) -> Vec<GlyphDiff> {
+fn compare_encoded_glyphs(
+    font_a: &DFont,
+    font_b: &DFont,
+    threshold: f32,
+    render_options: &RenderOptions,
+    shared_fonts: Option<&SharedFonts>,
+    keep_images: bool,
+) -> (Vec<GlyphImages>, Vec<String>) {
     let cmap_a = &font_a.codepoints;
     let cmap_b = &font_b.codepoints;
-    let same_glyphs = cmap_a.intersection(cmap_b);
-    let threshold = 0.1;
+    let mut same_glyphs: Vec<&u32> = cmap_a.intersection(cmap_b).collect();
+    if !render_options.only_glyphs.is_empty() {
+        let selected = selected_codepoints(font_a, font_b, &render_options.only_glyphs);
+        same_glyphs.retain(|codepoint| selected.contains(codepoint));
+    }
+    same_glyphs.sort();
     let word_list: Vec<String> = same_glyphs
+        .into_iter()
         .map(|i| char::from_u32(*i))
         .filter(|x| x.is_some())
         .map(|c| c.unwrap().to_string())
         .collect();
-    let mut result: Vec<GlyphDiff> = diff_many_words(
-        font_a,
-        font_b,
-        40.0,
-        word_list,
-        threshold,
-        Direction::LeftToRight,
-        None,
-    )
-    .into_iter()
-    .map(|x| x.into())
-    .collect();
-    result.sort_by_key(|x| (-x.percent * 10_000.0) as i32);
-    result
+    let mut timed_out = BTreeSet::new();
+    let mut diff_glyphs = |render_options: &RenderOptions| {
+        let comparison = WordComparison {
+            font_size: GLYPH_FONT_SIZE,
+            threshold: 0.1,
+            direction: Direction::LeftToRight,
+            script: None,
+            render_options,
+            shared_fonts,
+            keep_images,
+        };
+        let (differences, renderability) =
+            diff_many_words(font_a, font_b, word_list.clone(), &comparison, &|| {});
+        timed_out.extend(renderability.timed_out);
+        differences.into_iter().filter(|x| x.percent >= threshold)
+    };
+    let mut glyphs: BTreeMap<String, GlyphImages> = diff_glyphs(render_options)
+        .map(|mut x| {
+            let images = x.renderings.take();
+            (
+                x.word.clone(),
+                GlyphImages {
+                    diff: x.into(),
+                    images,
+                    ppem_images: BTreeMap::new(),
+                },
+            )
+        })
+        .collect();
+    for &ppem in &render_options.ppems {
+        for mut diff in diff_glyphs(&render_options.at_ppem(ppem)) {
+            let images = diff.renderings.take();
+            let glyph = glyphs
+                .entry(diff.word.clone())
+                .or_insert_with(|| GlyphImages {
+                    diff: GlyphDiff {
+                        percent: 0.0,
+                        ..diff.into()
+                    },
+                    images: None,
+                    ppem_images: BTreeMap::new(),
+                });
+            glyph.diff.ppems.push(ppem);
+            if let Some(images) = images {
+                glyph.ppem_images.insert(ppem, images);
+            }
+        }
+    }
+    let mut result: Vec<GlyphImages> = glyphs.into_values().collect();
+    // UTF-8 strings sort in codepoint order
+    result.sort_by(|a, b| {
+        b.diff
+            .percent
+            .total_cmp(&a.diff.percent)
+            .then_with(|| a.diff.string.cmp(&b.diff.string))
+    });
+    (result, timed_out.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render::renderer::Renderer,
+        test_fonts::{font, outline_tables},
+    };
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+    const TALL: &[(i32, i32)] = &[(100, 0), (100, 700), (400, 700), (400, 0)];
+
+    fn render(font: &DFont, font_size: f32, options: &RenderOptions) -> DynamicImage {
+        let mut renderer = Renderer::new(font, font_size, Direction::LeftToRight, None, options);
+        let (_, layers) = renderer.string_to_positioned_glyphs("A").unwrap();
+        renderer.render_positioned_glyphs(&layers)
+    }
+
+    #[test]
+    fn test_glyph_images_are_those_compared() {
+        let old = DFont::new(&font(outline_tables(1000, &[(0x41, SQUARE)]))).unwrap();
+        let new = DFont::new(&font(outline_tables(1000, &[(0x41, TALL)]))).unwrap();
+        let options = RenderOptions {
+            render_scale: 2.0,
+            ppems: vec![12],
+            ..Default::default()
+        };
+        let (glyphs, timed_out) = modified_encoded_glyph_images(&old, &new, 0.0, &options, None);
+        assert!(timed_out.is_empty());
+        assert_eq!(glyphs.len(), 1);
+        let glyph = &glyphs[0];
+        assert_eq!(glyph.diff.ppems, vec![12]);
+
+        // The usual comparison is rasterized at the render scale
+        let (image_a, image_b) = glyph.images.as_ref().unwrap();
+        let compared_size = options.comparison_size(GLYPH_FONT_SIZE);
+        assert_eq!(image_a, &render(&old, compared_size, &options));
+        assert_eq!(image_b, &render(&new, compared_size, &options));
+
+        // and the ppem comparison hinted at exactly that size
+        let (ppem_a, ppem_b) = &glyph.ppem_images[&12];
+        let at_ppem = options.at_ppem(12);
+        assert_eq!(ppem_a, &render(&old, 12.0, &at_ppem));
+        assert_eq!(ppem_b, &render(&new, 12.0, &at_ppem));
+
+        // Images aren't kept unless asked for
+        let (diffs, _) = modified_encoded_glyphs(&old, &new, 0.0, &options, None);
+        assert_eq!(diffs.len(), 1);
+    }
 }
@@ -1,49 +1,478 @@
+mod clusters;
+mod colr;
 pub mod encodedglyphs;
 mod renderer;
+mod ssim;
 mod utils;
-mod wordlists;
+pub(crate) mod wordlists;
+
+pub use clusters::{ClusterChange, ClusterDiff};
+pub use renderer::{DifferenceMetric, HintingMode, RenderOptions, Unrenderable};
+pub use wordlists::CustomWordlists;
 
 use crate::dfont::DFont;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use cfg_if::cfg_if;
-use image::{GenericImage, GrayImage, ImageBuffer};
-use renderer::Renderer;
-use rustybuzz::Direction;
+use clusters::cluster_diff;
+use colr::Layer;
+use image::{
+    DynamicImage, GenericImage, GrayImage, ImageBuffer, ImageOutputFormat, Pixel, Rgb, RgbImage,
+};
+use renderer::{features_to_string, serialize_buffer, Renderer};
+use rustybuzz::{Direction, UnicodeBuffer};
+use schemars::JsonSchema;
 use serde::Serialize;
-use serde_json::{json, Value};
-use std::collections::HashSet;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
+use unicode_normalization::UnicodeNormalization;
 
 cfg_if! {
     if #[cfg(not(target_family = "wasm"))] {
-        use indicatif::ParallelProgressIterator;
-        use rayon::{iter::ParallelIterator, prelude::IntoParallelRefIterator};
-        use thread_local::ThreadLocal;
-        use std::cell::RefCell;
-        use std::sync::RwLock;
+        mod worker;
+
+        use rayon::{
+            iter::{IntoParallelIterator, ParallelIterator},
+            prelude::IntoParallelRefIterator,
+        };
+        use worker::{TimedOut, WordRenderers};
     }
 }
 
 const FUZZ: u8 = 20;
 
-pub fn test_font_words(font_a: &DFont, font_b: &DFont) -> Value {
-    let mut map = serde_json::Map::new();
+/// Copies of the old and new fonts which threads rendering with a
+/// timeout can share
+///
+/// Make these once for a location and pass them to each of its tests,
+/// rather than have each test copy the fonts.
+#[derive(Clone)]
+pub struct SharedFonts(pub(crate) Arc<DFont>, pub(crate) Arc<DFont>);
+
+impl SharedFonts {
+    pub fn new(font_a: &DFont, font_b: &DFont) -> Self {
+        SharedFonts(Arc::new(font_a.clone()), Arc::new(font_b.clone()))
+    }
+}
+
+/// Size in pixels per em at which encoded glyphs are compared
+pub const GLYPH_FONT_SIZE: f32 = 40.0;
+/// Size in pixels per em at which words are compared
+pub const WORD_FONT_SIZE: f32 = 20.0;
+
+/// Scripts which are commonly set vertically, by Unicode script name
+pub const VERTICAL_SCRIPTS: [&str; 6] = ["Han", "Hiragana", "Katakana", "Hangul", "Bopomofo", "Yi"];
+
+/// Word differences, keyed by script name
+pub type WordDiffs = BTreeMap<String, ScriptWordDiffs>;
+
+/// The word differences found in a script
+///
+/// These are a list, from most to least different, unless they have been
+/// grouped by the kind of difference.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ScriptWordDiffs {
+    List(Vec<Difference>),
+    ByKind(BTreeMap<DifferenceKind, Vec<Difference>>),
+}
+
+impl ScriptWordDiffs {
+    pub fn iter(&self) -> impl Iterator<Item = &Difference> {
+        let (list, groups) = match self {
+            ScriptWordDiffs::List(list) => (Some(list), None),
+            ScriptWordDiffs::ByKind(groups) => (None, Some(groups)),
+        };
+        list.into_iter().flatten().chain(
+            groups
+                .into_iter()
+                .flat_map(|groups| groups.values().flatten()),
+        )
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Difference> {
+        let (list, groups) = match self {
+            ScriptWordDiffs::List(list) => (Some(list), None),
+            ScriptWordDiffs::ByKind(groups) => (None, Some(groups)),
+        };
+        list.into_iter().flatten().chain(
+            groups
+                .into_iter()
+                .flat_map(|groups| groups.values_mut().flatten()),
+        )
+    }
+
+    /// Split the differences by kind, keeping each kind's list in order
+    pub fn group_by_kind(self) -> Self {
+        match self {
+            ScriptWordDiffs::List(list) => {
+                let mut groups: BTreeMap<DifferenceKind, Vec<Difference>> = BTreeMap::new();
+                for difference in list {
+                    groups.entry(difference.kind).or_default().push(difference);
+                }
+                ScriptWordDiffs::ByKind(groups)
+            }
+            grouped => grouped,
+        }
+    }
+
+    /// Sort the differences, within each kind if they are grouped
+    pub fn sort_by(&mut self, compare: impl Fn(&Difference, &Difference) -> Ordering) {
+        match self {
+            ScriptWordDiffs::List(list) => list.sort_by(&compare),
+            ScriptWordDiffs::ByKind(groups) => {
+                for list in groups.values_mut() {
+                    list.sort_by(&compare);
+                }
+            }
+        }
+    }
+}
+
+/// A word which shapes to `.notdef` in one or both fonts
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NotdefWord {
+    pub word: String,
+    /// Character positions (counting from 0) of the `.notdef` glyphs in
+    /// the old font; empty if the old font renders the word
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub old: Vec<usize>,
+    /// Character positions of the `.notdef` glyphs in the new font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub new: Vec<usize>,
+}
+
+/// Words which only one of the fonts can render without missing glyphs
+#[derive(Debug, Serialize, Default, JsonSchema)]
+pub struct Renderability {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_renderable_in_old: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_renderable_in_new: Vec<String>,
+    /// Words which shape to `.notdef` in either font, including those
+    /// which neither font can render
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notdef: Vec<NotdefWord>,
+    /// Words which took longer than the render timeout to shape or render
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timed_out: Vec<String>,
+}
+
+impl Renderability {
+    pub fn is_some(&self) -> bool {
+        !self.only_renderable_in_old.is_empty()
+            || !self.only_renderable_in_new.is_empty()
+            || !self.notdef.is_empty()
+            || !self.timed_out.is_empty()
+    }
+
+    /// Record a word which one or both fonts can't render
+    fn add_unrenderable(
+        &mut self,
+        word: &str,
+        old: Option<Unrenderable>,
+        new: Option<Unrenderable>,
+    ) {
+        match (&old, &new) {
+            (None, Some(_)) => self.only_renderable_in_old.push(word.to_string()),
+            (Some(_), None) => self.only_renderable_in_new.push(word.to_string()),
+            _ => {}
+        }
+        let positions = |unrenderable: Option<Unrenderable>| match unrenderable {
+            Some(Unrenderable::Notdef(positions)) => positions,
+            _ => vec![],
+        };
+        let (old, new) = (positions(old), positions(new));
+        if !old.is_empty() || !new.is_empty() {
+            self.notdef.push(NotdefWord {
+                word: word.to_string(),
+                old,
+                new,
+            });
+        }
+    }
+}
+
+/// Renderability differences, keyed by script name
+pub type RenderabilityDiffs = BTreeMap<String, Renderability>;
+
+/// How far the word tests have got through a script's wordlist
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// The name of the script being tested
+    pub script: String,
+    /// The number of words shaped so far in this script
+    pub words_done: usize,
+    /// The number of words to be tested in this script
+    pub words_total: usize,
+}
+
+/// How [test_font_words] tests words, besides the fonts to test
+#[derive(Clone, Copy)]
+pub struct WordTestOptions<'a> {
+    /// Words whose difference percentage is below this are not reported
+    pub threshold: f32,
+    /// Wordlists added to (or replacing) the built-in wordlists for their
+    /// script
+    pub custom_wordlists: Option<&'a CustomWordlists>,
+    /// Only test the first this many words of each script's wordlist, in
+    /// sorted order
+    pub max_words: Option<usize>,
+    pub render: &'a RenderOptions,
+    /// Called as each word is shaped. Words are shaped on several threads,
+    /// but this is called by one thread at a time, with an increasing
+    /// count.
+    pub progress: Option<&'a (dyn Fn(Progress) + Sync)>,
+    /// Copies of the fonts for renders with a timeout to use, rather than
+    /// making their own
+    pub shared_fonts: Option<&'a SharedFonts>,
+}
+
+impl<'a> WordTestOptions<'a> {
+    /// Test every word with the given render options, reporting any
+    /// difference
+    pub fn new(render: &'a RenderOptions) -> Self {
+        WordTestOptions {
+            threshold: 0.0,
+            custom_wordlists: None,
+            max_words: None,
+            render,
+            progress: None,
+            shared_fonts: None,
+        }
+    }
+}
+
+/// Compare the rendering of words in all scripts supported by both fonts,
+/// or those of them which the render options select.
+///
+/// Words are normalized to NFC first if the render options ask for it.
+/// Words which only one font can render are reported separately.
+pub fn test_font_words(
+    font_a: &DFont,
+    font_b: &DFont,
+    options: &WordTestOptions,
+) -> (WordDiffs, RenderabilityDiffs) {
+    let WordTestOptions {
+        threshold,
+        custom_wordlists,
+        max_words,
+        render: render_options,
+        progress,
+        shared_fonts,
+    } = *options;
+    let mut map = WordDiffs::new();
+    let mut renderability_map = RenderabilityDiffs::new();
     for script in font_a
         .supported_scripts()
-        .intersection(&font_b.supported_scripts())
+        .intersection(font_b.supported_scripts())
     {
-        if let Some(wordlist) = wordlists::get_wordlist(script) {
-            let direction = wordlists::get_script_direction(script);
-            let script_tag = wordlists::get_script_tag(script);
-            let results =
-                diff_many_words(font_a, font_b, 20.0, wordlist, 0.2, direction, script_tag);
-            if !results.is_empty() {
-                map.insert(script.to_string(), serde_json::to_value(results).unwrap());
+        let name = script.name();
+        if !render_options.tests_script(&name) {
+            log::debug!("Skipping words in {}", name);
+            continue;
+        }
+        let mut wordlist = match custom_wordlists {
+            Some(custom) if custom.replace => None,
+            _ => wordlists::get_wordlist(script.script),
+        };
+        if let Some(custom) = custom_wordlists.and_then(|c| c.words.get(&name)) {
+            wordlist
+                .get_or_insert_with(Vec::new)
+                .extend(custom.iter().cloned());
+        }
+        if let Some(mut wordlist) = wordlist {
+            if render_options.normalize {
+                wordlist = wordlist.iter().map(|word| word.nfc().collect()).collect();
+            }
+            if let Some(max_words) = max_words {
+                wordlist.sort();
+                wordlist.dedup();
+                wordlist.truncate(max_words);
+            }
+            let vertical = render_options.vertical_scripts.contains(&name);
+            let words_total = wordlist.len() * if vertical { 2 } else { 1 };
+            // Words are shaped in parallel, but the count is reported one
+            // word at a time so that it never goes backwards
+            let words_done = Mutex::new(0);
+            let on_word = || {
+                let mut words_done = words_done.lock().unwrap_or_else(|e| e.into_inner());
+                *words_done += 1;
+                if let Some(progress) = progress {
+                    progress(Progress {
+                        script: name.clone(),
+                        words_done: *words_done,
+                        words_total,
+                    });
+                }
+            };
+            let script_direction = wordlists::get_script_direction(script.script);
+            // Vertical results are reported separately from horizontal ones
+            let vertical_words = vertical.then(|| wordlist.clone());
+            // Shape each group of words in its own direction
+            let (rtl, ltr): (Vec<String>, Vec<String>) = wordlist.into_iter().partition(|word| {
+                wordlists::get_word_direction(word, script_direction) == Direction::RightToLeft
+            });
+            let mut passes = vec![
+                (name.clone(), ltr, Direction::LeftToRight),
+                (name.clone(), rtl, Direction::RightToLeft),
+            ];
+            if let Some(words) = vertical_words {
+                passes.push((
+                    format!("{} (vertical)", name),
+                    words,
+                    Direction::TopToBottom,
+                ));
+            }
+            let script_options = render_options.for_script(&name);
+            let mut results: BTreeMap<String, (Vec<Difference>, Renderability)> = BTreeMap::new();
+            for (key, words, direction) in passes {
+                if words.is_empty() {
+                    continue;
+                }
+                let comparison = WordComparison {
+                    font_size: WORD_FONT_SIZE,
+                    threshold: 0.2,
+                    direction,
+                    script: Some(script.tag),
+                    render_options: &script_options,
+                    shared_fonts,
+                };
+                let (differences, group_renderability) =
+                    diff_many_words(font_a, font_b, words, &comparison, &on_word);
+                let (key_results, renderability) = results.entry(key).or_default();
+                key_results.extend(differences);
+                renderability
+                    .only_renderable_in_old
+                    .extend(group_renderability.only_renderable_in_old);
+                renderability
+                    .only_renderable_in_new
+                    .extend(group_renderability.only_renderable_in_new);
+                renderability.notdef.extend(group_renderability.notdef);
+                renderability
+                    .timed_out
+                    .extend(group_renderability.timed_out);
+            }
+            for (key, (mut differences, mut renderability)) in results {
+                differences.retain(|diff| diff.percent >= threshold);
+                differences.sort_by(by_percent_then_word);
+                renderability.only_renderable_in_old.sort();
+                renderability.only_renderable_in_new.sort();
+                renderability.notdef.sort_by(|a, b| a.word.cmp(&b.word));
+                renderability.timed_out.sort();
+                if !differences.is_empty() {
+                    map.insert(key.clone(), ScriptWordDiffs::List(differences));
+                }
+                if renderability.is_some() {
+                    renderability_map.insert(key, renderability);
+                }
             }
         }
     }
-    json!(map)
+    (map, renderability_map)
+}
+
+type Image<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// Render a string at the font's current location
+///
+/// The direction and script are guessed from the string. If the options
+/// specify a font size, it overrides `font_size`.
+pub fn render_string(
+    font: &DFont,
+    string: &str,
+    font_size: f32,
+    options: &RenderOptions,
+) -> Option<DynamicImage> {
+    render_string_in_direction(font, string, font_size, None, options)
+}
+
+/// Render a string set vertically, top to bottom, at the font's current
+/// location, as the word tests do for vertical scripts
+pub fn render_vertical_string(
+    font: &DFont,
+    string: &str,
+    font_size: f32,
+    options: &RenderOptions,
+) -> Option<DynamicImage> {
+    render_string_in_direction(
+        font,
+        string,
+        font_size,
+        Some(Direction::TopToBottom),
+        options,
+    )
+}
+
+fn render_string_in_direction(
+    font: &DFont,
+    string: &str,
+    font_size: f32,
+    direction: Option<Direction>,
+    options: &RenderOptions,
+) -> Option<DynamicImage> {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(string);
+    buffer.guess_segment_properties();
+    let mut renderer = Renderer::new(
+        font,
+        options.font_size.unwrap_or(font_size),
+        direction.unwrap_or(buffer.direction()),
+        Some(buffer.script()),
+        options,
+    );
+    let (_, layers) = renderer.string_to_positioned_glyphs(string).ok()?;
+    Some(renderer.render_positioned_glyphs(&layers))
 }
 
-fn make_same_size(image_a: GrayImage, image_b: GrayImage) -> (GrayImage, GrayImage) {
+/// Shape a string at the font's current location and serialize the result
+///
+/// This is the serialization which the word tests compare: each glyph ID,
+/// with its offset in font units if it has one. The direction is guessed
+/// from the string, as is the script unless one is given. Unlike the word
+/// tests, strings containing missing glyphs are still shaped.
+pub fn shape_string(
+    font: &DFont,
+    string: &str,
+    script: Option<rustybuzz::Script>,
+    options: &RenderOptions,
+) -> String {
+    let string = if options.normalize {
+        string.nfc().collect()
+    } else {
+        string.to_string()
+    };
+    let string = string.as_str();
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(string);
+    buffer.guess_segment_properties();
+    let renderer = Renderer::new(
+        font,
+        options.font_size.unwrap_or(WORD_FONT_SIZE),
+        buffer.direction(),
+        Some(script.unwrap_or(buffer.script())),
+        options,
+    );
+    serialize_buffer(&renderer.shape(string))
+}
+
+/// Render a string and return the image as base64-encoded PNG data
+///
+/// This is intended for environments such as WASM where images cannot be
+/// written to files.
+pub fn render_png_base64(font: &DFont, string: &str, font_size: f32) -> Option<String> {
+    let image = render_string(font, string, font_size, &RenderOptions::default())?;
+    let mut png = vec![];
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .ok()?;
+    Some(STANDARD.encode(png))
+}
+
+fn make_same_size<P: Pixel>(image_a: Image<P>, image_b: Image<P>) -> (Image<P>, Image<P>) {
     let max_width = image_a.width().max(image_b.width());
     let max_height = image_a.height().max(image_b.height());
     let mut a = ImageBuffer::new(max_width, max_height);
@@ -53,23 +482,114 @@ fn make_same_size(image_a: GrayImage, image_b: GrayImage) -> (GrayImage, GrayIma
     (a, b)
 }
 
-fn count_differences(img_a: GrayImage, img_b: GrayImage) -> f32 {
-    let (img_a, img_b) = make_same_size(img_a, img_b);
-    let img_a_vec = img_a.to_vec();
-    let differing_pixels = img_a_vec
-        .iter()
-        .zip(img_b.to_vec())
-        .filter(|(&cha, chb)| cha.abs_diff(*chb) > FUZZ)
-        .count();
-    differing_pixels as f32 / (img_a.width() as f32 * img_a.height() as f32) * 100.0
+/// The ink coverage of each pixel of a rendering: the value of a
+/// grayscale mask, or the opacity of a colour rendering
+fn coverage(image: &DynamicImage) -> GrayImage {
+    match image {
+        DynamicImage::ImageLuma8(image) => image.clone(),
+        image => {
+            let image = image.to_rgba8();
+            GrayImage::from_fn(image.width(), image.height(), |x, y| {
+                image::Luma([image.get_pixel(x, y).0[3]])
+            })
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+/// Draw two renderings over each other on white, the old in red and the
+/// new in blue
+///
+/// Ink in both renderings comes out black, or gray where partly covered,
+/// so that only what moved or changed shape is coloured.
+pub fn overlay_renderings(old: &DynamicImage, new: &DynamicImage) -> RgbImage {
+    let (old, new) = make_same_size(coverage(old), coverage(new));
+    RgbImage::from_fn(old.width(), old.height(), |x, y| {
+        let old = old.get_pixel(x, y).0[0];
+        let new = new.get_pixel(x, y).0[0];
+        Rgb([255 - new, 255 - old.max(new), 255 - old])
+    })
+}
+
+/// How different two renderings are, as a percentage, by the given metric
+fn image_difference(img_a: DynamicImage, img_b: DynamicImage, metric: DifferenceMetric) -> f32 {
+    match metric {
+        DifferenceMetric::Pixel => count_differences(img_a, img_b),
+        DifferenceMetric::Ssim => {
+            let (img_a, img_b) = make_same_size(img_a.into_luma8(), img_b.into_luma8());
+            ((1.0 - ssim::mean_ssim(&img_a, &img_b)) * 100.0).clamp(0.0, 100.0) as f32
+        }
+    }
+}
+
+fn count_differences(img_a: DynamicImage, img_b: DynamicImage) -> f32 {
+    let (differing_pixels, total_pixels) = match (img_a, img_b) {
+        (DynamicImage::ImageLuma8(img_a), DynamicImage::ImageLuma8(img_b)) => {
+            let (img_a, img_b) = make_same_size(img_a, img_b);
+            let img_a_vec = img_a.to_vec();
+            let differing_pixels = img_a_vec
+                .iter()
+                .zip(img_b.to_vec())
+                .filter(|(&cha, chb)| cha.abs_diff(*chb) > FUZZ)
+                .count();
+            (
+                differing_pixels,
+                img_a.width() as f32 * img_a.height() as f32,
+            )
+        }
+        // At least one of the images is in color; compare all channels
+        (img_a, img_b) => {
+            let (img_a, img_b) = make_same_size(img_a.into_rgba8(), img_b.into_rgba8());
+            let differing_pixels = img_a
+                .pixels()
+                .zip(img_b.pixels())
+                .filter(|(pa, pb)| pa.0.iter().zip(pb.0).any(|(a, b)| a.abs_diff(b) > FUZZ))
+                .count();
+            (
+                differing_pixels,
+                img_a.width() as f32 * img_a.height() as f32,
+            )
+        }
+    };
+    if total_pixels == 0.0 {
+        return 0.0;
+    }
+    differing_pixels as f32 / total_pixels * 100.0
+}
+
+/// Order differences from largest to smallest, then by word, so that
+/// output is deterministic
+fn by_percent_then_word(a: &Difference, b: &Difference) -> Ordering {
+    b.percent
+        .total_cmp(&a.percent)
+        .then_with(|| a.word.cmp(&b.word))
+}
+
+/// Whether a rendering has nothing to draw (whitespace, controls)
+fn is_blank(layers: &[Layer]) -> bool {
+    layers.iter().all(Layer::is_empty)
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct GlyphDiff {
     pub string: String,
     pub name: String,
     pub unicode: String,
     pub percent: f32,
+    /// Paths of PNG renderings in the old and new fonts, if written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<(String, String)>,
+    /// Path of an animated GIF flickering between the renderings, if
+    /// written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flicker: Option<String>,
+    /// Path of a PNG of the renderings overlaid in different colours, if
+    /// written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<String>,
+    /// The sizes, in pixels per em, at which the hinted glyph renders
+    /// differently, of those the render options asked for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ppems: Vec<u16>,
 }
 
 impl From<Difference> for GlyphDiff {
@@ -82,6 +602,10 @@ impl From<Difference> for GlyphDiff {
                     .unwrap_or_default(),
                 unicode: format!("U+{:04X}", c as i32),
                 percent: diff.percent,
+                images: None,
+                flicker: None,
+                overlay: None,
+                ppems: vec![],
             }
         } else {
             GlyphDiff {
@@ -89,92 +613,240 @@ impl From<Difference> for GlyphDiff {
                 name: "".to_string(),
                 unicode: "".to_string(),
                 percent: 0.0,
+                images: None,
+                flicker: None,
+                overlay: None,
+                ppems: vec![],
             }
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+/// What made a word render differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DifferenceKind {
+    /// Shaping chose different glyphs
+    Substitution,
+    /// Shaping chose the same glyphs but positioned them differently
+    /// (kerning, mark attachment)
+    Positioning,
+    /// Shaping was the same, so the glyphs' outlines must differ
+    Outline,
+}
+
+impl DifferenceKind {
+    /// Classify a difference from the shaped buffers and the clusters
+    /// which shaped differently
+    fn classify(buffer_a: &str, buffer_b: &str, clusters: &[ClusterDiff]) -> Self {
+        if clusters.iter().any(|c| c.change == ClusterChange::Glyphs) {
+            return DifferenceKind::Substitution;
+        }
+        if clusters.iter().any(|c| c.change == ClusterChange::Position) {
+            return DifferenceKind::Positioning;
+        }
+        // The clusters couldn't be aligned, or nothing changed in them
+        let glyph_ids = |buffer: &str| -> Vec<String> {
+            buffer
+                .split('|')
+                .map(|glyph| glyph.split('@').next().unwrap_or_default().to_string())
+                .collect()
+        };
+        if glyph_ids(buffer_a) != glyph_ids(buffer_b) {
+            DifferenceKind::Substitution
+        } else if buffer_a != buffer_b {
+            DifferenceKind::Positioning
+        } else {
+            DifferenceKind::Outline
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Difference {
     pub word: String,
     pub buffer_a: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub buffer_b: Option<String>,
     // pub diff_map: Vec<i16>,
     pub percent: f32,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub ot_features: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub lang: String,
+    /// Paths of PNG renderings in the old and new fonts, if written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<(String, String)>,
+    /// Path of an animated GIF flickering between the renderings, if
+    /// written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flicker: Option<String>,
+    /// Path of a PNG of the renderings overlaid in different colours, if
+    /// written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<String>,
+    /// The clusters of the word which shaped differently
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clusters: Vec<ClusterDiff>,
+    pub kind: DifferenceKind,
+    /// Whether the word was set vertically
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vertical: bool,
+    /// The old and new renderings which were compared, if the comparison
+    /// kept them
+    #[serde(skip)]
+    pub renderings: Option<(DynamicImage, DynamicImage)>,
 }
 
+/// How [diff_many_words] sets and compares a list of words
+pub(crate) struct WordComparison<'a> {
+    pub(crate) font_size: f32,
+    /// Words whose difference percentage is not above this are dropped
+    pub(crate) threshold: f32,
+    pub(crate) direction: Direction,
+    pub(crate) script: Option<rustybuzz::Script>,
+    pub(crate) render_options: &'a RenderOptions,
+    pub(crate) shared_fonts: Option<&'a SharedFonts>,
+    /// Keep the renderings compared for each reported difference
+    pub(crate) keep_images: bool,
+}
+
+/// A word's difference percentage, with the renderings compared if they
+/// were kept
+type Rendered = (f32, Option<(DynamicImage, DynamicImage)>);
+
 // A fast but complicated version
 #[cfg(not(target_family = "wasm"))]
 pub(crate) fn diff_many_words(
     font_a: &DFont,
     font_b: &DFont,
-    font_size: f32,
     wordlist: Vec<String>,
-    threshold: f32,
-    direction: Direction,
-    script: Option<rustybuzz::Script>,
-) -> Vec<Difference> {
-    let tl_a = ThreadLocal::new();
-    let tl_b = ThreadLocal::new();
-    // The cache should not be thread local
-    let seen_glyphs = RwLock::new(HashSet::new());
-    let differences: Vec<Option<Difference>> = wordlist
+    comparison: &WordComparison,
+    on_word: &(dyn Fn() + Sync),
+) -> (Vec<Difference>, Renderability) {
+    let WordComparison {
+        font_size,
+        threshold,
+        direction,
+        script,
+        render_options,
+        shared_fonts,
+        keep_images,
+    } = *comparison;
+    let font_size = render_options.comparison_size(font_size);
+    let renderers = WordRenderers::new(
+        font_a,
+        font_b,
+        font_size,
+        direction,
+        script,
+        render_options,
+        shared_fonts,
+    );
+    // Shape everything in parallel, but decide which words to render in
+    // wordlist order, so that the words skipped as having only seen
+    // glyphs are the same from run to run. Everything later steps need
+    // from the shaping is kept, so that a word is only shaped once, within
+    // the timeout.
+    let shaped: Vec<Result<_, TimedOut>> = wordlist
         .par_iter()
-        .progress()
         .map(|word| {
-            let renderer_a =
-                tl_a.get_or(|| RefCell::new(Renderer::new(font_a, font_size, direction, script)));
-            let renderer_b =
-                tl_b.get_or(|| RefCell::new(Renderer::new(font_b, font_size, direction, script)));
-
-            let (buffer_a, commands_a) =
-                renderer_a.borrow_mut().string_to_positioned_glyphs(word)?;
-            if buffer_a
-                .split('|')
-                .all(|glyph| seen_glyphs.read().unwrap().contains(glyph))
-            {
-                return None;
-            }
-            for glyph in buffer_a.split('|') {
-                seen_glyphs.write().unwrap().insert(glyph.to_string());
+            let word = word.clone();
+            let result = renderers.run(move |renderer_a, renderer_b| {
+                let (output_a, output_b) = (renderer_a.shape(&word), renderer_b.shape(&word));
+                match (
+                    renderer_a.draw_shaped(&word, &output_a),
+                    renderer_b.draw_shaped(&word, &output_b),
+                ) {
+                    (Ok(a), Ok(b)) => Ok((a, b, cluster_diff(&word, &output_a, &output_b))),
+                    (a, b) => Err((a.err(), b.err())),
+                }
+            });
+            on_word();
+            result
+        })
+        .collect();
+    let mut renderability = Renderability::default();
+    let mut seen_glyphs = HashSet::new();
+    // Identical pairs of shaped buffers render identically, so we only
+    // need to rasterize each pair once
+    let mut to_render: HashMap<(String, String), (Vec<Layer>, Vec<Layer>)> = HashMap::new();
+    let mut candidates = vec![];
+    for (word, results) in wordlist.iter().zip(shaped) {
+        let ((buffer_a, layers_a), (buffer_b, layers_b), clusters) = match results {
+            Ok(Ok(shaped)) => shaped,
+            Ok(Err((a, b))) => {
+                renderability.add_unrenderable(word, a, b);
+                continue;
             }
-            let (buffer_b, commands_b) =
-                renderer_b.borrow_mut().string_to_positioned_glyphs(word)?;
-            if commands_a == commands_b {
-                return None;
+            Err(TimedOut) => {
+                log::warn!("Timed out shaping '{}'", word);
+                renderability.timed_out.push(word.clone());
+                continue;
             }
-            let img_a = renderer_a
-                .borrow_mut()
-                .render_positioned_glyphs(&commands_a);
-            let img_b = renderer_b
-                .borrow_mut()
-                .render_positioned_glyphs(&commands_b);
-            let percent = count_differences(img_a, img_b);
+        };
+        if buffer_a.split('|').all(|glyph| seen_glyphs.contains(glyph)) {
+            continue;
+        }
+        for glyph in buffer_a.split('|') {
+            seen_glyphs.insert(glyph.to_string());
+        }
+        let key = (buffer_a, buffer_b);
+        to_render.entry(key.clone()).or_insert((layers_a, layers_b));
+        candidates.push((word, key, clusters));
+    }
+    let metric = render_options.metric;
+    let percents: HashMap<(String, String), Result<Option<Rendered>, TimedOut>> = to_render
+        .into_par_iter()
+        .map(|(key, (layers_a, layers_b))| {
+            let rendered = renderers.run(move |renderer_a, renderer_b| {
+                (layers_a != layers_b && !(is_blank(&layers_a) && is_blank(&layers_b))).then(|| {
+                    let img_a = renderer_a.render_positioned_glyphs(&layers_a);
+                    let img_b = renderer_b.render_positioned_glyphs(&layers_b);
+                    let renderings = keep_images.then(|| (img_a.clone(), img_b.clone()));
+                    (image_difference(img_a, img_b, metric), renderings)
+                })
+            });
+            (key, rendered)
+        })
+        .collect();
+    let mut diffs: Vec<Difference> = candidates
+        .into_iter()
+        .filter_map(|(word, key, clusters)| {
+            let (percent, renderings) = match &percents[&key] {
+                Ok(rendered) => rendered
+                    .as_ref()
+                    .filter(|(percent, _)| *percent > threshold)
+                    .map(|(percent, renderings)| (*percent, renderings.clone()))?,
+                Err(TimedOut) => {
+                    log::warn!("Timed out rendering '{}'", word);
+                    renderability.timed_out.push(word.clone());
+                    return None;
+                }
+            };
+            let (buffer_a, buffer_b) = key;
             let buffers_same = buffer_a == buffer_b;
-
+            let kind = DifferenceKind::classify(&buffer_a, &buffer_b, &clusters);
             Some(Difference {
                 word: word.to_string(),
                 buffer_a,
                 buffer_b: if buffers_same { None } else { Some(buffer_b) },
                 // diff_map,
                 percent,
-                ot_features: "".to_string(),
-                lang: "".to_string(),
+                ot_features: features_to_string(&render_options.features),
+                lang: render_options.language.clone().unwrap_or_default(),
+                images: None,
+                flicker: None,
+                overlay: None,
+                clusters,
+                kind,
+                vertical: direction == Direction::TopToBottom,
+                renderings,
             })
         })
         .collect();
-    let mut diffs: Vec<Difference> = differences
-        .into_iter()
-        .flatten()
-        .filter(|diff| diff.percent > threshold)
-        .collect();
-    diffs.sort_by_key(|x| (-x.percent * 10_000.0) as i32);
-    diffs
+    diffs.sort_by(by_percent_then_word);
+    (diffs, renderability)
 }
 
 // A slow and simple version
@@ -182,55 +854,87 @@ pub(crate) fn diff_many_words(
 pub(crate) fn diff_many_words(
     font_a: &DFont,
     font_b: &DFont,
-    font_size: f32,
     wordlist: Vec<String>,
-    threshold: f32,
-    direction: Direction,
-    script: Option<rustybuzz::Script>,
-) -> Vec<Difference> {
-    let mut renderer_a = Renderer::new(font_a, font_size, direction, script);
-    let mut renderer_b = Renderer::new(font_b, font_size, direction, script);
+    comparison: &WordComparison,
+    on_word: &(dyn Fn() + Sync),
+) -> (Vec<Difference>, Renderability) {
+    let WordComparison {
+        font_size,
+        threshold,
+        direction,
+        script,
+        render_options,
+        keep_images,
+        ..
+    } = *comparison;
+    let font_size = render_options.comparison_size(font_size);
+    let mut renderer_a = Renderer::new(font_a, font_size, direction, script, render_options);
+    let mut renderer_b = Renderer::new(font_b, font_size, direction, script, render_options);
     let mut seen_glyphs: HashSet<String> = HashSet::new();
+    let mut seen_buffers: HashMap<(String, String), Rendered> = HashMap::new();
 
     let mut differences: Vec<Difference> = vec![];
+    let mut renderability = Renderability::default();
     for word in wordlist {
-        let result_a = renderer_a.string_to_positioned_glyphs(&word);
-        if result_a.is_none() {
-            continue;
-        }
-        let (buffer_a, commands_a) = result_a.unwrap();
+        let (output_a, output_b) = (renderer_a.shape(&word), renderer_b.shape(&word));
+        let result_a = renderer_a.draw_shaped(&word, &output_a);
+        let result_b = renderer_b.draw_shaped(&word, &output_b);
+        on_word();
+        let ((buffer_a, commands_a), (buffer_b, commands_b)) = match (result_a, result_b) {
+            (Ok(a), Ok(b)) => (a, b),
+            (a, b) => {
+                renderability.add_unrenderable(&word, a.err(), b.err());
+                continue;
+            }
+        };
         if buffer_a.split('|').all(|glyph| seen_glyphs.contains(glyph)) {
             continue;
         }
         for glyph in buffer_a.split('|') {
             seen_glyphs.insert(glyph.to_string());
         }
-        let result_b = renderer_b.string_to_positioned_glyphs(&word);
-        if result_b.is_none() {
-            continue;
-        }
-        let (buffer_b, commands_b) = result_b.unwrap();
-        if commands_a == commands_b {
+        if commands_a == commands_b || (is_blank(&commands_a) && is_blank(&commands_b)) {
             continue;
         }
-        let img_a = renderer_a.render_positioned_glyphs(&commands_a);
-        let img_b = renderer_b.render_positioned_glyphs(&commands_b);
-        let percent = count_differences(img_a, img_b);
+        let key = (buffer_a, buffer_b);
+        let (percent, renderings) = seen_buffers
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let img_a = renderer_a.render_positioned_glyphs(&commands_a);
+                let img_b = renderer_b.render_positioned_glyphs(&commands_b);
+                let renderings = keep_images.then(|| (img_a.clone(), img_b.clone()));
+                (
+                    image_difference(img_a, img_b, render_options.metric),
+                    renderings,
+                )
+            })
+            .clone();
+        let (buffer_a, buffer_b) = key;
         if percent > threshold {
+            let buffers_same = buffer_a == buffer_b;
+            let clusters = cluster_diff(&word, &output_a, &output_b);
+            let kind = DifferenceKind::classify(&buffer_a, &buffer_b, &clusters);
             differences.push(Difference {
                 word: word.to_string(),
                 buffer_a,
-                buffer_b,
+                buffer_b: if buffers_same { None } else { Some(buffer_b) },
                 // diff_map,
-                ot_features: "".to_string(),
-                lang: "".to_string(),
+                ot_features: features_to_string(&render_options.features),
+                lang: render_options.language.clone().unwrap_or_default(),
+                images: None,
+                flicker: None,
+                overlay: None,
+                clusters,
+                kind,
+                vertical: direction == Direction::TopToBottom,
                 percent,
+                renderings,
             })
         }
     }
-    differences.sort_by_key(|x| (-x.percent * 10_000.0) as i32);
+    differences.sort_by(by_percent_then_word);
 
-    differences
+    (differences, renderability)
 }
 
 // #[cfg(test)]
@@ -1,23 +1,281 @@
-use image::{DynamicImage, GrayImage, Luma};
-use rustybuzz::{shape_with_plan, Direction, Face, ShapePlan, UnicodeBuffer};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use rustybuzz::{
+    shape_with_plan, Direction, Face, Feature, GlyphBuffer, Language, ShapePlan, UnicodeBuffer,
+};
+use serde::{Deserialize, Deserializer};
 use skrifa::{
+    color::ColorGlyphCollection,
     instance::{LocationRef, Size},
-    outline::DrawSettings,
+    outline::{DrawSettings, HintingInstance, LcdLayout},
     raw::TableProvider,
     GlyphId, MetadataProvider, OutlineGlyphCollection,
 };
-use zeno::Command;
+use std::{collections::BTreeMap, str::FromStr};
+use zeno::{Command, Vector};
 
-use super::utils::{terrible_bounding_box, RecordingPen};
+use super::{
+    colr::{first_palette, Layer, LayerPainter},
+    utils::{terrible_bounding_box, RecordingPen},
+};
 use crate::dfont::DFont;
 
+/// How glyph outlines are hinted and rasterized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HintingMode {
+    /// Unhinted outlines, grayscale rasterization
+    #[default]
+    None,
+    /// Outlines hinted with the font's instructions, grayscale rasterization
+    Grayscale,
+    /// Outlines hinted for horizontal LCD subpixels, rasterized at triple
+    /// horizontal resolution and decomposed into RGB channels (no LCD
+    /// filtering is applied)
+    Subpixel,
+}
+
+impl FromStr for HintingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(HintingMode::None),
+            "grayscale" => Ok(HintingMode::Grayscale),
+            "subpixel" => Ok(HintingMode::Subpixel),
+            _ => Err(format!(
+                "Unknown hinting mode '{}' (expected none, grayscale or subpixel)",
+                s
+            )),
+        }
+    }
+}
+
+/// How the difference between two renderings is measured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DifferenceMetric {
+    /// The percentage of pixels which differ noticeably
+    #[default]
+    Pixel,
+    /// One minus the mean structural similarity (SSIM) of the grayscale
+    /// renderings, as a percentage; less sensitive than `Pixel` to small
+    /// shifts, and more to changes in shape
+    Ssim,
+}
+
+impl FromStr for DifferenceMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pixel" => Ok(DifferenceMetric::Pixel),
+            "ssim" => Ok(DifferenceMetric::Ssim),
+            _ => Err(format!(
+                "Unknown difference metric '{}' (expected pixel or ssim)",
+                s
+            )),
+        }
+    }
+}
+
+/// Options controlling how words are shaped and glyphs are rasterized
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderOptions {
+    /// Keep the anti-aliased coverage of each pixel. If false, coverage is
+    /// binarized at `coverage_threshold`, which exaggerates small
+    /// differences at glyph edges.
+    pub antialias: bool,
+    /// The coverage (from 0 to 1) above which a pixel is considered inked
+    /// when not anti-aliasing. Lower values catch fainter hairlines.
+    pub coverage_threshold: f32,
+    pub hinting: HintingMode,
+    /// Render at this size (in pixels per em) instead of each test's
+    /// default size
+    pub font_size: Option<f32>,
+    /// Rasterize at this multiple of the size when comparing renderings,
+    /// to catch differences smaller than a pixel at that size. Images
+    /// written for reports are rendered at the unscaled size.
+    pub render_scale: f32,
+    /// Also compare encoded glyphs at each of these sizes in pixels per
+    /// em, hinted (in grayscale, if no hinting mode is chosen), to find
+    /// differences in instructions such as delta hints which only show at
+    /// particular sizes
+    pub ppems: Vec<u16>,
+    /// OpenType features to apply when shaping, e.g. `ss01` or `-liga`
+    #[serde(deserialize_with = "deserialize_features")]
+    pub features: Vec<Feature>,
+    /// Normalize words to NFC before shaping them
+    pub normalize: bool,
+    /// How to measure the difference between renderings; thresholds are
+    /// percentages in this metric
+    pub metric: DifferenceMetric,
+    /// Give up on shaping or rendering a word after this many seconds,
+    /// reporting it as timed out. This is ignored in WebAssembly, which
+    /// can't run renders on other threads.
+    pub timeout: Option<f32>,
+    /// Scripts, by Unicode script name, whose words are also tested set
+    /// vertically (top to bottom, with vertical metrics and the `vert` and
+    /// `vrt2` features)
+    pub vertical_scripts: Vec<String>,
+    /// Only test words in these scripts, by Unicode script name; if empty,
+    /// words in every script both fonts support are tested
+    pub scripts: Vec<String>,
+    /// Don't test words in these scripts
+    pub skip_scripts: Vec<String>,
+    /// Only test the encoded glyphs given here, by glyph name, character or
+    /// `U+XXXX` codepoint; if empty, all encoded glyphs are tested
+    pub only_glyphs: Vec<String>,
+    /// Shape in this language (a BCP 47 tag such as `sr` or `tr`) rather
+    /// than the fonts' default language systems
+    pub language: Option<String>,
+    /// Languages to shape each script's words in, by Unicode script name,
+    /// overriding `language`
+    pub script_languages: BTreeMap<String, String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            antialias: true,
+            coverage_threshold: 0.5,
+            hinting: HintingMode::None,
+            font_size: None,
+            render_scale: 1.0,
+            ppems: vec![],
+            features: vec![],
+            normalize: false,
+            metric: DifferenceMetric::Pixel,
+            timeout: None,
+            vertical_scripts: vec![],
+            scripts: vec![],
+            skip_scripts: vec![],
+            only_glyphs: vec![],
+            language: None,
+            script_languages: BTreeMap::new(),
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Whether words in the script with this Unicode name should be tested
+    pub fn tests_script(&self, name: &str) -> bool {
+        (self.scripts.is_empty() || self.scripts.iter().any(|s| s == name))
+            && !self.skip_scripts.iter().any(|s| s == name)
+    }
+
+    /// These options, shaping in the language chosen for the script with
+    /// this Unicode name
+    pub fn for_script(&self, name: &str) -> RenderOptions {
+        let mut options = self.clone();
+        if let Some(language) = self.script_languages.get(name) {
+            options.language = Some(language.clone());
+        }
+        options
+    }
+
+    /// The size, in pixels per em, at which to rasterize for comparison,
+    /// given the test's default size
+    pub fn comparison_size(&self, default: f32) -> f32 {
+        self.font_size.unwrap_or(default) * self.render_scale
+    }
+
+    /// These options, rasterizing hinted at exactly `ppem` pixels per em
+    pub fn at_ppem(&self, ppem: u16) -> RenderOptions {
+        let mut options = self.clone();
+        options.font_size = Some(ppem as f32);
+        options.render_scale = 1.0;
+        if options.hinting == HintingMode::None {
+            options.hinting = HintingMode::Grayscale;
+        }
+        options
+    }
+
+    /// The language to shape in, if one was chosen and is valid
+    fn shaping_language(&self) -> Option<Language> {
+        let language = self.language.as_deref()?;
+        Language::from_str(language)
+            .inspect_err(|e| log::warn!("Ignoring language {}: {}", language, e))
+            .ok()
+    }
+
+    /// The coverage at which to binarize pixels, or `None` to keep the
+    /// anti-aliased coverage
+    fn binarize_threshold(&self) -> Option<f32> {
+        (!self.antialias).then_some(self.coverage_threshold)
+    }
+}
+
+/// Parse a comma-separated list of features in HarfBuzz syntax, such as
+/// `ss01,+dlig,-liga`
+fn parse_features(features: &str) -> Result<Vec<Feature>, String> {
+    features
+        .split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(|feature| {
+            Feature::from_str(feature)
+                .map_err(|e| format!("Couldn't parse feature '{}': {}", feature, e))
+        })
+        .collect()
+}
+
+/// Describe features in the syntax accepted by [`parse_features`]
+pub(crate) fn features_to_string(features: &[Feature]) -> String {
+    features
+        .iter()
+        .map(|feature| match feature.value {
+            0 => format!("-{}", feature.tag),
+            1 => feature.tag.to_string(),
+            value => format!("{}={}", feature.tag, value),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn deserialize_features<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Feature>, D::Error> {
+    let features = String::deserialize(deserializer)?;
+    parse_features(&features).map_err(serde::de::Error::custom)
+}
+
+/// Serialize a shaped buffer as each glyph ID, with its offset if it has
+/// one, followed by `|`; e.g. "36|72@0,120|"
+pub(crate) fn serialize_buffer(buffer: &GlyphBuffer) -> String {
+    let mut serialized = String::new();
+    for (position, info) in buffer.glyph_positions().iter().zip(buffer.glyph_infos()) {
+        serialized.push_str(&format!("{}", info.glyph_id));
+        if position.x_offset != 0 || position.y_offset != 0 {
+            serialized.push_str(&format!("@{},{}", position.x_offset, position.y_offset));
+        }
+        serialized.push('|');
+    }
+    serialized
+}
+
+/// Why a string couldn't be rendered
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unrenderable {
+    /// The string shaped to `.notdef` at these character positions
+    /// (counting from 0)
+    Notdef(Vec<usize>),
+    /// The string shaped to no glyphs at all
+    Empty,
+}
+
 pub struct Renderer<'a> {
     face: Face<'a>,
     scale: f32,
     font: skrifa::FontRef<'a>,
     location: LocationRef<'a>,
     plan: ShapePlan,
+    direction: Direction,
     outlines: OutlineGlyphCollection<'a>,
+    color_glyphs: Option<ColorGlyphCollection<'a>>,
+    palette: Vec<[u8; 4]>,
+    hinting_instance: Option<HintingInstance>,
+    options: RenderOptions,
 }
 
 impl<'a> Renderer<'a> {
@@ -26,121 +284,324 @@ impl<'a> Renderer<'a> {
         font_size: f32,
         direction: Direction,
         script: Option<rustybuzz::Script>,
+        options: &RenderOptions,
     ) -> Self {
-        let face = Face::from_slice(&dfont.backing, 0).expect("Foo");
-        let font = skrifa::FontRef::new(&dfont.backing).unwrap_or_else(|_| {
-            panic!(
-                "error constructing a Font from data for {:}",
-                dfont.family_name()
-            );
-        });
-        let plan = ShapePlan::new(&face, direction, script, None, &[]);
+        let face = Face::from_slice(&dfont.backing, dfont.face_index).expect("Foo");
+        let font =
+            skrifa::FontRef::from_index(&dfont.backing, dfont.face_index).unwrap_or_else(|_| {
+                panic!(
+                    "error constructing a Font from data for {:}",
+                    dfont.family_name()
+                );
+            });
+        let language = options.shaping_language();
+        let plan = ShapePlan::new(
+            &face,
+            direction,
+            script,
+            language.as_ref(),
+            &options.features,
+        );
         let outlines = font.outline_glyphs();
+        let location: LocationRef = (&dfont.normalized_location).into();
+        let hinting_mode = match options.hinting {
+            HintingMode::None => None,
+            HintingMode::Grayscale => Some(skrifa::outline::HintingMode::Smooth {
+                lcd_subpixel: None,
+                preserve_linear_metrics: true,
+            }),
+            HintingMode::Subpixel => Some(skrifa::outline::HintingMode::Smooth {
+                lcd_subpixel: Some(LcdLayout::Horizontal),
+                preserve_linear_metrics: true,
+            }),
+        };
+        let hinting_instance = hinting_mode.and_then(|mode| {
+            HintingInstance::new(&outlines, Size::new(font_size), location, mode).ok()
+        });
+        let (color_glyphs, palette) = if dfont.is_color() {
+            (Some(font.color_glyphs()), first_palette(&font))
+        } else {
+            (None, vec![])
+        };
 
         Self {
             face,
             font,
             plan,
+            direction,
             scale: font_size,
-            location: (&dfont.normalized_location).into(),
+            location,
             outlines,
+            color_glyphs,
+            palette,
+            hinting_instance,
+            options: options.clone(),
         }
     }
-    pub fn string_to_positioned_glyphs(&mut self, string: &str) -> Option<(String, Vec<Command>)> {
-        let mut pen = RecordingPen::default();
 
+    /// Shape a string without drawing it
+    pub fn shape(&self, string: &str) -> GlyphBuffer {
         let mut buffer = UnicodeBuffer::new();
         buffer.push_str(string);
-        let output = shape_with_plan(&self.face, &self.plan, buffer);
+        // Horizontal directions are guessed from the string, but vertical
+        // layout has to be asked for
+        if self.direction == Direction::TopToBottom {
+            buffer.set_direction(self.direction);
+        }
+        shape_with_plan(&self.face, &self.plan, buffer)
+    }
+
+    /// Shape a string and draw the resulting glyphs
+    ///
+    /// Returns a serialization of the shaped buffer and the layers to be
+    /// rendered. Monochrome fonts produce a single foreground layer;
+    /// COLR glyphs produce one layer per color. Strings which shape to
+    /// `.notdef`, or to nothing, aren't drawn.
+    pub fn string_to_positioned_glyphs(
+        &mut self,
+        string: &str,
+    ) -> Result<(String, Vec<Layer>), Unrenderable> {
+        let output = self.shape(string);
+        self.draw_shaped(string, &output)
+    }
+
+    /// Draw the glyphs of a string which has already been shaped, as
+    /// [Renderer::string_to_positioned_glyphs] does
+    pub fn draw_shaped(
+        &mut self,
+        string: &str,
+        output: &GlyphBuffer,
+    ) -> Result<(String, Vec<Layer>), Unrenderable> {
+        let mut pen = RecordingPen::default();
+        let mut layers = vec![];
+
         let upem = self.font.head().unwrap().units_per_em();
 
         // The results of the shaping operation are stored in the `output` buffer.
         let positions = output.glyph_positions();
-        let mut serialized_buffer = String::new();
         let infos = output.glyph_infos();
-        let mut cursor = 0.0;
+        let mut notdefs: Vec<usize> = infos
+            .iter()
+            .filter(|info| info.glyph_id == 0)
+            .map(|info| {
+                string
+                    .get(..info.cluster as usize)
+                    .map_or(0, |prefix| prefix.chars().count())
+            })
+            .collect();
+        if !notdefs.is_empty() {
+            notdefs.dedup();
+            return Err(Unrenderable::Notdef(notdefs));
+        }
+        // Vertical runs advance down the y axis rather than along the x axis
+        let (mut cursor_x, mut cursor_y) = (0.0, 0.0);
         let factor = self.scale / upem as f32;
         for (position, info) in positions.iter().zip(infos) {
-            if info.glyph_id == 0 {
-                return None;
-            }
-            pen.offset_x = cursor + (position.x_offset as f32 * factor);
-            pen.offset_y = -position.y_offset as f32 * factor;
-            let settings = DrawSettings::unhinted(Size::new(self.scale), self.location);
-
-            let _ = self
-                .outlines
-                .get(GlyphId::new(info.glyph_id as u16))
-                .unwrap()
-                .draw(settings, &mut pen);
-            serialized_buffer.push_str(&format!("{}", info.glyph_id,));
-            if position.x_offset != 0 || position.y_offset != 0 {
-                serialized_buffer
-                    .push_str(&format!("@{},{}", position.x_offset, position.y_offset));
+            pen.offset_x = cursor_x + (position.x_offset as f32 * factor);
+            pen.offset_y = -cursor_y - (position.y_offset as f32 * factor);
+            let glyph_id = GlyphId::new(info.glyph_id as u16);
+            if let Some(color_glyph) = self.color_glyphs.as_ref().and_then(|c| c.get(glyph_id)) {
+                if !pen.buffer.is_empty() {
+                    layers.push(Layer::foreground(std::mem::take(&mut pen.buffer)));
+                }
+                let mut painter = LayerPainter::new(
+                    &self.outlines,
+                    self.location,
+                    &self.palette,
+                    factor,
+                    pen.offset_x,
+                    pen.offset_y,
+                );
+                let _ = color_glyph.paint(self.location, &mut painter);
+                layers.extend(painter.layers);
+            } else {
+                let settings = match &self.hinting_instance {
+                    Some(instance) => DrawSettings::hinted(instance, false),
+                    None => DrawSettings::unhinted(Size::new(self.scale), self.location),
+                };
+                let _ = self
+                    .outlines
+                    .get(glyph_id)
+                    .unwrap()
+                    .draw(settings, &mut pen);
             }
-            serialized_buffer.push('|');
-            cursor += position.x_advance as f32 * factor;
+            cursor_x += position.x_advance as f32 * factor;
+            cursor_y += position.y_advance as f32 * factor;
         }
+        let serialized_buffer = serialize_buffer(output);
         if serialized_buffer.is_empty() {
-            return None;
+            return Err(Unrenderable::Empty);
+        }
+        if layers.is_empty() || !pen.buffer.is_empty() {
+            layers.push(Layer::foreground(pen.buffer));
         }
-        Some((serialized_buffer, pen.buffer))
+        Ok((serialized_buffer, layers))
     }
 
-    pub fn render_positioned_glyphs(&mut self, pen_buffer: &[Command]) -> GrayImage {
-        let (min_x, min_y, max_x, max_y) = terrible_bounding_box(pen_buffer);
-        let x_origin = min_x.min(0.0);
-        let y_origin = min_y.min(0.0);
-        let x_size = (max_x - x_origin).ceil() as usize;
-        let y_size = (max_y - y_origin).ceil() as usize;
+    /// Render a set of layers
+    ///
+    /// If all layers are in the foreground color, a grayscale image is
+    /// returned; otherwise the layers are composited into an RGBA image.
+    pub fn render_positioned_glyphs(&mut self, layers: &[Layer]) -> DynamicImage {
+        let all_commands: Vec<Command> = layers
+            .iter()
+            .flat_map(|l| l.commands.iter().copied())
+            .collect();
+        let (min_x, min_y, max_x, max_y) = terrible_bounding_box(&all_commands);
+        // Round the origin down to a whole pixel, so that outlines which
+        // extend below or to the left of it (negative side bearings,
+        // descenders, right-to-left runs) aren't clipped
+        let x_origin = min_x.min(0.0).floor();
+        let y_origin = min_y.min(0.0).floor();
+        // Very small sizes can produce empty bounding boxes
+        let x_size = ((max_x - x_origin).ceil() as usize).max(1);
+        let y_size = ((max_y - y_origin).ceil() as usize).max(1);
 
-        let mut rasterizer = ab_glyph_rasterizer::Rasterizer::new(x_size, y_size);
+        if layers.iter().all(|l| l.color.is_none()) {
+            if self.options.hinting == HintingMode::Subpixel {
+                return DynamicImage::ImageRgb8(rasterize_subpixel(
+                    &all_commands,
+                    x_origin,
+                    y_origin,
+                    x_size,
+                    y_size,
+                    self.options.binarize_threshold(),
+                ));
+            }
+            return DynamicImage::ImageLuma8(rasterize(
+                &all_commands,
+                x_origin,
+                y_origin,
+                x_size,
+                y_size,
+                self.options.binarize_threshold(),
+            ));
+        }
 
-        let mut cursor = ab_glyph::Point { x: 0.0, y: 0.0 };
-        let v2p = |v: &zeno::Vector| ab_glyph::Point {
-            x: v.x - x_origin.ceil(),
-            y: v.y - y_origin.ceil(),
-        };
-        let mut home = v2p(&zeno::Vector::new(0.0, 0.0));
-        for command in pen_buffer {
-            match command {
-                Command::MoveTo(to) => {
-                    cursor = v2p(to);
-                    home = cursor;
-                }
-                Command::LineTo(to) => {
-                    let newpt = v2p(to);
-                    rasterizer.draw_line(cursor, newpt);
-                    cursor = newpt;
-                }
-                Command::QuadTo(ctrl, to) => {
-                    let ctrlpt = v2p(ctrl);
-                    let newpt = v2p(to);
-                    rasterizer.draw_quad(cursor, ctrlpt, newpt);
-                    cursor = newpt;
-                }
-                Command::CurveTo(ctrl0, ctrl1, to) => {
-                    let ctrl0pt = v2p(ctrl0);
-                    let ctrl1pt = v2p(ctrl1);
-                    let newpt = v2p(to);
-                    rasterizer.draw_cubic(cursor, ctrl0pt, ctrl1pt, newpt);
-                    cursor = newpt;
-                }
-                Command::Close => {
-                    if cursor != home {
-                        rasterizer.draw_line(cursor, home);
-                    }
-                }
-            };
+        let mut image = RgbaImage::new(x_size as u32, y_size as u32);
+        for layer in layers {
+            let [r, g, b, a] = layer.color.unwrap_or([0, 0, 0, 255]);
+            let coverage = rasterize(
+                &layer.commands,
+                x_origin,
+                y_origin,
+                x_size,
+                y_size,
+                self.options.binarize_threshold(),
+            );
+            for (pixel, alpha) in image.pixels_mut().zip(coverage.pixels()) {
+                let src_alpha = alpha.0[0] as f32 / 255.0 * a as f32 / 255.0;
+                let Rgba([dr, dg, db, da]) = *pixel;
+                let blend =
+                    |s: u8, d: u8| (s as f32 * src_alpha + d as f32 * (1.0 - src_alpha)) as u8;
+                *pixel = Rgba([
+                    blend(r, dr),
+                    blend(g, dg),
+                    blend(b, db),
+                    (src_alpha * 255.0 + da as f32 * (1.0 - src_alpha)) as u8,
+                ]);
+            }
         }
-        let mut image = DynamicImage::new_luma8(x_size as u32, y_size as u32).into_luma8();
-        rasterizer.for_each_pixel_2d(|x, y, alpha| {
-            image.put_pixel(x, y, Luma([(alpha * 255.0) as u8]));
-        });
-        image
+        DynamicImage::ImageRgba8(image)
     }
 }
 
+fn rasterize(
+    pen_buffer: &[Command],
+    x_origin: f32,
+    y_origin: f32,
+    x_size: usize,
+    y_size: usize,
+    binarize_threshold: Option<f32>,
+) -> GrayImage {
+    let mut rasterizer = ab_glyph_rasterizer::Rasterizer::new(x_size, y_size);
+
+    let mut cursor = ab_glyph::Point { x: 0.0, y: 0.0 };
+    let v2p = |v: &zeno::Vector| ab_glyph::Point {
+        x: v.x - x_origin,
+        y: v.y - y_origin,
+    };
+    let mut home = v2p(&zeno::Vector::new(0.0, 0.0));
+    for command in pen_buffer {
+        match command {
+            Command::MoveTo(to) => {
+                cursor = v2p(to);
+                home = cursor;
+            }
+            Command::LineTo(to) => {
+                let newpt = v2p(to);
+                rasterizer.draw_line(cursor, newpt);
+                cursor = newpt;
+            }
+            Command::QuadTo(ctrl, to) => {
+                let ctrlpt = v2p(ctrl);
+                let newpt = v2p(to);
+                rasterizer.draw_quad(cursor, ctrlpt, newpt);
+                cursor = newpt;
+            }
+            Command::CurveTo(ctrl0, ctrl1, to) => {
+                let ctrl0pt = v2p(ctrl0);
+                let ctrl1pt = v2p(ctrl1);
+                let newpt = v2p(to);
+                rasterizer.draw_cubic(cursor, ctrl0pt, ctrl1pt, newpt);
+                cursor = newpt;
+            }
+            Command::Close => {
+                if cursor != home {
+                    rasterizer.draw_line(cursor, home);
+                }
+            }
+        };
+    }
+    let mut image = DynamicImage::new_luma8(x_size as u32, y_size as u32).into_luma8();
+    rasterizer.for_each_pixel_2d(|x, y, alpha| {
+        let value = match binarize_threshold {
+            None => (alpha * 255.0) as u8,
+            Some(threshold) if alpha > threshold => 255,
+            Some(_) => 0,
+        };
+        image.put_pixel(x, y, Luma([value]));
+    });
+    image
+}
+
+/// Rasterize at three times the horizontal resolution, using each triple
+/// of horizontal samples as the red, green and blue subpixels of a pixel
+fn rasterize_subpixel(
+    pen_buffer: &[Command],
+    x_origin: f32,
+    y_origin: f32,
+    x_size: usize,
+    y_size: usize,
+    binarize_threshold: Option<f32>,
+) -> RgbImage {
+    let stretch = |v: &Vector| Vector::new(v.x * 3.0, v.y);
+    let stretched: Vec<Command> = pen_buffer
+        .iter()
+        .map(|command| match command {
+            Command::MoveTo(to) => Command::MoveTo(stretch(to)),
+            Command::LineTo(to) => Command::LineTo(stretch(to)),
+            Command::QuadTo(ctrl, to) => Command::QuadTo(stretch(ctrl), stretch(to)),
+            Command::CurveTo(ctrl0, ctrl1, to) => {
+                Command::CurveTo(stretch(ctrl0), stretch(ctrl1), stretch(to))
+            }
+            Command::Close => Command::Close,
+        })
+        .collect();
+    let coverage = rasterize(
+        &stretched,
+        x_origin * 3.0,
+        y_origin,
+        x_size * 3,
+        y_size,
+        binarize_threshold,
+    );
+    RgbImage::from_fn(x_size as u32, y_size as u32, |x, y| {
+        let subpixel = |i: u32| coverage.get_pixel(x * 3 + i, y).0[0];
+        Rgb([subpixel(0), subpixel(1), subpixel(2)])
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,12 +611,30 @@ mod tests {
     fn test_zeno_path() {
         let path = "NotoSansArabic-NewRegular.ttf";
         let data = std::fs::read(path).unwrap();
-        let font = DFont::new(&data);
-        let mut renderer = Renderer::new(&font, 40.0, Direction::RightToLeft, Some(script::ARABIC));
-        let (_serialized_buffer, commands) = renderer
-            .string_to_positioned_glyphs("السلام عليكم")
-            .unwrap();
-        let image = renderer.render_positioned_glyphs(&commands);
+        let font = DFont::new(&data).unwrap();
+        let mut renderer = Renderer::new(
+            &font,
+            40.0,
+            Direction::RightToLeft,
+            Some(script::ARABIC),
+            &RenderOptions::default(),
+        );
+        let (_serialized_buffer, layers) =
+            renderer.string_to_positioned_glyphs("السلام عليكم").unwrap();
+        let image = renderer.render_positioned_glyphs(&layers);
         image.save("test.png").unwrap();
     }
+
+    #[test]
+    fn test_script_selection() {
+        let mut options = RenderOptions::default();
+        assert!(options.tests_script("Arabic"));
+        options.skip_scripts = vec!["Arabic".to_string()];
+        assert!(!options.tests_script("Arabic"));
+        assert!(options.tests_script("Latin"));
+        options.scripts = vec!["Latin".to_string(), "Arabic".to_string()];
+        assert!(options.tests_script("Latin"));
+        assert!(!options.tests_script("Greek"));
+        assert!(!options.tests_script("Arabic"));
+    }
 }
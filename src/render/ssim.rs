@@ -0,0 +1,168 @@
+use image::GrayImage;
+
+/// Side of the square window over which local statistics are gathered
+const WINDOW: u32 = 7;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Sums over rectangles of an image, and of products of two images,
+/// in constant time
+struct SummedArea {
+    width: usize,
+    sums: Vec<f64>,
+}
+
+impl SummedArea {
+    fn new(width: u32, height: u32, value: impl Fn(u32, u32) -> f64) -> Self {
+        let stride = width as usize + 1;
+        let mut sums = vec![0.0; stride * (height as usize + 1)];
+        for y in 0..height {
+            let mut row = 0.0;
+            for x in 0..width {
+                row += value(x, y);
+                let ix = (y as usize + 1) * stride + x as usize + 1;
+                sums[ix] = sums[ix - stride] + row;
+            }
+        }
+        SummedArea {
+            width: stride,
+            sums,
+        }
+    }
+
+    /// The sum over the window whose top left corner is at (x, y)
+    fn window(&self, x: u32, y: u32) -> f64 {
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + WINDOW as usize, y0 + WINDOW as usize);
+        self.sums[y1 * self.width + x1]
+            - self.sums[y0 * self.width + x1]
+            - self.sums[y1 * self.width + x0]
+            + self.sums[y0 * self.width + x0]
+    }
+}
+
+/// The mean structural similarity (SSIM) of two images of the same size,
+/// from 1 for identical images down towards 0 (or below) for unrelated
+/// ones
+///
+/// Local statistics are taken over every 7x7 window, with equal weights.
+/// Images smaller than the window are compared as a single window.
+pub(crate) fn mean_ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (width, height) = a.dimensions();
+    if width < WINDOW || height < WINDOW {
+        return ssim(&window_stats(
+            a.pixels().map(|p| p.0[0]),
+            b.pixels().map(|p| p.0[0]),
+        ));
+    }
+    let pixel = |image: &GrayImage, x, y| image.get_pixel(x, y).0[0] as f64;
+    let sum_a = SummedArea::new(width, height, |x, y| pixel(a, x, y));
+    let sum_b = SummedArea::new(width, height, |x, y| pixel(b, x, y));
+    let sum_aa = SummedArea::new(width, height, |x, y| pixel(a, x, y).powi(2));
+    let sum_bb = SummedArea::new(width, height, |x, y| pixel(b, x, y).powi(2));
+    let sum_ab = SummedArea::new(width, height, |x, y| pixel(a, x, y) * pixel(b, x, y));
+    let n = (WINDOW * WINDOW) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in 0..=height - WINDOW {
+        for x in 0..=width - WINDOW {
+            let stats = Stats {
+                n,
+                sum_a: sum_a.window(x, y),
+                sum_b: sum_b.window(x, y),
+                sum_aa: sum_aa.window(x, y),
+                sum_bb: sum_bb.window(x, y),
+                sum_ab: sum_ab.window(x, y),
+            };
+            total += ssim(&stats);
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+struct Stats {
+    n: f64,
+    sum_a: f64,
+    sum_b: f64,
+    sum_aa: f64,
+    sum_bb: f64,
+    sum_ab: f64,
+}
+
+fn window_stats(a: impl Iterator<Item = u8>, b: impl Iterator<Item = u8>) -> Stats {
+    let mut stats = Stats {
+        n: 0.0,
+        sum_a: 0.0,
+        sum_b: 0.0,
+        sum_aa: 0.0,
+        sum_bb: 0.0,
+        sum_ab: 0.0,
+    };
+    for (a, b) in a.zip(b) {
+        let (a, b) = (a as f64, b as f64);
+        stats.n += 1.0;
+        stats.sum_a += a;
+        stats.sum_b += b;
+        stats.sum_aa += a * a;
+        stats.sum_bb += b * b;
+        stats.sum_ab += a * b;
+    }
+    stats
+}
+
+fn ssim(stats: &Stats) -> f64 {
+    if stats.n == 0.0 {
+        return 1.0;
+    }
+    let mean_a = stats.sum_a / stats.n;
+    let mean_b = stats.sum_b / stats.n;
+    let var_a = stats.sum_aa / stats.n - mean_a * mean_a;
+    let var_b = stats.sum_bb / stats.n - mean_b * mean_b;
+    let covariance = stats.sum_ab / stats.n - mean_a * mean_b;
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dfont::DFont,
+        render::{
+            image_difference, make_same_size, render_string, renderer::DifferenceMetric,
+            RenderOptions,
+        },
+        test_fonts::{font, outline_tables},
+    };
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+    const MOVED: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 540), (400, 0)];
+    const TRIANGLE: &[(i32, i32)] = &[(100, 0), (250, 500), (400, 0)];
+
+    fn render(contour: &[(i32, i32)]) -> GrayImage {
+        let font = DFont::new(&font(outline_tables(1000, &[(0x41, contour)]))).unwrap();
+        render_string(&font, "A", 40.0, &RenderOptions::default())
+            .unwrap()
+            .into_luma8()
+    }
+
+    #[test]
+    fn test_ssim_of_renderings() {
+        let ssim = |a: &GrayImage, b: &GrayImage| {
+            let (a, b) = make_same_size(a.clone(), b.clone());
+            mean_ssim(&a, &b)
+        };
+        let (square, moved, triangle) = (render(SQUARE), render(MOVED), render(TRIANGLE));
+        assert_eq!(ssim(&square, &square), 1.0);
+        assert!(ssim(&square, &moved) < 1.0);
+        assert!(ssim(&square, &triangle) < ssim(&square, &moved));
+
+        let difference = |a: &GrayImage, b: &GrayImage| {
+            image_difference(a.clone().into(), b.clone().into(), DifferenceMetric::Ssim)
+        };
+        assert_eq!(difference(&square, &square), 0.0);
+        assert!(difference(&square, &moved) > 0.0);
+        assert!(difference(&square, &triangle) > difference(&square, &moved));
+    }
+}
@@ -1,6 +1,8 @@
-use std::io::{BufRead, BufReader};
+use std::{collections::BTreeMap, io::BufRead, path::Path};
 
 use lazy_static::lazy_static;
+use serde::Deserialize;
+use ucd::{BidiClass, Codepoint, Script};
 
 macro_rules! include_script {
     ($var:ident, $path:literal ) => {
@@ -39,7 +41,6 @@ include_script!(GUJARATI, "../../test-data/Gujarati.txt.br");
 include_script!(GURMUKHI, "../../test-data/Gurmukhi.txt.br");
 include_script!(HEBREW, "../../test-data/Hebrew.txt.br");
 include_script!(HIRAGANA, "../../test-data/Hiragana.txt.br");
-include_script!(JAPANESE, "../../test-data/Japanese.txt.br");
 include_script!(KANNADA, "../../test-data/Kannada.txt.br");
 include_script!(KATAKANA, "../../test-data/Katakana.txt.br");
 include_script!(KHMER, "../../test-data/Khmer.txt.br");
@@ -62,115 +63,369 @@ include_script!(TIBETAN, "../../test-data/Tibetan.txt.br");
 include_script!(TIFINAGH, "../../test-data/Tifinagh.txt.br");
 include_script!(VAI, "../../test-data/Vai.txt.br");
 
-pub(crate) fn get_wordlist(script: &str) -> Option<Vec<String>> {
+pub(crate) fn get_wordlist(script: Script) -> Option<Vec<String>> {
     let compressed = match script {
-        "Adlam" => ADLAM.as_slice(),
-        "Arabic" => ARABIC.as_slice(),
-        "Armenian" => ARMENIAN.as_slice(),
-        "Avestan" => AVESTAN.as_slice(),
-        "Bengali" => BENGALI.as_slice(),
-        "Bopomofo" => BOPOMOFO.as_slice(),
-        "Canadian_Aboriginal" => CANADIAN_ABORIGINAL.as_slice(),
-        "Chakma" => CHAKMA.as_slice(),
-        "Cherokee" => CHEROKEE.as_slice(),
-        "Common" => COMMON.as_slice(),
-        "Cyrillic" => CYRILLIC.as_slice(),
-        "Devanagari" => DEVANAGARI.as_slice(),
-        "Ethiopic" => ETHIOPIC.as_slice(),
-        "Georgian" => GEORGIAN.as_slice(),
-        "Grantha" => GRANTHA.as_slice(),
-        "Greek" => GREEK.as_slice(),
-        "Gujarati" => GUJARATI.as_slice(),
-        "Gurmukhi" => GURMUKHI.as_slice(),
-        "Hebrew" => HEBREW.as_slice(),
-        "Hiragana" => HIRAGANA.as_slice(),
-        "Japanese" => JAPANESE.as_slice(),
-        "Kannada" => KANNADA.as_slice(),
-        "Katakana" => KATAKANA.as_slice(),
-        "Khmer" => KHMER.as_slice(),
-        "Lao" => LAO.as_slice(),
-        "Latin" => LATIN.as_slice(),
-        "Lisu" => LISU.as_slice(),
-        "Malayalam" => MALAYALAM.as_slice(),
-        "Mongolian" => MONGOLIAN.as_slice(),
-        "Myanmar" => MYANMAR.as_slice(),
-        "Ol_Chiki" => OL_CHIKI.as_slice(),
-        "Oriya" => ORIYA.as_slice(),
-        "Osage" => OSAGE.as_slice(),
-        "Sinhala" => SINHALA.as_slice(),
-        "Syriac" => SYRIAC.as_slice(),
-        "Tamil" => TAMIL.as_slice(),
-        "Telugu" => TELUGU.as_slice(),
-        "Thai" => THAI.as_slice(),
-        "Thanaa" => THANAA.as_slice(),
-        "Tibetan" => TIBETAN.as_slice(),
-        "Tifinagh" => TIFINAGH.as_slice(),
-        "Vai" => VAI.as_slice(),
+        Script::Adlam => ADLAM.as_slice(),
+        Script::Arabic => ARABIC.as_slice(),
+        Script::Armenian => ARMENIAN.as_slice(),
+        Script::Avestan => AVESTAN.as_slice(),
+        Script::Bengali => BENGALI.as_slice(),
+        Script::Bopomofo => BOPOMOFO.as_slice(),
+        Script::CanadianAboriginal => CANADIAN_ABORIGINAL.as_slice(),
+        Script::Chakma => CHAKMA.as_slice(),
+        Script::Cherokee => CHEROKEE.as_slice(),
+        Script::Common => COMMON.as_slice(),
+        Script::Cyrillic => CYRILLIC.as_slice(),
+        Script::Devanagari => DEVANAGARI.as_slice(),
+        Script::Ethiopic => ETHIOPIC.as_slice(),
+        Script::Georgian => GEORGIAN.as_slice(),
+        Script::Grantha => GRANTHA.as_slice(),
+        Script::Greek => GREEK.as_slice(),
+        Script::Gujarati => GUJARATI.as_slice(),
+        Script::Gurmukhi => GURMUKHI.as_slice(),
+        Script::Hebrew => HEBREW.as_slice(),
+        Script::Hiragana => HIRAGANA.as_slice(),
+        Script::Kannada => KANNADA.as_slice(),
+        Script::Katakana => KATAKANA.as_slice(),
+        Script::Khmer => KHMER.as_slice(),
+        Script::Lao => LAO.as_slice(),
+        Script::Latin => LATIN.as_slice(),
+        Script::Lisu => LISU.as_slice(),
+        Script::Malayalam => MALAYALAM.as_slice(),
+        Script::Mongolian => MONGOLIAN.as_slice(),
+        Script::Myanmar => MYANMAR.as_slice(),
+        Script::OlChiki => OL_CHIKI.as_slice(),
+        Script::Oriya => ORIYA.as_slice(),
+        Script::Osage => OSAGE.as_slice(),
+        Script::Sinhala => SINHALA.as_slice(),
+        Script::Syriac => SYRIAC.as_slice(),
+        Script::Tamil => TAMIL.as_slice(),
+        Script::Telugu => TELUGU.as_slice(),
+        Script::Thai => THAI.as_slice(),
+        Script::Thaana => THANAA.as_slice(),
+        Script::Tibetan => TIBETAN.as_slice(),
+        Script::Tifinagh => TIFINAGH.as_slice(),
+        Script::Vai => VAI.as_slice(),
 
         _ => return None,
     };
-    let buf = BufReader::new(compressed);
-    let wordlist: Vec<String> = buf
-        .lines()
-        .map(|l| l.expect("Could not parse line"))
-        .collect();
-    Some(wordlist)
+    Some(read_lines(compressed).collect())
 }
 
+/// Split a buffer into lines, skipping (with a warning) any line which
+/// is not valid UTF-8
+fn read_lines(data: impl BufRead) -> impl Iterator<Item = String> {
+    data.split(b'\n').enumerate().filter_map(|(ix, line)| {
+        let line = line.ok()?;
+        match String::from_utf8(line) {
+            Ok(line) => Some(line.trim_end_matches('\r').to_string()),
+            Err(e) => {
+                log::warn!("Skipping line {} of wordlist: {}", ix + 1, e);
+                None
+            }
+        }
+    })
+}
+
+/// User-supplied wordlists, keyed by script name
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CustomWordlists {
+    pub words: BTreeMap<String, Vec<String>>,
+    /// Replace the built-in wordlists rather than adding to them
+    #[serde(default)]
+    pub replace: bool,
+}
 
-pub(crate) fn get_script_tag(script: &str) -> Option<rustybuzz::Script> {
-    match script {
-        "Adlam" => Some(rustybuzz::script::ADLAM),
-        "Arabic" => Some(rustybuzz::script::ARABIC),
-        "Armenian" => Some(rustybuzz::script::ARMENIAN),
-        "Avestan" => Some(rustybuzz::script::AVESTAN),
-        "Bengali" => Some(rustybuzz::script::BENGALI),
-        "Bopomofo" => Some(rustybuzz::script::BOPOMOFO),
-        "Canadian_Aboriginal" => Some(rustybuzz::script::CANADIAN_SYLLABICS),
-        "Chakma" => Some(rustybuzz::script::CHAKMA),
-        "Cherokee" => Some(rustybuzz::script::CHEROKEE),
-        "Common" => Some(rustybuzz::script::COMMON),
-        "Cyrillic" => Some(rustybuzz::script::CYRILLIC),
-        "Devanagari" => Some(rustybuzz::script::DEVANAGARI),
-        "Ethiopic" => Some(rustybuzz::script::ETHIOPIC),
-        "Georgian" => Some(rustybuzz::script::GEORGIAN),
-        "Grantha" => Some(rustybuzz::script::GRANTHA),
-        "Greek" => Some(rustybuzz::script::GREEK),
-        "Gujarati" => Some(rustybuzz::script::GUJARATI),
-        "Gurmukhi" => Some(rustybuzz::script::GURMUKHI),
-        "Hebrew" => Some(rustybuzz::script::HEBREW),
-        "Hiragana" => Some(rustybuzz::script::HIRAGANA),
-        "Kannada" => Some(rustybuzz::script::KANNADA),
-        "Katakana" => Some(rustybuzz::script::KATAKANA),
-        "Khmer" => Some(rustybuzz::script::KHMER),
-        "Lao" => Some(rustybuzz::script::LAO),
-        "Latin" => Some(rustybuzz::script::LATIN),
-        "Lisu" => Some(rustybuzz::script::LISU),
-        "Malayalam" => Some(rustybuzz::script::MALAYALAM),
-        "Mongolian" => Some(rustybuzz::script::MONGOLIAN),
-        "Myanmar" => Some(rustybuzz::script::MYANMAR),
-        "Ol_Chiki" => Some(rustybuzz::script::OL_CHIKI),
-        "Oriya" => Some(rustybuzz::script::ORIYA),
-        "Osage" => Some(rustybuzz::script::OSAGE),
-        "Sinhala" => Some(rustybuzz::script::SINHALA),
-        "Syriac" => Some(rustybuzz::script::SYRIAC),
-        "Tamil" => Some(rustybuzz::script::TAMIL),
-        "Telugu" => Some(rustybuzz::script::TELUGU),
-        "Thai" => Some(rustybuzz::script::THAI),
-        "Tibetan" => Some(rustybuzz::script::TIBETAN),
-        "Tifinagh" => Some(rustybuzz::script::TIFINAGH),
-        "Vai" => Some(rustybuzz::script::VAI),
-        _ =>  None,
+impl CustomWordlists {
+    /// Load wordlists from a file, or from every file in a directory
+    ///
+    /// Each line contains a word, optionally followed by a tab and the
+    /// script name (e.g. `Latin`). Words without a script column are
+    /// assigned to the script of their first non-Common character.
+    pub fn from_path(path: &Path, replace: bool) -> std::io::Result<Self> {
+        let mut wordlists = CustomWordlists {
+            words: BTreeMap::new(),
+            replace,
+        };
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|p| p.is_file());
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for file in files {
+            let data = std::fs::read(file)?;
+            for line in read_lines(data.as_slice()) {
+                let mut columns = line.split('\t');
+                let word = columns.next().unwrap_or_default().trim();
+                if word.is_empty() {
+                    continue;
+                }
+                let script = columns
+                    .next()
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .or_else(|| guess_script(word));
+                if let Some(script) = script {
+                    wordlists
+                        .words
+                        .entry(script)
+                        .or_default()
+                        .push(word.to_string());
+                }
+            }
+        }
+        Ok(wordlists)
     }
 }
 
-pub(crate) fn get_script_direction(script: &str) -> rustybuzz::Direction {
-    match script {
-        "Arabic" => rustybuzz::Direction::RightToLeft,
-        "Avestan" => rustybuzz::Direction::RightToLeft,
-        "Hebrew" => rustybuzz::Direction::RightToLeft,
-        "Syriac" => rustybuzz::Direction::RightToLeft,
-        "Thanaa" => rustybuzz::Direction::RightToLeft,
-        _ => rustybuzz::Direction::LeftToRight,
+fn guess_script(word: &str) -> Option<String> {
+    let scripts: Vec<Script> = word.chars().filter_map(|c| c.script()).collect();
+    scripts
+        .iter()
+        .find(|s| !matches!(s, Script::Common | Script::Inherited))
+        .or(scripts.first())
+        .map(|s| format!("{:?}", s))
+}
+
+/// The OpenType script tag for a Unicode script
+///
+/// `KatakanaOrHiragana` is only used in script extensions, so never
+/// returned as the script of a character, and has no tag of its own.
+pub(crate) fn script_tag(script: Script) -> Option<rustybuzz::Script> {
+    Some(match script {
+        Script::Adlam => rustybuzz::script::ADLAM,
+        Script::Ahom => rustybuzz::script::AHOM,
+        Script::AnatolianHieroglyphs => rustybuzz::script::ANATOLIAN_HIEROGLYPHS,
+        Script::Arabic => rustybuzz::script::ARABIC,
+        Script::Armenian => rustybuzz::script::ARMENIAN,
+        Script::Avestan => rustybuzz::script::AVESTAN,
+        Script::Balinese => rustybuzz::script::BALINESE,
+        Script::Bamum => rustybuzz::script::BAMUM,
+        Script::BassaVah => rustybuzz::script::BASSA_VAH,
+        Script::Batak => rustybuzz::script::BATAK,
+        Script::Bengali => rustybuzz::script::BENGALI,
+        Script::Bhaiksuki => rustybuzz::script::BHAIKSUKI,
+        Script::Bopomofo => rustybuzz::script::BOPOMOFO,
+        Script::Brahmi => rustybuzz::script::BRAHMI,
+        Script::Braille => rustybuzz::script::BRAILLE,
+        Script::Buginese => rustybuzz::script::BUGINESE,
+        Script::Buhid => rustybuzz::script::BUHID,
+        Script::CanadianAboriginal => rustybuzz::script::CANADIAN_SYLLABICS,
+        Script::Carian => rustybuzz::script::CARIAN,
+        Script::CaucasianAlbanian => rustybuzz::script::CAUCASIAN_ALBANIAN,
+        Script::Chakma => rustybuzz::script::CHAKMA,
+        Script::Cham => rustybuzz::script::CHAM,
+        Script::Cherokee => rustybuzz::script::CHEROKEE,
+        Script::Common => rustybuzz::script::COMMON,
+        Script::Coptic => rustybuzz::script::COPTIC,
+        Script::Cuneiform => rustybuzz::script::CUNEIFORM,
+        Script::Cypriot => rustybuzz::script::CYPRIOT,
+        Script::Cyrillic => rustybuzz::script::CYRILLIC,
+        Script::Deseret => rustybuzz::script::DESERET,
+        Script::Devanagari => rustybuzz::script::DEVANAGARI,
+        Script::Duployan => rustybuzz::script::DUPLOYAN,
+        Script::EgyptianHieroglyphs => rustybuzz::script::EGYPTIAN_HIEROGLYPHS,
+        Script::Elbasan => rustybuzz::script::ELBASAN,
+        Script::Ethiopic => rustybuzz::script::ETHIOPIC,
+        Script::Georgian => rustybuzz::script::GEORGIAN,
+        Script::Glagolitic => rustybuzz::script::GLAGOLITIC,
+        Script::Gothic => rustybuzz::script::GOTHIC,
+        Script::Grantha => rustybuzz::script::GRANTHA,
+        Script::Greek => rustybuzz::script::GREEK,
+        Script::Gujarati => rustybuzz::script::GUJARATI,
+        Script::Gurmukhi => rustybuzz::script::GURMUKHI,
+        Script::Han => rustybuzz::script::HAN,
+        Script::Hangul => rustybuzz::script::HANGUL,
+        Script::Hanunoo => rustybuzz::script::HANUNOO,
+        Script::Hatran => rustybuzz::script::HATRAN,
+        Script::Hebrew => rustybuzz::script::HEBREW,
+        Script::Hiragana => rustybuzz::script::HIRAGANA,
+        Script::ImperialAramaic => rustybuzz::script::IMPERIAL_ARAMAIC,
+        Script::Inherited => rustybuzz::script::INHERITED,
+        Script::InscriptionalPahlavi => rustybuzz::script::INSCRIPTIONAL_PAHLAVI,
+        Script::InscriptionalParthian => rustybuzz::script::INSCRIPTIONAL_PARTHIAN,
+        Script::Javanese => rustybuzz::script::JAVANESE,
+        Script::Kaithi => rustybuzz::script::KAITHI,
+        Script::Kannada => rustybuzz::script::KANNADA,
+        Script::Katakana => rustybuzz::script::KATAKANA,
+        Script::KayahLi => rustybuzz::script::KAYAH_LI,
+        Script::Kharoshthi => rustybuzz::script::KHAROSHTHI,
+        Script::Khmer => rustybuzz::script::KHMER,
+        Script::Khojki => rustybuzz::script::KHOJKI,
+        Script::Khudawadi => rustybuzz::script::KHUDAWADI,
+        Script::Lao => rustybuzz::script::LAO,
+        Script::Latin => rustybuzz::script::LATIN,
+        Script::Lepcha => rustybuzz::script::LEPCHA,
+        Script::Limbu => rustybuzz::script::LIMBU,
+        Script::LinearA => rustybuzz::script::LINEAR_A,
+        Script::LinearB => rustybuzz::script::LINEAR_B,
+        Script::Lisu => rustybuzz::script::LISU,
+        Script::Lycian => rustybuzz::script::LYCIAN,
+        Script::Lydian => rustybuzz::script::LYDIAN,
+        Script::Mahajani => rustybuzz::script::MAHAJANI,
+        Script::Malayalam => rustybuzz::script::MALAYALAM,
+        Script::Mandaic => rustybuzz::script::MANDAIC,
+        Script::Manichaean => rustybuzz::script::MANICHAEAN,
+        Script::Marchen => rustybuzz::script::MARCHEN,
+        Script::MeeteiMayek => rustybuzz::script::MEETEI_MAYEK,
+        Script::MendeKikakui => rustybuzz::script::MENDE_KIKAKUI,
+        Script::MeroiticCursive => rustybuzz::script::MEROITIC_CURSIVE,
+        Script::MeroiticHieroglyphs => rustybuzz::script::MEROITIC_HIEROGLYPHS,
+        Script::Miao => rustybuzz::script::MIAO,
+        Script::Modi => rustybuzz::script::MODI,
+        Script::Mongolian => rustybuzz::script::MONGOLIAN,
+        Script::Mro => rustybuzz::script::MRO,
+        Script::Multani => rustybuzz::script::MULTANI,
+        Script::Myanmar => rustybuzz::script::MYANMAR,
+        Script::Nabataean => rustybuzz::script::NABATAEAN,
+        Script::NewTaiLue => rustybuzz::script::NEW_TAI_LUE,
+        Script::Newa => rustybuzz::script::NEWA,
+        Script::Nko => rustybuzz::script::NKO,
+        Script::Ogham => rustybuzz::script::OGHAM,
+        Script::OlChiki => rustybuzz::script::OL_CHIKI,
+        Script::OldHungarian => rustybuzz::script::OLD_HUNGARIAN,
+        Script::OldItalic => rustybuzz::script::OLD_ITALIC,
+        Script::OldNorthArabian => rustybuzz::script::OLD_NORTH_ARABIAN,
+        Script::OldPermic => rustybuzz::script::OLD_PERMIC,
+        Script::OldPersian => rustybuzz::script::OLD_PERSIAN,
+        Script::OldSouthArabian => rustybuzz::script::OLD_SOUTH_ARABIAN,
+        Script::OldTurkic => rustybuzz::script::OLD_TURKIC,
+        Script::Oriya => rustybuzz::script::ORIYA,
+        Script::Osage => rustybuzz::script::OSAGE,
+        Script::Osmanya => rustybuzz::script::OSMANYA,
+        Script::PahawhHmong => rustybuzz::script::PAHAWH_HMONG,
+        Script::Palmyrene => rustybuzz::script::PALMYRENE,
+        Script::PauCinHau => rustybuzz::script::PAU_CIN_HAU,
+        Script::PhagsPa => rustybuzz::script::PHAGS_PA,
+        Script::Phoenician => rustybuzz::script::PHOENICIAN,
+        Script::PsalterPahlavi => rustybuzz::script::PSALTER_PAHLAVI,
+        Script::Rejang => rustybuzz::script::REJANG,
+        Script::Runic => rustybuzz::script::RUNIC,
+        Script::Samaritan => rustybuzz::script::SAMARITAN,
+        Script::Saurashtra => rustybuzz::script::SAURASHTRA,
+        Script::Sharada => rustybuzz::script::SHARADA,
+        Script::Shavian => rustybuzz::script::SHAVIAN,
+        Script::Siddham => rustybuzz::script::SIDDHAM,
+        Script::SignWriting => rustybuzz::script::SIGNWRITING,
+        Script::Sinhala => rustybuzz::script::SINHALA,
+        Script::SoraSompeng => rustybuzz::script::SORA_SOMPENG,
+        Script::Sundanese => rustybuzz::script::SUNDANESE,
+        Script::SylotiNagri => rustybuzz::script::SYLOTI_NAGRI,
+        Script::Syriac => rustybuzz::script::SYRIAC,
+        Script::Tagalog => rustybuzz::script::TAGALOG,
+        Script::Tagbanwa => rustybuzz::script::TAGBANWA,
+        Script::TaiLe => rustybuzz::script::TAI_LE,
+        Script::TaiTham => rustybuzz::script::TAI_THAM,
+        Script::TaiViet => rustybuzz::script::TAI_VIET,
+        Script::Takri => rustybuzz::script::TAKRI,
+        Script::Tamil => rustybuzz::script::TAMIL,
+        Script::Tangut => rustybuzz::script::TANGUT,
+        Script::Telugu => rustybuzz::script::TELUGU,
+        Script::Thaana => rustybuzz::script::THAANA,
+        Script::Thai => rustybuzz::script::THAI,
+        Script::Tibetan => rustybuzz::script::TIBETAN,
+        Script::Tifinagh => rustybuzz::script::TIFINAGH,
+        Script::Tirhuta => rustybuzz::script::TIRHUTA,
+        Script::Ugaritic => rustybuzz::script::UGARITIC,
+        Script::Vai => rustybuzz::script::VAI,
+        Script::WarangCiti => rustybuzz::script::WARANG_CITI,
+        Script::Yi => rustybuzz::script::YI,
+        Script::KatakanaOrHiragana => return None,
+    })
+}
+
+lazy_static! {
+    /// For each script, by its discriminant, the number of its strongly
+    /// right-to-left characters less the number of its strongly
+    /// left-to-right ones
+    static ref SCRIPT_DIRECTION_BALANCE: Vec<isize> = {
+        let mut balance = vec![0; 256];
+        for c in (0..=0x10FFFF).filter_map(char::from_u32) {
+            if let (Some(script), Some(direction)) = (c.script(), strong_direction(c)) {
+                balance[script as usize] += match direction {
+                    rustybuzz::Direction::RightToLeft => 1,
+                    _ => -1,
+                };
+            }
+        }
+        balance
+    };
+}
+
+/// The direction of a character with a strong bidi class
+fn strong_direction(c: char) -> Option<rustybuzz::Direction> {
+    match c.bidi_class() {
+        BidiClass::LeftToRight => Some(rustybuzz::Direction::LeftToRight),
+        BidiClass::RightToLeft | BidiClass::ArabicLetter => Some(rustybuzz::Direction::RightToLeft),
+        _ => None,
     }
-}
\ No newline at end of file
+}
+
+/// The horizontal direction of a script
+///
+/// A script is right-to-left if most of its strongly-directional
+/// characters are, and left-to-right otherwise.
+pub(crate) fn get_script_direction(script: Script) -> rustybuzz::Direction {
+    if SCRIPT_DIRECTION_BALANCE[script as usize] > 0 {
+        rustybuzz::Direction::RightToLeft
+    } else {
+        rustybuzz::Direction::LeftToRight
+    }
+}
+
+/// The direction of a single word, taken from the bidi class of its first
+/// strongly-directional character, falling back to the script's direction
+///
+/// Wordlists may mix directions (for example, Latin words in an Arabic
+/// list), so each word is shaped in its own direction.
+pub(crate) fn get_word_direction(
+    word: &str,
+    script_direction: rustybuzz::Direction,
+) -> rustybuzz::Direction {
+    word.chars()
+        .find_map(strong_direction)
+        .unwrap_or(script_direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_direction() {
+        for script in [
+            Script::Arabic,
+            Script::Hebrew,
+            Script::Syriac,
+            Script::Thaana,
+            Script::Nko,
+            Script::Adlam,
+            Script::OldHungarian,
+        ] {
+            assert_eq!(
+                get_script_direction(script),
+                rustybuzz::Direction::RightToLeft,
+                "{:?}",
+                script
+            );
+        }
+        for script in [
+            Script::Latin,
+            Script::Devanagari,
+            Script::Han,
+            Script::Common,
+        ] {
+            assert_eq!(
+                get_script_direction(script),
+                rustybuzz::Direction::LeftToRight,
+                "{:?}",
+                script
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::{cell::RefCell, sync::mpsc, thread, time::Duration};
+
+use rustybuzz::Direction;
+use thread_local::ThreadLocal;
+
+use super::{
+    renderer::{RenderOptions, Renderer},
+    SharedFonts,
+};
+use crate::dfont::DFont;
+
+type Job = Box<dyn FnOnce(&mut Renderer<'_>, &mut Renderer<'_>) + Send>;
+
+/// A job didn't finish before its deadline
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimedOut;
+
+/// A thread with its own renderers for a pair of fonts, which runs jobs
+/// with a deadline
+///
+/// A job which misses its deadline is abandoned rather than cancelled, so
+/// the worker's thread may still be busy with it; the worker should then
+/// be dropped and a new one started. The abandoned thread exits once the
+/// job finishes.
+pub(crate) struct RenderWorker {
+    jobs: mpsc::Sender<Job>,
+    timeout: Duration,
+}
+
+impl RenderWorker {
+    pub(crate) fn new(
+        fonts: &SharedFonts,
+        font_size: f32,
+        direction: Direction,
+        script: Option<rustybuzz::Script>,
+        options: &RenderOptions,
+        timeout: Duration,
+    ) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let (font_a, font_b) = (fonts.0.clone(), fonts.1.clone());
+        let options = options.clone();
+        thread::spawn(move || {
+            let mut renderer_a = Renderer::new(&font_a, font_size, direction, script, &options);
+            let mut renderer_b = Renderer::new(&font_b, font_size, direction, script, &options);
+            for job in receiver {
+                job(&mut renderer_a, &mut renderer_b);
+            }
+        });
+        Self { jobs, timeout }
+    }
+
+    /// Run a job with the old and new fonts' renderers, waiting no longer
+    /// than the worker's timeout for its result
+    pub(crate) fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Renderer<'_>, &mut Renderer<'_>) -> T + Send + 'static,
+    ) -> Result<T, TimedOut> {
+        let (sender, receiver) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |renderer_a, renderer_b| {
+                // Nobody is listening if the job took too long
+                let _ = sender.send(job(renderer_a, renderer_b));
+            }))
+            .map_err(|_| TimedOut)?;
+        receiver.recv_timeout(self.timeout).map_err(|_| TimedOut)
+    }
+}
+
+/// The renderers for comparing words in a pair of fonts, one pair for each
+/// thread
+///
+/// If the render options have a timeout, jobs are run on a worker for each
+/// thread, and a worker whose job times out is replaced.
+pub(crate) struct WordRenderers<'a> {
+    fonts: (&'a DFont, &'a DFont),
+    font_size: f32,
+    direction: Direction,
+    script: Option<rustybuzz::Script>,
+    options: &'a RenderOptions,
+    local: ThreadLocal<(RefCell<Renderer<'a>>, RefCell<Renderer<'a>>)>,
+    workers: Option<WorkerPool>,
+}
+
+struct WorkerPool {
+    fonts: SharedFonts,
+    timeout: Duration,
+    workers: ThreadLocal<RefCell<Option<RenderWorker>>>,
+}
+
+impl<'a> WordRenderers<'a> {
+    /// Workers render copies of the fonts, which are taken from `shared`
+    /// if given, and otherwise made here.
+    pub(crate) fn new(
+        font_a: &'a DFont,
+        font_b: &'a DFont,
+        font_size: f32,
+        direction: Direction,
+        script: Option<rustybuzz::Script>,
+        options: &'a RenderOptions,
+        shared: Option<&SharedFonts>,
+    ) -> Self {
+        let workers = options
+            .timeout
+            .and_then(|timeout| Duration::try_from_secs_f32(timeout).ok())
+            .map(|timeout| WorkerPool {
+                fonts: shared
+                    .cloned()
+                    .unwrap_or_else(|| SharedFonts::new(font_a, font_b)),
+                timeout,
+                workers: ThreadLocal::new(),
+            });
+        Self {
+            fonts: (font_a, font_b),
+            font_size,
+            direction,
+            script,
+            options,
+            local: ThreadLocal::new(),
+            workers,
+        }
+    }
+
+    /// This thread's renderers, which run jobs without a deadline
+    pub(crate) fn local(&self) -> &(RefCell<Renderer<'a>>, RefCell<Renderer<'a>>) {
+        self.local.get_or(|| {
+            let renderer = |font| {
+                RefCell::new(Renderer::new(
+                    font,
+                    self.font_size,
+                    self.direction,
+                    self.script,
+                    self.options,
+                ))
+            };
+            (renderer(self.fonts.0), renderer(self.fonts.1))
+        })
+    }
+
+    /// Run a job with the old and new fonts' renderers, within the timeout
+    /// if there is one
+    pub(crate) fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Renderer<'_>, &mut Renderer<'_>) -> T + Send + 'static,
+    ) -> Result<T, TimedOut> {
+        let Some(pool) = &self.workers else {
+            let (renderer_a, renderer_b) = self.local();
+            return Ok(job(
+                &mut renderer_a.borrow_mut(),
+                &mut renderer_b.borrow_mut(),
+            ));
+        };
+        let mut worker = pool.workers.get_or(|| RefCell::new(None)).borrow_mut();
+        let result = worker
+            .get_or_insert_with(|| {
+                RenderWorker::new(
+                    &pool.fonts,
+                    self.font_size,
+                    self.direction,
+                    self.script,
+                    self.options,
+                    pool.timeout,
+                )
+            })
+            .run(job);
+        if result.is_err() {
+            *worker = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, outline_tables};
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+
+    #[test]
+    fn test_timed_out_worker_is_replaced() {
+        // "A" is the second glyph in the old font and the third in the new
+        let old = DFont::new(&font(outline_tables(1000, &[(0x41, SQUARE)]))).unwrap();
+        let new = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x42, SQUARE), (0x41, SQUARE)],
+        )))
+        .unwrap();
+        let options = RenderOptions {
+            timeout: Some(0.2),
+            ..Default::default()
+        };
+        let renderers = WordRenderers::new(
+            &old,
+            &new,
+            20.0,
+            Direction::LeftToRight,
+            None,
+            &options,
+            None,
+        );
+        let shape = |renderer_a: &mut Renderer<'_>, renderer_b: &mut Renderer<'_>| {
+            (
+                renderer_a.string_to_positioned_glyphs("A").map(|r| r.0),
+                renderer_b.string_to_positioned_glyphs("A").map(|r| r.0),
+            )
+        };
+        let shaped = |result: Result<_, TimedOut>| match result {
+            Ok((Ok(a), Ok(b))) => (a, b),
+            _ => panic!("'A' should have been shaped"),
+        };
+        let expected = (String::from("1|"), String::from("2|"));
+        assert_eq!(shaped(renderers.run(shape)), expected);
+
+        let slow = renderers.run(|_, _| thread::sleep(Duration::from_secs(1)));
+        assert!(slow.is_err());
+        // The busy worker has been dropped, and a new one shapes the word
+        assert_eq!(shaped(renderers.run(shape)), expected);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{identity, to_value, without_summary, Report};
+use crate::{
+    ttj::jsondiff::{diff, Substantial},
+    utils::die,
+};
+
+/// How a report differs from a baseline report saved from an earlier run
+#[derive(Debug, Serialize)]
+pub struct BaselineDiff {
+    /// Differences in the report which aren't in the baseline
+    pub new: Value,
+    /// Differences in the baseline which are no longer in the report
+    pub fixed: Value,
+}
+
+impl BaselineDiff {
+    /// Whether there are new differences
+    pub fn is_some(&self) -> bool {
+        self.new.is_something()
+    }
+}
+
+/// The parts of `this` which aren't in `other`, or null if there are none
+///
+/// Objects are compared key by key. Arrays of objects or arrays, such as
+/// lists of locations or of glyph and word differences, are compared as
+/// sets, so entries which have only moved don't count; an object entry is
+/// compared with the entry in `other` with the same string fields, if
+/// there is one, and what remains is reported along with those fields.
+/// Other arrays, such as the `[old, new]` pairs of a table diff, are
+/// compared whole, as are all other values.
+fn difference(this: &Value, other: &Value) -> Value {
+    match (this, other) {
+        (Value::Object(this), Value::Object(other)) => {
+            let mut result = Map::new();
+            for (key, value) in this {
+                let remaining = match other.get(key) {
+                    Some(other_value) => difference(value, other_value),
+                    None => value.clone(),
+                };
+                if remaining.is_something() {
+                    result.insert(key.clone(), remaining);
+                }
+            }
+            Value::Object(result)
+        }
+        (Value::Array(this), Value::Array(other))
+            if this.iter().all(|item| item.is_object() || item.is_array()) =>
+        {
+            let mut result = vec![];
+            for item in this.iter().filter(|item| !other.contains(item)) {
+                let id = identity(item);
+                let counterpart = id
+                    .as_ref()
+                    .and_then(|id| other.iter().find(|o| identity(o).as_ref() == Some(id)));
+                match (id, counterpart) {
+                    (Some(mut id), Some(counterpart)) => {
+                        if let Value::Object(remaining) = difference(item, counterpart) {
+                            if !remaining.is_empty() {
+                                id.extend(remaining);
+                                result.push(Value::Object(id));
+                            }
+                        }
+                    }
+                    _ => result.push(item.clone()),
+                }
+            }
+            Value::Array(result)
+        }
+        _ => {
+            if diff(this, other).is_something() {
+                this.clone()
+            } else {
+                Value::Null
+            }
+        }
+    }
+}
+
+/// Compare a report with a baseline report, read as JSON
+///
+/// The report is compared as it would be saved, so that numbers are
+/// rounded the same way in both. The reports' summaries are left out, as
+/// they change with any difference.
+pub fn compare_to_baseline(report: &Report, baseline: &Value) -> BaselineDiff {
+    let report = to_value(report);
+    let baseline = without_summary(baseline.clone());
+    BaselineDiff {
+        new: difference(&report, &baseline),
+        fixed: difference(&baseline, &report),
+    }
+}
+
+/// Read a baseline report saved with `--json`
+pub fn read_baseline(path: &Path) -> Value {
+    let file = File::open(path).unwrap_or_else(|e| die("opening baseline report", e));
+    serde_json::from_reader(BufReader::new(file))
+        .unwrap_or_else(|e| die("reading baseline report", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        diff::GlobalDiff,
+        ttj::{gdef::GdefDiff, kerning::KernDiff},
+    };
+
+    fn kern(left: &str, right: &str, old: i32, new: i32) -> KernDiff {
+        KernDiff {
+            left: left.to_string(),
+            right: right.to_string(),
+            old,
+            new,
+        }
+    }
+
+    fn gdef(glyph: &str, old: &str, new: &str) -> GdefDiff {
+        GdefDiff {
+            glyph: glyph.to_string(),
+            classification: "glyph class".to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    fn baseline_of(report: &Report) -> Value {
+        serde_json::to_value(report).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_report() {
+        let report = Report {
+            global: GlobalDiff {
+                kerning: vec![kern("A", "V", -80, -60)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut baseline = baseline_of(&report);
+        // Summaries are ignored
+        baseline["summary"] = json!({ "tables_changed": 3 });
+        let changes = compare_to_baseline(&report, &baseline);
+        assert!(!changes.is_some());
+        assert_eq!(changes.new, json!({}));
+        assert_eq!(changes.fixed, json!({}));
+    }
+
+    #[test]
+    fn test_new_and_fixed() {
+        let baseline = baseline_of(&Report {
+            global: GlobalDiff {
+                kerning: vec![kern("A", "V", -80, -60), kern("T", "o", -40, -20)],
+                ..Default::default()
+            },
+            missing_instances: vec!["Thin".to_string()],
+            ..Default::default()
+        });
+        // Entries which have only moved aren't differences
+        let report = Report {
+            global: GlobalDiff {
+                kerning: vec![kern("L", "T", -50, -70), kern("A", "V", -80, -60)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let changes = compare_to_baseline(&report, &baseline);
+        assert!(changes.is_some());
+        assert_eq!(
+            changes.new,
+            json!({ "kerning": [{ "left": "L", "right": "T", "old": -50, "new": -70 }] })
+        );
+        assert_eq!(
+            changes.fixed,
+            json!({
+                "kerning": [{ "left": "T", "right": "o", "old": -40, "new": -20 }],
+                "missing_instances": ["Thin"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_changed_difference() {
+        // A difference which has changed is matched with the baseline's by
+        // its identifying fields, not its old and new values
+        let baseline = baseline_of(&Report {
+            global: GlobalDiff {
+                kerning: vec![kern("A", "V", -80, -60)],
+                gdef: vec![gdef("a", "base", "mark")],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let report = Report {
+            global: GlobalDiff {
+                kerning: vec![kern("A", "V", -80, -50)],
+                gdef: vec![gdef("a", "base", "ligature")],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let changes = compare_to_baseline(&report, &baseline);
+        assert_eq!(
+            changes.new,
+            json!({
+                "kerning": [{ "left": "A", "right": "V", "new": -50 }],
+                "gdef": [{ "glyph": "a", "classification": "glyph class", "new": "ligature" }],
+            })
+        );
+        assert_eq!(
+            changes.fixed,
+            json!({
+                "kerning": [{ "left": "A", "right": "V", "new": -60 }],
+                "gdef": [{ "glyph": "a", "classification": "glyph class", "new": "mark" }],
+            })
+        );
+    }
+}
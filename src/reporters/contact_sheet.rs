@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    dfont::DFont,
+    render::{render_string, render_vertical_string, Difference, RenderOptions, WORD_FONT_SIZE},
+    utils::die,
+};
+
+use super::{html::path_safe, LocationResult};
+
+/// Pixels of blank space around and between the renderings in a sheet
+const MARGIN: u32 = 8;
+
+/// Write a contact sheet for each script's differing words at a location
+///
+/// Each sheet is a PNG with a row for each word, most different first,
+/// showing the old font's rendering on the left and the new font's on the
+/// right. If `limit` is given, only that many words are shown per script.
+/// The sheets are written to `contact-sheets/<location>/<script>.png`
+/// under `output_dir`. The fonts must be set to the location of `location`.
+pub fn write_contact_sheets(
+    location: &LocationResult,
+    font_a: &DFont,
+    font_b: &DFont,
+    output_dir: &Path,
+    options: &RenderOptions,
+    limit: Option<usize>,
+) {
+    let Some(words) = &location.words else {
+        return;
+    };
+    let sheet_dir = output_dir
+        .join("contact-sheets")
+        .join(path_safe(&location.location));
+    for (script, differences) in words {
+        let mut differences: Vec<&Difference> = differences.iter().collect();
+        differences.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+        differences.truncate(limit.unwrap_or(usize::MAX));
+        let options = options.for_script(script);
+        let rows: Vec<(RgbaImage, RgbaImage)> = differences
+            .into_iter()
+            .filter_map(|difference| {
+                let render = if difference.vertical {
+                    render_vertical_string
+                } else {
+                    render_string
+                };
+                let image_a = render(font_a, &difference.word, WORD_FONT_SIZE, &options)?;
+                let image_b = render(font_b, &difference.word, WORD_FONT_SIZE, &options)?;
+                // Renderings are rasterized with y pointing up
+                Some((
+                    imageops::flip_vertical(&image_a.into_rgba8()),
+                    imageops::flip_vertical(&image_b.into_rgba8()),
+                ))
+            })
+            .collect();
+        if rows.is_empty() {
+            continue;
+        }
+        std::fs::create_dir_all(&sheet_dir)
+            .unwrap_or_else(|e| die("creating contact sheet directory", e));
+        let path = sheet_dir.join(format!("{}.png", path_safe(script)));
+        DynamicImage::ImageRgba8(contact_sheet(&rows))
+            .save(&path)
+            .unwrap_or_else(|e| die("writing contact sheet", e));
+        log::info!("Wrote contact sheet {}", path.display());
+    }
+}
+
+/// Stack pairs of renderings vertically, in two columns, on black
+fn contact_sheet(rows: &[(RgbaImage, RgbaImage)]) -> RgbaImage {
+    let column_width = |column: fn(&(RgbaImage, RgbaImage)) -> &RgbaImage| {
+        rows.iter()
+            .map(|row| column(row).width())
+            .max()
+            .unwrap_or(0)
+    };
+    let width_a = column_width(|(a, _)| a);
+    let width_b = column_width(|(_, b)| b);
+    let height: u32 = rows
+        .iter()
+        .map(|(a, b)| a.height().max(b.height()) + MARGIN)
+        .sum();
+    let mut sheet = RgbaImage::from_pixel(
+        width_a + width_b + 3 * MARGIN,
+        height + MARGIN,
+        Rgba([0, 0, 0, 255]),
+    );
+    let mut y = MARGIN;
+    for (image_a, image_b) in rows {
+        imageops::overlay(&mut sheet, image_a, MARGIN as i64, y as i64);
+        imageops::overlay(&mut sheet, image_b, (width_a + 2 * MARGIN) as i64, y as i64);
+        y += image_a.height().max(image_b.height()) + MARGIN;
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use image::GenericImageView;
+
+    use super::*;
+    use crate::{
+        render::{DifferenceKind, ScriptWordDiffs},
+        test_fonts::{font, outline_tables},
+    };
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+    const TALL: &[(i32, i32)] = &[(100, 0), (100, 700), (400, 700), (400, 0)];
+
+    fn difference(word: &str, percent: f32) -> Difference {
+        Difference {
+            word: word.to_string(),
+            buffer_a: "1|".to_string(),
+            buffer_b: None,
+            percent,
+            ot_features: String::new(),
+            lang: String::new(),
+            images: None,
+            flicker: None,
+            overlay: None,
+            clusters: vec![],
+            kind: DifferenceKind::Outline,
+            vertical: false,
+            renderings: None,
+        }
+    }
+
+    #[test]
+    fn test_contact_sheet_rows() {
+        let old = DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, SQUARE), (0x42, SQUARE)],
+        )))
+        .unwrap();
+        let new = DFont::new(&font(outline_tables(1000, &[(0x41, SQUARE), (0x42, TALL)]))).unwrap();
+        let location = LocationResult {
+            location: "wght=400".to_string(),
+            words: Some(BTreeMap::from([(
+                "Latin".to_string(),
+                ScriptWordDiffs::List(vec![difference("AB", 10.0), difference("B", 30.0)]),
+            )])),
+            ..Default::default()
+        };
+        let output_dir = std::env::temp_dir().join("diffenator3-contact-sheet-test");
+        let sheet = output_dir.join("contact-sheets/wght_400/Latin.png");
+        let options = RenderOptions::default();
+        let size = |font, word| {
+            render_string(font, word, WORD_FONT_SIZE, &options)
+                .unwrap()
+                .dimensions()
+        };
+
+        // Only the most different word
+        write_contact_sheets(&location, &old, &new, &output_dir, &options, Some(1));
+        let (old_b, new_b) = (size(&old, "B"), size(&new, "B"));
+        assert!(new_b.1 > old_b.1);
+        assert_eq!(
+            image::open(&sheet).unwrap().dimensions(),
+            (old_b.0 + new_b.0 + 3 * MARGIN, new_b.1 + 2 * MARGIN)
+        );
+
+        // Every word, each row as tall as its taller rendering
+        write_contact_sheets(&location, &old, &new, &output_dir, &options, None);
+        let (old_ab, new_ab) = (size(&old, "AB"), size(&new, "AB"));
+        assert_eq!(
+            image::open(&sheet).unwrap().dimensions(),
+            (
+                old_ab.0.max(old_b.0) + new_ab.0.max(new_b.0) + 3 * MARGIN,
+                new_b.1 + new_ab.1 + 3 * MARGIN
+            )
+        );
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
@@ -1,59 +1,344 @@
-use crate::utils::die;
+use crate::{
+    dfont::DFont,
+    render::{
+        overlay_renderings, render_string, render_vertical_string, RenderOptions, GLYPH_FONT_SIZE,
+        WORD_FONT_SIZE,
+    },
+    utils::die,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops, Delay, DynamicImage, Frame, ImageResult, Rgba, RgbaImage,
+};
+use serde::Serialize;
 use serde_json::json;
-use std::path::{Path, PathBuf};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
 use tera::{Context, Tera};
 use walkdir::WalkDir;
 
-use super::Report;
+use super::{LocationResult, Report, Summary};
 
+/// Write the HTML report, along with copies of the fonts, to `output_dir`
+///
+/// Each font is given as its path (or URL) and its contents. If `embed`
+/// is true, the fonts are embedded in the HTML as data URIs instead of
+/// being copied, so that the report is a single standalone file.
 pub fn report(
-    font1_pb: &PathBuf,
-    font2_pb: &PathBuf,
+    old: (&Path, &[u8]),
+    new: (&Path, &[u8]),
     output_dir: &Path,
     diff: Report,
     tera: Tera,
-) -> ! {
+    pt_size: f32,
+    embed: bool,
+) {
     // Make output directory
     if !output_dir.exists() {
-        std::fs::create_dir(output_dir).expect("Couldn't create output directory");
+        std::fs::create_dir_all(output_dir).expect("Couldn't create output directory");
     }
 
-    // Copy old font to output/old-<existing name>
-    let old_font = output_dir.join(format!(
-        "old-{}",
-        font2_pb.file_name().unwrap().to_str().unwrap()
-    ));
-    std::fs::copy(font1_pb, &old_font).expect("Couldn't copy old font");
-    let new_font = output_dir.join(format!(
-        "new-{}",
-        font2_pb.file_name().unwrap().to_str().unwrap()
-    ));
-    std::fs::copy(font2_pb, &new_font).expect("Couldn't copy new font");
+    let (old_url, new_url) = if embed {
+        (font_data_uri(old.1), font_data_uri(new.1))
+    } else {
+        // Copy old font to output/old-<existing name>
+        let old_font = format!("old-{}", old.0.file_name().unwrap().to_str().unwrap());
+        std::fs::write(output_dir.join(&old_font), old.1).expect("Couldn't copy old font");
+        let new_font = format!("new-{}", new.0.file_name().unwrap().to_str().unwrap());
+        std::fs::write(output_dir.join(&new_font), new.1).expect("Couldn't copy new font");
+        (old_font, new_font)
+    };
 
     let value = serde_json::to_value(diff).unwrap_or_else(|e| {
         die("serializing diff", e);
     });
-    let html = render_output(
-        &value,
-        old_font.file_name().unwrap().to_str().unwrap(),
-        new_font.file_name().unwrap().to_str().unwrap(),
-        &tera,
-    )
-    .unwrap_or_else(|err| die("rendering HTML", err));
+    let html = render_output(&value, &old_url, &new_url, &tera, pt_size)
+        .unwrap_or_else(|err| die("rendering HTML", err));
 
     // Write output
     let output_file = output_dir.join("diffenator.html");
-    println!("Writing output to {}", output_file.to_str().unwrap());
+    log::info!("Writing output to {}", output_file.to_str().unwrap());
     std::fs::write(output_file, html).expect("Couldn't write output file");
-    std::process::exit(0);
 }
 
-pub fn template_engine(user_templates: Option<&String>) -> Tera {
-    let homedir = create_user_home_templates_directory();
-    let mut tera = Tera::new(&format!("{}/*", homedir.to_str().unwrap())).unwrap_or_else(|e| {
-        println!("Problem parsing templates: {:?}", e);
-        std::process::exit(1)
-    });
+/// A pair of fonts compared as part of a batch, for the index of reports
+#[derive(Serialize)]
+pub struct IndexEntry {
+    /// The subdirectory of the output directory holding the pair's report
+    pub name: String,
+    pub old: String,
+    pub new: String,
+    pub differs: bool,
+    pub summary: Summary,
+    /// Why the pair couldn't be compared, if it couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Write an index of the reports on a batch of font pairs to
+/// `output_dir`, listing the fonts which couldn't be paired up
+pub fn write_index(output_dir: &Path, entries: &[IndexEntry], unmatched: &[String], tera: &Tera) {
+    std::fs::create_dir_all(output_dir).unwrap_or_else(|e| die("creating output directory", e));
+    let html = Context::from_serialize(json!({
+        "entries": entries,
+        "unmatched": unmatched,
+        "differing": entries.iter().filter(|entry| entry.differs).count(),
+        "failed": entries.iter().filter(|entry| entry.error.is_some()).count(),
+    }))
+    .and_then(|context| tera.render("index.html", &context))
+    .unwrap_or_else(|err| die("rendering HTML index", err));
+    let output_file = output_dir.join("index.html");
+    log::info!("Writing index to {}", output_file.display());
+    std::fs::write(output_file, html).unwrap_or_else(|e| die("writing HTML index", e));
+}
+
+/// Encode a font file as a data URI, with a media type guessed from its
+/// magic number
+fn font_data_uri(data: &[u8]) -> String {
+    let media_type = match data.get(0..4) {
+        Some(b"OTTO") => "font/otf",
+        Some(b"wOFF") => "font/woff",
+        Some(b"wOF2") => "font/woff2",
+        Some(b"ttcf") => "font/collection",
+        _ => "font/ttf",
+    };
+    format!("data:{};base64,{}", media_type, STANDARD.encode(data))
+}
+
+/// How the PNG renderings in the HTML report are presented
+///
+/// This only affects the images written out, not the images which are
+/// compared.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PngStyle {
+    /// Pixels of blank space around each rendering
+    pub padding: u32,
+    /// Draw black ink on a white background, rather than white on black
+    pub black_on_white: bool,
+    /// Also write an animated GIF of each pair of renderings, flickering
+    /// between the old and new
+    pub flicker: bool,
+    /// Also write a PNG of each pair of renderings drawn over each other,
+    /// the old in red and the new in blue
+    pub overlay: bool,
+}
+
+impl PngStyle {
+    fn background(&self) -> Rgba<u8> {
+        if self.black_on_white {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    }
+
+    fn apply(&self, image: DynamicImage) -> DynamicImage {
+        // Colour renderings are drawn over a transparent background;
+        // everything else is a coverage mask of white ink on black
+        let is_colour = matches!(image, DynamicImage::ImageRgba8(_));
+        let mut image = image.into_rgba8();
+        if self.black_on_white {
+            for pixel in image.pixels_mut() {
+                let Rgba([r, g, b, a]) = *pixel;
+                *pixel = if is_colour {
+                    let over_white = |c: u8| c.saturating_add(255 - a);
+                    Rgba([over_white(r), over_white(g), over_white(b), 255])
+                } else {
+                    Rgba([255 - r, 255 - g, 255 - b, a])
+                };
+            }
+        }
+        DynamicImage::ImageRgba8(self.pad(image, self.background()))
+    }
+
+    fn pad(&self, image: RgbaImage, background: Rgba<u8>) -> RgbaImage {
+        if self.padding == 0 {
+            return image;
+        }
+        let mut padded = RgbaImage::from_pixel(
+            image.width() + 2 * self.padding,
+            image.height() + 2 * self.padding,
+            background,
+        );
+        imageops::overlay(
+            &mut padded,
+            &image,
+            self.padding as i64,
+            self.padding as i64,
+        );
+        padded
+    }
+}
+
+/// How long each frame of a flicker GIF is shown
+const FLICKER_FRAME_MS: u32 = 500;
+
+/// Write an animated GIF which alternates between two renderings, drawn
+/// on canvases of the same size
+fn write_flicker(
+    old: &DynamicImage,
+    new: &DynamicImage,
+    style: PngStyle,
+    path: &Path,
+) -> ImageResult<()> {
+    let width = old.width().max(new.width());
+    let height = old.height().max(new.height());
+    let frame = |image: &DynamicImage| {
+        let mut canvas = RgbaImage::from_pixel(width, height, style.background());
+        imageops::overlay(&mut canvas, &image.to_rgba8(), 0, 0);
+        Frame::from_parts(
+            canvas,
+            0,
+            0,
+            Delay::from_numer_denom_ms(FLICKER_FRAME_MS, 1),
+        )
+    };
+    let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames([frame(old), frame(new)])
+}
+
+/// Write PNG renderings of each differing glyph and word to the output
+/// directory, so that the report shows the bitmaps which were compared
+/// rather than the browser's rendering
+///
+/// The fonts must be set to the location of `location`. The image paths,
+/// relative to `output_dir`, are recorded in the location result, along
+/// with the paths of flicker GIFs and overlays if the style asks for them.
+pub fn write_images(
+    location: &mut LocationResult,
+    font_a: &DFont,
+    font_b: &DFont,
+    output_dir: &Path,
+    options: &RenderOptions,
+    style: PngStyle,
+) {
+    let image_dir = Path::new("images").join(path_safe(&location.location));
+    std::fs::create_dir_all(output_dir.join(&image_dir))
+        .unwrap_or_else(|e| die("creating image directory", e));
+    let render =
+        |font: &DFont, string: &str, font_size: f32, vertical: bool, options: &RenderOptions| {
+            let render = if vertical {
+                render_vertical_string
+            } else {
+                render_string
+            };
+            render(font, string, font_size, options)
+        };
+    let save = |image: &DynamicImage, filename: String| {
+        let path = image_dir.join(filename);
+        image
+            .save(output_dir.join(&path))
+            .unwrap_or_else(|e| die("writing image", e));
+        path.to_string_lossy().to_string()
+    };
+    let write_pair = |kind: &str,
+                      index: usize,
+                      string: &str,
+                      font_size: f32,
+                      vertical: bool,
+                      options: &RenderOptions| {
+        let (Some(old), Some(new)) = (
+            render(font_a, string, font_size, vertical, options),
+            render(font_b, string, font_size, vertical, options),
+        ) else {
+            return (None, None, None);
+        };
+        // The overlay is drawn from the coverage, on white whatever the style
+        let overlay = style.overlay.then(|| {
+            let image = DynamicImage::ImageRgb8(overlay_renderings(&old, &new)).into_rgba8();
+            let image = style.pad(image, Rgba([255, 255, 255, 255]));
+            save(
+                &DynamicImage::ImageRgba8(image),
+                format!("{}-{}-overlay.png", kind, index),
+            )
+        });
+        let (old, new) = (style.apply(old), style.apply(new));
+        let images = (
+            save(&old, format!("{}-{}-old.png", kind, index)),
+            save(&new, format!("{}-{}-new.png", kind, index)),
+        );
+        let flicker = style.flicker.then(|| {
+            let path = image_dir.join(format!("{}-{}-flicker.gif", kind, index));
+            write_flicker(&old, &new, style, &output_dir.join(&path))
+                .unwrap_or_else(|e| die("writing flicker GIF", e));
+            path.to_string_lossy().to_string()
+        });
+        (Some(images), flicker, overlay)
+    };
+    for (index, glyph) in location.glyphs.iter_mut().enumerate() {
+        // Show a glyph which differs hinted at the first size it does so
+        let options = match glyph.ppems.first() {
+            Some(&ppem) => options.at_ppem(ppem),
+            None => options.clone(),
+        };
+        (glyph.images, glyph.flicker, glyph.overlay) = write_pair(
+            "glyph",
+            index,
+            &glyph.string,
+            GLYPH_FONT_SIZE,
+            false,
+            &options,
+        );
+    }
+    // Words are numbered across all scripts, but shaped in each script's
+    // language, as they were compared
+    let mut index = 0;
+    for (script, words) in location.words.iter_mut().flatten() {
+        let options = options.for_script(script);
+        for word in words.iter_mut() {
+            (word.images, word.flicker, word.overlay) = write_pair(
+                "word",
+                index,
+                &word.word,
+                WORD_FONT_SIZE,
+                word.vertical,
+                &options,
+            );
+            index += 1;
+        }
+    }
+}
+
+/// Replace everything but alphanumerics in a name with underscores, so
+/// that it can be used as a file name
+pub(crate) fn path_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// The templates built into the binary, as (name, contents)
+const BUILTIN_TEMPLATES: [(&str, &str); 5] = [
+    ("script.js", include_str!("../templates/script.js")),
+    ("shared.js", include_str!("../templates/shared.js")),
+    ("style.css", include_str!("../templates/style.css")),
+    (
+        "diffenator.html",
+        include_str!("../templates/diffenator.html"),
+    ),
+    ("index.html", include_str!("../templates/index.html")),
+];
+
+/// Load the HTML templates, overlaid with any in `user_templates`
+///
+/// The base templates are read from `~/.diffenator3/templates`, which is
+/// created and populated with the built-in templates if necessary. If
+/// `use_home` is false, the built-in templates are used directly and
+/// nothing is read from or written to the home directory.
+pub fn template_engine(user_templates: Option<&String>, use_home: bool) -> Tera {
+    let base = if use_home {
+        let homedir = create_user_home_templates_directory();
+        Tera::new(&format!("{}/*", homedir.to_str().unwrap()))
+    } else {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(BUILTIN_TEMPLATES).map(|_| tera)
+    };
+    let mut tera = base.unwrap_or_else(|e| die("parsing templates", e));
     if let Some(template_dir) = user_templates {
         for entry in WalkDir::new(template_dir) {
             if entry.as_ref().is_ok_and(|e| e.file_type().is_dir()) {
@@ -61,21 +346,17 @@ pub fn template_engine(user_templates: Option<&String>) -> Tera {
             }
             let path = entry
                 .as_ref()
-                .unwrap_or_else(|e| {
-                    println!("Problem reading template path: {:}", e);
-                    std::process::exit(1)
-                })
+                .unwrap_or_else(|e| die("reading template path", e))
                 .path();
+            log::debug!("Adding template {}", path.display());
             if let Err(e) =
                 tera.add_template_file(path, path.strip_prefix(template_dir).unwrap().to_str())
             {
-                println!("Problem adding template file: {:}", e);
-                std::process::exit(1)
+                die("adding template file", e);
             }
         }
         if let Err(e) = tera.build_inheritance_chains() {
-            println!("Problem building inheritance chains: {:}", e);
-            std::process::exit(1)
+            die("building inheritance chains", e);
         }
     }
     tera
@@ -87,41 +368,34 @@ pub fn create_user_home_templates_directory() -> PathBuf {
         .expect("No home directory found");
     let templates_dir = home.join(".diffenator3/templates");
     if !templates_dir.exists() {
-        std::fs::create_dir_all(&templates_dir).unwrap_or_else(|e| {
-            println!("Couldn't create {}: {}", templates_dir.to_str().unwrap(), e);
-            std::process::exit(1);
-        });
+        log::debug!("Creating {}", templates_dir.display());
+        std::fs::create_dir_all(&templates_dir)
+            .unwrap_or_else(|e| die(&format!("creating {}", templates_dir.to_str().unwrap()), e));
     }
-    let all_templates = [
-        ["script.js", include_str!("../templates/script.js")],
-        ["shared.js", include_str!("../templates/shared.js")],
-        ["style.css", include_str!("../templates/style.css")],
-        [
-            "diffenator.html",
-            include_str!("../templates/diffenator.html"),
-        ],
-    ];
-    for template in all_templates.iter() {
-        let path = templates_dir.join(template[0]);
+    for (name, contents) in BUILTIN_TEMPLATES.iter() {
+        let path = templates_dir.join(name);
         if !path.exists() {
-            std::fs::write(&path, template[1]).unwrap_or_else(|e| {
-                println!(
-                    "Couldn't write template file {}: {}",
-                    path.to_str().unwrap(),
-                    e
-                );
-                std::process::exit(1)
+            std::fs::write(&path, contents).unwrap_or_else(|e| {
+                die(
+                    &format!("writing template file {}", path.to_str().unwrap()),
+                    e,
+                )
             });
         }
     }
     templates_dir
 }
 
+/// Render the report template
+///
+/// The fonts are referred to by `old_filename` and `new_filename`, which
+/// may be relative URLs or data URIs.
 pub fn render_output(
     value: &serde_json::Value,
     old_filename: &str,
     new_filename: &str,
     tera: &Tera,
+    pt_size: f32,
 ) -> Result<String, tera::Error> {
     tera.render(
         "diffenator.html",
@@ -129,7 +403,7 @@ pub fn render_output(
             "report": value,
             "old_filename": old_filename,
             "new_filename": new_filename,
-            "pt_size": 40,
+            "pt_size": pt_size,
         }))?,
     )
 }
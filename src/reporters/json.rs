@@ -1,10 +1,34 @@
-use super::Report;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
 
-pub fn report(result: Report, pretty: bool) {
+use serde::Serialize;
+
+use crate::utils::die;
+
+/// Print a report as JSON to standard output, or to `path` if given
+///
+/// The report is serialized straight to the output rather than built up
+/// as a string first, so large reports start appearing sooner and need
+/// less memory.
+pub fn report(result: impl Serialize, pretty: bool, path: Option<&Path>) {
+    let written = match path {
+        Some(path) => File::create(path).and_then(|file| write_report(&result, pretty, file)),
+        None => write_report(&result, pretty, io::stdout().lock()),
+    };
+    written.unwrap_or_else(|e| die("writing JSON report", e));
+}
+
+/// Serialize a report as JSON to a writer, followed by a newline
+pub fn write_report(result: &impl Serialize, pretty: bool, writer: impl Write) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
     if pretty {
-        println!("{}", serde_json::to_string_pretty(&result).expect("foo"));
+        serde_json::to_writer_pretty(&mut writer, result)?;
     } else {
-        println!("{}", serde_json::to_string(&result).expect("foo"));
+        serde_json::to_writer(&mut writer, result)?;
     }
-    std::process::exit(0);
+    writeln!(writer)?;
+    writer.flush()
 }
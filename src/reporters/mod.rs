@@ -1,34 +1,101 @@
+/// Like `println!`, but escapes non-ASCII characters in ASCII-only mode
+macro_rules! outln {
+    () => {
+        println!()
+    };
+    ($($arg:tt)*) => {
+        println!("{}", $crate::utils::terminal_safe(&format!($($arg)*)))
+    };
+}
+
+/// Like `print!`, but escapes non-ASCII characters in ASCII-only mode
+macro_rules! out {
+    ($($arg:tt)*) => {
+        print!("{}", $crate::utils::terminal_safe(&format!($($arg)*)))
+    };
+}
+
+pub mod baseline;
+pub mod contact_sheet;
 pub mod html;
 pub mod json;
 pub mod text;
+pub mod three_way;
+pub mod unified;
 
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
 
+use indexmap::IndexMap;
+use schemars::{gen::SchemaSettings, schema::RootSchema, JsonSchema};
 use serde::Serialize;
+use serde_json::{Map, Value};
 
 use crate::{
-    render::{encodedglyphs::CmapDiff, GlyphDiff},
-    ttj::jsondiff::Substantial,
+    diff::GlobalDiff,
+    outlines::{GlyphNameDiff, OutlineDiff},
+    render::{Difference, GlyphDiff, RenderabilityDiffs, WordDiffs},
+    ttj::mvar::MvarDiff,
+    utils::die,
 };
 
-#[derive(Serialize, Default)]
+/// A JSON Schema describing the serialized [Report], derived from the
+/// report structures
+pub fn schema() -> RootSchema {
+    let mut schema = SchemaSettings::draft2019_09()
+        .into_generator()
+        .into_root_schema_for::<Report>();
+    schema.schema.metadata().title = Some("diffenator3 report".to_string());
+    schema
+}
+
+#[derive(Serialize, Default, JsonSchema)]
 pub struct LocationResult {
     pub location: String,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    pub coords: HashMap<String, f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// User-space coordinates, keyed by axis tag
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub coords: BTreeMap<String, f32>,
+    /// The new font's coordinates, if they differ from `coords`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub coords_b: BTreeMap<String, f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub glyphs: Vec<GlyphDiff>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub words: Option<serde_json::Value>,
+    /// Encoded glyphs which took too long to render to be compared
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub glyphs_timed_out: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outlines: Vec<OutlineDiff>,
+    /// All glyphs compared by name, including unencoded glyphs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glyph_names: Option<GlyphNameDiff>,
+    /// Word differences, keyed by script name; with --group-words-by-kind,
+    /// each script's differences are further keyed by kind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<WordDiffs>,
+    /// Words which only one of the fonts can render, which shape to
+    /// .notdef, or which took too long to render, keyed by script name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub renderability: RenderabilityDiffs,
+    /// Metrics adjusted by MVAR which differ at this location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mvar: Vec<MvarDiff>,
 }
 
 impl LocationResult {
     pub fn is_some(&self) -> bool {
         self.error.is_some()
             || !self.glyphs.is_empty()
-            || (self.words.is_some() && self.words.as_ref().unwrap().is_something())
+            || !self.glyphs_timed_out.is_empty()
+            || !self.outlines.is_empty()
+            || self.glyph_names.is_some()
+            || self.words.as_ref().is_some_and(|w| !w.is_empty())
+            || !self.renderability.is_empty()
+            || !self.mvar.is_empty()
     }
 
     pub fn from_error(location: String, error: String) -> Self {
@@ -38,13 +105,439 @@ impl LocationResult {
             ..Default::default()
         }
     }
+
+    /// Reorder the glyph and word differences
+    pub fn sort(&mut self, by: SortBy) {
+        self.glyphs.sort_by(|a, b| by.compare_glyphs(a, b));
+        for differences in self.words.iter_mut().flat_map(|w| w.values_mut()) {
+            differences.sort_by(|a, b| by.compare_words(a, b));
+        }
+    }
+}
+
+/// The order in which glyph and word differences are listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// Most different first
+    #[default]
+    Percent,
+    /// In codepoint order of their strings
+    Codepoint,
+    /// By Unicode character name, unnamed characters last; words, which
+    /// have no names, are sorted in codepoint order
+    Name,
+    /// Shortest strings first
+    String,
+}
+
+impl SortBy {
+    fn compare_glyphs(&self, a: &GlyphDiff, b: &GlyphDiff) -> Ordering {
+        let by_string = a.string.cmp(&b.string);
+        match self {
+            SortBy::Percent => b.percent.total_cmp(&a.percent).then(by_string),
+            // UTF-8 strings sort in codepoint order
+            SortBy::Codepoint => by_string,
+            // Characters without names, such as private use ones, go last
+            SortBy::Name => (a.name.is_empty(), &a.name)
+                .cmp(&(b.name.is_empty(), &b.name))
+                .then(by_string),
+            SortBy::String => compare_lengths(&a.string, &b.string).then(by_string),
+        }
+    }
+
+    fn compare_words(&self, a: &Difference, b: &Difference) -> Ordering {
+        let by_word = a.word.cmp(&b.word);
+        match self {
+            SortBy::Percent => b.percent.total_cmp(&a.percent).then(by_word),
+            SortBy::Codepoint | SortBy::Name => by_word,
+            SortBy::String => compare_lengths(&a.word, &b.word).then(by_word),
+        }
+    }
+}
+
+fn compare_lengths(a: &str, b: &str) -> Ordering {
+    a.chars().count().cmp(&b.chars().count())
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "percent" => Ok(SortBy::Percent),
+            "codepoint" => Ok(SortBy::Codepoint),
+            "name" => Ok(SortBy::Name),
+            "string" => Ok(SortBy::String),
+            _ => Err(format!(
+                "Unknown sort order '{}' (expected percent, codepoint, name or string)",
+                s
+            )),
+        }
+    }
+}
+
+/// How to print the report, other than as JSON or HTML
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Colored terminal output
+    #[default]
+    Text,
+    /// A unified diff of the two fonts serialized as JSON
+    Unified,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "unified" => Ok(ReportFormat::Unified),
+            _ => Err(format!(
+                "Unknown report format '{}' (expected text or unified)",
+                s
+            )),
+        }
+    }
+}
+
+/// Headline counts derived from a [Report], for tracking how different
+/// two fonts are without walking the full report
+#[derive(Serialize, Default, Debug, JsonSchema)]
+pub struct Summary {
+    /// Number of tables with differences
+    pub tables_changed: usize,
+    pub glyphs_added: usize,
+    pub glyphs_removed: usize,
+    /// The new font's glyph count less the old font's
+    pub glyph_count_change: i32,
+    /// Number of encoded glyphs which render differently at any location
+    pub glyphs_modified: usize,
+    /// Number of words which render differently at any location, keyed
+    /// by script name
+    pub words_differing: BTreeMap<String, usize>,
+    /// The largest word difference percentage at any location
+    pub max_word_percent: f32,
+    /// The mean of all reported word difference percentages
+    pub mean_word_percent: f32,
 }
-#[derive(Serialize, Default)]
+
+#[derive(Serialize, Default, JsonSchema)]
 pub struct Report {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tables: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub cmap_diff: Option<CmapDiff>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// The differences which don't depend on the location
+    #[serde(flatten)]
+    pub global: GlobalDiff,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub locations: Vec<LocationResult>,
+    /// Results for each comparison from a comparisons file, keyed by name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub comparisons: IndexMap<String, LocationResult>,
+    /// Results for each named instance, keyed by instance name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub instances: IndexMap<String, LocationResult>,
+    /// Named instances present in the old font but not the new
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_instances: Vec<String>,
+    /// Named instances present in the new font but not the old
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub new_instances: Vec<String>,
+    /// Named instances at the same location in both fonts but with
+    /// different names, as old name to new name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub renamed_instances: IndexMap<String, String>,
+    /// Named instances matched by location within the tolerance but not
+    /// at exactly the same location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub near_matched_instances: Vec<String>,
+}
+
+/// Fields which describe a change rather than what changed
+const CHANGE_FIELDS: [&str; 4] = ["old", "new", "buffer_a", "buffer_b"];
+
+/// The string fields of an object which identify it among the entries of
+/// an array when comparing reports: a location's name, a glyph, a kerning
+/// pair...
+///
+/// Fields describing the change itself are left out, so that differences
+/// in the same thing are matched whatever fonts were compared.
+pub(crate) fn identity(value: &Value) -> Option<Map<String, Value>> {
+    let Value::Object(map) = value else {
+        return None;
+    };
+    let identity: Map<String, Value> = map
+        .iter()
+        .filter(|(key, value)| value.is_string() && !CHANGE_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    (!identity.is_empty()).then_some(identity)
+}
+
+/// A report as it would be saved, for comparing with other reports
+///
+/// The summary is left out, as it changes with any difference.
+pub(crate) fn to_value(report: &Report) -> Value {
+    let value = serde_json::to_string(report)
+        .and_then(|json| serde_json::from_str(&json))
+        .unwrap_or_else(|e| die("serializing report", e));
+    without_summary(value)
+}
+
+/// Remove the summary from a serialized report
+pub(crate) fn without_summary(mut value: Value) -> Value {
+    if let Value::Object(map) = &mut value {
+        map.remove("summary");
+    }
+    value
+}
+
+impl Report {
+    /// Whether any differences (or errors) were found
+    pub fn is_some(&self) -> bool {
+        self.global.is_some()
+            || self.locations.iter().any(|l| l.is_some())
+            || self.comparisons.values().any(|l| l.is_some())
+            || self.instances.values().any(|l| l.is_some())
+            || !self.missing_instances.is_empty()
+            || !self.new_instances.is_empty()
+            || !self.renamed_instances.is_empty()
+            || !self.near_matched_instances.is_empty()
+    }
+
+    /// Reorder the glyph and word differences at every location
+    pub fn sort(&mut self, by: SortBy) {
+        for location in self
+            .locations
+            .iter_mut()
+            .chain(self.comparisons.values_mut())
+            .chain(self.instances.values_mut())
+        {
+            location.sort(by);
+        }
+    }
+
+    /// Count the differences in this report
+    pub fn summarize(&self) -> Summary {
+        // A table may be reported both in the table diff and semantically,
+        // as `name` is when names are compared, so count each tag once
+        let mut tables_changed: BTreeSet<&str> = self
+            .global
+            .tables
+            .as_ref()
+            .and_then(|t| t.as_object())
+            .into_iter()
+            .flat_map(|t| t.keys())
+            .map(|k| k.as_str())
+            .filter(|k| *k != "error")
+            .collect();
+        if !self.global.stat.is_empty() {
+            tables_changed.insert("STAT");
+        }
+        if !self.global.names.is_empty() {
+            tables_changed.insert("name");
+        }
+        let mut summary = Summary {
+            tables_changed: tables_changed.len(),
+            glyphs_added: self
+                .global
+                .cmap_diff
+                .as_ref()
+                .map_or(0, |c| c.added_codepoints.len()),
+            glyphs_removed: self
+                .global
+                .cmap_diff
+                .as_ref()
+                .map_or(0, |c| c.removed_codepoints.len()),
+            glyph_count_change: self
+                .global
+                .glyph_count
+                .as_ref()
+                .map_or(0, |g| g.new as i32 - g.old as i32),
+            ..Default::default()
+        };
+        let mut modified_glyphs = BTreeSet::new();
+        let mut words: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut percents = vec![];
+        for location in self
+            .locations
+            .iter()
+            .chain(self.comparisons.values())
+            .chain(self.instances.values())
+        {
+            modified_glyphs.extend(location.glyphs.iter().map(|g| g.string.as_str()));
+            for (script, differences) in location.words.iter().flatten() {
+                words
+                    .entry(script)
+                    .or_default()
+                    .extend(differences.iter().map(|d| d.word.as_str()));
+                percents.extend(differences.iter().map(|d| d.percent));
+            }
+        }
+        summary.glyphs_modified = modified_glyphs.len();
+        summary.words_differing = words
+            .into_iter()
+            .map(|(script, words)| (script.to_string(), words.len()))
+            .collect();
+        if !percents.is_empty() {
+            summary.max_word_percent = percents.iter().copied().fold(0.0, f32::max);
+            summary.mean_word_percent = percents.iter().sum::<f32>() / percents.len() as f32;
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        render::{DifferenceKind, Renderability, ScriptWordDiffs},
+        ttj::{kerning::KernDiff, upm::UpmDiff},
+    };
+
+    /// Check that a serialized value only uses properties the schema
+    /// declares, and has all those it requires
+    fn conforms(value: &Value, schema: &Value, definitions: &Value) -> bool {
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            let name = reference.trim_start_matches("#/definitions/");
+            return conforms(value, &definitions[name], definitions);
+        }
+        for key in ["anyOf", "oneOf"] {
+            if let Some(branches) = schema.get(key).and_then(|b| b.as_array()) {
+                return branches.iter().any(|b| conforms(value, b, definitions));
+            }
+        }
+        if let Some(branches) = schema.get("allOf").and_then(|b| b.as_array()) {
+            return branches.iter().all(|b| conforms(value, b, definitions));
+        }
+        match value {
+            Value::Object(object) => {
+                let required = schema["required"].as_array().into_iter().flatten();
+                if required
+                    .clone()
+                    .any(|r| !object.contains_key(r.as_str().unwrap()))
+                {
+                    return false;
+                }
+                object.iter().all(|(key, value)| {
+                    match schema["properties"].get(key) {
+                        Some(property) => conforms(value, property, definitions),
+                        None => match &schema["additionalProperties"] {
+                            Value::Object(_) => {
+                                conforms(value, &schema["additionalProperties"], definitions)
+                            }
+                            // Maps of arbitrary values, such as table diffs
+                            Value::Null => schema.get("properties").is_none(),
+                            _ => false,
+                        },
+                    }
+                })
+            }
+            Value::Array(items) => match &schema["items"] {
+                Value::Array(tuple) => items
+                    .iter()
+                    .zip(tuple)
+                    .all(|(item, schema)| conforms(item, schema, definitions)),
+                Value::Null => true,
+                item => items.iter().all(|i| conforms(i, item, definitions)),
+            },
+            _ => true,
+        }
+    }
+
+    fn difference(word: &str, kind: DifferenceKind) -> Difference {
+        Difference {
+            word: word.to_string(),
+            buffer_a: "a=0+500".to_string(),
+            buffer_b: Some("a=0+520".to_string()),
+            percent: 12.5,
+            ot_features: "+liga".to_string(),
+            lang: "en".to_string(),
+            images: Some(("old.png".to_string(), "new.png".to_string())),
+            flicker: None,
+            overlay: None,
+            clusters: vec![],
+            kind,
+            vertical: false,
+            renderings: None,
+        }
+    }
+
+    #[test]
+    fn test_schema_describes_report() {
+        let location = LocationResult {
+            location: "wght=400".to_string(),
+            coords: BTreeMap::from([("wght".to_string(), 400.0)]),
+            glyphs: vec![difference("a", DifferenceKind::Outline).into()],
+            glyphs_timed_out: vec!["b".to_string()],
+            words: Some(BTreeMap::from([
+                (
+                    "Latin".to_string(),
+                    ScriptWordDiffs::List(vec![difference("hello", DifferenceKind::Positioning)]),
+                ),
+                (
+                    "Greek".to_string(),
+                    ScriptWordDiffs::List(vec![difference("γεια", DifferenceKind::Substitution)])
+                        .group_by_kind(),
+                ),
+            ])),
+            renderability: BTreeMap::from([(
+                "Latin".to_string(),
+                Renderability {
+                    only_renderable_in_new: vec!["ŋ".to_string()],
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let mut report = Report {
+            global: GlobalDiff {
+                tables: Some(json!({"head": {"fontRevision": [1.0, 1.1]}})),
+                upm: Some(UpmDiff {
+                    old: 1000,
+                    new: 2048,
+                    warning: "scaled".to_string(),
+                }),
+                kerning: vec![KernDiff {
+                    left: "A".to_string(),
+                    right: "V".to_string(),
+                    old: -80,
+                    new: -60,
+                }],
+                ..Default::default()
+            },
+            locations: vec![location],
+            missing_instances: vec!["Thin".to_string()],
+            ..Default::default()
+        };
+        report.summary = Some(report.summarize());
+
+        let schema = serde_json::to_value(schema()).unwrap();
+        let serialized = serde_json::to_value(&report).unwrap();
+        assert!(conforms(&serialized, &schema, &schema["definitions"]));
+
+        // A property the schema doesn't know about is caught
+        let mut extra = serialized.clone();
+        extra["locations"][0]["unknown"] = json!(1);
+        assert!(!conforms(&extra, &schema, &schema["definitions"]));
+    }
+
+    #[test]
+    fn test_tables_changed_counts_each_table_once() {
+        let report = Report {
+            global: GlobalDiff {
+                tables: Some(json!({
+                    "name": {"1": ["Old", "New"]},
+                    "head": {"fontRevision": [1.0, 1.1]},
+                })),
+                names: vec!["Family name changed".to_string()],
+                stat: vec!["Axis value added".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(report.summarize().tables_changed, 3);
+    }
 }
@@ -1,34 +1,40 @@
 use super::{LocationResult, Report};
 
-use crate::ttj::jsondiff::Substantial;
+use crate::{
+    outlines::OutlineChange,
+    render::{encodedglyphs::EncodedGlyph, Difference, ScriptWordDiffs},
+    ttj::jsondiff::Substantial,
+};
 use colored::Colorize;
+use itertools::Itertools;
 use serde_json::Map;
+use std::collections::HashSet;
 
 fn show_map_diff(fields: &Map<String, serde_json::Value>, indent: usize, succinct: bool) {
     for (field, diff) in fields.iter() {
-        print!("{}", " ".repeat(indent * 2));
+        out!("{}", " ".repeat(indent * 2));
         if field == "error" {
-            println!("{}", diff.as_str().unwrap().red());
+            outln!("{}", diff.as_str().unwrap().red());
             continue;
         }
         if let Some(lr) = diff.as_array() {
             let (left, right) = (&lr[0], &lr[1]);
             if succinct && (left.is_something() && !right.is_something()) {
-                println!(
+                outln!(
                     "{}: {} => {}",
                     field,
                     format!("{}", left).green(),
                     "<absent>".red().italic()
                 );
             } else if succinct && (right.is_something() && !left.is_something()) {
-                println!(
+                outln!(
                     "{}: {} => {}",
                     field,
                     "<absent>".green().italic(),
                     format!("{}", right).red()
                 );
             } else {
-                println!(
+                outln!(
                     "{}: {} => {}",
                     field,
                     format!("{}", left).green(),
@@ -36,48 +42,248 @@ fn show_map_diff(fields: &Map<String, serde_json::Value>, indent: usize, succinc
                 );
             }
         } else if let Some(fields) = diff.as_object() {
-            println!("{}:", field);
+            outln!("{}:", field);
             show_map_diff(fields, indent + 1, succinct)
         }
     }
 }
 
 pub fn report(result: Report, succinct: bool) {
-    if let Some(tables) = result.tables {
+    // Shown first, as it affects how the other differences should be read
+    if let Some(upm) = &result.global.upm {
+        outln!("# Units per em");
+        outln!(
+            " - {} => {}",
+            format!("{}", upm.old).green(),
+            format!("{}", upm.new).red()
+        );
+        outln!(" - {}", upm.warning.yellow().bold());
+    }
+    if let Some(tables) = result.global.tables {
         for (table_name, diff) in tables.as_object().unwrap().iter() {
             if diff.is_something() {
-                println!("\n# {}", table_name);
+                outln!("\n# {}", table_name);
             }
             if let Some(lr) = diff.as_array() {
                 let (left, right) = (&lr[0], &lr[1]);
                 if succinct && (left.is_something() && !right.is_something()) {
-                    println!("Table was present in LHS but absent in RHS");
+                    outln!("Table was present in LHS but absent in RHS");
                 } else if succinct && (right.is_something() && !left.is_something()) {
-                    println!("Table was present in RHS but absent in LHS");
+                    outln!("Table was present in RHS but absent in LHS");
                 } else {
-                    println!("LHS had: {}", left);
-                    println!("RHS had: {}", right);
+                    outln!("LHS had: {}", left);
+                    outln!("RHS had: {}", right);
                 }
             } else if let Some(fields) = diff.as_object() {
                 show_map_diff(fields, 0, succinct);
             } else {
-                println!("Unexpected diff format: {}", diff);
+                outln!("Unexpected diff format: {}", diff);
+            }
+        }
+    }
+
+    if let Some(cmap_diff) = result.global.cmap_diff {
+        outln!("\n# Encoded Glyphs");
+        if !cmap_diff.removed_codepoints.is_empty() {
+            outln!("\nMissing glyphs:");
+            show_encoded_glyphs(&cmap_diff.removed_codepoints);
+        }
+        if !cmap_diff.added_codepoints.is_empty() {
+            outln!("\nNew glyphs:");
+            show_encoded_glyphs(&cmap_diff.added_codepoints);
+        }
+        if !cmap_diff.remapped_glyphs.is_empty() {
+            outln!("\nGlyphs mapped from different codepoints:");
+            for remapped in cmap_diff.remapped_glyphs {
+                outln!("  {}:", remapped.glyph.bold());
+                for codepoint in remapped.removed {
+                    outln!("   - {}", format!("{}", codepoint).green());
+                }
+                for codepoint in remapped.added {
+                    outln!("   + {}", format!("{}", codepoint).red());
+                }
+            }
+        }
+    }
+
+    if !result.global.stat.is_empty() {
+        outln!("\n# STAT");
+        for change in result.global.stat {
+            outln!(" - {}", change);
+        }
+    }
+
+    if !result.global.default_instance.is_empty() {
+        outln!("\n# Default instance");
+        for change in result.global.default_instance {
+            outln!(" - {}", change);
+        }
+    }
+
+    if let Some(glyph_count) = result.global.glyph_count {
+        outln!("\n# Glyph count");
+        outln!(
+            " - {} => {}",
+            format!("{}", glyph_count.old).green(),
+            format!("{}", glyph_count.new).red()
+        );
+        if let Some(warning) = glyph_count.warning {
+            outln!(" - {}", warning.yellow());
+        }
+    }
+
+    if !result.global.names.is_empty() {
+        outln!("\n# Names");
+        for change in result.global.names {
+            outln!(" - {}", change);
+        }
+    }
+
+    if !result.global.unicode_ranges.is_empty() {
+        outln!("\n# Unicode ranges");
+        for change in result.global.unicode_ranges {
+            outln!(" - {}", change);
+        }
+    }
+
+    if !result.global.style_bits.is_empty() {
+        outln!("\n# Style bits");
+        for change in result.global.style_bits {
+            outln!(" - {}", change);
+        }
+    }
+
+    if !result.global.kerning.is_empty() {
+        outln!("\n# Kerning");
+        for kern in result.global.kerning {
+            outln!(
+                " - {} {}: {} => {}",
+                kern.left,
+                kern.right,
+                format!("{}", kern.old).green(),
+                format!("{}", kern.new).red()
+            );
+        }
+    }
+
+    if !result.global.anchors.is_empty() {
+        outln!("\n# Mark attachment");
+        let position = |p: Option<[i32; 2]>| {
+            p.map_or("(none)".to_string(), |[x, y]| format!("({}, {})", x, y))
+        };
+        for anchor in result.global.anchors {
+            let delta = match (anchor.old, anchor.new) {
+                (Some([x1, y1]), Some([x2, y2])) => format!(" ({:+}, {:+})", x2 - x1, y2 - y1),
+                _ => String::new(),
+            };
+            outln!(
+                " - {} {} on {}: {} => {}{}",
+                anchor.kind,
+                anchor.mark,
+                anchor.base,
+                position(anchor.old).green(),
+                position(anchor.new).red(),
+                delta
+            );
+        }
+    }
+
+    if !result.global.metrics.is_empty() {
+        outln!("\n# Metrics");
+        for metrics in result.global.metrics {
+            outln!(
+                " - {} ({}): advance {} => {}, side bearing {} => {}",
+                metrics.glyph,
+                metrics.table,
+                format!("{}", metrics.old_advance).green(),
+                format!("{}", metrics.new_advance).red(),
+                format!("{}", metrics.old_side_bearing).green(),
+                format!("{}", metrics.new_side_bearing).red()
+            );
+        }
+    }
+
+    if !result.global.gdef.is_empty() {
+        outln!("\n# GDEF classes");
+        for gdef in result.global.gdef {
+            outln!(
+                " - {} {}: {} => {}",
+                gdef.glyph,
+                gdef.classification,
+                gdef.old.green(),
+                gdef.new.red()
+            );
+        }
+    }
+
+    if let Some(post_names) = result.global.post_names {
+        outln!("\n# Glyph names");
+        for renamed in post_names.renamed {
+            outln!(
+                " - gid {}: {} => {}",
+                renamed.gid,
+                renamed.old.green(),
+                renamed.new.red()
+            );
+        }
+        for (heading, list) in [("Added", post_names.added), ("Removed", post_names.removed)] {
+            if !list.is_empty() {
+                outln!("\n{} glyph names:\n{}", heading, list.join(" "));
+            }
+        }
+    }
+
+    if let Some(colr) = result.global.colr {
+        outln!("\n# Color glyphs");
+        for (heading, list) in [("Added", colr.added), ("Removed", colr.removed)] {
+            if !list.is_empty() {
+                outln!("\n{} color glyphs:\n{}", heading, list.join(" "));
+            }
+        }
+        for (glyph, changes) in colr.changed {
+            outln!("\n## {}", glyph);
+            for change in changes {
+                outln!(" - {}", change);
             }
         }
     }
 
-    if let Some(cmap_diff) = result.cmap_diff {
-        println!("\n# Encoded Glyphs");
-        if !cmap_diff.missing.is_empty() {
-            println!("\nMissing glyphs:");
-            for glyph in cmap_diff.missing {
-                println!(" - {} ", glyph);
+    if !result.global.substitutions.is_empty() {
+        outln!("\n# Single substitutions");
+        let none = || "(none)".to_string();
+        for (feature, substitutions) in &result
+            .global
+            .substitutions
+            .into_iter()
+            .chunk_by(|s| s.feature.clone())
+        {
+            outln!("\n## {}", feature);
+            for substitution in substitutions {
+                outln!(
+                    " - {}: {} => {}",
+                    substitution.glyph,
+                    substitution.old.unwrap_or_else(none).green(),
+                    substitution.new.unwrap_or_else(none).red()
+                );
             }
         }
-        if !cmap_diff.new.is_empty() {
-            println!("\nNew glyphs:");
-            for glyph in cmap_diff.new {
-                println!(" - {} ", glyph);
+    }
+
+    if let Some(features) = result.global.features {
+        outln!("\n# Features");
+        for (heading, list) in [("Added", features.added), ("Removed", features.removed)] {
+            if list.is_empty() {
+                continue;
+            }
+            outln!("\n{} features:", heading);
+            for feature in list {
+                outln!(
+                    " - {} {}/{}: {}",
+                    feature.table,
+                    feature.script,
+                    feature.language,
+                    feature.feature
+                );
             }
         }
     }
@@ -87,38 +293,206 @@ pub fn report(result: Report, succinct: bool) {
             report_location(locationresult);
         }
     }
+    for (_name, locationresult) in result.comparisons {
+        if locationresult.is_some() {
+            report_location(locationresult);
+        }
+    }
+
+    if !result.missing_instances.is_empty() {
+        outln!("\n# Missing instances");
+        for instance in result.missing_instances {
+            outln!(" - {}", instance);
+        }
+    }
+    if !result.new_instances.is_empty() {
+        outln!("\n# New instances");
+        for instance in result.new_instances {
+            outln!(" - {}", instance);
+        }
+    }
+    if !result.renamed_instances.is_empty() {
+        outln!("\n# Renamed instances");
+        for (old, new) in result.renamed_instances {
+            outln!(" - {} => {}", old.green(), new.red());
+        }
+    }
+    if !result.near_matched_instances.is_empty() {
+        outln!("\n# Instances matched within tolerance");
+        for instance in result.near_matched_instances {
+            outln!(" - {}", instance);
+        }
+    }
+    for (_name, locationresult) in result.instances {
+        if locationresult.is_some() {
+            report_location(locationresult);
+        }
+    }
+}
+
+fn show_encoded_glyphs(glyphs: &[EncodedGlyph]) {
+    let mut current_block = None;
+    for glyph in glyphs {
+        if current_block != Some(&glyph.block) {
+            outln!("  {}:", glyph.block.as_deref().unwrap_or("No block").bold());
+            current_block = Some(&glyph.block);
+        }
+        outln!("   - {} ", glyph);
+    }
 }
 
 fn report_location(locationresult: LocationResult) {
-    print!("# Differences at location {} ", locationresult.location);
+    out!("# Differences at location {} ", locationresult.location);
     if !locationresult.coords.is_empty() {
-        print!("( ");
+        out!("( ");
         for (k, v) in locationresult.coords.iter() {
-            print!("{}: {}, ", k, v);
+            out!("{}: {}, ", k, v);
+        }
+        out!(")");
+    }
+    if !locationresult.coords_b.is_empty() {
+        out!(" vs ( ");
+        for (k, v) in locationresult.coords_b.iter() {
+            out!("{}: {}, ", k, v);
         }
-        print!(")");
+        out!(")");
     }
-    println!();
+    outln!();
 
     if !locationresult.glyphs.is_empty() {
-        println!("\n## Glyphs");
+        outln!("\n## Glyphs");
         for glyph in locationresult.glyphs {
-            println!(" - {} ({:.3}%)", glyph.string, glyph.percent);
+            if glyph.ppems.is_empty() {
+                outln!(" - {} ({:.3}%)", glyph.string, glyph.percent);
+            } else {
+                let ppems: Vec<String> = glyph.ppems.iter().map(|p| p.to_string()).collect();
+                outln!(
+                    " - {} ({:.3}%; differs at {} ppem)",
+                    glyph.string,
+                    glyph.percent,
+                    ppems.join(", ")
+                );
+            }
+        }
+    }
+    if !locationresult.glyphs_timed_out.is_empty() {
+        outln!("\n## Glyphs which timed out");
+        for glyph in locationresult.glyphs_timed_out {
+            outln!(" - {}", glyph);
+        }
+    }
+
+    if !locationresult.outlines.is_empty() {
+        outln!("\n## Outlines");
+        for outline in locationresult.outlines {
+            out!(" - {} ({})", outline.string, outline.unicode);
+            print_outline_change(&outline.change);
+        }
+    }
+
+    if let Some(glyph_names) = locationresult.glyph_names {
+        outln!("\n## Glyphs by name");
+        if !glyph_names.added.is_empty() {
+            outln!("\n### Added\n{}", glyph_names.added.join(" "));
+        }
+        if !glyph_names.removed.is_empty() {
+            outln!("\n### Removed\n{}", glyph_names.removed.join(" "));
+        }
+        if !glyph_names.modified.is_empty() {
+            outln!("\n### Modified");
+            for outline in glyph_names.modified {
+                out!(" - {}", outline.name);
+                print_outline_change(&outline.change);
+            }
         }
     }
 
     if let Some(words) = locationresult.words {
-        println!("# Words");
-        let map = words.as_object().unwrap();
-        for (script, script_diff) in map.iter() {
-            println!("\n## {}", script);
-            for difference in script_diff.as_array().unwrap().iter() {
-                println!(
-                    "  - {} ({:.3}%)",
-                    difference["word"].as_str().unwrap(),
-                    difference["percent"].as_f64().unwrap()
-                );
+        outln!("# Words");
+        let print_differences = |differences: &[Difference]| {
+            for difference in differences {
+                outln!("  - {} ({:.3}%)", difference.word, difference.percent);
+            }
+        };
+        for (script, script_diff) in words.iter() {
+            outln!("\n## {}", script);
+            match script_diff {
+                ScriptWordDiffs::List(differences) => print_differences(differences),
+                ScriptWordDiffs::ByKind(groups) => {
+                    for (kind, differences) in groups {
+                        outln!("\n### {:?} changes", kind);
+                        print_differences(differences);
+                    }
+                }
+            }
+        }
+    }
+
+    if !locationresult.mvar.is_empty() {
+        outln!("\n## MVAR metrics");
+        for metric in locationresult.mvar {
+            outln!(
+                " - {} ({}): {} => {}",
+                metric.metric,
+                metric.tag,
+                format!("{}", metric.old).green(),
+                format!("{}", metric.new).red()
+            );
+        }
+    }
+
+    if !locationresult.renderability.is_empty() {
+        outln!("# Renderability");
+        for (script, renderability) in locationresult.renderability.iter() {
+            outln!("\n## {}", script);
+            // Words which shape to .notdef are listed with their positions
+            let notdef: HashSet<&str> = renderability
+                .notdef
+                .iter()
+                .map(|n| n.word.as_str())
+                .collect();
+            for word in renderability.only_renderable_in_old.iter() {
+                if !notdef.contains(word.as_str()) {
+                    outln!("  - {} (only renders in old font)", word);
+                }
+            }
+            for word in renderability.only_renderable_in_new.iter() {
+                if !notdef.contains(word.as_str()) {
+                    outln!("  - {} (only renders in new font)", word);
+                }
+            }
+            for word in renderability.notdef.iter() {
+                let positions = |font: &str, positions: &[usize]| {
+                    (!positions.is_empty()).then(|| {
+                        format!(
+                            ".notdef in {} font at {}",
+                            font,
+                            positions.iter().join(", ")
+                        )
+                    })
+                };
+                let fonts = [positions("old", &word.old), positions("new", &word.new)];
+                outln!("  - {} ({})", word.word, fonts.iter().flatten().join("; "));
+            }
+            for word in renderability.timed_out.iter() {
+                outln!("  - {} (timed out)", word);
             }
         }
     }
 }
+
+fn print_outline_change(change: &OutlineChange) {
+    let (contours, points) = (change.contours, change.points);
+    if contours.0 != contours.1 {
+        out!(" contours: {} => {}", contours.0, contours.1);
+    }
+    if points.0 != points.1 {
+        out!(" points: {} => {}", points.0, points.1);
+    }
+    if let Some(delta) = change.max_delta {
+        out!(" moved by up to {:.1} units", delta);
+    } else if contours.0 == contours.1 && points.0 == points.1 {
+        out!(" contour structure differs");
+    }
+    outln!();
+}
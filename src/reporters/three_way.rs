@@ -0,0 +1,155 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{identity, to_value, Report};
+use crate::ttj::jsondiff::Substantial;
+
+/// A comparison of two fonts descended from a common base font
+#[derive(Serialize)]
+pub struct ThreeWayReport {
+    /// How the old font differs from the base font
+    pub a_changes: Report,
+    /// How the new font differs from the base font
+    pub b_changes: Report,
+    /// How the old and new fonts differ, in the parts both changed from
+    /// the base font
+    pub conflicts: Value,
+}
+
+impl ThreeWayReport {
+    /// Whether the two fonts made conflicting changes
+    pub fn is_some(&self) -> bool {
+        self.conflicts.is_something()
+    }
+}
+
+/// The parts of `this` which are also in `other`, or null if there are none
+///
+/// Objects are intersected key by key. Arrays of objects, such as lists of
+/// locations or of glyph and word differences, are intersected as sets,
+/// matching entries by their identifying fields. Other values are kept
+/// whole if `other` has a value in the same place, as two reports having
+/// a value there means both found a difference.
+fn intersection(this: &Value, other: &Value) -> Value {
+    match (this, other) {
+        (Value::Object(this), Value::Object(other)) => Value::Object(
+            this.iter()
+                .filter_map(|(key, value)| {
+                    let common = intersection(value, other.get(key)?);
+                    common.is_something().then(|| (key.clone(), common))
+                })
+                .collect(),
+        ),
+        (Value::Array(this), Value::Array(other)) if this.iter().all(Value::is_object) => {
+            Value::Array(
+                this.iter()
+                    .filter_map(|item| {
+                        let id = identity(item);
+                        let counterpart = other.iter().find(|o| match &id {
+                            Some(id) => identity(o).as_ref() == Some(id),
+                            None => *o == item,
+                        })?;
+                        let common = intersection(item, counterpart);
+                        // An entry left with only its identifying fields,
+                        // such as a location with none of its differences,
+                        // has nothing in common
+                        let identified_only = |value: &Value| match (value, &id) {
+                            (Value::Object(map), Some(id)) => {
+                                map.keys().all(|key| id.contains_key(key))
+                            }
+                            _ => false,
+                        };
+                        (common.is_something()
+                            && (identified_only(item) || !identified_only(&common)))
+                        .then_some(common)
+                    })
+                    .collect(),
+            )
+        }
+        _ => this.clone(),
+    }
+}
+
+/// Combine the comparisons of a base font with each of two fonts, and of
+/// the two fonts with each other, into a three-way report
+///
+/// The conflicts are the differences between the two fonts which are in
+/// things both fonts changed from the base font.
+pub fn three_way(a_changes: Report, b_changes: Report, a_to_b: &Report) -> ThreeWayReport {
+    let both_changed = intersection(&to_value(&a_changes), &to_value(&b_changes));
+    ThreeWayReport {
+        conflicts: intersection(&to_value(a_to_b), &both_changed),
+        a_changes,
+        b_changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compare::{compare_fonts, CompareOptions},
+        dfont::DFont,
+        diff::DiffOptions,
+        test_fonts::{font, outline_tables},
+    };
+
+    const SQUARE: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 500), (400, 0)];
+    const MOVED: &[(i32, i32)] = &[(100, 0), (100, 500), (400, 540), (400, 0)];
+    const TRIANGLE: &[(i32, i32)] = &[(100, 0), (250, 500), (400, 0)];
+
+    fn dfont(a: &[(i32, i32)], b: &[(i32, i32)], c: &[(i32, i32)]) -> DFont {
+        DFont::new(&font(outline_tables(
+            1000,
+            &[(0x41, a), (0x42, b), (0x43, c)],
+        )))
+        .unwrap()
+    }
+
+    fn compare(old: &mut DFont, new: &mut DFont) -> Report {
+        let options = CompareOptions {
+            diff: DiffOptions {
+                words: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        compare_fonts(old, new, &options, None)
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut base = dfont(SQUARE, SQUARE, SQUARE);
+        // Both change A, and each changes one other glyph
+        let mut font_a = dfont(MOVED, MOVED, SQUARE);
+        let mut font_b = dfont(TRIANGLE, SQUARE, MOVED);
+        let a_to_b = compare(&mut font_a, &mut font_b);
+        let changed = |report: &Value| -> Vec<String> {
+            report["locations"][0]["glyphs"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|glyph| glyph["string"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(changed(&to_value(&a_to_b)), vec!["A", "B", "C"]);
+
+        let report = three_way(
+            compare(&mut base, &mut font_a),
+            compare(&mut base, &mut font_b),
+            &a_to_b,
+        );
+        assert!(report.is_some());
+        assert_eq!(changed(&report.conflicts), vec!["A"]);
+
+        // Changes to different glyphs don't conflict
+        let mut font_b = dfont(SQUARE, SQUARE, MOVED);
+        let a_to_b = compare(&mut font_a, &mut font_b);
+        let report = three_way(
+            compare(&mut base, &mut font_a),
+            compare(&mut base, &mut font_b),
+            &a_to_b,
+        );
+        assert!(!report.is_some());
+    }
+}
@@ -0,0 +1,26 @@
+use similar::TextDiff;
+use skrifa::Tag;
+
+use crate::{dfont::DFont, ttj::font_to_json_filtered};
+
+/// Print a unified diff of the two fonts serialized as JSON, as `ttj`
+/// serializes them, one value per line
+///
+/// Only the given tables are serialized, if any are given. Returns whether
+/// the serializations differ.
+pub fn report(
+    (name_a, font_a): (&str, &DFont),
+    (name_b, font_b): (&str, &DFont),
+    tables: Option<&[Tag]>,
+) -> bool {
+    let serialize = |font: &DFont| {
+        let json = font_to_json_filtered(&font.fontref(), tables);
+        // Without a final newline, the last line of each would differ
+        serde_json::to_string_pretty(&json).expect("JSON values serialize") + "\n"
+    };
+    let (text_a, text_b) = (serialize(font_a), serialize(font_b));
+    let diff = TextDiff::from_lines(&text_a, &text_b);
+    let unified = diff.unified_diff().header(name_a, name_b).to_string();
+    out!("{}", unified);
+    !unified.is_empty()
+}
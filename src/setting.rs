@@ -1,4 +1,5 @@
-use skrifa::setting::VariationSetting;
+use serde::Deserialize;
+use skrifa::{setting::VariationSetting, Tag};
 
 use crate::dfont::DFont;
 
@@ -9,23 +10,147 @@ use crate::dfont::DFont;
 pub enum Setting {
     Instance(String),
     Location(Vec<VariationSetting>),
+    /// Separate locations for the old and new fonts
+    LocationPair(Vec<VariationSetting>, Vec<VariationSetting>),
     Default,
 }
 
+/// The most locations a sweep may expand to, across all its axes
+pub const MAX_SWEEP_LOCATIONS: usize = 1000;
+
+/// Check that an axis tag is one to four printable ASCII characters
+fn check_axis(axis: &str) -> Result<(), String> {
+    if axis.is_empty() || Tag::new_checked(axis.as_bytes()).is_err() {
+        return Err(format!("Invalid axis tag '{}'", axis));
+    }
+    Ok(())
+}
+
+/// Parse a location in the form `wght=400,wdth=87.5`
+///
+/// Whitespace around axes and values is ignored, and values may be
+/// negative or have a leading `+`. An empty string is the default
+/// location, and gives no settings.
 pub fn parse_location(variations: &str) -> Result<Vec<VariationSetting>, String> {
     let mut settings: Vec<VariationSetting> = vec![];
-    for variation in variations.split(',') {
-        let mut parts = variation.split('=');
-        let axis = parts.next().ok_or("Couldn't parse axis".to_string())?;
-        let value = parts.next().ok_or("Couldn't parse value".to_string())?;
+    for variation in variations.split(',').map(str::trim) {
+        if variation.is_empty() {
+            continue;
+        }
+        let (axis, value) = variation
+            .split_once('=')
+            .ok_or_else(|| format!("Expected axis=value, found '{}'", variation))?;
+        let (axis, value) = (axis.trim(), value.trim());
+        check_axis(axis)?;
         let value = value
             .parse::<f32>()
-            .map_err(|_| "Couldn't parse value".to_string())?;
+            .map_err(|_| format!("Couldn't parse value '{}' for axis '{}'", value, axis))?;
         settings.push((axis, value).into());
     }
     Ok(settings)
 }
 
+/// Parse a sweep across one or more axes, in the form
+/// `wght=100:900:100,wdth=75:100:25`, into every combination of the
+/// values from start to end (inclusive) in steps for each axis
+///
+/// A sweep which would give more than [MAX_SWEEP_LOCATIONS] locations is
+/// an error.
+pub fn parse_sweep(sweep: &str) -> Result<Vec<Vec<VariationSetting>>, String> {
+    let mut locations: Vec<Vec<VariationSetting>> = vec![vec![]];
+    for axis_sweep in sweep.split(',') {
+        let (axis, range) = axis_sweep
+            .split_once('=')
+            .ok_or("Couldn't parse axis".to_string())?;
+        let axis = axis.trim();
+        check_axis(axis)?;
+        let range = range
+            .split(':')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| "Couldn't parse value".to_string())?;
+        let [start, end, step] = range[..] else {
+            return Err(format!(
+                "Expected start:end:step for axis '{}', e.g. {}=100:900:100",
+                axis, axis
+            ));
+        };
+        if !(start.is_finite() && end.is_finite() && step.is_finite()) {
+            return Err(format!("Sweep for axis '{}' must have finite values", axis));
+        }
+        if step <= 0.0 || end < start {
+            return Err(format!(
+                "Sweep for axis '{}' must have a positive step and end after it starts",
+                axis
+            ));
+        }
+        // Multiply rather than accumulate, so that rounding errors don't
+        // skip the end value
+        let steps = ((end - start) as f64 / step as f64 + 0.001).floor();
+        if (steps + 1.0) * locations.len() as f64 > MAX_SWEEP_LOCATIONS as f64 {
+            return Err(format!(
+                "Sweep gives more than {} locations; use a larger step",
+                MAX_SWEEP_LOCATIONS
+            ));
+        }
+        let steps = steps as usize;
+        let values: Vec<f32> = (0..=steps).map(|i| start + step * i as f32).collect();
+        locations = locations
+            .into_iter()
+            .flat_map(|location| {
+                values.iter().map(move |value| {
+                    let mut location = location.clone();
+                    location.push((axis, *value).into());
+                    location
+                })
+            })
+            .collect();
+    }
+    Ok(locations)
+}
+
+/// A named comparison of a location in the old font with a location in
+/// the new font, as listed in a comparisons file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Comparison {
+    name: String,
+    location_a: String,
+    /// The same as `location_a` if not given
+    location_b: Option<String>,
+}
+
+/// Parse a comparisons file: a JSON array of objects with a `name`, a
+/// `location_a` for the old font and a `location_b` for the new font, each
+/// location in the form accepted by [parse_location]
+///
+/// Returns the setting for each comparison, with its name.
+pub fn parse_comparisons(json: &str) -> Result<Vec<(String, Setting)>, String> {
+    let comparisons: Vec<Comparison> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    comparisons
+        .into_iter()
+        .map(|comparison| {
+            let parse = |location: &str| {
+                parse_location(location).map_err(|e| {
+                    format!(
+                        "Error in location '{}' of comparison '{}': {}",
+                        location, comparison.name, e
+                    )
+                })
+            };
+            let location_a = parse(&comparison.location_a)?;
+            let location_b = match &comparison.location_b {
+                Some(location_b) => parse(location_b)?,
+                None => location_a.clone(),
+            };
+            Ok((
+                comparison.name,
+                Setting::from_settings(location_a, location_b),
+            ))
+        })
+        .collect()
+}
+
 impl Setting {
     pub fn from_instance(instance: String) -> Self {
         Setting::Instance(instance)
@@ -33,6 +158,12 @@ impl Setting {
     pub fn from_setting(location: Vec<VariationSetting>) -> Self {
         Setting::Location(location)
     }
+    pub fn from_settings(
+        location_a: Vec<VariationSetting>,
+        location_b: Vec<VariationSetting>,
+    ) -> Self {
+        Setting::LocationPair(location_a, location_b)
+    }
     pub fn set_on_fonts(&self, font_a: &mut DFont, font_b: &mut DFont) -> Result<(), String> {
         match self {
             Setting::Instance(inst) => {
@@ -43,12 +174,8 @@ impl Setting {
                     .set_instance(inst)
                     .map_err(|_e| format!("New font does not contain instance '{}'", inst))?;
             }
-            Setting::Location(loc) => {
-                font_a.location = loc.clone();
-                font_a.normalize_location();
-                font_b.location = loc.clone();
-                font_b.normalize_location();
-            }
+            Setting::Location(loc) => set_locations(font_a, loc, font_b, loc)?,
+            Setting::LocationPair(loc_a, loc_b) => set_locations(font_a, loc_a, font_b, loc_b)?,
             Setting::Default => {}
         }
         Ok(())
@@ -57,12 +184,94 @@ impl Setting {
     pub fn name(&self) -> String {
         match self {
             Setting::Instance(inst) => inst.clone(),
-            Setting::Location(loc) => loc
-                .iter()
-                .map(|vs| format!("{}={}", vs.selector, vs.value))
-                .collect::<Vec<String>>()
-                .join(","),
+            Setting::Location(loc) => location_name(loc),
+            Setting::LocationPair(loc_a, loc_b) => {
+                format!("{} vs {}", location_name(loc_a), location_name(loc_b))
+            }
             Setting::Default => "Default".to_string(),
         }
     }
 }
+
+/// Check each location against its font, then set it
+fn set_locations(
+    font_a: &mut DFont,
+    loc_a: &[VariationSetting],
+    font_b: &mut DFont,
+    loc_b: &[VariationSetting],
+) -> Result<(), String> {
+    font_a
+        .check_location(loc_a)
+        .map_err(|e| format!("Old font: {}", e))?;
+    font_b
+        .check_location(loc_b)
+        .map_err(|e| format!("New font: {}", e))?;
+    font_a.location = loc_a.to_vec();
+    font_a.normalize_location();
+    font_b.location = loc_b.to_vec();
+    font_b.normalize_location();
+    Ok(())
+}
+
+fn location_name(location: &[VariationSetting]) -> String {
+    location
+        .iter()
+        .map(|vs| format!("{}={}", vs.selector, vs.value))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        let location = parse_location(" wght = +400.5 , opsz=-12 ,").unwrap();
+        assert_eq!(location_name(&location), "wght=400.5,opsz=-12");
+        assert!(parse_location("").unwrap().is_empty());
+        assert_eq!(
+            parse_location("wght=bold").unwrap_err(),
+            "Couldn't parse value 'bold' for axis 'wght'"
+        );
+        assert_eq!(
+            parse_location("wght400").unwrap_err(),
+            "Expected axis=value, found 'wght400'"
+        );
+    }
+
+    #[test]
+    fn test_parse_sweep() {
+        let locations = parse_sweep("wght=100:300:100,wdth=75:100:25").unwrap();
+        let names: Vec<String> = locations
+            .iter()
+            .map(Vec::as_slice)
+            .map(location_name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "wght=100,wdth=75",
+                "wght=100,wdth=100",
+                "wght=200,wdth=75",
+                "wght=200,wdth=100",
+                "wght=300,wdth=75",
+                "wght=300,wdth=100",
+            ]
+        );
+        assert_eq!(
+            parse_sweep("wght=0:inf:100").unwrap_err(),
+            "Sweep for axis 'wght' must have finite values"
+        );
+        assert_eq!(
+            parse_sweep("wght=0:1000:0.0001").unwrap_err(),
+            "Sweep gives more than 1000 locations; use a larger step"
+        );
+        // Each axis is within the limit, but together they aren't
+        assert!(parse_sweep("wght=0:100:1,wdth=0:100:1").is_err());
+        assert_eq!(
+            parse_sweep("weight=100:900:100").unwrap_err(),
+            "Invalid axis tag 'weight'"
+        );
+    }
+}
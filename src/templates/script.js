@@ -1,41 +1,195 @@
 function diffTables_statichtml() {
   $("#difftable").empty();
+  // Style bit checks are shown alongside the OS/2 table diff too
+  if (report["style_bits"]) {
+    tables["OS/2"] = Object.assign(
+      Object.fromEntries(report["style_bits"].map((change) => [change, ""])),
+      tables["OS/2"]
+    );
+  }
   $("#difftable").append(`<h4 class="mt-2 box-title">Table-level details</h4>`);
+  let tables = Object.assign({}, report["tables"]);
+  // STAT changes are reported as a list of messages
+  if (report["stat"]) {
+    tables["STAT"] = Object.fromEntries(report["stat"].map((change) => [change, ""]));
+  }
+  // Default location changes are shown alongside the fvar table diff
+  if (report["default_instance"]) {
+    tables["fvar"] = Object.assign(
+      Object.fromEntries(report["default_instance"].map((change) => [change, ""])),
+      tables["fvar"]
+    );
+  }
+  // Glyph count changes are shown alongside the maxp table diff
+  if (report["glyph_count"]) {
+    let glyph_count = report["glyph_count"];
+    let changes = [`Glyph count: ${glyph_count.old} => ${glyph_count.new}`];
+    if (glyph_count.warning) {
+      changes.push(glyph_count.warning);
+    }
+    tables["maxp"] = Object.assign(
+      Object.fromEntries(changes.map((change) => [change, ""])),
+      tables["maxp"]
+    );
+  }
+  // The units per em change is shown first, as it affects how the other
+  // differences should be read
+  if (report["upm"]) {
+    let upm = report["upm"];
+    let changes = [`Units per em: ${upm.old} => ${upm.new}`, upm.warning];
+    tables = Object.assign(
+      { "Units per em": Object.fromEntries(changes.map((change) => [change, ""])) },
+      tables
+    );
+  }
+  if (report["names"]) {
+    tables["name"] = Object.fromEntries(report["names"].map((change) => [change, ""]));
+  }
+  // Unicode range checks are shown alongside the OS/2 table diff
+  if (report["unicode_ranges"]) {
+    tables["OS/2"] = Object.assign(
+      Object.fromEntries(report["unicode_ranges"].map((change) => [change, ""])),
+      tables["OS/2"]
+    );
+  }
+  $("#difftable").append(`
+    <div class="table-controls">
+      <input type="search" id="table-filter" placeholder="Filter tables">
+      <label><input type="checkbox" id="hide-unchanged"> Hide unchanged</label>
+      <button type="button" id="expand-tables">Expand all</button>
+      <button type="button" id="collapse-tables">Collapse all</button>
+    </div>`);
   $("#difftable").append(
-    renderTableDiff({ tables: report["tables"] }, true).children()
+    renderTableDiff({ tables: tables }, true).children()
   );
+  $("#difftable > .node").each(function () {
+    markTableNode(this);
+  });
   $("#difftable .node").on("click", function (e) {
-    $(this).toggleClass("closed open");
-    $(this).children(".node").toggle();
+    if ($(this).children(".node").length > 0) {
+      $(this).toggleClass("closed open");
+      updateTableTree();
+    }
     e.stopPropagation();
   });
+  $("#table-filter").on("input", updateTableTree);
+  $("#hide-unchanged").on("change", updateTableTree);
+  $("#expand-tables").on("click", function () {
+    $("#difftable .node.closed").toggleClass("closed open");
+    updateTableTree();
+  });
+  $("#collapse-tables").on("click", function () {
+    $("#difftable .node.open").toggleClass("closed open");
+    updateTableTree();
+  });
+}
+
+// Record the text each table diff node matches filters against, and mark
+// nodes with nothing changed beneath them. Returns whether the node has
+// changes.
+function markTableNode(node) {
+  let $node = $(node);
+  $node.data("text", $node.contents().not(".node").text().toLowerCase());
+  let children = $node.children(".node").toArray();
+  let changed;
+  if (children.length > 0) {
+    changed = children.map(markTableNode).some((c) => c);
+  } else {
+    // Leaves without a before/after pair are messages about a change
+    let after = $node.children(".attr-after");
+    changed = after.length == 0 || !after.hasClass("unchanged");
+  }
+  $node.toggleClass("unchanged", !changed);
+  return changed;
+}
+
+// Mark the nodes whose own text matches the filter, returning whether the
+// node or anything beneath it does
+function matchTableNode(node, filter) {
+  let $node = $(node);
+  let matches = filter != "" && $node.data("text").includes(filter);
+  let beneath = $node
+    .children(".node")
+    .toArray()
+    .map((child) => matchTableNode(child, filter))
+    .some((m) => m);
+  $node.toggleClass("match", matches);
+  $node.data("has-match", matches || beneath);
+  return matches || beneath;
+}
+
+// Show a node if its parent is open and it passes the filter: either it
+// or something beneath it matches, or it is beneath a match. Nodes leading
+// to a match are opened.
+function showTableNode(node, visible, filter, hideUnchanged, underMatch) {
+  let $node = $(node);
+  let show =
+    visible &&
+    !(hideUnchanged && $node.hasClass("unchanged")) &&
+    (filter == "" || underMatch || $node.data("has-match"));
+  $node.toggle(show);
+  if (filter != "" && $node.data("has-match") && $node.hasClass("closed")) {
+    $node.toggleClass("closed open");
+  }
+  let open = $node.hasClass("open");
+  $node.children(".node").each(function () {
+    showTableNode(this, show && open, filter, hideUnchanged, underMatch || $node.hasClass("match"));
+  });
+}
+
+function updateTableTree() {
+  let filter = ($("#table-filter").val() || "").toLowerCase();
+  let hideUnchanged = $("#hide-unchanged").is(":checked");
+  $("#difftable > .node").each(function () {
+    matchTableNode(this, filter);
+    showTableNode(this, true, filter, hideUnchanged, false);
+  });
 }
 
 function cmapDiff_static_html() {
-  if (report.cmap_diff && (report.cmap_diff.new || report.cmap_diff.missing)) {
+  if (report.cmap_diff && (report.cmap_diff.added_codepoints || report.cmap_diff.removed_codepoints || report.cmap_diff.remapped_glyphs)) {
 	$("#cmapdiff").append(`<h4 class="mt-2">Added and Removed Encoded Glyphs</h4>`);
-    if (report["cmap_diff"]["new"]) {
+    if (report["cmap_diff"]["added_codepoints"]) {
       $("#cmapdiff").append(`<h4 class="box-title">Added Glyphs</h4>`);
-      let added = $("<div>");
-      for (let glyph of report["cmap_diff"]["new"]) {
-        addAGlyph(glyph, added);
-      }
-      $("#cmapdiff").append(added);
+      $("#cmapdiff").append(encodedGlyphsByBlock(report["cmap_diff"]["added_codepoints"]));
     }
 
-    if (report["cmap_diff"]["missing"]) {
+    if (report["cmap_diff"]["removed_codepoints"]) {
       $("#cmapdiff").append(`<h4 class="box-title">Removed Glyphs</h4>`);
-      let missing = $("<div>");
-      for (let glyph of report["cmap_diff"]["missing"]) {
-        addAGlyph(glyph, missing);
+      $("#cmapdiff").append(encodedGlyphsByBlock(report["cmap_diff"]["removed_codepoints"]));
+    }
+
+    if (report["cmap_diff"]["remapped_glyphs"]) {
+      $("#cmapdiff").append(`<h4 class="box-title">Glyphs Mapped From Different Codepoints</h4>`);
+      let list = $("<ul>");
+      for (let remapped of report["cmap_diff"]["remapped_glyphs"]) {
+        let changes = (remapped.removed || []).map((cp) => `-${cp.character} (U+${cp.codepoint.toString(16).toUpperCase().padStart(4, "0")})`)
+          .concat((remapped.added || []).map((cp) => `+${cp.character} (U+${cp.codepoint.toString(16).toUpperCase().padStart(4, "0")})`));
+        list.append($("<li>").text(`${remapped.glyph}: ${changes.join(", ")}`));
       }
-      $("#cmapdiff").append(missing);
+      $("#cmapdiff").append(list);
     }
   } else {
     $("#cmapdiff").append(`<p>No changes to encoded glyphs</p>`);
   }
 }
 
+function encodedGlyphsByBlock(codepoints) {
+  let container = $("<div>");
+  let block = undefined;
+  let glyphs;
+  for (let cp of codepoints) {
+    if (cp.block !== block) {
+      block = cp.block;
+      container.append(`<h6 class="mt-2">${block || "No block"}</h6>`);
+      glyphs = $("<div>");
+      container.append(glyphs);
+    }
+    addAGlyph({ string: cp.character, name: cp.name }, glyphs);
+  }
+  return container;
+}
+
 function buildLocation_statichtml(loc) {
 	// Set font styles to appropriate axis locations
 	let rule = document.styleSheets[0].cssRules[2].style
@@ -50,6 +204,18 @@ function buildLocation_statichtml(loc) {
 		}).join(" ");
 		rule.setProperty("font-variation-settings", cssSetting)
 	}
+	// The new font may be at a different location to the old
+	let afterRule = document.styleSheets[0].cssRules[4].style
+	if (loc.coords_b) {
+		afterRule.setProperty("font-variation-settings", Object.entries(loc.coords_b).map(function ([axis, value]) {
+			return `"${axis}" ${value}`
+		}).join(", "));
+		textLocation += " vs " + Object.entries(loc.coords_b).map(function ([axis, value]) {
+			return `${axis}=${value}`
+		}).join(" ");
+	} else {
+		afterRule.removeProperty("font-variation-settings");
+	}
 
 	$("#main").empty();
 
@@ -64,16 +230,28 @@ function buildLocation_statichtml(loc) {
 		$("#main").append(glyphs);
 	}
 
+	if (loc.glyphs_timed_out) {
+		$("#main").append("<h6>Glyphs which timed out</h6>");
+		$("#main").append($("<p>").text(loc.glyphs_timed_out.join(" ")));
+	}
+
 	if (loc.words) {
 		$("#main").append("<h4>Modified Words</h4>");
 		for (let [script, words] of Object.entries(loc.words)) {
 			let scriptTitle = $(`<h6>${script}</h6>`);
 			$("#main").append(scriptTitle);
-			let worddiv = $("<div>");
-			for (let word of words) {
-				addAWord(word, worddiv);
+			// Grouped by kind of difference, or a single list
+			let groups = Array.isArray(words) ? { "": words } : words;
+			for (let [kind, differences] of Object.entries(groups)) {
+				if (kind) {
+					$("#main").append($(`<p class="text-muted mb-1">${kind} changes</p>`));
+				}
+				let worddiv = $("<div>");
+				for (let word of differences) {
+					addAWord(word, worddiv);
+				}
+				$("#main").append(worddiv);
 			}
-			$("#main").append(worddiv);
 		}
 	}
 	$('[data-toggle="tooltip"]').tooltip()
@@ -81,18 +259,22 @@ function buildLocation_statichtml(loc) {
 }
 
 $(function () {
-  if (report["tables"]) {
+  if (report["tables"] || report["stat"] || report["default_instance"] || report["glyph_count"] || report["upm"] || report["names"] || report["unicode_ranges"] || report["style_bits"]) {
     diffTables_statichtml();
   }
   cmapDiff_static_html();
   $('[data-toggle="tooltip"]').tooltip()
-  if (!report["locations"]) {
+  let locations = (report["locations"] || []).concat(
+    Object.values(report["comparisons"] || {}),
+    Object.values(report["instances"] || {})
+  );
+  if (!locations.length) {
 	$("#title").html("<h4 class='mt-2'>No differences found</h4>");
 	$("#ui-nav").hide();
 	return;
   }
 
-  for (var [index, loc] of report["locations"].entries()) {
+  for (var [index, loc] of locations.entries()) {
     var loc_nav = $(`<li class="nav-item">
 		<a class="nav-link text-secondary" href="#" data-index="${index}">${loc.location.replaceAll(',', ',\u200b')}</a>
 	</li>`);
@@ -101,7 +283,7 @@ $(function () {
   $("#locationnav li a").on("click", function (e) {
 	$("#locationnav li a").removeClass("active");
 	$(this).addClass("active");
-    buildLocation_statichtml(report.locations[$(this).data("index")]);
+    buildLocation_statichtml(locations[$(this).data("index")]);
   });
   $("#locationnav li a").eq(0).click();
 
@@ -117,11 +299,11 @@ $(function () {
 
   document.styleSheets[0].cssRules[0].style.setProperty(
     "src",
-    "url({{ old_filename }})"
+    "url({{ old_filename | safe }})"
   );
   document.styleSheets[0].cssRules[1].style.setProperty(
     "src",
-    "url({{ new_filename }})"
+    "url({{ new_filename | safe }})"
   );
 
   let animationHandle;
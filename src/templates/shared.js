@@ -10,6 +10,11 @@ function renderTableDiff(node, toplevel) {
 		var after = $("<span/>");
 		after.addClass("attr-after");
 		after.append(renderTableDiff(node[1], true).children());
+		// Values which differ only in ways that don't survive being
+		// displayed, such as tiny float differences
+		if (JSON.stringify(node[0]) === JSON.stringify(node[1])) {
+			after.addClass("unchanged");
+		}
 		wrapper.append(before);
 		wrapper.append(after);
 		return wrapper
@@ -38,15 +43,30 @@ function renderTableDiff(node, toplevel) {
 }
 
 
+// Show the images diffenator3 compared, if it wrote them, or else let
+// the browser render the text
+function renderedOrText(diff, text) {
+	if (!diff.images) {
+		return text;
+	}
+	let flicker = diff.flicker ? `<img class="image-flicker" src="${diff.flicker}" alt="${text}">` : "";
+	let overlay = diff.overlay ? `<img class="image-overlay" src="${diff.overlay}" alt="${text}">` : "";
+	return `<img class="image-before" src="${diff.images[0]}" alt="${text}">` +
+		`<img class="image-after" src="${diff.images[1]}" alt="${text}">` + flicker + overlay;
+}
+
 function addAGlyph(glyph, where) {
     let title = "";
     if (glyph.name) {
         title = "name: "+glyph.name;
     }
     let cp = "<br>U+"+glyph.string.charCodeAt(0).toString(16).padStart(4, '0').toUpperCase();
+    if (glyph.ppems) {
+        cp += `<br>${glyph.ppems.join(", ")} ppem`;
+    }
     where.append(`
         <div class="cell-glyph font-before">
-        ${glyph.string}
+        ${renderedOrText(glyph, glyph.string)}
         <div class="codepoint" data-toggle="tooltip" data-html="true" data-title="${title}">
 		${cp}
         </div>
@@ -54,14 +74,34 @@ function addAGlyph(glyph, where) {
     `);
 }
 
+// Wrap the clusters which shaped differently in highlighting spans
+function highlightClusters(diff) {
+	if (!diff.clusters) {
+		return diff.word;
+	}
+	let chars = Array.from(diff.word);
+	let html = "";
+	let last = 0;
+	for (let cluster of diff.clusters) {
+		html += chars.slice(last, cluster.start).join("");
+		html += `<span class="changed-cluster changed-${cluster.change}">${chars.slice(cluster.start, cluster.end).join("")}</span>`;
+		last = cluster.end;
+	}
+	return html + chars.slice(last).join("");
+}
+
 function addAWord(diff, where) {
 	if (!diff.buffer_b) {
 		diff.buffer_b = diff.buffer_a;
 	}
+	let clusters = (diff.clusters || []).map((c) => `${c.text}: ${c.change}`).join("<br>");
+	if (clusters) {
+		clusters = `<br>changed clusters:<br>${clusters}`;
+	}
 	where.append(`
-		<div class="cell-word font-before">
-		<span data-toggle="tooltip" data-html="true" data-title="Before: <pre>${diff.buffer_a}</pre>After: <pre>${diff.buffer_b}</pre><br>difference: ${Math.round(diff.percent*100)/100}%">
-		${diff.word}
+		<div class="cell-word font-before${diff.vertical ? " vertical" : ""}">
+		<span data-toggle="tooltip" data-html="true" data-title="Before: <pre>${diff.buffer_a}</pre>After: <pre>${diff.buffer_b}</pre><br>difference: ${Math.round(diff.percent*100)/100}%${clusters}">
+		${diff.images ? renderedOrText(diff, diff.word) : highlightClusters(diff)}
 		</span>
 		</div>
 	`);
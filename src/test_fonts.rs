@@ -0,0 +1,394 @@
+//! Fonts built in code, for tests
+//!
+//! Each test builds just the tables it needs; [font] assembles them into
+//! an SFNT font with [build_sfnt].
+
+use crate::woff::{build_sfnt, Table};
+
+/// Each value as a big-endian 16-bit word, negative values in two's
+/// complement
+pub(crate) fn be16(values: &[i32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| (*value as u16).to_be_bytes())
+        .collect()
+}
+
+/// Each value as a big-endian 32-bit word
+pub(crate) fn be32(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+/// Assemble a TrueType font from (tag, data) tables
+pub(crate) fn font<'a>(tables: impl IntoIterator<Item = (&'a [u8; 4], Vec<u8>)>) -> Vec<u8> {
+    sfnt(*b"\x00\x01\x00\x00", tables)
+}
+
+/// Assemble a font with the given SFNT version, such as `OTTO` for CFF
+/// outlines
+pub(crate) fn sfnt<'a>(
+    flavor: [u8; 4],
+    tables: impl IntoIterator<Item = (&'a [u8; 4], Vec<u8>)>,
+) -> Vec<u8> {
+    let tables = tables
+        .into_iter()
+        .map(|(tag, data)| Table { tag: *tag, data })
+        .collect();
+    build_sfnt(flavor, tables).unwrap()
+}
+
+/// A `cmap` with a Windows Unicode BMP (format 4) subtable mapping each
+/// (codepoint, glyph ID), with a segment for each mapping
+pub(crate) fn cmap(mappings: &[(u16, u16)]) -> Vec<u8> {
+    let mut mappings = mappings.to_vec();
+    mappings.sort();
+    let segments = mappings.len() as i32 + 1;
+    let entry_selector = segments.ilog2() as i32;
+    let search_range = 2 << entry_selector;
+    let ends: Vec<i32> = mappings
+        .iter()
+        .map(|(codepoint, _)| *codepoint as i32)
+        .chain([0xFFFF])
+        .collect();
+    let deltas: Vec<i32> = mappings
+        .iter()
+        .map(|(codepoint, gid)| *gid as i32 - *codepoint as i32)
+        .chain([1])
+        .collect();
+
+    let mut cmap = be16(&[0, 1, 3, 1]);
+    cmap.extend(be32(&[12]));
+    cmap.extend(be16(&[
+        4,
+        16 + 8 * segments,
+        0,
+        2 * segments,
+        search_range,
+        entry_selector,
+        2 * segments - search_range,
+    ]));
+    cmap.extend(be16(&ends));
+    cmap.extend(be16(&[0]));
+    cmap.extend(be16(&ends));
+    cmap.extend(be16(&deltas));
+    cmap.extend(be16(&vec![0; segments as usize]));
+    cmap
+}
+
+/// A version 1 `head` table with the given units per em and short `loca`
+/// offsets
+pub(crate) fn head(units_per_em: u16) -> Vec<u8> {
+    let mut head = vec![0; 54];
+    head[0..4].copy_from_slice(&0x00010000u32.to_be_bytes());
+    head[12..16].copy_from_slice(&0x5F0F3CF5u32.to_be_bytes());
+    head[18..20].copy_from_slice(&units_per_em.to_be_bytes());
+    head
+}
+
+/// A version 0.5 `maxp` table, as used with CFF outlines
+pub(crate) fn maxp(num_glyphs: u16) -> Vec<u8> {
+    let mut maxp = be32(&[0x00005000]);
+    maxp.extend(num_glyphs.to_be_bytes());
+    maxp
+}
+
+/// A `name` table with a Windows English record for each (name ID, string)
+pub(crate) fn name(records: &[(u16, &str)]) -> Vec<u8> {
+    let count = records.len() as i32;
+    let mut name = be16(&[0, count, 6 + 12 * count]);
+    let mut strings = vec![];
+    for (name_id, string) in records {
+        let string: Vec<u8> = string.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        name.extend(be16(&[3, 1, 0x409, *name_id as i32]));
+        name.extend(be16(&[string.len() as i32, strings.len() as i32]));
+        strings.extend(string);
+    }
+    name.extend(strings);
+    name
+}
+
+/// A version 2 `post` table naming each glyph in turn, with every name
+/// stored in the table rather than taken from the standard Macintosh names
+pub(crate) fn post(names: &[&str]) -> Vec<u8> {
+    let mut post = be32(&[0x00020000, 0, 0, 0, 0, 0, 0, 0]);
+    post.extend(be16(&[names.len() as i32]));
+    post.extend(be16(
+        &(0..names.len() as i32)
+            .map(|index| 258 + index)
+            .collect::<Vec<_>>(),
+    ));
+    for name in names {
+        post.push(name.len() as u8);
+        post.extend(name.as_bytes());
+    }
+    post
+}
+
+/// A version 1.0 `GSUB` or `GPOS` table with the given features, all on
+/// the default language system of the `DFLT` script, each of which uses
+/// every lookup
+///
+/// Each lookup is a (lookup type, subtables) pair.
+pub(crate) fn layout(features: &[&[u8; 4]], lookups: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+    let feature_count = features.len() as i32;
+    let lookup_count = lookups.len() as i32;
+
+    let mut script_list = be16(&[1]);
+    script_list.extend(b"DFLT");
+    script_list.extend(be16(&[8, 4, 0, 0, 0xFFFF, feature_count]));
+    script_list.extend(be16(&(0..feature_count).collect::<Vec<_>>()));
+
+    let mut feature_list = be16(&[feature_count]);
+    let feature_size = 4 + 2 * lookup_count;
+    for (index, tag) in features.iter().enumerate() {
+        feature_list.extend(*tag);
+        feature_list.extend(be16(&[2 + 6 * feature_count + index as i32 * feature_size]));
+    }
+    for _ in features {
+        feature_list.extend(be16(&[0, lookup_count]));
+        feature_list.extend(be16(&(0..lookup_count).collect::<Vec<_>>()));
+    }
+
+    let mut lookup_list = be16(&[lookup_count]);
+    let mut lookup_data = vec![];
+    for (lookup_type, subtables) in lookups {
+        lookup_list.extend(be16(&[2 + 2 * lookup_count + lookup_data.len() as i32]));
+        let mut offset = 6 + 2 * subtables.len() as i32;
+        lookup_data.extend(be16(&[*lookup_type as i32, 0, subtables.len() as i32]));
+        for subtable in subtables {
+            lookup_data.extend(be16(&[offset]));
+            offset += subtable.len() as i32;
+        }
+        for subtable in subtables {
+            lookup_data.extend(subtable);
+        }
+    }
+    lookup_list.extend(lookup_data);
+
+    let mut table = be16(&[1, 0, 10]);
+    table.extend(be16(&[10 + script_list.len() as i32]));
+    table.extend(be16(
+        &[10 + (script_list.len() + feature_list.len()) as i32],
+    ));
+    table.extend(script_list);
+    table.extend(feature_list);
+    table.extend(lookup_list);
+    table
+}
+
+/// A format 1 extension subtable (GSUB type 7 or GPOS type 9) wrapping a
+/// subtable of the given lookup type
+pub(crate) fn extension(lookup_type: u16, subtable: &[u8]) -> Vec<u8> {
+    let mut extension = be16(&[1, lookup_type as i32]);
+    extension.extend(be32(&[8]));
+    extension.extend(subtable);
+    extension
+}
+
+/// A format 1 coverage table of the given glyphs, which must be sorted
+pub(crate) fn coverage(glyphs: &[u16]) -> Vec<u8> {
+    let mut coverage = be16(&[1, glyphs.len() as i32]);
+    coverage.extend(be16(
+        &glyphs.iter().map(|gid| *gid as i32).collect::<Vec<_>>(),
+    ));
+    coverage
+}
+
+/// A format 2 class definition table giving each (glyph, class), in glyph
+/// order
+pub(crate) fn class_def(classes: &[(u16, u16)]) -> Vec<u8> {
+    let mut class_def = be16(&[2, classes.len() as i32]);
+    for (gid, class) in classes {
+        class_def.extend(be16(&[*gid as i32, *gid as i32, *class as i32]));
+    }
+    class_def
+}
+
+/// A version 1.0 `GDEF` table with the given glyph classes and mark
+/// attachment classes, each a list of (glyph, class)
+pub(crate) fn gdef(glyph_classes: &[(u16, u16)], mark_classes: &[(u16, u16)]) -> Vec<u8> {
+    let glyph_class_def = class_def(glyph_classes);
+    let mut gdef = be16(&[1, 0, 12, 0, 0, 12 + glyph_class_def.len() as i32]);
+    gdef.extend(glyph_class_def);
+    gdef.extend(class_def(mark_classes));
+    gdef
+}
+
+/// A version 1 `meta` table with a `dlng` (design languages) entry
+pub(crate) fn meta(design_languages: &str) -> Vec<u8> {
+    let mut meta = be32(&[1, 0, 0, 1]);
+    meta.extend(b"dlng");
+    meta.extend(be32(&[28, design_languages.len() as u32]));
+    meta.extend(design_languages.as_bytes());
+    meta
+}
+
+/// A version 1.1 `STAT` table with a `wght` axis and a format 1 axis
+/// value for each (value, name ID)
+pub(crate) fn stat(values: &[(i32, u16)]) -> Vec<u8> {
+    let count = values.len() as i32;
+    // One design axis record at 20, axis value offsets at 28
+    let mut stat = be16(&[1, 1, 8, 1]);
+    stat.extend(be32(&[20]));
+    stat.extend(be16(&[count]));
+    stat.extend(be32(&[28]));
+    stat.extend(be16(&[2]));
+    stat.extend(b"wght");
+    stat.extend(be16(&[256, 0]));
+    for index in 0..count {
+        stat.extend(be16(&[count * 2 + index * 12]));
+    }
+    for (value, name_id) in values {
+        stat.extend(be16(&[1, 0, 0, *name_id as i32]));
+        stat.extend(be32(&[(value << 16) as u32]));
+    }
+    stat
+}
+
+/// A version 0 `OS/2` table setting the given Unicode range bits and
+/// `fsSelection` flags, and zero elsewhere
+pub(crate) fn os2(unicode_range_bits: &[u32], fs_selection: u16) -> Vec<u8> {
+    let mut os2 = vec![0; 78];
+    for bit in unicode_range_bits {
+        let offset = 42 + (*bit / 32) as usize * 4;
+        let word = u32::from_be_bytes(os2[offset..offset + 4].try_into().unwrap());
+        os2[offset..offset + 4].copy_from_slice(&(word | 1 << (bit % 32)).to_be_bytes());
+    }
+    os2[62..64].copy_from_slice(&fs_selection.to_be_bytes());
+    os2
+}
+
+/// The tables of a TrueType font with a glyph for each (codepoint,
+/// contour), after `.notdef`, for tests to add to
+///
+/// Each glyph is one closed contour of on-curve points (or empty, if it
+/// has none), is named `uniXXXX` in the `post` table, and is advanced by
+/// half an em.
+pub(crate) fn outline_tables(
+    units_per_em: u16,
+    glyphs: &[(u16, &[(i32, i32)])],
+) -> Vec<(&'static [u8; 4], Vec<u8>)> {
+    let contours: Vec<&[(i32, i32)]> = [&[][..]]
+        .into_iter()
+        .chain(glyphs.iter().map(|(_, contour)| *contour))
+        .collect();
+    let names: Vec<String> = [".notdef".to_string()]
+        .into_iter()
+        .chain(
+            glyphs
+                .iter()
+                .map(|(codepoint, _)| format!("uni{:04X}", codepoint)),
+        )
+        .collect();
+    let mappings: Vec<(u16, u16)> = glyphs
+        .iter()
+        .enumerate()
+        .map(|(index, (codepoint, _))| (*codepoint, index as u16 + 1))
+        .collect();
+    let advance = units_per_em as i32 / 2;
+    let metrics: Vec<(i32, i32)> = contours
+        .iter()
+        .map(|contour| (advance, contour.iter().map(|(x, _)| *x).min().unwrap_or(0)))
+        .collect();
+    let max_points = contours.iter().map(|c| c.len()).max().unwrap_or(0) as i32;
+    let (glyf, loca) = glyf(&contours);
+
+    let mut maxp = be32(&[0x00010000]);
+    maxp.extend(be16(&[contours.len() as i32, max_points, 1, 0, 0, 2]));
+    maxp.extend(be16(&[0; 8]));
+    let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    vec![
+        (b"cmap", cmap(&mappings)),
+        (b"glyf", glyf),
+        (b"head", head(units_per_em)),
+        (
+            b"hhea",
+            hhea(
+                units_per_em as i32 * 4 / 5,
+                -(units_per_em as i32) / 5,
+                metrics.len(),
+            ),
+        ),
+        (b"hmtx", hmtx(&metrics)),
+        (b"loca", loca),
+        (b"maxp", maxp),
+        (b"post", post(&names)),
+    ]
+}
+
+/// A `glyf` table and short-offset `loca` table for glyphs which are
+/// each one closed contour of on-curve points, or empty
+pub(crate) fn glyf(contours: &[&[(i32, i32)]]) -> (Vec<u8>, Vec<u8>) {
+    let mut glyf = vec![];
+    let mut loca = be16(&[0]);
+    for contour in contours {
+        if !contour.is_empty() {
+            let xs: Vec<i32> = contour.iter().map(|(x, _)| *x).collect();
+            let ys: Vec<i32> = contour.iter().map(|(_, y)| *y).collect();
+            let bounds = [
+                xs.iter().min(),
+                ys.iter().min(),
+                xs.iter().max(),
+                ys.iter().max(),
+            ];
+            glyf.extend(be16(&[1]));
+            glyf.extend(be16(&bounds.map(|b| *b.unwrap())));
+            glyf.extend(be16(&[contour.len() as i32 - 1, 0]));
+            // On curve, with coordinates as 16-bit deltas
+            glyf.extend(vec![1; contour.len()]);
+            for coordinates in [xs, ys] {
+                let deltas: Vec<i32> = [0]
+                    .iter()
+                    .chain(&coordinates)
+                    .zip(&coordinates)
+                    .map(|(last, this)| this - last)
+                    .collect();
+                glyf.extend(be16(&deltas));
+            }
+            if glyf.len() % 2 == 1 {
+                glyf.push(0);
+            }
+        }
+        loca.extend(be16(&[glyf.len() as i32 / 2]));
+    }
+    (glyf, loca)
+}
+
+/// An `hhea` table with the given ascender, descender and number of
+/// horizontal metrics
+pub(crate) fn hhea(ascender: i32, descender: i32, num_h_metrics: usize) -> Vec<u8> {
+    let mut hhea = be32(&[0x00010000]);
+    hhea.extend(be16(&[ascender, descender, 0, 0, 0, 0, 0, 1, 0, 0]));
+    hhea.extend(be16(&[0, 0, 0, 0, 0, num_h_metrics as i32]));
+    hhea
+}
+
+/// An `hmtx` table with a (advance, left side bearing) for each glyph
+pub(crate) fn hmtx(metrics: &[(i32, i32)]) -> Vec<u8> {
+    metrics
+        .iter()
+        .flat_map(|(advance, lsb)| be16(&[*advance, *lsb]))
+        .collect()
+}
+
+/// An `fvar` table with each (tag, minimum, default, maximum) axis and
+/// each (subfamily name ID, coordinates) named instance
+pub(crate) fn fvar(axes: &[(&[u8; 4], f32, f32, f32)], instances: &[(u16, &[f32])]) -> Vec<u8> {
+    let fixed = |value: f32| (value * 65536.0).round() as i32 as u32;
+    let axis_count = axes.len() as i32;
+    let mut fvar = be16(&[1, 0, 16, 2, axis_count, 20]);
+    fvar.extend(be16(&[instances.len() as i32, 4 + 4 * axis_count]));
+    for (index, (tag, min, default, max)) in axes.iter().enumerate() {
+        fvar.extend(*tag);
+        fvar.extend(be32(&[fixed(*min), fixed(*default), fixed(*max)]));
+        fvar.extend(be16(&[0, 256 + index as i32]));
+    }
+    for (name_id, coords) in instances {
+        fvar.extend(be16(&[*name_id as i32, 0]));
+        fvar.extend(be32(&coords.iter().map(|c| fixed(*c)).collect::<Vec<_>>()));
+    }
+    fvar
+}
@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use read_fonts::{
+    tables::{
+        gpos::{
+            AnchorTable, ExtensionSubtable, MarkArray, MarkBasePosFormat1, MarkLigPosFormat1,
+            MarkMarkPosFormat1, PositionLookup,
+        },
+        layout::CoverageTable,
+    },
+    types::Offset16,
+    ArrayOfNullableOffsets, FontRef, ReadError, TableProvider,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::GlyphId;
+
+use super::{
+    glyph_name, glyph_names,
+    upm::{scale_units, upm_scale},
+};
+
+/// A mark attachment whose position differs between two fonts
+///
+/// Positions are the offset of the mark's anchor from the base's (or
+/// ligature component's, or base mark's) anchor, in font units; the new
+/// position is in the old font's units. A position is `None` if the mark
+/// doesn't attach to the base in that font.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AnchorDiff {
+    /// "mark-to-base", "mark-to-ligature" or "mark-to-mark"
+    pub kind: String,
+    /// The base glyph; ligature components are numbered, as "f_i[1]"
+    pub base: String,
+    pub mark: String,
+    pub old: Option<[i32; 2]>,
+    pub new: Option<[i32; 2]>,
+}
+
+/// The position of each mark attachment, keyed by kind, base and mark
+///
+/// Positions are kept as `i32`, as the difference between two anchors can
+/// be more than fits in the tables' 16 bits.
+pub type Attachments = BTreeMap<(&'static str, String, String), [i32; 2]>;
+
+fn point(anchor: Option<Result<AnchorTable<'_>, ReadError>>) -> Option<[i16; 2]> {
+    let anchor = anchor?.ok()?;
+    Some([anchor.x_coordinate(), anchor.y_coordinate()])
+}
+
+/// Each covered mark with its class and anchor
+fn marks(
+    coverage: Result<CoverageTable, ReadError>,
+    array: Result<MarkArray, ReadError>,
+) -> Vec<(GlyphId, u16, [i16; 2])> {
+    let (Ok(coverage), Ok(array)) = (coverage, array) else {
+        return vec![];
+    };
+    coverage
+        .iter()
+        .zip(array.mark_records())
+        .filter_map(|(glyph, record)| {
+            let anchor = point(Some(record.mark_anchor(array.offset_data())))?;
+            Some((glyph, record.mark_class(), anchor))
+        })
+        .collect()
+}
+
+/// Record where each mark attaches to a base, given the base's anchors
+/// by mark class
+///
+/// Within a lookup only the first subtable which covers a pair applies.
+fn attach<'a>(
+    attachments: &mut Attachments,
+    seen: &mut HashSet<(&'static str, String, String)>,
+    kind: &'static str,
+    base: String,
+    anchors: ArrayOfNullableOffsets<'a, AnchorTable<'a>, Offset16>,
+    marks: &[(GlyphId, u16, [i16; 2])],
+    names: &[String],
+) {
+    for (mark, class, mark_anchor) in marks {
+        let Some(base_anchor) = point(anchors.get(*class as usize)) else {
+            continue;
+        };
+        let key = (kind, base.clone(), glyph_name(names, *mark));
+        if seen.insert(key.clone()) {
+            attachments.insert(
+                key,
+                [
+                    i32::from(base_anchor[0]) - i32::from(mark_anchor[0]),
+                    i32::from(base_anchor[1]) - i32::from(mark_anchor[1]),
+                ],
+            );
+        }
+    }
+}
+
+/// A mark attachment subtable of any of the three kinds
+enum MarkSubtable<'a> {
+    Base(MarkBasePosFormat1<'a>),
+    Ligature(MarkLigPosFormat1<'a>),
+    Mark(MarkMarkPosFormat1<'a>),
+}
+
+/// The mark attachment subtables of a lookup, unwrapping them from an
+/// extension lookup
+fn mark_subtables<'a>(lookup: &PositionLookup<'a>) -> Vec<MarkSubtable<'a>> {
+    match lookup {
+        PositionLookup::MarkToBase(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .map(MarkSubtable::Base)
+            .collect(),
+        PositionLookup::MarkToLig(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .map(MarkSubtable::Ligature)
+            .collect(),
+        PositionLookup::MarkToMark(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .map(MarkSubtable::Mark)
+            .collect(),
+        PositionLookup::Extension(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .filter_map(|subtable| match subtable {
+                ExtensionSubtable::MarkToBase(extension) => {
+                    extension.extension().ok().map(MarkSubtable::Base)
+                }
+                ExtensionSubtable::MarkToLig(extension) => {
+                    extension.extension().ok().map(MarkSubtable::Ligature)
+                }
+                ExtensionSubtable::MarkToMark(extension) => {
+                    extension.extension().ok().map(MarkSubtable::Mark)
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Collect the mark attachments in a font's GPOS table
+///
+/// Walks the mark-to-base, mark-to-ligature and mark-to-mark lookups
+/// (types 4, 5 and 6), including those wrapped in extension lookups,
+/// keying each attachment by kind, base and mark glyph name. If several
+/// lookups attach the same mark to the same base, the last one applies.
+/// Anchors are taken at the default location; device tables and
+/// variations are ignored.
+pub fn mark_attachments(font: &FontRef) -> Attachments {
+    let mut attachments = Attachments::new();
+    let Ok(lookups) = font.gpos().and_then(|gpos| gpos.lookup_list()) else {
+        return attachments;
+    };
+    let names = glyph_names(font);
+    for lookup in lookups.lookups().iter().flatten() {
+        let mut seen = HashSet::new();
+        for subtable in mark_subtables(&lookup) {
+            match subtable {
+                MarkSubtable::Base(subtable) => {
+                    let marks = marks(subtable.mark_coverage(), subtable.mark_array());
+                    let (Ok(coverage), Ok(bases)) =
+                        (subtable.base_coverage(), subtable.base_array())
+                    else {
+                        continue;
+                    };
+                    for (base, record) in coverage.iter().zip(bases.base_records().iter()) {
+                        let Ok(record) = record else {
+                            continue;
+                        };
+                        attach(
+                            &mut attachments,
+                            &mut seen,
+                            "mark-to-base",
+                            glyph_name(&names, base),
+                            record.base_anchors(bases.offset_data()),
+                            &marks,
+                            &names,
+                        );
+                    }
+                }
+                MarkSubtable::Ligature(subtable) => {
+                    let marks = marks(subtable.mark_coverage(), subtable.mark_array());
+                    let (Ok(coverage), Ok(ligatures)) =
+                        (subtable.ligature_coverage(), subtable.ligature_array())
+                    else {
+                        continue;
+                    };
+                    for (ligature, attach_table) in
+                        coverage.iter().zip(ligatures.ligature_attaches().iter())
+                    {
+                        let Ok(attach_table) = attach_table else {
+                            continue;
+                        };
+                        let name = glyph_name(&names, ligature);
+                        for (component, record) in
+                            attach_table.component_records().iter().enumerate()
+                        {
+                            let Ok(record) = record else {
+                                continue;
+                            };
+                            attach(
+                                &mut attachments,
+                                &mut seen,
+                                "mark-to-ligature",
+                                format!("{}[{}]", name, component),
+                                record.ligature_anchors(attach_table.offset_data()),
+                                &marks,
+                                &names,
+                            );
+                        }
+                    }
+                }
+                MarkSubtable::Mark(subtable) => {
+                    let marks = marks(subtable.mark1_coverage(), subtable.mark1_array());
+                    let (Ok(coverage), Ok(bases)) =
+                        (subtable.mark2_coverage(), subtable.mark2_array())
+                    else {
+                        continue;
+                    };
+                    for (base, record) in coverage.iter().zip(bases.mark2_records().iter()) {
+                        let Ok(record) = record else {
+                            continue;
+                        };
+                        attach(
+                            &mut attachments,
+                            &mut seen,
+                            "mark-to-mark",
+                            glyph_name(&names, base),
+                            record.mark2_anchors(bases.offset_data()),
+                            &marks,
+                            &names,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    attachments
+}
+
+/// Report the mark attachments whose position differs between two fonts
+///
+/// An attachment found in only one font is reported only if both its
+/// glyphs are in the other font, as added and removed glyphs are reported
+/// elsewhere. If the fonts' units per em differ, the new font's positions
+/// are scaled to the old font's units first.
+pub fn anchor_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<AnchorDiff> {
+    let scale = upm_scale(font_a, font_b);
+    let attachments_a = mark_attachments(font_a);
+    let attachments_b = mark_attachments(font_b);
+    let glyphs_a: HashSet<String> = glyph_names(font_a).into_iter().collect();
+    let glyphs_b: HashSet<String> = glyph_names(font_b).into_iter().collect();
+    let base_glyph = |base: &str| base.split('[').next().unwrap_or(base).to_string();
+    let all: BTreeSet<&(&str, String, String)> =
+        attachments_a.keys().chain(attachments_b.keys()).collect();
+    all.into_iter()
+        .filter_map(|key| {
+            let (kind, base, mark) = key;
+            let old = attachments_a.get(key).copied();
+            let new = attachments_b
+                .get(key)
+                .map(|offset| offset.map(|v| scale_units(v, scale)));
+            if old == new {
+                return None;
+            }
+            let other = if old.is_none() { &glyphs_a } else { &glyphs_b };
+            if (old.is_none() || new.is_none())
+                && !(other.contains(&base_glyph(base)) && other.contains(mark))
+            {
+                return None;
+            }
+            Some(AnchorDiff {
+                kind: kind.to_string(),
+                base: base.clone(),
+                mark: mark.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, coverage, extension, font, layout, maxp, post};
+
+    const NAMES: [&str; 5] = [".notdef", "A", "B", "acutecomb", "gravecomb"];
+
+    /// A mark-to-base subtable attaching the two combining marks, anchored
+    /// at (0, 500), to each base glyph at its anchor
+    fn mark_base_subtable(bases: &[(u16, [i32; 2])]) -> Vec<u8> {
+        let base_glyphs: Vec<u16> = bases.iter().map(|(glyph, _)| *glyph).collect();
+        let (mark_coverage, base_coverage) = (coverage(&[3, 4]), coverage(&base_glyphs));
+        let mut mark_array = be16(&[2, 0, 10, 0, 16]);
+        mark_array.extend(be16(&[1, 0, 500, 1, 0, 500]));
+        let count = bases.len() as i32;
+        let mut base_array = be16(&[count]);
+        base_array.extend(be16(
+            &(0..count)
+                .map(|i| 2 + 2 * count + 6 * i)
+                .collect::<Vec<_>>(),
+        ));
+        for (_, [x, y]) in bases {
+            base_array.extend(be16(&[1, *x, *y]));
+        }
+
+        let mark_array_offset = 12 + (mark_coverage.len() + base_coverage.len()) as i32;
+        let mut subtable = be16(&[1, 12, 12 + mark_coverage.len() as i32, 1]);
+        subtable.extend(be16(&[
+            mark_array_offset,
+            mark_array_offset + mark_array.len() as i32,
+        ]));
+        subtable.extend(mark_coverage);
+        subtable.extend(base_coverage);
+        subtable.extend(mark_array);
+        subtable.extend(base_array);
+        subtable
+    }
+
+    /// A font whose GPOS has the given mark lookup
+    fn gpos_font(lookup: (u16, Vec<Vec<u8>>)) -> Vec<u8> {
+        font([
+            (b"GPOS", layout(&[b"mark"], &[lookup])),
+            (b"maxp", maxp(NAMES.len() as u16)),
+            (b"post", post(&NAMES)),
+        ])
+    }
+
+    #[test]
+    fn test_anchor_diff() {
+        let old = gpos_font((
+            4,
+            vec![mark_base_subtable(&[(1, [250, 700]), (2, [300, 700])])],
+        ));
+        let new = gpos_font((4, vec![mark_base_subtable(&[(1, [260, 700])])]));
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert_eq!(
+            mark_attachments(&old).get(&("mark-to-base", "B".to_string(), "acutecomb".to_string())),
+            Some(&[300, 200])
+        );
+        assert!(anchor_diff(&old, &old).is_empty());
+
+        let diffs: Vec<_> = anchor_diff(&old, &new)
+            .into_iter()
+            .map(|diff| (diff.base, diff.mark, diff.old, diff.new))
+            .collect();
+        let attachment =
+            |base: &str, mark: &str, old, new| (base.to_string(), mark.to_string(), old, new);
+        assert_eq!(
+            diffs,
+            vec![
+                attachment("A", "acutecomb", Some([250, 200]), Some([260, 200])),
+                attachment("A", "gravecomb", Some([250, 200]), Some([260, 200])),
+                // B is still in the new font, but marks no longer attach to it
+                attachment("B", "acutecomb", Some([300, 200]), None),
+                attachment("B", "gravecomb", Some([300, 200]), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extension_lookup() {
+        // A mark lookup wrapped in an extension lookup is walked like any
+        // other
+        let wrapped = gpos_font((
+            9,
+            vec![extension(4, &mark_base_subtable(&[(1, [250, 700])]))],
+        ));
+        let unwrapped = gpos_font((4, vec![mark_base_subtable(&[(1, [250, 700])])]));
+        let (wrapped, unwrapped) = (
+            FontRef::new(&wrapped).unwrap(),
+            FontRef::new(&unwrapped).unwrap(),
+        );
+        assert_eq!(mark_attachments(&wrapped).len(), 2);
+        assert_eq!(mark_attachments(&wrapped), mark_attachments(&unwrapped));
+    }
+
+    #[test]
+    fn test_offset_beyond_16_bits() {
+        // The offset from the mark's anchor is more than an i16 can hold,
+        // and isn't clamped
+        let data = gpos_font((4, vec![mark_base_subtable(&[(1, [250, -32768])])]));
+        let font = FontRef::new(&data).unwrap();
+        assert_eq!(
+            mark_attachments(&font).get(&(
+                "mark-to-base",
+                "A".to_string(),
+                "acutecomb".to_string()
+            )),
+            Some(&[250, -33268])
+        );
+    }
+}
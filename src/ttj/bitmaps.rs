@@ -0,0 +1,165 @@
+use read_fonts::{tables::bitmap::BitmapSize, FontData, ReadError, TableProvider};
+use serde_json::{json, Map, Value};
+use skrifa::Tag;
+
+/// Serialize a bitmap location table (`EBLC` or `CBLC`) as a map from each
+/// strike, named by its size and bit depth, to the number of glyphs it has
+/// bitmaps for and its line metrics
+///
+/// Added and removed strikes, and glyphs added to or removed from a strike,
+/// then show up in the table diff.
+fn serialize_strikes(data: FontData, sizes: &[BitmapSize]) -> Result<Value, ReadError> {
+    let mut map = Map::new();
+    for size in sizes {
+        let key = format!(
+            "{}x{} ppem, {} bpp",
+            size.ppem_x(),
+            size.ppem_y(),
+            size.bit_depth()
+        );
+        map.insert(
+            key,
+            json!({
+                "glyphs": strike_glyph_count(data, size)?,
+                "ascender": size.hori().ascender(),
+                "descender": size.hori().descender(),
+            }),
+        );
+    }
+    Ok(Value::Object(map))
+}
+
+/// The number of glyphs which have bitmaps in a strike
+///
+/// Formats 1 and 3 cover a range of glyphs but some may have no image
+/// data, so only those with data are counted.
+fn strike_glyph_count(data: FontData, size: &BitmapSize) -> Result<usize, ReadError> {
+    let array_offset = size.index_subtable_array_offset() as usize;
+    let mut count = 0;
+    for index in 0..size.number_of_index_subtables() as usize {
+        let record = array_offset + index * 8;
+        let first: u16 = data.read_at(record)?;
+        let last: u16 = data.read_at(record + 2)?;
+        let subtable = array_offset + data.read_at::<u32>(record + 4)? as usize;
+        let glyphs = last.saturating_sub(first) as usize + 1;
+        let index_format: u16 = data.read_at(subtable)?;
+        count += match index_format {
+            1 => (0..glyphs)
+                .map(|i| {
+                    let offset = subtable + 8 + i * 4;
+                    Ok(data.read_at::<u32>(offset + 4)? > data.read_at::<u32>(offset)?)
+                })
+                .collect::<Result<Vec<bool>, ReadError>>()?
+                .into_iter()
+                .filter(|has_data| *has_data)
+                .count(),
+            3 => (0..glyphs)
+                .map(|i| {
+                    let offset = subtable + 8 + i * 2;
+                    Ok(data.read_at::<u16>(offset + 2)? > data.read_at::<u16>(offset)?)
+                })
+                .collect::<Result<Vec<bool>, ReadError>>()?
+                .into_iter()
+                .filter(|has_data| *has_data)
+                .count(),
+            2 => glyphs,
+            4 => data.read_at::<u32>(subtable + 8)? as usize,
+            5 => data.read_at::<u32>(subtable + 20)? as usize,
+            _ => return Err(ReadError::InvalidFormat(index_format as i64)),
+        };
+    }
+    Ok(count)
+}
+
+pub(crate) fn serialize_eblc_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let eblc = font.eblc()?;
+    serialize_strikes(eblc.offset_data(), eblc.bitmap_sizes())
+}
+
+pub(crate) fn serialize_cblc_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let cblc = font.cblc()?;
+    serialize_strikes(cblc.offset_data(), cblc.bitmap_sizes())
+}
+
+/// Serialize a bitmap data table (`EBDT` or `CBDT`) as its version, length
+/// and checksum, rather than as its bytes, so that a change to the images
+/// is shown without the diff being swamped
+pub(crate) fn serialize_bitmap_data_table<'a>(
+    font: &impl TableProvider<'a>,
+    tag: Tag,
+) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(tag)?;
+    let major: u16 = data.read_at(0)?;
+    let minor: u16 = data.read_at(2)?;
+    let checksum = data
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0u32, |sum, word| sum.wrapping_add(word));
+    Ok(json!({
+        "version": format!("{}.{}", major, minor),
+        "length": data.len(),
+        "checksum": format!("0x{:08X}", checksum),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use read_fonts::FontRef;
+    use serde_json::{json, Value};
+
+    use crate::{
+        test_fonts::{be16, be32, font},
+        ttj::table_diff,
+    };
+
+    /// A font with a one-strike `EBLC` table, whose index subtable (of the
+    /// given format, 1 or 3) has these image data offsets for glyphs 1 to
+    /// 3, and an `EBDT` table holding `image_data`
+    fn bitmap_font(ascender: u8, index_format: u16, offsets: &[u32], image_data: &[u8]) -> Vec<u8> {
+        let mut eblc = be16(&[2, 0]);
+        eblc.extend(be32(&[1]));
+        // The strike's BitmapSize record, with its index subtable array
+        // straight after it
+        let subtable_size = 8 + offsets.len() as u32 * if index_format == 1 { 4 } else { 2 };
+        eblc.extend(be32(&[56, 8 + subtable_size, 1, 0]));
+        eblc.extend([ascender, (-2i8) as u8]);
+        eblc.extend([0; 22]);
+        eblc.extend(be16(&[1, 3]));
+        eblc.extend([12, 12, 1, 1]);
+        eblc.extend(be16(&[1, 3]));
+        eblc.extend(be32(&[8]));
+        eblc.extend(be16(&[index_format as i32, 1]));
+        eblc.extend(be32(&[4]));
+        for offset in offsets {
+            match index_format {
+                1 => eblc.extend(be32(&[*offset])),
+                _ => eblc.extend(be16(&[*offset as i32])),
+            }
+        }
+        let mut ebdt = be16(&[2, 0]);
+        ebdt.extend(image_data);
+        font([(b"EBLC", eblc), (b"EBDT", ebdt)])
+    }
+
+    #[test]
+    fn test_bitmap_diff() {
+        // Glyph 2 has no image in the old font
+        let old = bitmap_font(10, 1, &[0, 5, 5, 10], &[0xAA; 10]);
+        let same = bitmap_font(10, 3, &[0, 5, 5, 10], &[0xAA; 10]);
+        let new = bitmap_font(11, 3, &[0, 5, 10, 15], &[0xAA; 15]);
+        let old = FontRef::new(&old).unwrap();
+        assert_eq!(table_diff(&old, &FontRef::new(&same).unwrap()), Value::Null);
+        assert_eq!(
+            table_diff(&old, &FontRef::new(&new).unwrap()),
+            json!({
+                "EBDT": {"length": [14, 19], "checksum": ["0x00015554", "0xAAACA9FE"]},
+                "EBLC": {"12x12 ppem, 1 bpp": {"glyphs": [2, 3], "ascender": [10, 11]}},
+            })
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use read_fonts::{
+    tables::{
+        postscript::{
+            dict::{self, Entry},
+            BlendState, Index, StringId,
+        },
+        variations::ItemVariationStore,
+    },
+    types::Fixed,
+    FontData, FontRead, FontRef, ReadError, TableProvider,
+};
+use serde_json::{json, Map, Value};
+use skrifa::{
+    instance::{LocationRef, Size},
+    outline::{DrawSettings, OutlinePen},
+    GlyphId, MetadataProvider,
+};
+
+use super::gid_to_name;
+
+/// A pen which records an outline as an SVG-style path, in font units
+#[derive(Default)]
+struct PathPen(String);
+
+impl OutlinePen for PathPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.push_str(&format!("M{} {}", x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.push_str(&format!("L{} {}", x, y));
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.0.push_str(&format!("Q{} {} {} {}", cx0, cy0, x, y));
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        self.0
+            .push_str(&format!("C{} {} {} {} {} {}", cx0, cy0, cx1, cy1, x, y));
+    }
+
+    fn close(&mut self) {
+        self.0.push('Z');
+    }
+}
+
+/// The outline of each glyph at the default location, keyed by glyph name
+fn glyph_paths(font: &FontRef) -> Value {
+    let outlines = font.outline_glyphs();
+    let glyph_count = font.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let mut map = Map::new();
+    for gid in 0..glyph_count {
+        let glyph_id = GlyphId::new(gid);
+        let mut pen = PathPen::default();
+        let path = match outlines.get(glyph_id).map(|glyph| {
+            glyph.draw(
+                DrawSettings::unhinted(Size::unscaled(), LocationRef::default()),
+                &mut pen,
+            )
+        }) {
+            Some(Ok(_)) => Value::String(pen.0),
+            _ => Value::String("Could not draw".to_string()),
+        };
+        map.insert(gid_to_name(font, glyph_id), path);
+    }
+    Value::Object(map)
+}
+
+fn fixed(value: Fixed) -> Value {
+    json!(value.to_f64())
+}
+
+fn fixeds(values: &[Fixed]) -> Value {
+    Value::Array(values.iter().copied().map(fixed).collect())
+}
+
+/// Convert a DICT entry to a key and value, resolving string IDs with
+/// `string`
+///
+/// Offsets into the table are left out, as they change whenever anything
+/// before them changes; the data they point to is serialized separately.
+fn entry_to_json(
+    entry: Entry,
+    string: &dyn Fn(StringId) -> Value,
+) -> Option<(&'static str, Value)> {
+    Some(match entry {
+        Entry::Version(id) => ("version", string(id)),
+        Entry::Notice(id) => ("notice", string(id)),
+        Entry::FullName(id) => ("full_name", string(id)),
+        Entry::FamilyName(id) => ("family_name", string(id)),
+        Entry::Weight(id) => ("weight", string(id)),
+        Entry::Copyright(id) => ("copyright", string(id)),
+        Entry::PostScript(id) => ("postscript", string(id)),
+        Entry::BaseFontName(id) => ("base_font_name", string(id)),
+        Entry::FontName(id) => ("font_name", string(id)),
+        Entry::FontBbox(bbox) => ("font_bbox", fixeds(&bbox)),
+        Entry::FontMatrix(matrix) => ("font_matrix", fixeds(&matrix)),
+        Entry::IsFixedPitch(value) => ("is_fixed_pitch", json!(value)),
+        Entry::ItalicAngle(value) => ("italic_angle", fixed(value)),
+        Entry::UnderlinePosition(value) => ("underline_position", fixed(value)),
+        Entry::UnderlineThickness(value) => ("underline_thickness", fixed(value)),
+        Entry::PaintType(value) => ("paint_type", json!(value)),
+        Entry::CharstringType(value) => ("charstring_type", json!(value)),
+        Entry::StrokeWidth(value) => ("stroke_width", fixed(value)),
+        Entry::UniqueId(value) => ("unique_id", json!(value)),
+        Entry::SyntheticBase(value) => ("synthetic_base", json!(value)),
+        Entry::Ros {
+            registry,
+            ordering,
+            supplement,
+        } => (
+            "ros",
+            json!([string(registry), string(ordering), supplement.to_f64()]),
+        ),
+        Entry::CidFontVersion(value) => ("cid_font_version", fixed(value)),
+        Entry::CidFontRevision(value) => ("cid_font_revision", fixed(value)),
+        Entry::CidFontType(value) => ("cid_font_type", json!(value)),
+        Entry::CidCount(value) => ("cid_count", json!(value)),
+        Entry::UidBase(value) => ("uid_base", json!(value)),
+        Entry::BlueValues(blues) => ("blue_values", blues_to_json(blues.values())),
+        Entry::OtherBlues(blues) => ("other_blues", blues_to_json(blues.values())),
+        Entry::FamilyBlues(blues) => ("family_blues", blues_to_json(blues.values())),
+        Entry::FamilyOtherBlues(blues) => ("family_other_blues", blues_to_json(blues.values())),
+        Entry::BlueScale(value) => ("blue_scale", fixed(value)),
+        Entry::BlueShift(value) => ("blue_shift", fixed(value)),
+        Entry::BlueFuzz(value) => ("blue_fuzz", fixed(value)),
+        Entry::StdHw(value) => ("std_hw", fixed(value)),
+        Entry::StdVw(value) => ("std_vw", fixed(value)),
+        Entry::StemSnapH(snaps) => ("stem_snap_h", fixeds(snaps.values())),
+        Entry::StemSnapV(snaps) => ("stem_snap_v", fixeds(snaps.values())),
+        Entry::ForceBold(value) => ("force_bold", json!(value)),
+        Entry::LanguageGroup(value) => ("language_group", json!(value)),
+        Entry::ExpansionFactor(value) => ("expansion_factor", fixed(value)),
+        Entry::InitialRandomSeed(value) => ("initial_random_seed", json!(value)),
+        Entry::DefaultWidthX(value) => ("default_width_x", fixed(value)),
+        Entry::NominalWidthX(value) => ("nominal_width_x", fixed(value)),
+        Entry::VariationStoreIndex(value) => ("variation_store_index", json!(value)),
+        _ => return None,
+    })
+}
+
+fn blues_to_json(blues: &[(Fixed, Fixed)]) -> Value {
+    Value::Array(
+        blues
+            .iter()
+            .map(|(bottom, top)| json!([bottom.to_f64(), top.to_f64()]))
+            .collect(),
+    )
+}
+
+/// Where the parts of a (CFF or CFF2) font referenced from its top DICT
+/// are found
+#[derive(Default)]
+struct Offsets {
+    charstrings: Option<usize>,
+    private_dict: Option<std::ops::Range<usize>>,
+    subrs: Option<usize>,
+    fd_array: Option<usize>,
+    variation_store: Option<usize>,
+}
+
+impl Offsets {
+    fn record(&mut self, entry: &Entry) {
+        match entry {
+            Entry::CharstringsOffset(offset) => self.charstrings = Some(*offset),
+            Entry::PrivateDictRange(range) => self.private_dict = Some(range.clone()),
+            Entry::SubrsOffset(offset) => self.subrs = Some(*offset),
+            Entry::FdArrayOffset(offset) => self.fd_array = Some(*offset),
+            Entry::VariationStoreOffset(offset) => self.variation_store = Some(*offset),
+            _ => {}
+        }
+    }
+}
+
+/// Serialize a DICT, returning it along with the offsets it contains
+fn serialize_dict(
+    data: &[u8],
+    blend_state: Option<BlendState>,
+    string: &dyn Fn(StringId) -> Value,
+) -> (Map<String, Value>, Offsets) {
+    let mut map = Map::new();
+    let mut offsets = Offsets::default();
+    for entry in dict::entries(data, blend_state).filter_map(Result::ok) {
+        offsets.record(&entry);
+        if let Some((key, value)) = entry_to_json(entry, string) {
+            map.insert(key.to_string(), value);
+        }
+    }
+    (map, offsets)
+}
+
+/// The number of entries in the INDEX at `offset`
+fn index_count(table: &[u8], offset: usize, is_cff2: bool) -> Value {
+    table
+        .get(offset..)
+        .and_then(|data| Index::new(data, is_cff2).ok())
+        .map_or(Value::Null, |index| json!(index.count()))
+}
+
+/// Serialize a Private DICT, along with the number of local subroutines
+fn serialize_private_dict(
+    table: &[u8],
+    range: std::ops::Range<usize>,
+    variation_store: Option<&ItemVariationStore>,
+    is_cff2: bool,
+    string: &dyn Fn(StringId) -> Value,
+) -> Value {
+    let Some(data) = table.get(range.clone()) else {
+        return Value::Null;
+    };
+    // Blends are resolved at the default location
+    let blend_state = variation_store.and_then(|store| BlendState::new(store.clone(), &[], 0).ok());
+    let (mut map, offsets) = serialize_dict(data, blend_state, string);
+    if let Some(subrs) = offsets.subrs {
+        map.insert(
+            "local_subrs".to_string(),
+            index_count(table, range.start + subrs, is_cff2),
+        );
+    }
+    Value::Object(map)
+}
+
+/// Serialize each font DICT in the FDArray of a CID-keyed CFF font or a
+/// CFF2 font, with its Private DICT
+fn serialize_fd_array(
+    table: &[u8],
+    offset: usize,
+    variation_store: Option<&ItemVariationStore>,
+    is_cff2: bool,
+    string: &dyn Fn(StringId) -> Value,
+) -> Value {
+    let Some(fd_array) = table
+        .get(offset..)
+        .and_then(|data| Index::new(data, is_cff2).ok())
+    else {
+        return Value::Null;
+    };
+    let font_dicts = (0..fd_array.count() as usize)
+        .filter_map(|ix| fd_array.get(ix).ok())
+        .map(|data| {
+            let (mut map, offsets) = serialize_dict(data, None, string);
+            if let Some(range) = offsets.private_dict {
+                map.insert(
+                    "private".to_string(),
+                    serialize_private_dict(table, range, variation_store, is_cff2, string),
+                );
+            }
+            Value::Object(map)
+        })
+        .collect();
+    Value::Array(font_dicts)
+}
+
+/// Serialize the `CFF ` table
+///
+/// The top DICT and Private DICTs are serialized with their string IDs
+/// resolved, along with the number of charstrings and subroutines, and
+/// each glyph's outline as a path.
+pub fn serialize_cff_table(font: &FontRef) -> Result<Value, ReadError> {
+    let cff = font.cff()?;
+    let table = cff.offset_data().as_bytes();
+    let string = |id: StringId| {
+        cff.string(id)
+            .map_or(Value::Null, |s| Value::String(s.to_string()))
+    };
+    let mut map = Map::new();
+    map.insert(
+        "version".to_string(),
+        json!(format!("{}.{}", cff.header().major(), cff.header().minor())),
+    );
+    if let Some(name) = cff.name(0) {
+        map.insert("font_name".to_string(), json!(name.to_string()));
+    }
+    let top_dict = cff
+        .top_dicts()
+        .get(0)
+        .map_err(|_| ReadError::MalformedData("CFF table has no top DICT"))?;
+    let (top_dict, offsets) = serialize_dict(top_dict, None, &string);
+    map.insert("top_dict".to_string(), Value::Object(top_dict));
+    if let Some(range) = offsets.private_dict {
+        map.insert(
+            "private_dict".to_string(),
+            serialize_private_dict(table, range, None, false, &string),
+        );
+    }
+    if let Some(fd_array) = offsets.fd_array {
+        map.insert(
+            "font_dicts".to_string(),
+            serialize_fd_array(table, fd_array, None, false, &string),
+        );
+    }
+    map.insert(
+        "global_subrs".to_string(),
+        json!(cff.global_subrs().count()),
+    );
+    if let Some(charstrings) = offsets.charstrings {
+        map.insert(
+            "charstrings".to_string(),
+            index_count(table, charstrings, false),
+        );
+    }
+    map.insert("glyphs".to_string(), glyph_paths(font));
+    Ok(Value::Object(map))
+}
+
+/// Serialize the `CFF2` table
+///
+/// As for `CFF `, but values which vary are given at the default
+/// location, as are the glyph outlines.
+pub fn serialize_cff2_table(font: &FontRef) -> Result<Value, ReadError> {
+    let cff2 = font.cff2()?;
+    let table = cff2.offset_data().as_bytes();
+    // CFF2 has no string INDEX
+    let string = |_: StringId| Value::Null;
+    let mut map = Map::new();
+    map.insert(
+        "version".to_string(),
+        json!(format!(
+            "{}.{}",
+            cff2.header().major_version(),
+            cff2.header().minor_version()
+        )),
+    );
+    let (top_dict, offsets) = serialize_dict(cff2.top_dict_data(), None, &string);
+    map.insert("top_dict".to_string(), Value::Object(top_dict));
+    // The variation store is preceded by its length
+    let variation_store = offsets.variation_store.and_then(|offset| {
+        let data = table.get(offset + 2..)?;
+        ItemVariationStore::read(FontData::new(data)).ok()
+    });
+    if let Some(fd_array) = offsets.fd_array {
+        map.insert(
+            "font_dicts".to_string(),
+            serialize_fd_array(table, fd_array, variation_store.as_ref(), true, &string),
+        );
+    }
+    map.insert(
+        "global_subrs".to_string(),
+        json!(cff2.global_subrs().count()),
+    );
+    if let Some(charstrings) = offsets.charstrings {
+        map.insert(
+            "charstrings".to_string(),
+            index_count(table, charstrings, true),
+        );
+    }
+    map.insert("glyphs".to_string(), glyph_paths(font));
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{head, maxp, sfnt};
+
+    /// An INDEX of `items`, with one-byte offsets
+    fn index(items: &[&[u8]]) -> Vec<u8> {
+        let mut data = (items.len() as u16).to_be_bytes().to_vec();
+        if items.is_empty() {
+            return data;
+        }
+        data.push(1);
+        let mut offset = 1;
+        data.push(offset);
+        for item in items {
+            offset += item.len() as u8;
+            data.push(offset);
+        }
+        for item in items {
+            data.extend(*item);
+        }
+        data
+    }
+
+    /// A DICT operand, as a five-byte integer so that the DICT's length
+    /// doesn't depend on it
+    fn int(value: usize) -> Vec<u8> {
+        let mut data = vec![29];
+        data.extend((value as i32).to_be_bytes());
+        data
+    }
+
+    /// Build a font with a `CFF ` table of two glyphs, `.notdef` and a
+    /// triangle, and the `head` and `maxp` tables needed to draw them
+    fn cff_font() -> Vec<u8> {
+        // defaultWidthX 500, nominalWidthX 0
+        let private = [28, 0x01, 0xF4, 20, 139, 21];
+        // 0 0 rmoveto 100 0 rlineto 0 100 rlineto endchar
+        let triangle = [139, 139, 21, 239, 139, 5, 139, 239, 5, 14];
+        let charstrings = index(&[&[14], &triangle]);
+        let strings = index(&[b"Version 1.0"]);
+        let global_subrs = index(&[]);
+
+        let mut cff = vec![1, 0, 4, 1];
+        cff.extend(index(&[b"Test"]));
+        // The version (the first custom string), CharStrings and Private
+        let top_dict_len = 4 + 6 + 11;
+        let charstrings_offset = cff.len() + 5 + top_dict_len + strings.len() + global_subrs.len();
+        let private_offset = charstrings_offset + charstrings.len();
+        let mut top_dict = vec![28, 0x01, 0x87, 0];
+        top_dict.extend(int(charstrings_offset));
+        top_dict.push(17);
+        top_dict.extend(int(private.len()));
+        top_dict.extend(int(private_offset));
+        top_dict.push(18);
+        cff.extend(index(&[&top_dict]));
+        cff.extend(strings);
+        cff.extend(global_subrs);
+        cff.extend(charstrings);
+        cff.extend(private);
+
+        sfnt(
+            *b"OTTO",
+            [(b"CFF ", cff), (b"head", head(1000)), (b"maxp", maxp(2))],
+        )
+    }
+
+    #[test]
+    fn test_serialize_cff_table() {
+        let data = cff_font();
+        let font = FontRef::new(&data).unwrap();
+        let serialized = serialize_cff_table(&font).unwrap();
+        assert_eq!(serialized["font_name"], "Test");
+        assert_eq!(serialized["top_dict"], json!({ "version": "Version 1.0" }));
+        assert_eq!(
+            serialized["private_dict"],
+            json!({ "default_width_x": 500.0, "nominal_width_x": 0.0 })
+        );
+        assert_eq!(serialized["global_subrs"], 0);
+        assert_eq!(serialized["charstrings"], 2);
+        let triangle = gid_to_name(&font, GlyphId::new(1));
+        assert_eq!(serialized["glyphs"][triangle], "M0 0L100 0L100 100Z");
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+
+use read_fonts::{types::BoundingBox, FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use skrifa::{
+    color::{Brush, ColorPainter, ColorStop, CompositeMode, Transform},
+    instance::LocationRef,
+    GlyphId, MetadataProvider,
+};
+
+use super::{
+    gid_to_name,
+    jsondiff::{diff, Substantial},
+};
+
+/// Changes to the color glyphs of the `COLR` table
+#[derive(Debug, Serialize, Default, JsonSchema)]
+pub struct ColrDiff {
+    /// Color glyphs only in the new font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Color glyphs only in the old font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// Changes to the paint graph of each color glyph in both fonts, keyed
+    /// by glyph name, as "path: old => new"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changed: BTreeMap<String, Vec<String>>,
+}
+
+impl ColrDiff {
+    pub fn is_some(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty() || !self.changed.is_empty()
+    }
+}
+
+/// Round to a few decimal places, so that values which only differ by
+/// floating point noise compare equal
+fn number(value: f32) -> Value {
+    json!((value as f64 * 10000.0).round() / 10000.0)
+}
+
+fn point(x: f32, y: f32) -> Value {
+    json!([number(x), number(y)])
+}
+
+fn transform(transform: &Transform) -> Value {
+    let Transform {
+        xx,
+        yx,
+        xy,
+        yy,
+        dx,
+        dy,
+    } = *transform;
+    Value::Array([xx, yx, xy, yy, dx, dy].map(number).to_vec())
+}
+
+fn color_stops(stops: &[ColorStop]) -> Value {
+    stops
+        .iter()
+        .map(|stop| {
+            json!({
+                "offset": number(stop.offset),
+                "palette_index": stop.palette_index,
+                "alpha": number(stop.alpha),
+            })
+        })
+        .collect()
+}
+
+fn brush(brush: &Brush) -> Value {
+    match brush {
+        Brush::Solid {
+            palette_index,
+            alpha,
+        } => json!({
+            "solid": { "palette_index": palette_index, "alpha": number(*alpha) }
+        }),
+        Brush::LinearGradient {
+            p0,
+            p1,
+            color_stops: stops,
+            extend,
+        } => json!({
+            "linear_gradient": {
+                "p0": point(p0.x, p0.y),
+                "p1": point(p1.x, p1.y),
+                "stops": color_stops(stops),
+                "extend": format!("{:?}", extend),
+            }
+        }),
+        Brush::RadialGradient {
+            c0,
+            r0,
+            c1,
+            r1,
+            color_stops: stops,
+            extend,
+        } => json!({
+            "radial_gradient": {
+                "c0": point(c0.x, c0.y),
+                "r0": number(*r0),
+                "c1": point(c1.x, c1.y),
+                "r1": number(*r1),
+                "stops": color_stops(stops),
+                "extend": format!("{:?}", extend),
+            }
+        }),
+        Brush::SweepGradient {
+            c0,
+            start_angle,
+            end_angle,
+            color_stops: stops,
+            extend,
+        } => json!({
+            "sweep_gradient": {
+                "c0": point(c0.x, c0.y),
+                "start_angle": number(*start_angle),
+                "end_angle": number(*end_angle),
+                "stops": color_stops(stops),
+                "extend": format!("{:?}", extend),
+            }
+        }),
+    }
+}
+
+/// A painter which records a color glyph's paint graph as a JSON tree
+///
+/// Each transform, clip and layer is an object holding the paints drawn
+/// within it under `paint`.
+struct PaintRecorder<'a> {
+    font: &'a FontRef<'a>,
+    /// The open nodes, each with the paints recorded within it so far
+    stack: Vec<(Map<String, Value>, Vec<Value>)>,
+    paints: Vec<Value>,
+}
+
+impl<'a> PaintRecorder<'a> {
+    fn new(font: &'a FontRef<'a>) -> Self {
+        PaintRecorder {
+            font,
+            stack: vec![],
+            paints: vec![],
+        }
+    }
+
+    fn glyph_name(&self, glyph_id: GlyphId) -> Value {
+        Value::String(gid_to_name(self.font, glyph_id))
+    }
+
+    fn push(&mut self, key: &str, value: Value) {
+        let mut node = Map::new();
+        node.insert(key.to_string(), value);
+        self.stack.push((node, vec![]));
+    }
+
+    fn pop(&mut self) {
+        if let Some((mut node, paints)) = self.stack.pop() {
+            node.insert("paint".to_string(), Value::Array(paints));
+            self.add(Value::Object(node));
+        }
+    }
+
+    fn add(&mut self, paint: Value) {
+        match self.stack.last_mut() {
+            Some((_, paints)) => paints.push(paint),
+            None => self.paints.push(paint),
+        }
+    }
+}
+
+impl ColorPainter for PaintRecorder<'_> {
+    fn push_transform(&mut self, t: Transform) {
+        self.push("transform", transform(&t));
+    }
+
+    fn pop_transform(&mut self) {
+        self.pop();
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        self.push("clip_glyph", self.glyph_name(glyph_id));
+    }
+
+    fn push_clip_box(&mut self, clip_box: BoundingBox<f32>) {
+        self.push(
+            "clip_box",
+            json!([
+                number(clip_box.x_min),
+                number(clip_box.y_min),
+                number(clip_box.x_max),
+                number(clip_box.y_max)
+            ]),
+        );
+    }
+
+    fn pop_clip(&mut self) {
+        self.pop();
+    }
+
+    fn fill(&mut self, b: Brush<'_>) {
+        self.add(json!({ "fill": brush(&b) }));
+    }
+
+    fn fill_glyph(&mut self, glyph_id: GlyphId, brush_transform: Option<Transform>, b: Brush<'_>) {
+        let mut paint = Map::new();
+        paint.insert("fill_glyph".to_string(), self.glyph_name(glyph_id));
+        if let Some(t) = brush_transform {
+            paint.insert("brush_transform".to_string(), transform(&t));
+        }
+        paint.insert("brush".to_string(), brush(&b));
+        self.add(Value::Object(paint));
+    }
+
+    fn push_layer(&mut self, composite_mode: CompositeMode) {
+        self.push("layer", Value::String(format!("{:?}", composite_mode)));
+    }
+
+    fn pop_layer(&mut self) {
+        self.pop();
+    }
+}
+
+/// The paint graph of each color glyph at the default location, keyed by
+/// glyph name
+fn paint_graphs(font: &FontRef) -> BTreeMap<String, Value> {
+    let glyph_count = font.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let color_glyphs = font.color_glyphs();
+    let mut graphs = BTreeMap::new();
+    for gid in 0..glyph_count {
+        let glyph_id = GlyphId::new(gid);
+        let Some(glyph) = color_glyphs.get(glyph_id) else {
+            continue;
+        };
+        let mut recorder = PaintRecorder::new(font);
+        let graph = match glyph.paint(LocationRef::default(), &mut recorder) {
+            Ok(()) => Value::Array(recorder.paints),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        graphs.insert(gid_to_name(font, glyph_id), graph);
+    }
+    graphs
+}
+
+/// Flatten a JSON diff into "path: old => new" lines
+fn flatten(path: &str, difference: &Value, changes: &mut Vec<String>) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match difference {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&join(key), value, changes);
+            }
+        }
+        Value::Array(pair) if pair.len() == 2 => {
+            changes.push(format!("{}: {} => {}", path, pair[0], pair[1]));
+        }
+        other => changes.push(format!("{}: {}", path, other)),
+    }
+}
+
+/// Compare the paint graphs of the color glyphs in two fonts' `COLR`
+/// tables
+///
+/// Color glyphs are matched by glyph name. Each glyph's paint graph (its
+/// layers and composite modes, transforms, clips and brushes, with
+/// gradient stops) is resolved at the default location, so that changes
+/// to how the graph is stored, such as reused layers or paint offsets,
+/// aren't reported. Both COLRv0 and COLRv1 glyphs are compared.
+pub fn colr_diff(font_a: &FontRef, font_b: &FontRef) -> ColrDiff {
+    let graphs_a = paint_graphs(font_a);
+    let graphs_b = paint_graphs(font_b);
+    let mut result = ColrDiff {
+        added: graphs_b
+            .keys()
+            .filter(|glyph| !graphs_a.contains_key(*glyph))
+            .cloned()
+            .collect(),
+        removed: graphs_a
+            .keys()
+            .filter(|glyph| !graphs_b.contains_key(*glyph))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+    for (glyph, graph_a) in &graphs_a {
+        let Some(graph_b) = graphs_b.get(glyph) else {
+            continue;
+        };
+        let difference = diff(graph_a, graph_b);
+        if difference.is_something() {
+            let mut changes = vec![];
+            flatten("", &difference, &mut changes);
+            result.changed.insert(glyph.clone(), changes);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, be32, font, maxp, post};
+
+    const NAMES: [&str; 5] = [".notdef", "heart", "heart.shape", "star", "star.shape"];
+
+    /// A font with a COLRv1 table in which each (glyph, palette index)
+    /// fills the glyph after it with that palette entry, and the first
+    /// glyph is clipped to a box
+    fn colr_font(glyphs: &[(u16, u16)]) -> Vec<u8> {
+        let count = glyphs.len() as u32;
+        let mut colr = be16(&[1, 0]);
+        colr.extend(be32(&[0, 0]));
+        colr.extend(be16(&[0]));
+        colr.extend(be32(&[34, 0, 34 + 4 + 17 * count, 0, 0]));
+        // The base glyph list, then a PaintGlyph and PaintSolid per glyph
+        colr.extend(be32(&[count]));
+        for (index, (glyph, _)) in glyphs.iter().enumerate() {
+            colr.extend(be16(&[*glyph as i32]));
+            colr.extend(be32(&[4 + 6 * count + 11 * index as u32]));
+        }
+        for (glyph, palette_index) in glyphs {
+            colr.extend([10, 0, 0, 6]);
+            colr.extend(be16(&[*glyph as i32 + 1]));
+            colr.push(2);
+            colr.extend(be16(&[*palette_index as i32, 0x4000]));
+        }
+        // The clip list
+        colr.push(1);
+        colr.extend(be32(&[1]));
+        colr.extend(be16(&[glyphs[0].0 as i32, glyphs[0].0 as i32]));
+        colr.extend([0, 0, 12, 1]);
+        colr.extend(be16(&[0, 0, 500, 500]));
+        font([
+            (b"COLR", colr),
+            (b"maxp", maxp(NAMES.len() as u16)),
+            (b"post", post(&NAMES)),
+        ])
+    }
+
+    #[test]
+    fn test_colr_diff() {
+        let old = colr_font(&[(1, 0), (3, 1)]);
+        let new = colr_font(&[(1, 2)]);
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert!(!colr_diff(&old, &old).is_some());
+        let diff = colr_diff(&old, &new);
+        assert_eq!(diff.removed, vec!["star"]);
+        assert_eq!(
+            diff.changed,
+            BTreeMap::from([(
+                "heart".to_string(),
+                vec!["0.paint.0.brush.solid.palette_index: 0 => 2".to_string()]
+            )])
+        );
+        assert_eq!(colr_diff(&new, &old).added, vec!["star"]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+
+use read_fonts::{
+    tables::layout::{FeatureList, LangSys, ScriptList},
+    FontRef, TableProvider,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// An OpenType feature registered for a script and language system
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
+pub struct LayoutFeature {
+    /// "GSUB" or "GPOS"
+    pub table: String,
+    pub script: String,
+    /// The language system tag, or "dflt" for the script's default
+    pub language: String,
+    pub feature: String,
+}
+
+/// Features registered in only one of two fonts
+#[derive(Debug, Serialize, Default, JsonSchema)]
+pub struct FeatureDiff {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<LayoutFeature>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<LayoutFeature>,
+}
+
+impl FeatureDiff {
+    pub fn is_some(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty()
+    }
+}
+
+fn lang_sys_features(lang_sys: &LangSys, feature_list: &FeatureList) -> Vec<String> {
+    let required = lang_sys.required_feature_index();
+    let records = feature_list.feature_records();
+    lang_sys
+        .feature_indices()
+        .iter()
+        .map(|index| index.get())
+        .chain((required != 0xFFFF).then_some(required))
+        .filter_map(|index| records.get(index as usize))
+        .map(|record| record.feature_tag().to_string())
+        .collect()
+}
+
+fn table_features(
+    table: &str,
+    script_list: &ScriptList,
+    feature_list: &FeatureList,
+    features: &mut BTreeSet<LayoutFeature>,
+) {
+    for script_record in script_list.script_records() {
+        let Ok(script) = script_record.script(script_list.offset_data()) else {
+            continue;
+        };
+        let mut lang_systems = vec![];
+        if let Some(Ok(default)) = script.default_lang_sys() {
+            lang_systems.push(("dflt".to_string(), default));
+        }
+        for lang_sys_record in script.lang_sys_records() {
+            if let Ok(lang_sys) = lang_sys_record.lang_sys(script.offset_data()) {
+                lang_systems.push((lang_sys_record.lang_sys_tag().to_string(), lang_sys));
+            }
+        }
+        for (language, lang_sys) in lang_systems {
+            for feature in lang_sys_features(&lang_sys, feature_list) {
+                features.insert(LayoutFeature {
+                    table: table.to_string(),
+                    script: script_record.script_tag().to_string(),
+                    language: language.clone(),
+                    feature,
+                });
+            }
+        }
+    }
+}
+
+/// List the (script, language system, feature) combinations in a font's
+/// GSUB and GPOS tables
+pub fn layout_features(font: &FontRef) -> BTreeSet<LayoutFeature> {
+    let mut features = BTreeSet::new();
+    if let Ok(gsub) = font.gsub() {
+        if let (Ok(scripts), Ok(feature_list)) = (gsub.script_list(), gsub.feature_list()) {
+            table_features("GSUB", &scripts, &feature_list, &mut features);
+        }
+    }
+    if let Ok(gpos) = font.gpos() {
+        if let (Ok(scripts), Ok(feature_list)) = (gpos.script_list(), gpos.feature_list()) {
+            table_features("GPOS", &scripts, &feature_list, &mut features);
+        }
+    }
+    features
+}
+
+/// Report the features added and removed between two fonts
+pub fn feature_diff(font_a: &FontRef, font_b: &FontRef) -> FeatureDiff {
+    let features_a = layout_features(font_a);
+    let features_b = layout_features(font_b);
+    FeatureDiff {
+        added: features_b.difference(&features_a).cloned().collect(),
+        removed: features_a.difference(&features_b).cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, layout};
+
+    #[test]
+    fn test_feature_diff() {
+        let old = font([
+            (b"GPOS", layout(&[b"kern"], &[])),
+            (b"GSUB", layout(&[b"liga", b"smcp"], &[])),
+        ]);
+        let new = font([
+            (b"GPOS", layout(&[b"kern", b"mark"], &[])),
+            (b"GSUB", layout(&[b"liga"], &[])),
+        ]);
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert!(!feature_diff(&old, &old).is_some());
+
+        let feature = |table: &str, feature: &str| LayoutFeature {
+            table: table.to_string(),
+            script: "DFLT".to_string(),
+            language: "dflt".to_string(),
+            feature: feature.to_string(),
+        };
+        let diff = feature_diff(&old, &new);
+        assert_eq!(diff.added, vec![feature("GPOS", "mark")]);
+        assert_eq!(diff.removed, vec![feature("GSUB", "smcp")]);
+    }
+}
@@ -0,0 +1,132 @@
+use read_fonts::FontRef;
+use skrifa::MetadataProvider;
+
+/// The default value of each axis, as (tag, value), in axis order
+fn default_location(font: &FontRef) -> Vec<(String, f32)> {
+    font.axes()
+        .iter()
+        .map(|axis| (axis.tag().to_string(), axis.default_value()))
+        .collect()
+}
+
+fn describe(location: &[(String, f32)]) -> String {
+    location
+        .iter()
+        .map(|(tag, value)| format!("{}={}", tag, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The name of the named instance at the default location, if there is one
+fn default_instance_name(font: &FontRef) -> Option<String> {
+    let defaults: Vec<f32> = font
+        .axes()
+        .iter()
+        .map(|axis| axis.default_value())
+        .collect();
+    let instance = font.named_instances().iter().find(|instance| {
+        instance
+            .user_coords()
+            .zip(defaults.iter())
+            .all(|(coord, default)| (coord - default).abs() < 0.001)
+    })?;
+    font.localized_strings(instance.subfamily_name_id())
+        .english_or_first()
+        .map(|name| name.chars().collect())
+}
+
+/// Compare the default locations of two variable fonts
+///
+/// Reports each axis whose default value moved, and whether the default
+/// instance as a whole moved, naming it where a named instance sits at
+/// the default location. Fonts without axes are not compared. Returns a
+/// list of human-readable changes, e.g. "default wght moved from 400 to
+/// 300".
+pub fn default_instance_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<String> {
+    let location_a = default_location(font_a);
+    let location_b = default_location(font_b);
+    if location_a.is_empty() || location_b.is_empty() {
+        return vec![];
+    }
+    let mut changes = vec![];
+    for (tag, old) in location_a.iter() {
+        let Some((_, new)) = location_b.iter().find(|(other, _)| other == tag) else {
+            continue;
+        };
+        if old != new {
+            changes.push(format!("default {} moved from {} to {}", tag, old, new));
+        }
+    }
+
+    let name_a = default_instance_name(font_a);
+    let name_b = default_instance_name(font_b);
+    let named = |name: &Option<String>, location: &[(String, f32)]| match name {
+        Some(name) => format!("'{}' ({})", name, describe(location)),
+        None => format!("{}, which is not a named instance", describe(location)),
+    };
+    if !changes.is_empty() {
+        changes.push(format!(
+            "default instance moved from {} to {}",
+            named(&name_a, &location_a),
+            named(&name_b, &location_b)
+        ));
+    } else if name_a != name_b {
+        changes.push(match (name_a, name_b) {
+            (Some(old), Some(new)) => format!(
+                "default instance is now named '{}' instead of '{}'",
+                new, old
+            ),
+            (None, Some(new)) => format!("default instance is now named '{}'", new),
+            (Some(old), None) => format!(
+                "default instance is no longer a named instance (was '{}')",
+                old
+            ),
+            (None, None) => unreachable!(),
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, fvar, name};
+
+    #[test]
+    fn test_default_instance_diff() {
+        // Fonts with a `wght` axis defaulting to each weight, and Light and
+        // Regular instances
+        let [regular, light, unnamed] = [400.0, 300.0, 500.0].map(|default| {
+            font([
+                (
+                    b"fvar",
+                    fvar(
+                        &[(b"wght", 100.0, default, 900.0)],
+                        &[(257, &[300.0]), (258, &[400.0])],
+                    ),
+                ),
+                (b"name", name(&[(257, "Light"), (258, "Regular")])),
+            ])
+        });
+        let (regular, light) = (
+            FontRef::new(&regular).unwrap(),
+            FontRef::new(&light).unwrap(),
+        );
+        assert!(default_instance_diff(&regular, &regular).is_empty());
+        assert_eq!(
+            default_instance_diff(&regular, &light),
+            vec![
+                "default wght moved from 400 to 300",
+                "default instance moved from 'Regular' (wght=400) to 'Light' (wght=300)",
+            ]
+        );
+
+        assert_eq!(
+            default_instance_diff(&FontRef::new(&unnamed).unwrap(), &regular),
+            vec![
+                "default wght moved from 500 to 400",
+                "default instance moved from wght=500, which is not a named instance to 'Regular' (wght=400)",
+            ]
+        );
+    }
+}
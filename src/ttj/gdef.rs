@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use read_fonts::{FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::GlyphId;
+
+use super::glyph_names;
+
+/// A glyph whose GDEF classification differs between two fonts
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GdefDiff {
+    pub glyph: String,
+    /// "glyph class" or "mark attachment class"
+    pub classification: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// The GDEF glyph class and mark attachment class of each glyph, keyed by
+/// glyph name. Glyphs not in a class definition are in class 0.
+fn classes(font: &FontRef) -> BTreeMap<String, (u16, u16)> {
+    let gdef = font.gdef().ok();
+    let glyph_classes = gdef
+        .as_ref()
+        .and_then(|gdef| gdef.glyph_class_def())
+        .and_then(|class_def| class_def.ok());
+    let mark_classes = gdef
+        .as_ref()
+        .and_then(|gdef| gdef.mark_attach_class_def())
+        .and_then(|class_def| class_def.ok());
+    let mut classes = BTreeMap::new();
+    for (gid, name) in glyph_names(font).into_iter().enumerate() {
+        let glyph_id = GlyphId::new(gid as u16);
+        let glyph_class = glyph_classes.as_ref().map_or(0, |c| c.get(glyph_id));
+        let mark_class = mark_classes.as_ref().map_or(0, |c| c.get(glyph_id));
+        classes.entry(name).or_insert((glyph_class, mark_class));
+    }
+    classes
+}
+
+fn glyph_class_name(class: u16) -> String {
+    match class {
+        0 => "unclassified".to_string(),
+        1 => "base".to_string(),
+        2 => "ligature".to_string(),
+        3 => "mark".to_string(),
+        4 => "component".to_string(),
+        _ => format!("class {}", class),
+    }
+}
+
+fn mark_class_name(class: u16) -> String {
+    match class {
+        0 => "none".to_string(),
+        _ => format!("class {}", class),
+    }
+}
+
+/// Compare the GDEF glyph classes and mark attachment classes of two fonts
+///
+/// Glyphs are matched by name; glyphs present in only one font are not
+/// reported. A font without a GDEF table has all glyphs unclassified.
+pub fn gdef_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<GdefDiff> {
+    let classes_a = classes(font_a);
+    let classes_b = classes(font_b);
+    let mut diffs = vec![];
+    for (glyph, (old_glyph_class, old_mark_class)) in classes_a.iter() {
+        let Some((new_glyph_class, new_mark_class)) = classes_b.get(glyph) else {
+            continue;
+        };
+        if old_glyph_class != new_glyph_class {
+            diffs.push(GdefDiff {
+                glyph: glyph.clone(),
+                classification: "glyph class".to_string(),
+                old: glyph_class_name(*old_glyph_class),
+                new: glyph_class_name(*new_glyph_class),
+            });
+        }
+        if old_mark_class != new_mark_class {
+            diffs.push(GdefDiff {
+                glyph: glyph.clone(),
+                classification: "mark attachment class".to_string(),
+                old: mark_class_name(*old_mark_class),
+                new: mark_class_name(*new_mark_class),
+            });
+        }
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, gdef, maxp, post};
+
+    const GLYPHS: [&str; 4] = [".notdef", "a", "acute", "grave"];
+
+    #[test]
+    fn test_gdef_diff() {
+        let old = font([
+            (b"GDEF", gdef(&[(1, 1), (2, 3), (3, 3)], &[(2, 1)])),
+            (b"maxp", maxp(GLYPHS.len() as u16)),
+            (b"post", post(&GLYPHS)),
+        ]);
+        let new = font([
+            (b"GDEF", gdef(&[(1, 1), (2, 3), (3, 1)], &[(2, 2)])),
+            (b"maxp", maxp(GLYPHS.len() as u16)),
+            (b"post", post(&GLYPHS)),
+        ]);
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert!(gdef_diff(&old, &old).is_empty());
+
+        let diffs: Vec<_> = gdef_diff(&old, &new)
+            .into_iter()
+            .map(|d| (d.glyph, d.classification, d.old, d.new))
+            .collect();
+        let diff = |glyph: &str, classification: &str, old: &str, new: &str| {
+            (
+                glyph.to_string(),
+                classification.to_string(),
+                old.to_string(),
+                new.to_string(),
+            )
+        };
+        assert_eq!(
+            diffs,
+            vec![
+                diff("acute", "mark attachment class", "class 1", "class 2"),
+                diff("grave", "glyph class", "mark", "base"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use read_fonts::{FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::glyph_names;
+
+/// The proportion of glyphs which can be added or removed before glyph IDs
+/// are unlikely to correspond between two fonts
+const SUBSTANTIAL_CHANGE: f32 = 0.1;
+
+/// A change to the number or order of glyphs
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GlyphCountDiff {
+    pub old: u16,
+    pub new: u16,
+    /// The number of glyph IDs in both fonts whose glyph names differ
+    pub renamed: usize,
+    /// A warning that glyph IDs don't correspond between the fonts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Compare the number of glyphs in two fonts, and check whether glyph IDs
+/// still refer to the same glyphs
+///
+/// Returns `None` if the counts are the same and no glyph ID present in
+/// both fonts has changed its name. A warning is given if the count has
+/// changed by more than 10%, or if glyphs have been reordered, as glyph
+/// IDs then can't be relied on to match the same glyphs.
+pub fn glyph_count_diff(font_a: &FontRef, font_b: &FontRef) -> Option<GlyphCountDiff> {
+    let old = font_a.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let new = font_b.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let renamed = glyph_names(font_a)
+        .iter()
+        .zip(glyph_names(font_b).iter())
+        .filter(|(name_a, name_b)| name_a != name_b)
+        .count();
+    if old == new && renamed == 0 {
+        return None;
+    }
+    let change = old.abs_diff(new) as f32 / old.max(1) as f32;
+    let warning = if renamed > 0 {
+        Some(format!(
+            "{} glyph IDs refer to differently named glyphs; compare glyphs by name (--glyphs-by-name) rather than by index",
+            renamed
+        ))
+    } else if change > SUBSTANTIAL_CHANGE {
+        Some(format!(
+            "The number of glyphs changed by {:.0}%; glyph IDs may not refer to the same glyphs, so compare glyphs by name (--glyphs-by-name) rather than by index",
+            change * 100.0
+        ))
+    } else {
+        None
+    };
+    Some(GlyphCountDiff {
+        old,
+        new,
+        renamed,
+        warning,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, maxp, post};
+
+    fn diff(old: &[&str], new: &[&str]) -> Option<GlyphCountDiff> {
+        let [old, new] = [old, new]
+            .map(|names| font([(b"maxp", maxp(names.len() as u16)), (b"post", post(names))]));
+        glyph_count_diff(&FontRef::new(&old).unwrap(), &FontRef::new(&new).unwrap())
+    }
+
+    #[test]
+    fn test_glyph_count_diff() {
+        let glyphs = [".notdef", "a", "b", "c", "d", "e", "f", "g", "h", "i"];
+        assert!(diff(&glyphs, &glyphs).is_none());
+
+        // One glyph more is within 10%
+        let diff_one = diff(&glyphs, &[&glyphs[..], &["j"]].concat()).unwrap();
+        assert_eq!((diff_one.old, diff_one.new, diff_one.renamed), (10, 11, 0));
+        assert!(diff_one.warning.is_none());
+
+        let diff_two = diff(&glyphs, &[&glyphs[..], &["j", "k"]].concat()).unwrap();
+        assert!(diff_two.warning.unwrap().contains("changed by 20%"));
+
+        // Swapping two glyphs keeps the count, but renames two glyph IDs
+        let mut swapped = glyphs;
+        swapped.swap(1, 2);
+        let reordered = diff(&glyphs, &swapped).unwrap();
+        assert_eq!(
+            (reordered.old, reordered.new, reordered.renamed),
+            (10, 10, 2)
+        );
+        assert!(reordered.warning.unwrap().starts_with("2 glyph IDs"));
+    }
+}
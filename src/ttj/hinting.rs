@@ -0,0 +1,336 @@
+use read_fonts::{FontData, ReadError, TableProvider};
+use serde_json::{Map, Value};
+use skrifa::Tag;
+
+/// The mnemonic of an opcode, and how many low bits of it are flags
+fn mnemonic(opcode: u8) -> Option<(&'static str, u32)> {
+    Some(match opcode {
+        0x00..=0x01 => ("SVTCA", 1),
+        0x02..=0x03 => ("SPVTCA", 1),
+        0x04..=0x05 => ("SFVTCA", 1),
+        0x06..=0x07 => ("SPVTL", 1),
+        0x08..=0x09 => ("SFVTL", 1),
+        0x0A => ("SPVFS", 0),
+        0x0B => ("SFVFS", 0),
+        0x0C => ("GPV", 0),
+        0x0D => ("GFV", 0),
+        0x0E => ("SFVTPV", 0),
+        0x0F => ("ISECT", 0),
+        0x10 => ("SRP0", 0),
+        0x11 => ("SRP1", 0),
+        0x12 => ("SRP2", 0),
+        0x13 => ("SZP0", 0),
+        0x14 => ("SZP1", 0),
+        0x15 => ("SZP2", 0),
+        0x16 => ("SZPS", 0),
+        0x17 => ("SLOOP", 0),
+        0x18 => ("RTG", 0),
+        0x19 => ("RTHG", 0),
+        0x1A => ("SMD", 0),
+        0x1B => ("ELSE", 0),
+        0x1C => ("JMPR", 0),
+        0x1D => ("SCVTCI", 0),
+        0x1E => ("SSWCI", 0),
+        0x1F => ("SSW", 0),
+        0x20 => ("DUP", 0),
+        0x21 => ("POP", 0),
+        0x22 => ("CLEAR", 0),
+        0x23 => ("SWAP", 0),
+        0x24 => ("DEPTH", 0),
+        0x25 => ("CINDEX", 0),
+        0x26 => ("MINDEX", 0),
+        0x27 => ("ALIGNPTS", 0),
+        0x29 => ("UTP", 0),
+        0x2A => ("LOOPCALL", 0),
+        0x2B => ("CALL", 0),
+        0x2C => ("FDEF", 0),
+        0x2D => ("ENDF", 0),
+        0x2E..=0x2F => ("MDAP", 1),
+        0x30..=0x31 => ("IUP", 1),
+        0x32..=0x33 => ("SHP", 1),
+        0x34..=0x35 => ("SHC", 1),
+        0x36..=0x37 => ("SHZ", 1),
+        0x38 => ("SHPIX", 0),
+        0x39 => ("IP", 0),
+        0x3A..=0x3B => ("MSIRP", 1),
+        0x3C => ("ALIGNRP", 0),
+        0x3D => ("RTDG", 0),
+        0x3E..=0x3F => ("MIAP", 1),
+        0x40 => ("NPUSHB", 0),
+        0x41 => ("NPUSHW", 0),
+        0x42 => ("WS", 0),
+        0x43 => ("RS", 0),
+        0x44 => ("WCVTP", 0),
+        0x45 => ("RCVT", 0),
+        0x46..=0x47 => ("GC", 1),
+        0x48 => ("SCFS", 0),
+        0x49..=0x4A => ("MD", 1),
+        0x4B => ("MPPEM", 0),
+        0x4C => ("MPS", 0),
+        0x4D => ("FLIPON", 0),
+        0x4E => ("FLIPOFF", 0),
+        0x4F => ("DEBUG", 0),
+        0x50 => ("LT", 0),
+        0x51 => ("LTEQ", 0),
+        0x52 => ("GT", 0),
+        0x53 => ("GTEQ", 0),
+        0x54 => ("EQ", 0),
+        0x55 => ("NEQ", 0),
+        0x56 => ("ODD", 0),
+        0x57 => ("EVEN", 0),
+        0x58 => ("IF", 0),
+        0x59 => ("EIF", 0),
+        0x5A => ("AND", 0),
+        0x5B => ("OR", 0),
+        0x5C => ("NOT", 0),
+        0x5D => ("DELTAP1", 0),
+        0x5E => ("SDB", 0),
+        0x5F => ("SDS", 0),
+        0x60 => ("ADD", 0),
+        0x61 => ("SUB", 0),
+        0x62 => ("DIV", 0),
+        0x63 => ("MUL", 0),
+        0x64 => ("ABS", 0),
+        0x65 => ("NEG", 0),
+        0x66 => ("FLOOR", 0),
+        0x67 => ("CEILING", 0),
+        0x68..=0x6B => ("ROUND", 2),
+        0x6C..=0x6F => ("NROUND", 2),
+        0x70 => ("WCVTF", 0),
+        0x71 => ("DELTAP2", 0),
+        0x72 => ("DELTAP3", 0),
+        0x73 => ("DELTAC1", 0),
+        0x74 => ("DELTAC2", 0),
+        0x75 => ("DELTAC3", 0),
+        0x76 => ("SROUND", 0),
+        0x77 => ("S45ROUND", 0),
+        0x78 => ("JROT", 0),
+        0x79 => ("JROF", 0),
+        0x7A => ("ROFF", 0),
+        0x7C => ("RUTG", 0),
+        0x7D => ("RDTG", 0),
+        0x7E => ("SANGW", 0),
+        0x7F => ("AA", 0),
+        0x80 => ("FLIPPT", 0),
+        0x81 => ("FLIPRGON", 0),
+        0x82 => ("FLIPRGOFF", 0),
+        0x85 => ("SCANCTRL", 0),
+        0x86..=0x87 => ("SDPVTL", 1),
+        0x88 => ("GETINFO", 0),
+        0x89 => ("IDEF", 0),
+        0x8A => ("ROLL", 0),
+        0x8B => ("MAX", 0),
+        0x8C => ("MIN", 0),
+        0x8D => ("SCANTYPE", 0),
+        0x8E => ("INSTCTRL", 0),
+        0x91 => ("GETVARIATION", 0),
+        0x92 => ("GETDATA", 0),
+        0xB0..=0xB7 => ("PUSHB", 3),
+        0xB8..=0xBF => ("PUSHW", 3),
+        0xC0..=0xDF => ("MDRP", 5),
+        0xE0..=0xFF => ("MIRP", 5),
+        _ => return None,
+    })
+}
+
+/// A decoded instruction, along with any values it pushes
+struct Instruction {
+    text: String,
+    pushed: Vec<i32>,
+}
+
+/// Disassemble TrueType bytecode into one line per instruction
+///
+/// Flag bits are shown in brackets after the mnemonic, as in `MIRP[10110]`,
+/// and pushed values follow it. Opcodes which aren't defined are shown as
+/// `UNKNOWN[0x..]`.
+fn disassemble(bytecode: FontData) -> Result<Vec<Instruction>, ReadError> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < bytecode.len() {
+        let opcode: u8 = bytecode.read_at(offset)?;
+        offset += 1;
+        let Some((name, flag_bits)) = mnemonic(opcode) else {
+            instructions.push(Instruction {
+                text: format!("UNKNOWN[0x{:02X}]", opcode),
+                pushed: vec![],
+            });
+            continue;
+        };
+        let (count, words) = match opcode {
+            0x40 | 0x41 => {
+                let count: u8 = bytecode.read_at(offset)?;
+                offset += 1;
+                (count as usize, opcode == 0x41)
+            }
+            0xB0..=0xB7 => ((opcode - 0xB0 + 1) as usize, false),
+            0xB8..=0xBF => ((opcode - 0xB8 + 1) as usize, true),
+            _ => (0, false),
+        };
+        let mut pushed = Vec::with_capacity(count);
+        for _ in 0..count {
+            if words {
+                pushed.push(bytecode.read_at::<i16>(offset)? as i32);
+                offset += 2;
+            } else {
+                pushed.push(bytecode.read_at::<u8>(offset)? as i32);
+                offset += 1;
+            }
+        }
+        let mut text = name.to_string();
+        if flag_bits > 0 {
+            let flags = opcode as u32 & ((1 << flag_bits) - 1);
+            text.push_str(&format!("[{:0width$b}]", flags, width = flag_bits as usize));
+        }
+        for value in &pushed {
+            text.push_str(&format!(" {}", value));
+        }
+        instructions.push(Instruction { text, pushed });
+    }
+    Ok(instructions)
+}
+
+/// Serialize the `prep` table as its disassembled instructions
+pub(crate) fn serialize_prep_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"prep"))?;
+    Ok(Value::Array(
+        disassemble(data)?
+            .into_iter()
+            .map(|i| Value::String(i.text))
+            .collect(),
+    ))
+}
+
+/// Serialize the `fpgm` table as a map from each function (or instruction)
+/// definition to its disassembled body
+///
+/// Function numbers are found by following the values pushed at the top
+/// level of the program, so that a change to one function is reported
+/// against that function rather than shifting every later instruction.
+/// Instructions outside any definition are listed under "top level".
+pub(crate) fn serialize_fpgm_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"fpgm"))?;
+    let mut map = Map::new();
+    let mut top_level = vec![];
+    let mut stack: Vec<i32> = vec![];
+    let mut current: Option<(String, Vec<Value>)> = None;
+    for instruction in disassemble(data)? {
+        if let Some((name, body)) = current.as_mut() {
+            if instruction.text == "ENDF" {
+                map.insert(std::mem::take(name), Value::Array(std::mem::take(body)));
+                current = None;
+            } else {
+                body.push(Value::String(instruction.text));
+            }
+            continue;
+        }
+        let kind = match instruction.text.as_str() {
+            "FDEF" => "function",
+            "IDEF" => "instruction",
+            _ => {
+                stack.extend(instruction.pushed);
+                top_level.push(Value::String(instruction.text));
+                continue;
+            }
+        };
+        let number = stack
+            .pop()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "?".to_string());
+        current = Some((format!("{} {}", kind, number), vec![]));
+    }
+    // An unterminated definition still gets reported
+    if let Some((name, body)) = current {
+        map.insert(name, Value::Array(body));
+    }
+    if !top_level.is_empty() {
+        map.insert("top level".to_string(), Value::Array(top_level));
+    }
+    Ok(Value::Object(map))
+}
+
+/// Serialize the `cvt ` table as a list of its values
+pub(crate) fn serialize_cvt_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"cvt "))?;
+    Ok(Value::Array(
+        (0..data.len() / 2)
+            .map(|index| {
+                data.read_at::<i16>(index * 2)
+                    .map(|v| Value::Number(v.into()))
+            })
+            .collect::<Result<_, _>>()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use read_fonts::FontRef;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_fonts::font;
+
+    fn texts(bytecode: &[u8]) -> Result<Vec<String>, ReadError> {
+        Ok(disassemble(FontData::new(bytecode))?
+            .into_iter()
+            .map(|i| i.text)
+            .collect())
+    }
+
+    #[test]
+    fn test_push_operands() {
+        assert_eq!(
+            texts(&[0xB2, 1, 2, 255, 0xB9, 0xFF, 0xFE, 0x01, 0x00]).unwrap(),
+            ["PUSHB[010] 1 2 255", "PUSHW[001] -2 256"]
+        );
+        assert_eq!(
+            texts(&[0x40, 2, 7, 8, 0x41, 1, 0x80, 0x00, 0x41, 0]).unwrap(),
+            ["NPUSHB 7 8", "NPUSHW -32768", "NPUSHW"]
+        );
+    }
+
+    #[test]
+    fn test_flags_and_unknown_opcodes() {
+        assert_eq!(
+            texts(&[0xF6, 0x2F, 0x68, 0x28, 0x20]).unwrap(),
+            [
+                "MIRP[10110]",
+                "MDAP[1]",
+                "ROUND[00]",
+                "UNKNOWN[0x28]",
+                "DUP"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        // Missing a pushed byte, a pushed word's second byte, and a count
+        assert!(texts(&[0xB1, 5]).is_err());
+        assert!(texts(&[0xB8, 0x01]).is_err());
+        assert!(texts(&[0x20, 0x40]).is_err());
+        assert!(texts(&[0x41, 2, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_fpgm_function_numbers() {
+        // Push 0 and 1, then define function 1 (the top of the stack) and
+        // function 0, and leave function 2 unterminated
+        let data = font([(
+            b"fpgm",
+            vec![
+                0xB1, 0, 1, 0x2C, 0x20, 0x2D, 0x2C, 0x21, 0x2D, 0xB0, 2, 0x2C, 0x22,
+            ],
+        )]);
+        let font = FontRef::new(&data).unwrap();
+        assert_eq!(
+            serialize_fpgm_table(&font).unwrap(),
+            json!({
+                "function 1": ["DUP"],
+                "function 0": ["POP"],
+                "function 2": ["CLEAR"],
+                "top level": ["PUSHB[001] 0 1", "PUSHB[000] 2"],
+            })
+        );
+    }
+}
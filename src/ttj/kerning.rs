@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use read_fonts::{
+    tables::gpos::{ExtensionSubtable, PairPos, PositionLookup},
+    FontRef, TableProvider,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::GlyphId;
+
+use super::{
+    glyph_name, glyph_names,
+    upm::{scale_units, upm_scale},
+};
+
+/// A kerning pair whose adjustment differs between two fonts
+///
+/// The new adjustment is in the old font's units.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct KernDiff {
+    pub left: String,
+    pub right: String,
+    pub old: i32,
+    pub new: i32,
+}
+
+/// Collect the effective horizontal pair adjustments in a font's GPOS table
+///
+/// Pairs are keyed by glyph name so that fonts with different glyph orders
+/// can be compared. Within a lookup only the first subtable which covers a
+/// pair applies; adjustments from different lookups are summed. Class 0 of
+/// the second glyph ("all other glyphs") is not expanded.
+///
+/// Sums are kept as `i32`, as adjustments from several lookups can add up
+/// to more than fits in the tables' 16 bits.
+///
+/// Pair lookups wrapped in extension lookups (type 9), as compilers emit
+/// when a large font's offsets would overflow, are walked too.
+pub fn kerning_pairs(font: &FontRef) -> BTreeMap<(String, String), i32> {
+    let mut pairs: HashMap<(GlyphId, GlyphId), i32> = HashMap::new();
+    let Ok(lookups) = font.gpos().and_then(|gpos| gpos.lookup_list()) else {
+        return BTreeMap::new();
+    };
+    for lookup in lookups.lookups().iter().flatten() {
+        let mut seen: HashSet<(GlyphId, GlyphId)> = HashSet::new();
+        for subtable in pair_subtables(&lookup) {
+            for (pair, value) in subtable_pairs(&subtable) {
+                if seen.insert(pair) {
+                    *pairs.entry(pair).or_default() += i32::from(value);
+                }
+            }
+        }
+    }
+    let names = glyph_names(font);
+    pairs
+        .into_iter()
+        .filter(|(_, value)| *value != 0)
+        .map(|((left, right), value)| {
+            ((glyph_name(&names, left), glyph_name(&names, right)), value)
+        })
+        .collect()
+}
+
+/// The pair adjustment subtables of a lookup, unwrapping them from an
+/// extension lookup
+fn pair_subtables<'a>(lookup: &PositionLookup<'a>) -> Vec<PairPos<'a>> {
+    match lookup {
+        PositionLookup::Pair(lookup) => lookup.subtables().iter().flatten().collect(),
+        PositionLookup::Extension(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .filter_map(|subtable| match subtable {
+                ExtensionSubtable::Pair(extension) => extension.extension().ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn subtable_pairs(subtable: &PairPos) -> Vec<((GlyphId, GlyphId), i16)> {
+    let mut pairs = vec![];
+    match subtable {
+        PairPos::Format1(format1) => {
+            let (Ok(coverage), pair_sets) = (format1.coverage(), format1.pair_sets()) else {
+                return pairs;
+            };
+            for (first, pair_set) in coverage.iter().zip(pair_sets.iter()) {
+                let Ok(pair_set) = pair_set else {
+                    continue;
+                };
+                for record in pair_set.pair_value_records().iter().flatten() {
+                    let value = record.value_record1().x_advance().unwrap_or(0);
+                    pairs.push(((first, record.second_glyph()), value));
+                }
+            }
+        }
+        PairPos::Format2(format2) => {
+            let (Ok(coverage), Ok(class_def1), Ok(class_def2)) = (
+                format2.coverage(),
+                format2.class_def1(),
+                format2.class_def2(),
+            ) else {
+                return pairs;
+            };
+            let mut class2_glyphs: BTreeMap<u16, Vec<GlyphId>> = BTreeMap::new();
+            for (glyph, class) in class_def2.iter() {
+                class2_glyphs.entry(class).or_default().push(glyph);
+            }
+            let class1_records = format2.class1_records();
+            for first in coverage.iter() {
+                let Ok(class1_record) = class1_records.get(class_def1.get(first) as usize) else {
+                    continue;
+                };
+                for (class2, seconds) in class2_glyphs.iter() {
+                    let Ok(class2_record) = class1_record.class2_records().get(*class2 as usize)
+                    else {
+                        continue;
+                    };
+                    let value = class2_record.value_record1().x_advance().unwrap_or(0);
+                    for second in seconds {
+                        pairs.push(((first, *second), value));
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// Report the kerning pairs whose adjustment differs between two fonts
+///
+/// A pair absent from one font is treated as having an adjustment of zero.
+/// If the fonts' units per em differ, the new font's adjustments are
+/// scaled to the old font's units first.
+pub fn kern_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<KernDiff> {
+    let scale = upm_scale(font_a, font_b);
+    let pairs_a = kerning_pairs(font_a);
+    let pairs_b = kerning_pairs(font_b);
+    let all_pairs: BTreeSet<&(String, String)> = pairs_a.keys().chain(pairs_b.keys()).collect();
+    all_pairs
+        .into_iter()
+        .filter_map(|pair| {
+            let old = pairs_a.get(pair).copied().unwrap_or(0);
+            let new = scale_units(pairs_b.get(pair).copied().unwrap_or(0), scale);
+            (old != new).then(|| KernDiff {
+                left: pair.0.clone(),
+                right: pair.1.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, class_def, coverage, extension, font, layout, maxp, post};
+
+    const GLYPHS: [&str; 7] = [".notdef", "A", "B", "V", "W", "X", "C"];
+
+    /// A font with the glyphs in [GLYPHS] and a `kern` feature using a
+    /// pair adjustment lookup for each list of subtables
+    fn kern_font(lookups: &[Vec<Vec<u8>>]) -> Vec<u8> {
+        let lookups: Vec<(u16, Vec<Vec<u8>>)> = lookups
+            .iter()
+            .map(|subtables| (2, subtables.clone()))
+            .collect();
+        font([
+            (b"maxp", maxp(GLYPHS.len() as u16)),
+            (b"post", post(&GLYPHS)),
+            (b"GPOS", layout(&[b"kern"], &lookups)),
+        ])
+    }
+
+    /// A format 1 pair adjustment subtable adjusting the advance of each
+    /// first glyph before each (second glyph, value)
+    fn pair_pos_1(pairs: &[(u16, &[(u16, i32)])]) -> Vec<u8> {
+        let mut pair_sets = vec![];
+        let mut offsets = vec![];
+        let header_size = 10 + 2 * pairs.len() as i32;
+        for (_, seconds) in pairs {
+            offsets.push(header_size + pair_sets.len() as i32);
+            pair_sets.extend(be16(&[seconds.len() as i32]));
+            for (second, value) in *seconds {
+                pair_sets.extend(be16(&[*second as i32, *value]));
+            }
+        }
+        let firsts: Vec<u16> = pairs.iter().map(|(first, _)| *first).collect();
+        let mut subtable = be16(&[1, header_size + pair_sets.len() as i32, 4, 0]);
+        subtable.extend(be16(&[pairs.len() as i32]));
+        subtable.extend(be16(&offsets));
+        subtable.extend(pair_sets);
+        subtable.extend(coverage(&firsts));
+        subtable
+    }
+
+    /// A format 2 pair adjustment subtable covering `firsts`, with a row
+    /// of values (one for each second class) for each first class
+    fn pair_pos_2(
+        firsts: &[u16],
+        classes1: &[(u16, u16)],
+        classes2: &[(u16, u16)],
+        values: &[&[i32]],
+    ) -> Vec<u8> {
+        let class2_count = values[0].len() as i32;
+        let records: Vec<i32> = values.iter().flat_map(|row| row.iter().copied()).collect();
+        let coverage = coverage(firsts);
+        let class_def1 = class_def(classes1);
+        let coverage_offset = 16 + 2 * records.len() as i32;
+        let class_def1_offset = coverage_offset + coverage.len() as i32;
+        let class_def2_offset = class_def1_offset + class_def1.len() as i32;
+        let mut subtable = be16(&[
+            2,
+            coverage_offset,
+            4,
+            0,
+            class_def1_offset,
+            class_def2_offset,
+            values.len() as i32,
+            class2_count,
+        ]);
+        subtable.extend(be16(&records));
+        subtable.extend(coverage);
+        subtable.extend(class_def1);
+        subtable.extend(class_def(classes2));
+        subtable
+    }
+
+    fn pairs(data: &[u8]) -> Vec<(String, String, i32)> {
+        kerning_pairs(&FontRef::new(data).unwrap())
+            .into_iter()
+            .map(|((left, right), value)| (left, right, value))
+            .collect()
+    }
+
+    fn pair(left: &str, right: &str, value: i32) -> (String, String, i32) {
+        (left.to_string(), right.to_string(), value)
+    }
+
+    #[test]
+    fn test_format_1() {
+        let old = kern_font(&[vec![pair_pos_1(&[(1, &[(3, -80), (4, -60)])])]]);
+        let new = kern_font(&[vec![pair_pos_1(&[(1, &[(3, -90), (4, -60)])])]]);
+        assert_eq!(pairs(&old), vec![pair("A", "V", -80), pair("A", "W", -60)]);
+
+        let diff = kern_diff(&FontRef::new(&old).unwrap(), &FontRef::new(&new).unwrap());
+        assert_eq!(diff.len(), 1);
+        assert_eq!((diff[0].left.as_str(), diff[0].right.as_str()), ("A", "V"));
+        assert_eq!((diff[0].old, diff[0].new), (-80, -90));
+    }
+
+    #[test]
+    fn test_format_2_classes() {
+        // A and B are in first class 1; C is covered but in no class, so
+        // in class 0. V and W are in second class 1; X is in no class, so
+        // is in class 0, which isn't expanded.
+        let data = kern_font(&[vec![pair_pos_2(
+            &[1, 2, 6],
+            &[(1, 1), (2, 1)],
+            &[(3, 1), (4, 1)],
+            &[&[-5, -30], &[-10, -50]],
+        )]]);
+        assert_eq!(
+            pairs(&data),
+            vec![
+                pair("A", "V", -50),
+                pair("A", "W", -50),
+                pair("B", "V", -50),
+                pair("B", "W", -50),
+                pair("C", "V", -30),
+                pair("C", "W", -30),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pair_in_several_lookups() {
+        // Lookups are summed, beyond the range of an i16
+        let data = kern_font(&[
+            vec![pair_pos_1(&[(1, &[(3, 30000)])])],
+            vec![pair_pos_1(&[(1, &[(3, 30000)])])],
+        ]);
+        assert_eq!(pairs(&data), vec![pair("A", "V", 60000)]);
+
+        // Within a lookup, only the first subtable covering a pair applies
+        let data = kern_font(&[vec![
+            pair_pos_1(&[(1, &[(3, -80)])]),
+            pair_pos_1(&[(1, &[(3, -20), (4, -10)])]),
+        ]]);
+        assert_eq!(pairs(&data), vec![pair("A", "V", -80), pair("A", "W", -10)]);
+    }
+
+    #[test]
+    fn test_extension_lookup() {
+        // A pair lookup wrapped in an extension lookup is walked like any
+        // other, and summed with unwrapped lookups
+        let subtable = pair_pos_1(&[(1, &[(3, -80), (4, -60)])]);
+        let data = font([
+            (b"maxp", maxp(GLYPHS.len() as u16)),
+            (b"post", post(&GLYPHS)),
+            (
+                b"GPOS",
+                layout(
+                    &[b"kern"],
+                    &[
+                        (9, vec![extension(2, &subtable)]),
+                        (2, vec![pair_pos_1(&[(1, &[(3, -10)])])]),
+                    ],
+                ),
+            ),
+        ]);
+        assert_eq!(pairs(&data), vec![pair("A", "V", -90), pair("A", "W", -60)]);
+    }
+}
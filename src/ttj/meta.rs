@@ -0,0 +1,86 @@
+use read_fonts::{ReadError, TableProvider};
+use serde_json::{Map, Value};
+use skrifa::Tag;
+
+/// Serialize the `meta` table
+///
+/// The design and supported language lists (`dlng` and `slng`) are
+/// serialized as objects keyed by ScriptLangTag, so that they are diffed
+/// as sets: tags which are added or removed show up as such, however the
+/// lists are ordered. Other metadata is serialized as bytes.
+pub(crate) fn serialize_meta_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"meta"))?;
+    let version: u32 = data.read_at(0)?;
+    let data_maps_count: u32 = data.read_at(12)?;
+    let mut map = Map::new();
+    map.insert("version".to_string(), Value::Number(version.into()));
+    for index in 0..data_maps_count as usize {
+        let record = 16 + index * 12;
+        let tag: Tag = data.read_at(record)?;
+        let offset: u32 = data.read_at(record + 4)?;
+        let length: u32 = data.read_at(record + 8)?;
+        let bytes = data
+            .as_bytes()
+            .get(offset as usize..(offset as usize).saturating_add(length as usize))
+            .ok_or(ReadError::OutOfBounds)?;
+        let value = match tag.into_bytes().as_ref() {
+            b"dlng" | b"slng" => Value::Object(
+                String::from_utf8_lossy(bytes)
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty())
+                    .map(|language| (language.to_string(), Value::Bool(true)))
+                    .collect(),
+            ),
+            _ => Value::Array(bytes.iter().map(|&x| Value::Number(x.into())).collect()),
+        };
+        map.insert(tag.to_string(), value);
+    }
+    Ok(Value::Object(map))
+}
+
+/// Serialize the `DSIG` table
+///
+/// Only the number of signatures and the flags are serialized; the
+/// signatures themselves change whenever a font is signed.
+pub(crate) fn serialize_dsig_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"DSIG"))?;
+    let version: u32 = data.read_at(0)?;
+    let num_signatures: u16 = data.read_at(4)?;
+    let flags: u16 = data.read_at(6)?;
+    let mut map = Map::new();
+    map.insert("version".to_string(), Value::Number(version.into()));
+    map.insert(
+        "num_signatures".to_string(),
+        Value::Number(num_signatures.into()),
+    );
+    map.insert("flags".to_string(), Value::Number(flags.into()));
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use read_fonts::FontRef;
+    use serde_json::{json, Value};
+
+    use crate::{
+        test_fonts::{font, meta},
+        ttj::table_diff,
+    };
+
+    #[test]
+    fn test_languages_diffed_as_sets() {
+        let old = font([(b"meta", meta("Latn, Cyrl"))]);
+        let reordered = font([(b"meta", meta("Cyrl,Latn"))]);
+        let new = font([(b"meta", meta("Cyrl, Latn, Grek"))]);
+        let old = FontRef::new(&old).unwrap();
+        assert_eq!(
+            table_diff(&old, &FontRef::new(&reordered).unwrap()),
+            Value::Null
+        );
+        assert_eq!(
+            table_diff(&old, &FontRef::new(&new).unwrap()),
+            json!({"meta": {"dlng": {"Grek": [null, true]}}})
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use read_fonts::{FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::GlyphId;
+
+use super::{
+    glyph_names,
+    upm::{scale_units, upm_scale},
+};
+
+/// A glyph whose advance or side bearing differs between two fonts
+///
+/// The new values are in the old font's units.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MetricsDiff {
+    pub glyph: String,
+    /// "hmtx" for the advance width and left side bearing, or "vmtx" for
+    /// the advance height and top side bearing
+    pub table: String,
+    pub old_advance: i32,
+    pub new_advance: i32,
+    pub old_side_bearing: i32,
+    pub new_side_bearing: i32,
+}
+
+/// The advance and side bearing of each glyph, keyed by glyph name
+type Metrics = BTreeMap<String, (u16, i16)>;
+
+fn glyph_metrics(font: &FontRef, metric: impl Fn(GlyphId) -> Option<(u16, i16)>) -> Metrics {
+    let mut metrics = Metrics::new();
+    for (gid, name) in glyph_names(font).into_iter().enumerate() {
+        if let Some(value) = metric(GlyphId::new(gid as u16)) {
+            metrics.entry(name).or_insert(value);
+        }
+    }
+    metrics
+}
+
+fn horizontal_metrics(font: &FontRef) -> Metrics {
+    let Ok(hmtx) = font.hmtx() else {
+        return Metrics::new();
+    };
+    glyph_metrics(font, |gid| {
+        Some((hmtx.advance(gid)?, hmtx.side_bearing(gid)?))
+    })
+}
+
+fn vertical_metrics(font: &FontRef) -> Metrics {
+    let Ok(vmtx) = font.vmtx() else {
+        return Metrics::new();
+    };
+    glyph_metrics(font, |gid| {
+        Some((vmtx.advance(gid)?, vmtx.side_bearing(gid)?))
+    })
+}
+
+/// Compare the default advances and side bearings of glyphs in two fonts
+///
+/// Glyphs are matched by name, so fonts with different glyph orders can
+/// be compared; glyphs present in only one font are not reported. Only
+/// changes of more than `tolerance` font units are reported. If the fonts'
+/// units per em differ, the new font's metrics are scaled to the old
+/// font's units first.
+pub fn metrics_diff(font_a: &FontRef, font_b: &FontRef, tolerance: u16) -> Vec<MetricsDiff> {
+    let scale = upm_scale(font_a, font_b);
+    let mut diffs = vec![];
+    for (table, metrics_a, metrics_b) in [
+        (
+            "hmtx",
+            horizontal_metrics(font_a),
+            horizontal_metrics(font_b),
+        ),
+        ("vmtx", vertical_metrics(font_a), vertical_metrics(font_b)),
+    ] {
+        for (glyph, (old_advance, old_side_bearing)) in metrics_a.iter() {
+            let Some((new_advance, new_side_bearing)) = metrics_b.get(glyph) else {
+                continue;
+            };
+            let (old_advance, old_side_bearing) =
+                (i32::from(*old_advance), i32::from(*old_side_bearing));
+            let new_advance = scale_units(*new_advance, scale);
+            let new_side_bearing = scale_units(*new_side_bearing, scale);
+            if old_advance.abs_diff(new_advance) > tolerance.into()
+                || old_side_bearing.abs_diff(new_side_bearing) > tolerance.into()
+            {
+                diffs.push(MetricsDiff {
+                    glyph: glyph.clone(),
+                    table: table.to_string(),
+                    old_advance,
+                    new_advance,
+                    old_side_bearing,
+                    new_side_bearing,
+                });
+            }
+        }
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, head, hhea, hmtx, maxp, post};
+
+    #[test]
+    fn test_metrics_diff() {
+        // Glyphs `.notdef`, `a` and `b`, with (advance, left side bearing)
+        // metrics which differ for `a` and `b`
+        let old = font([
+            (b"head", head(1000)),
+            (b"hhea", hhea(800, -200, 3)),
+            (b"hmtx", hmtx(&[(500, 0), (500, 50), (600, 40)])),
+            (b"maxp", maxp(3)),
+            (b"post", post(&[".notdef", "a", "b"])),
+        ]);
+        let new = font([
+            (b"head", head(1000)),
+            (b"hhea", hhea(800, -200, 3)),
+            (b"hmtx", hmtx(&[(500, 0), (505, 50), (600, 10)])),
+            (b"maxp", maxp(3)),
+            (b"post", post(&[".notdef", "a", "b"])),
+        ]);
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert!(metrics_diff(&old, &old, 0).is_empty());
+
+        let diffs = metrics_diff(&old, &new, 0);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            (diffs[0].glyph.as_str(), diffs[0].table.as_str()),
+            ("a", "hmtx")
+        );
+        assert_eq!((diffs[0].old_advance, diffs[0].new_advance), (500, 505));
+        assert_eq!(diffs[1].glyph, "b");
+        assert_eq!(
+            (diffs[1].old_side_bearing, diffs[1].new_side_bearing),
+            (40, 10)
+        );
+
+        // Changes within the tolerance aren't reported
+        let diffs = metrics_diff(&old, &new, 5);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].glyph, "b");
+    }
+}
@@ -1,44 +1,137 @@
 use crate::ttj::{jsondiff::diff, serializefont::ToValue};
-use read_fonts::{traversal::SomeTable, FontRef, TableProvider};
+use font_types::NameId;
+use read_fonts::{traversal::SomeTable, FontRef, ReadError, TableProvider};
 use serde_json::{Map, Value};
-use skrifa::{charmap::Charmap, string::StringId, GlyphId, MetadataProvider};
+use skrifa::{charmap::Charmap, GlyphId, Tag};
 
+pub mod anchors;
+mod bitmaps;
+pub mod cff;
+pub mod colr;
+pub mod features;
+pub mod fvar;
+pub mod gdef;
+pub mod glyph_count;
+mod hinting;
 pub mod jsondiff;
+pub mod kerning;
+mod meta;
+pub mod metrics;
+pub mod mvar;
+pub mod names;
+pub mod post_names;
 mod serializefont;
+pub mod stat;
+pub mod style_bits;
+pub mod substitutions;
+pub mod unicode_ranges;
+pub mod upm;
 
-fn serialize_name_table<'a>(font: &impl MetadataProvider<'a>) -> Value {
+fn serialize_name_table<'a>(font: &impl TableProvider<'a>) -> Value {
     let mut map = Map::new();
     if let Ok(name) = font.name() {
-        let mut ids: Vec<StringId> = name.name_record().iter().map(|x| x.name_id()).collect();
-        ids.sort_by_key(|id| id.to_u16());
-        for id in ids {
-            let strings = font.localized_strings(id);
-            if strings.clone().next().is_some() {
-                let mut localized = Map::new();
-                for string in font.localized_strings(id) {
-                    localized.insert(
-                        string.language().unwrap_or("default").to_string(),
-                        Value::String(string.to_string()),
-                    );
-                }
-                map.insert(id.to_string(), Value::Object(localized));
+        let mut records: Vec<_> = name.name_record().iter().collect();
+        records.sort_by_key(|r| {
+            (
+                r.name_id().to_u16(),
+                r.platform_id(),
+                r.encoding_id(),
+                r.language_id(),
+            )
+        });
+        for record in records {
+            let Ok(string) = record.string(name.string_data()) else {
+                continue;
+            };
+            let localized = map
+                .entry(record.name_id().to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(localized) = localized {
+                localized.insert(
+                    format!(
+                        "{}/{}/{}",
+                        record.platform_id(),
+                        record.encoding_id(),
+                        record.language_id()
+                    ),
+                    Value::String(string.chars().collect()),
+                );
             }
         }
     }
     Value::Object(map)
 }
 
-fn gid_to_name<'a>(font: &impl TableProvider<'a>, gid: GlyphId) -> String {
+pub(crate) fn gid_to_name<'a>(font: &impl TableProvider<'a>, gid: GlyphId) -> String {
     if let Ok(Some(name)) = font
         .post()
         .map(|post| post.glyph_name(gid).map(|x| x.to_string()))
     {
         name
     } else {
-        format!("gid{:}", gid)
+        unnamed(gid)
     }
 }
 
+/// The name given to a glyph which the `post` table doesn't name
+fn unnamed(gid: GlyphId) -> String {
+    format!("gid{:}", gid)
+}
+
+/// The name of each glyph in the `post` table, by glyph ID, or `None` for
+/// glyphs it doesn't name (and for all glyphs if it stores no names)
+///
+/// The table's strings are read once, rather than once per glyph as
+/// [gid_to_name] reads them.
+pub(crate) fn post_glyph_names(font: &FontRef) -> Vec<Option<String>> {
+    let glyph_count = font.maxp().map_or(0, |maxp| maxp.num_glyphs());
+    let post = font.post().ok();
+    let strings: Vec<Option<&str>> = post
+        .as_ref()
+        .and_then(|post| post.string_data())
+        .map(|strings| {
+            strings
+                .iter()
+                .map(|string| string.ok().map(|string| string.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    (0..glyph_count)
+        .map(|gid| {
+            let post = post.as_ref()?;
+            let index = post
+                .glyph_name_index()
+                .and_then(|indices| indices.get(gid as usize))
+                .map(|index| index.get() as usize);
+            match index {
+                // Names after the 258 standard Macintosh names are stored
+                // in the table
+                Some(index) if index >= 258 => strings.get(index - 258).copied().flatten(),
+                _ => post.glyph_name(GlyphId::new(gid)),
+            }
+            .map(str::to_string)
+        })
+        .collect()
+}
+
+/// The name of each glyph, by glyph ID, as [gid_to_name] gives it
+pub(crate) fn glyph_names(font: &FontRef) -> Vec<String> {
+    post_glyph_names(font)
+        .into_iter()
+        .enumerate()
+        .map(|(gid, name)| name.unwrap_or_else(|| unnamed(GlyphId::new(gid as u16))))
+        .collect()
+}
+
+/// The name of a glyph in a list made by [glyph_names], or the name
+/// [gid_to_name] gives a glyph beyond its end
+pub(crate) fn glyph_name(names: &[String], gid: GlyphId) -> String {
+    names
+        .get(gid.to_u16() as usize)
+        .cloned()
+        .unwrap_or_else(|| unnamed(gid))
+}
+
 fn serialize_cmap_table<'a>(font: &impl TableProvider<'a>) -> Value {
     let charmap = Charmap::new(font);
     let mut map = Map::new();
@@ -49,6 +142,39 @@ fn serialize_cmap_table<'a>(font: &impl TableProvider<'a>) -> Value {
     Value::Object(map)
 }
 
+/// Serialize the `gasp` table, which read-fonts does not parse, as a map
+/// from each range's maximum PPEM to the names of its behavior flags
+fn serialize_gasp_table<'a>(font: &impl TableProvider<'a>) -> Result<Value, ReadError> {
+    let data = font.expect_data_for_tag(Tag::new(b"gasp"))?;
+    let num_ranges: u16 = data.read_at(2)?;
+    let mut ranges = Map::new();
+    for index in 0..num_ranges as usize {
+        let max_ppem: u16 = data.read_at(4 + index * 4)?;
+        let behavior: u16 = data.read_at(6 + index * 4)?;
+        let flags: Vec<&str> = [
+            (0x0001, "GRIDFIT"),
+            (0x0002, "DOGRAY"),
+            (0x0004, "SYMMETRIC_GRIDFIT"),
+            (0x0008, "SYMMETRIC_SMOOTHING"),
+        ]
+        .iter()
+        .filter(|(bit, _)| behavior & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+        let flags = if flags.is_empty() {
+            "none".to_string()
+        } else {
+            flags.join(" | ")
+        };
+        ranges.insert(max_ppem.to_string(), Value::String(flags));
+    }
+    let version: u16 = data.read_at(0)?;
+    let mut map = Map::new();
+    map.insert("version".to_string(), Value::Number(version.into()));
+    map.insert("ranges".to_string(), Value::Object(ranges));
+    Ok(Value::Object(map))
+}
+
 fn serialize_hmtx_table<'a>(font: &impl TableProvider<'a>) -> Value {
     let mut map = Map::new();
     if let Ok(hmtx) = font.hmtx() {
@@ -82,9 +208,20 @@ fn serialize_hmtx_table<'a>(font: &impl TableProvider<'a>) -> Value {
 }
 
 pub fn font_to_json(font: &FontRef) -> Value {
+    font_to_json_filtered(font, None)
+}
+
+/// Serialize a font to JSON, optionally restricted to the given tables
+///
+/// Tables not in the list are skipped without being serialized.
+pub fn font_to_json_filtered(font: &FontRef, tables: Option<&[Tag]>) -> Value {
     let mut map = Map::new();
+    let wanted = |tag: Tag| tables.is_none_or(|tables| tables.contains(&tag));
 
     for table in font.table_directory.table_records().iter() {
+        if !wanted(table.tag()) {
+            continue;
+        }
         let key = table.tag().to_string();
         let value = match table.tag().into_bytes().as_ref() {
             b"head" => font.head().map(|t| <dyn SomeTable>::serialize(&t)),
@@ -111,6 +248,17 @@ pub fn font_to_json(font: &FontRef) -> Value {
             b"COLR" => font.colr().map(|t| <dyn SomeTable>::serialize(&t)),
             b"CPAL" => font.cpal().map(|t| <dyn SomeTable>::serialize(&t)),
             b"STAT" => font.stat().map(|t| <dyn SomeTable>::serialize(&t)),
+            b"gasp" => serialize_gasp_table(font),
+            b"meta" => meta::serialize_meta_table(font),
+            b"DSIG" => meta::serialize_dsig_table(font),
+            b"CFF " => cff::serialize_cff_table(font),
+            b"CFF2" => cff::serialize_cff2_table(font),
+            b"fpgm" => hinting::serialize_fpgm_table(font),
+            b"prep" => hinting::serialize_prep_table(font),
+            b"cvt " => hinting::serialize_cvt_table(font),
+            b"EBLC" => bitmaps::serialize_eblc_table(font),
+            b"CBLC" => bitmaps::serialize_cblc_table(font),
+            b"EBDT" | b"CBDT" => bitmaps::serialize_bitmap_data_table(font, table.tag()),
             _ => font.expect_data_for_tag(table.tag()).map(|tabledata| {
                 Value::Array(
                     tabledata
@@ -127,14 +275,78 @@ pub fn font_to_json(font: &FontRef) -> Value {
         );
         // }
     }
-    map.insert("name".to_string(), serialize_name_table(font));
-    map.insert("cmap".to_string(), serialize_cmap_table(font));
-    map.insert("hmtx".to_string(), serialize_hmtx_table(font));
+    if wanted(Tag::new(b"name")) {
+        map.insert("name".to_string(), serialize_name_table(font));
+    }
+    if wanted(Tag::new(b"cmap")) {
+        map.insert("cmap".to_string(), serialize_cmap_table(font));
+    }
+    if wanted(Tag::new(b"hmtx")) {
+        map.insert("hmtx".to_string(), serialize_hmtx_table(font));
+    }
     Value::Object(map)
 }
 
+/// The name IDs left out of table diffs by default, as they change with
+/// every build: the unique ID and the version string
+pub fn build_name_ids() -> Vec<u16> {
+    vec![NameId::UNIQUE_ID.to_u16(), NameId::VERSION_STRING.to_u16()]
+}
+
+/// Entries left out of table diffs, usually because they change with
+/// every build
+#[derive(Debug, Default)]
+pub struct Ignored<'a> {
+    /// Name IDs whose records are not compared
+    pub name_ids: &'a [u16],
+    /// Don't compare the `head` table's `modified` and `font_revision`
+    pub head_revision: bool,
+}
+
+impl Ignored<'_> {
+    fn remove_from(&self, font_json: &mut Value) {
+        if let Some(Value::Object(name)) = font_json.get_mut("name") {
+            for name_id in self.name_ids {
+                name.shift_remove(&NameId::new(*name_id).to_string());
+            }
+        }
+        if self.head_revision {
+            if let Some(Value::Object(head)) = font_json.get_mut("head") {
+                head.shift_remove("modified");
+                head.shift_remove("font_revision");
+            }
+        }
+    }
+}
+
+/// Diff the font tables, leaving out the entries which change with every
+/// build, as [DiffOptions](crate::diff::DiffOptions) does by default
 pub fn table_diff(font_a: &FontRef, font_b: &FontRef) -> Value {
-    diff(&font_to_json(font_a), &font_to_json(font_b))
+    let name_ids = build_name_ids();
+    table_diff_filtered(
+        font_a,
+        font_b,
+        None,
+        &Ignored {
+            name_ids: &name_ids,
+            head_revision: true,
+        },
+    )
+}
+
+/// Diff the font tables, optionally restricted to the given tables and
+/// leaving out the `ignored` entries
+pub fn table_diff_filtered(
+    font_a: &FontRef,
+    font_b: &FontRef,
+    tables: Option<&[Tag]>,
+    ignored: &Ignored,
+) -> Value {
+    let mut json_a = font_to_json_filtered(font_a, tables);
+    let mut json_b = font_to_json_filtered(font_b, tables);
+    ignored.remove_from(&mut json_a);
+    ignored.remove_from(&mut json_b);
+    diff(&json_a, &json_b)
 }
 
 // fn main() {
@@ -149,3 +361,34 @@ pub fn table_diff(font_a: &FontRef, font_b: &FontRef) -> Value {
 //         serde_json::to_string_pretty(&diff(&left, &right)).unwrap()
 //     );
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, head, name};
+
+    #[test]
+    fn test_table_diff_ignores_build_entries() {
+        let build_font = |version: &str, revision: u32, family: &str| {
+            let mut head = head(1000);
+            head[4..8].copy_from_slice(&revision.to_be_bytes());
+            font([
+                (b"head", head),
+                (b"name", name(&[(1, family), (3, version), (5, version)])),
+            ])
+        };
+        let old = build_font("1.000", 0x00010000, "Test");
+        let rebuilt = build_font("1.001", 0x00010042, "Test");
+        let renamed = build_font("1.001", 0x00010042, "Renamed");
+        let (old, rebuilt, renamed) = (
+            FontRef::new(&old).unwrap(),
+            FontRef::new(&rebuilt).unwrap(),
+            FontRef::new(&renamed).unwrap(),
+        );
+        assert_eq!(table_diff(&old, &rebuilt), Value::Null);
+
+        let diff = table_diff(&old, &renamed);
+        assert!(diff.get("name").is_some());
+        assert!(diff.get("head").is_none());
+    }
+}
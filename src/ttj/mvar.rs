@@ -0,0 +1,191 @@
+use read_fonts::{tables::mvar::tags, types::F2Dot14, FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::Tag;
+
+use super::upm::{scale_units, upm_scale};
+
+/// A metric whose value, once adjusted by MVAR at the fonts' locations,
+/// differs between two fonts
+///
+/// The new value is in the old font's units.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MvarDiff {
+    /// The MVAR value tag, such as `xhgt`
+    pub tag: String,
+    /// The field the tag adjusts, such as `OS/2.sxHeight`
+    pub metric: String,
+    pub old: i32,
+    pub new: i32,
+}
+
+/// The metrics which MVAR can adjust, other than the gasp ranges, with the
+/// fields they adjust
+const METRICS: [(Tag, &str); 28] = [
+    (tags::HASC, "OS/2.sTypoAscender"),
+    (tags::HDSC, "OS/2.sTypoDescender"),
+    (tags::HLGP, "OS/2.sTypoLineGap"),
+    (tags::HCLA, "OS/2.usWinAscent"),
+    (tags::HCLD, "OS/2.usWinDescent"),
+    (tags::VASC, "vhea.ascent"),
+    (tags::VDSC, "vhea.descent"),
+    (tags::VLGP, "vhea.lineGap"),
+    (tags::HCRS, "hhea.caretSlopeRise"),
+    (tags::HCRN, "hhea.caretSlopeRun"),
+    (tags::HCOF, "hhea.caretOffset"),
+    (tags::VCRS, "vhea.caretSlopeRise"),
+    (tags::VCRN, "vhea.caretSlopeRun"),
+    (tags::VCOF, "vhea.caretOffset"),
+    (tags::XHGT, "OS/2.sxHeight"),
+    (tags::CPHT, "OS/2.sCapHeight"),
+    (tags::SBXO, "OS/2.ySubscriptXOffset"),
+    (tags::SBYO, "OS/2.ySubscriptYOffset"),
+    (tags::SBXS, "OS/2.ySubscriptXSize"),
+    (tags::SBYS, "OS/2.ySubscriptYSize"),
+    (tags::SPXO, "OS/2.ySuperscriptXOffset"),
+    (tags::SPYO, "OS/2.ySuperscriptYOffset"),
+    (tags::SPXS, "OS/2.ySuperscriptXSize"),
+    (tags::SPYS, "OS/2.ySuperscriptYSize"),
+    (tags::STRS, "OS/2.yStrikeoutSize"),
+    (tags::STRO, "OS/2.yStrikeoutPosition"),
+    (tags::UNDS, "post.underlineThickness"),
+    (tags::UNDO, "post.underlinePosition"),
+];
+
+/// The value of a metric in the font's tables, before any variation
+fn base_value(font: &FontRef, tag: Tag) -> Option<i32> {
+    let os2 = || font.os2().ok();
+    let hhea = || font.hhea().ok();
+    let vhea = || font.vhea().ok();
+    let post = || font.post().ok();
+    Some(match tag {
+        tags::HASC => os2()?.s_typo_ascender().into(),
+        tags::HDSC => os2()?.s_typo_descender().into(),
+        tags::HLGP => os2()?.s_typo_line_gap().into(),
+        tags::HCLA => os2()?.us_win_ascent().into(),
+        tags::HCLD => os2()?.us_win_descent().into(),
+        tags::VASC => vhea()?.ascender().to_i16().into(),
+        tags::VDSC => vhea()?.descender().to_i16().into(),
+        tags::VLGP => vhea()?.line_gap().to_i16().into(),
+        tags::HCRS => hhea()?.caret_slope_rise().into(),
+        tags::HCRN => hhea()?.caret_slope_run().into(),
+        tags::HCOF => hhea()?.caret_offset().into(),
+        tags::VCRS => vhea()?.caret_slope_rise().into(),
+        tags::VCRN => vhea()?.caret_slope_run().into(),
+        tags::VCOF => vhea()?.caret_offset().into(),
+        tags::XHGT => os2()?.sx_height()?.into(),
+        tags::CPHT => os2()?.s_cap_height()?.into(),
+        tags::SBXO => os2()?.y_subscript_x_offset().into(),
+        tags::SBYO => os2()?.y_subscript_y_offset().into(),
+        tags::SBXS => os2()?.y_subscript_x_size().into(),
+        tags::SBYS => os2()?.y_subscript_y_size().into(),
+        tags::SPXO => os2()?.y_superscript_x_offset().into(),
+        tags::SPYO => os2()?.y_superscript_y_offset().into(),
+        tags::SPXS => os2()?.y_superscript_x_size().into(),
+        tags::SPYS => os2()?.y_superscript_y_size().into(),
+        tags::STRS => os2()?.y_strikeout_size().into(),
+        tags::STRO => os2()?.y_strikeout_position().into(),
+        tags::UNDS => post()?.underline_thickness().to_i16().into(),
+        tags::UNDO => post()?.underline_position().to_i16().into(),
+        _ => return None,
+    })
+}
+
+/// The value of a metric at a location, with the MVAR delta applied and
+/// rounded to font units
+fn resolved_value(font: &FontRef, tag: Tag, coords: &[F2Dot14]) -> Option<i32> {
+    let base = base_value(font, tag)?;
+    let delta = font
+        .mvar()
+        .ok()
+        .and_then(|mvar| mvar.metric_delta(tag, coords).ok())
+        .map_or(0.0, |delta| delta.to_f64());
+    Some((base as f64 + delta).round() as i32)
+}
+
+/// Compare the metrics which MVAR adjusts, resolved at each font's
+/// location (given as normalized coordinates)
+///
+/// If neither font has an MVAR table, these metrics are the same at every
+/// location and the table diff already covers them, so nothing is
+/// reported. Metrics missing from either font's tables are skipped. If the
+/// fonts' units per em differ, the new font's values are scaled to the old
+/// font's units first.
+pub fn mvar_diff(
+    font_a: &FontRef,
+    coords_a: &[F2Dot14],
+    font_b: &FontRef,
+    coords_b: &[F2Dot14],
+) -> Vec<MvarDiff> {
+    if font_a.mvar().is_err() && font_b.mvar().is_err() {
+        return vec![];
+    }
+    let scale = upm_scale(font_a, font_b);
+    METRICS
+        .iter()
+        .filter_map(|(tag, metric)| {
+            let old = resolved_value(font_a, *tag, coords_a)?;
+            let new = scale_units(resolved_value(font_b, *tag, coords_b)?, scale);
+            (old != new).then(|| MvarDiff {
+                tag: tag.to_string(),
+                metric: metric.to_string(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, be32, font, hhea};
+
+    /// A font whose `hhea.caretOffset` is 0, and if `delta` is given,
+    /// an MVAR table moving it by `delta` units at the maximum of the
+    /// first axis
+    fn caret_font(delta: Option<i32>) -> Vec<u8> {
+        let mut tables = vec![(b"hhea", hhea(800, -200, 0))];
+        if let Some(delta) = delta {
+            let mut mvar = be16(&[1, 0, 0, 8, 1, 20]);
+            mvar.extend(b"hcof");
+            mvar.extend(be16(&[0, 0]));
+            // Item variation store: one region, peaking at the axis
+            // maximum, and one item with a single word delta
+            mvar.extend(be16(&[1]));
+            mvar.extend(be32(&[12]));
+            mvar.extend(be16(&[1]));
+            mvar.extend(be32(&[22]));
+            mvar.extend(be16(&[1, 1, 0, 16384, 16384]));
+            mvar.extend(be16(&[1, 1, 1, 0, delta]));
+            tables.push((b"MVAR", mvar));
+        }
+        font(tables)
+    }
+
+    #[test]
+    fn test_mvar_diff() {
+        let varied = caret_font(Some(50));
+        let fixed = caret_font(None);
+        let (varied, fixed) = (
+            FontRef::new(&varied).unwrap(),
+            FontRef::new(&fixed).unwrap(),
+        );
+        let (default, max) = ([F2Dot14::from_f32(0.0)], [F2Dot14::from_f32(1.0)]);
+        assert!(mvar_diff(&varied, &default, &fixed, &default).is_empty());
+        assert!(mvar_diff(&varied, &max, &varied, &max).is_empty());
+
+        let diffs = mvar_diff(&varied, &max, &fixed, &max);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(
+            (diffs[0].tag.as_str(), diffs[0].metric.as_str()),
+            ("hcof", "hhea.caretOffset")
+        );
+        assert_eq!((diffs[0].old, diffs[0].new), (50, 0));
+
+        // Halfway along the axis, the delta is halved
+        let half = [F2Dot14::from_f32(0.5)];
+        let diffs = mvar_diff(&varied, &half, &varied, &max);
+        assert_eq!((diffs[0].old, diffs[0].new), (25, 50));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use font_types::NameId;
+use read_fonts::{FontRef, TableProvider};
+use skrifa::MetadataProvider;
+
+/// The family and subfamily names an application sees, with the name IDs
+/// to try in turn. Typographic names fall back to the legacy names, and
+/// WWS names to the typographic names.
+const ROLES: [(&str, &[NameId]); 6] = [
+    ("family", &[NameId::FAMILY_NAME]),
+    ("subfamily", &[NameId::SUBFAMILY_NAME]),
+    (
+        "typographic family",
+        &[NameId::TYPOGRAPHIC_FAMILY_NAME, NameId::FAMILY_NAME],
+    ),
+    (
+        "typographic subfamily",
+        &[NameId::TYPOGRAPHIC_SUBFAMILY_NAME, NameId::SUBFAMILY_NAME],
+    ),
+    (
+        "WWS family",
+        &[
+            NameId::WWS_FAMILY_NAME,
+            NameId::TYPOGRAPHIC_FAMILY_NAME,
+            NameId::FAMILY_NAME,
+        ],
+    ),
+    (
+        "WWS subfamily",
+        &[
+            NameId::WWS_SUBFAMILY_NAME,
+            NameId::TYPOGRAPHIC_SUBFAMILY_NAME,
+            NameId::SUBFAMILY_NAME,
+        ],
+    ),
+];
+
+/// The subfamily names which legacy (style-linked) families may use
+const RIBBI: [&str; 4] = ["Regular", "Italic", "Bold", "Bold Italic"];
+
+/// The English (or first) string for each name ID in the font
+fn names(font: &FontRef) -> BTreeMap<NameId, String> {
+    let Ok(name) = font.name() else {
+        return BTreeMap::new();
+    };
+    name.name_record()
+        .iter()
+        .filter_map(|record| {
+            let name_id = record.name_id();
+            let string = font.localized_strings(name_id).english_or_first()?;
+            Some((name_id, string.chars().collect()))
+        })
+        .collect()
+}
+
+fn describe(name_id: NameId) -> String {
+    let description = match name_id {
+        NameId::COPYRIGHT_NOTICE => "copyright notice",
+        NameId::FAMILY_NAME => "family name",
+        NameId::SUBFAMILY_NAME => "subfamily name",
+        NameId::UNIQUE_ID => "unique identifier",
+        NameId::FULL_NAME => "full name",
+        NameId::VERSION_STRING => "version string",
+        NameId::POSTSCRIPT_NAME => "PostScript name",
+        NameId::TRADEMARK => "trademark",
+        NameId::MANUFACTURER => "manufacturer",
+        NameId::DESIGNER => "designer",
+        NameId::DESCRIPTION => "description",
+        NameId::VENDOR_URL => "vendor URL",
+        NameId::DESIGNER_URL => "designer URL",
+        NameId::LICENSE_DESCRIPTION => "license description",
+        NameId::LICENSE_URL => "license URL",
+        NameId::TYPOGRAPHIC_FAMILY_NAME => "typographic family name",
+        NameId::TYPOGRAPHIC_SUBFAMILY_NAME => "typographic subfamily name",
+        NameId::COMPATIBLE_FULL_NAME => "compatible full name",
+        NameId::SAMPLE_TEXT => "sample text",
+        NameId::POSTSCRIPT_CID_NAME => "PostScript CID findfont name",
+        NameId::WWS_FAMILY_NAME => "WWS family name",
+        NameId::WWS_SUBFAMILY_NAME => "WWS subfamily name",
+        NameId::LIGHT_BACKGROUND_PALETTE => "light background palette",
+        NameId::DARK_BACKGROUND_PALETTE => "dark background palette",
+        NameId::VARIATIONS_POSTSCRIPT_NAME_PREFIX => "variations PostScript name prefix",
+        _ => return format!("name ID {}", name_id.to_u16()),
+    };
+    format!("{} (ID {})", description, name_id.to_u16())
+}
+
+/// Find the name an application would use for a role, and the ID it
+/// came from
+fn resolve<'a>(
+    names: &'a BTreeMap<NameId, String>,
+    name_ids: &[NameId],
+) -> Option<(&'a String, NameId)> {
+    name_ids
+        .iter()
+        .find_map(|name_id| names.get(name_id).map(|name| (name, *name_id)))
+}
+
+/// Compare the name tables of two fonts semantically
+///
+/// Family and subfamily names are compared as an application would
+/// resolve them, so a typographic family name (ID 16) which is absent is
+/// taken from the legacy family name (ID 1), and so on. Changes to where
+/// a name is taken from are reported even when the name itself is the
+/// same. Other name IDs are compared directly, using the English (or
+/// first) string. Returns a list of human-readable changes, e.g.
+/// "typographic family changed from 'Foo' to 'Foo Pro'".
+pub fn name_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<String> {
+    let names_a = names(font_a);
+    let names_b = names(font_b);
+    let mut changes = vec![];
+
+    // A name which falls back to another is not reported as changing
+    // separately, unless it is taken from a different ID
+    let mut changed_ids = vec![];
+    for (role, name_ids) in ROLES.iter() {
+        match (resolve(&names_a, name_ids), resolve(&names_b, name_ids)) {
+            (Some((old, old_id)), Some((new, new_id))) => {
+                if old != new && !(old_id == new_id && changed_ids.contains(&new_id)) {
+                    changes.push(format!("{} changed from '{}' to '{}'", role, old, new));
+                    changed_ids.push(new_id);
+                }
+                if old_id != new_id {
+                    changes.push(format!(
+                        "{} is now taken from {} instead of {}",
+                        role,
+                        describe(new_id),
+                        describe(old_id)
+                    ));
+                }
+            }
+            (Some((old, _)), None) => changes.push(format!("{} '{}' removed", role, old)),
+            (None, Some((new, _))) => changes.push(format!("{} '{}' added", role, new)),
+            (None, None) => {}
+        }
+    }
+
+    let is_ribbi = |names: &BTreeMap<NameId, String>| {
+        names
+            .get(&NameId::SUBFAMILY_NAME)
+            .map(|subfamily| RIBBI.contains(&subfamily.as_str()))
+    };
+    if let (Some(true), Some(false)) = (is_ribbi(&names_a), is_ribbi(&names_b)) {
+        changes.push(format!(
+            "subfamily '{}' is no longer one of {}, so style linking may break",
+            names_b[&NameId::SUBFAMILY_NAME],
+            RIBBI.join(", ")
+        ));
+    }
+
+    // Family and subfamily names were compared above
+    let is_role = |name_id: &NameId| ROLES.iter().any(|(_, ids)| ids.contains(name_id));
+    for (name_id, old) in names_a.iter().filter(|(id, _)| !is_role(id)) {
+        match names_b.get(name_id) {
+            Some(new) if new != old => changes.push(format!(
+                "{} changed from '{}' to '{}'",
+                describe(*name_id),
+                old,
+                new
+            )),
+            Some(_) => {}
+            None => changes.push(format!("{} '{}' removed", describe(*name_id), old)),
+        }
+    }
+    for (name_id, new) in names_b.iter().filter(|(id, _)| !is_role(id)) {
+        if !names_a.contains_key(name_id) {
+            changes.push(format!("{} '{}' added", describe(*name_id), new));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, name};
+
+    #[test]
+    fn test_name_diff() {
+        let old = font([(
+            b"name",
+            name(&[(1, "Foo"), (2, "Bold"), (5, "Version 1.000")]),
+        )]);
+        let new = font([(
+            b"name",
+            name(&[
+                (1, "Foo"),
+                (2, "Heavy"),
+                (5, "Version 1.001"),
+                (16, "Foo Pro"),
+            ]),
+        )]);
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+        assert!(name_diff(&old, &old).is_empty());
+        assert_eq!(
+            name_diff(&old, &new),
+            vec![
+                "subfamily changed from 'Bold' to 'Heavy'",
+                "typographic family changed from 'Foo' to 'Foo Pro'",
+                "typographic family is now taken from typographic family name (ID 16) instead of family name (ID 1)",
+                "WWS family changed from 'Foo' to 'Foo Pro'",
+                "WWS family is now taken from typographic family name (ID 16) instead of family name (ID 1)",
+                "subfamily 'Heavy' is no longer one of Regular, Italic, Bold, Bold Italic, so style linking may break",
+                "version string (ID 5) changed from 'Version 1.000' to 'Version 1.001'",
+            ]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+
+use read_fonts::FontRef;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::post_glyph_names;
+
+/// A glyph ID whose `post` table name differs between two fonts
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RenamedGlyph {
+    pub gid: u16,
+    pub old: String,
+    pub new: String,
+}
+
+/// Changes to the glyph names stored in the `post` table
+#[derive(Debug, Serialize, Default, JsonSchema)]
+pub struct PostNameDiff {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed: Vec<RenamedGlyph>,
+    /// Names found only in the new font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Names found only in the old font
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl PostNameDiff {
+    pub fn is_some(&self) -> bool {
+        !self.renamed.is_empty() || !self.added.is_empty() || !self.removed.is_empty()
+    }
+}
+
+/// Compare the glyph names in the `post` tables of two fonts
+///
+/// Glyphs are aligned by glyph ID; those named in both fonts whose names
+/// differ are reported as renamed. Names found in only one font are
+/// reported as added or removed.
+pub fn post_name_diff(font_a: &FontRef, font_b: &FontRef) -> PostNameDiff {
+    let names_a = post_glyph_names(font_a);
+    let names_b = post_glyph_names(font_b);
+    let renamed = names_a
+        .iter()
+        .zip(names_b.iter())
+        .enumerate()
+        .filter_map(|(gid, names)| match names {
+            (Some(old), Some(new)) if old != new => Some(RenamedGlyph {
+                gid: gid as u16,
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            _ => None,
+        })
+        .collect();
+    let set_a: BTreeSet<&String> = names_a.iter().flatten().collect();
+    let set_b: BTreeSet<&String> = names_b.iter().flatten().collect();
+    PostNameDiff {
+        renamed,
+        added: set_b
+            .difference(&set_a)
+            .map(|name| name.to_string())
+            .collect(),
+        removed: set_a
+            .difference(&set_b)
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, maxp, post};
+
+    #[test]
+    fn test_renamed_glyph() {
+        let old = font([(b"maxp", maxp(3)), (b"post", post(&[".notdef", "a", "b"]))]);
+        let new = font([(b"maxp", maxp(3)), (b"post", post(&[".notdef", "a", "c"]))]);
+        let diff = post_name_diff(&FontRef::new(&old).unwrap(), &FontRef::new(&new).unwrap());
+        assert!(diff.is_some());
+        assert_eq!(diff.renamed.len(), 1);
+        assert_eq!(diff.renamed[0].gid, 2);
+        assert_eq!(diff.renamed[0].old, "b");
+        assert_eq!(diff.renamed[0].new, "c");
+        assert_eq!(diff.added, vec!["c"]);
+        assert_eq!(diff.removed, vec!["b"]);
+
+        let same = post_name_diff(&FontRef::new(&old).unwrap(), &FontRef::new(&old).unwrap());
+        assert!(!same.is_some());
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use font_types::{Fixed, NameId};
+use read_fonts::{
+    tables::stat::{AxisRecord, AxisValue, AxisValueTableFlags, Stat},
+    FontRef, TableProvider,
+};
+use skrifa::MetadataProvider;
+
+/// A design axis, keyed by its tag
+struct Axis {
+    name: String,
+    ordering: u16,
+}
+
+/// An axis value table, keyed by the location it describes
+struct Value {
+    name: String,
+    flags: AxisValueTableFlags,
+    linked_value: Option<f64>,
+}
+
+fn name(font: &FontRef, name_id: NameId) -> String {
+    font.localized_strings(name_id)
+        .english_or_first()
+        .map_or_else(|| format!("name ID {}", name_id), |s| s.chars().collect())
+}
+
+fn axes(font: &FontRef, stat: &Stat) -> BTreeMap<String, Axis> {
+    stat.design_axes()
+        .unwrap_or_default()
+        .iter()
+        .map(|axis| {
+            (
+                axis.axis_tag().to_string(),
+                Axis {
+                    name: name(font, axis.axis_name_id()),
+                    ordering: axis.axis_ordering(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Describe where an axis value applies, e.g. `wght=700` or
+/// `wght=300..500 (nominal 400)`
+fn location(axis_value: &AxisValue, design_axes: &[AxisRecord]) -> String {
+    let tag = |index: u16| {
+        design_axes
+            .get(index as usize)
+            .map_or_else(|| format!("axis {}", index), |a| a.axis_tag().to_string())
+    };
+    let value = |fixed: Fixed| fixed.to_f64();
+    match axis_value {
+        AxisValue::Format1(v) => format!("{}={}", tag(v.axis_index()), value(v.value())),
+        AxisValue::Format2(v) => format!(
+            "{}={}..{} (nominal {})",
+            tag(v.axis_index()),
+            value(v.range_min_value()),
+            value(v.range_max_value()),
+            value(v.nominal_value())
+        ),
+        AxisValue::Format3(v) => format!("{}={}", tag(v.axis_index()), value(v.value())),
+        AxisValue::Format4(v) => v
+            .axis_values()
+            .iter()
+            .map(|record| format!("{}={}", tag(record.axis_index()), value(record.value())))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// The axis value tables, keyed by the location they describe
+///
+/// If a table has more than one axis value for the same location, only the
+/// first is kept, as the others can never be chosen to name a font.
+fn values(font: &FontRef, stat: &Stat) -> BTreeMap<String, Value> {
+    let design_axes = stat.design_axes().unwrap_or_default();
+    let mut values = BTreeMap::new();
+    let Some(Ok(array)) = stat.offset_to_axis_values() else {
+        return values;
+    };
+    for axis_value in array.axis_values().iter().flatten() {
+        let linked_value = match &axis_value {
+            AxisValue::Format3(v) => Some(v.linked_value().to_f64()),
+            _ => None,
+        };
+        values
+            .entry(location(&axis_value, design_axes))
+            .or_insert(Value {
+                name: name(font, axis_value.value_name_id()),
+                flags: axis_value.flags(),
+                linked_value,
+            });
+    }
+    values
+}
+
+/// The name used for a font whose axis values are all elided
+///
+/// Version 1.0 tables have no elided fallback name ID, and the spec says
+/// to fall back to name ID 2.
+fn elided_fallback_name(font: &FontRef, stat: &Stat) -> String {
+    name(
+        font,
+        stat.elided_fallback_name_id()
+            .unwrap_or(NameId::SUBFAMILY_NAME),
+    )
+}
+
+fn flag_changes(
+    description: &str,
+    old: AxisValueTableFlags,
+    new: AxisValueTableFlags,
+    changes: &mut Vec<String>,
+) {
+    for (flag, flag_name) in [
+        (AxisValueTableFlags::ELIDABLE_AXIS_VALUE_NAME, "elidable"),
+        (
+            AxisValueTableFlags::OLDER_SIBLING_FONT_ATTRIBUTE,
+            "an older sibling font attribute",
+        ),
+    ] {
+        match (old.contains(flag), new.contains(flag)) {
+            (false, true) => changes.push(format!("{} is now {}", description, flag_name)),
+            (true, false) => changes.push(format!("{} is no longer {}", description, flag_name)),
+            _ => {}
+        }
+    }
+}
+
+/// Compare the STAT tables of two fonts semantically
+///
+/// Axis records are matched by tag and axis value tables by the location
+/// they describe, so reordering records is not reported as a change.
+/// Names are resolved through the `name` table. Returns a list of
+/// human-readable changes, e.g. "added axis value 'Condensed' for wdth=75".
+pub fn stat_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<String> {
+    let (stat_a, stat_b) = match (font_a.stat(), font_b.stat()) {
+        (Ok(stat_a), Ok(stat_b)) => (stat_a, stat_b),
+        (Ok(_), Err(_)) => return vec!["STAT table removed".to_string()],
+        (Err(_), Ok(_)) => return vec!["STAT table added".to_string()],
+        (Err(_), Err(_)) => return vec![],
+    };
+    let mut changes = vec![];
+
+    let axes_a = axes(font_a, &stat_a);
+    let axes_b = axes(font_b, &stat_b);
+    for (tag, axis) in axes_a.iter() {
+        let Some(new) = axes_b.get(tag) else {
+            changes.push(format!("removed axis {} '{}'", tag, axis.name));
+            continue;
+        };
+        if axis.name != new.name {
+            changes.push(format!(
+                "axis {} renamed from '{}' to '{}'",
+                tag, axis.name, new.name
+            ));
+        }
+        if axis.ordering != new.ordering {
+            changes.push(format!(
+                "axis {} ordering changed from {} to {}",
+                tag, axis.ordering, new.ordering
+            ));
+        }
+    }
+    for (tag, axis) in axes_b.iter() {
+        if !axes_a.contains_key(tag) {
+            changes.push(format!("added axis {} '{}'", tag, axis.name));
+        }
+    }
+
+    let values_a = values(font_a, &stat_a);
+    let values_b = values(font_b, &stat_b);
+    for (location, value) in values_a.iter() {
+        let Some(new) = values_b.get(location) else {
+            changes.push(format!(
+                "removed axis value '{}' for {}",
+                value.name, location
+            ));
+            continue;
+        };
+        if value.name != new.name {
+            changes.push(format!(
+                "axis value for {} renamed from '{}' to '{}'",
+                location, value.name, new.name
+            ));
+        }
+        let description = format!("axis value '{}' for {}", new.name, location);
+        flag_changes(&description, value.flags, new.flags, &mut changes);
+        match (value.linked_value, new.linked_value) {
+            (Some(old), Some(new)) if old != new => changes.push(format!(
+                "{} now links to {} instead of {}",
+                description, new, old
+            )),
+            (None, Some(new)) => changes.push(format!("{} now links to {}", description, new)),
+            (Some(old), None) => {
+                changes.push(format!("{} no longer links to {}", description, old))
+            }
+            _ => {}
+        }
+    }
+    for (location, value) in values_b.iter() {
+        if !values_a.contains_key(location) {
+            changes.push(format!(
+                "added axis value '{}' for {}",
+                value.name, location
+            ));
+        }
+    }
+
+    let fallback_a = elided_fallback_name(font_a, &stat_a);
+    let fallback_b = elided_fallback_name(font_b, &stat_b);
+    if fallback_a != fallback_b {
+        changes.push(format!(
+            "elided fallback name changed from '{}' to '{}'",
+            fallback_a, fallback_b
+        ));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, stat};
+
+    #[test]
+    fn test_duplicate_axis_values() {
+        let duplicated = font([(b"STAT", stat(&[(400, 257), (400, 258), (700, 259)]))]);
+        let single = font([(b"STAT", stat(&[(400, 257), (700, 259)]))]);
+        let reversed = font([(b"STAT", stat(&[(400, 258), (400, 257), (700, 259)]))]);
+        let font = |data| FontRef::new(data).unwrap();
+
+        // Only the first value for a location is compared
+        assert!(stat_diff(&font(&duplicated), &font(&single)).is_empty());
+        assert_eq!(
+            stat_diff(&font(&duplicated), &font(&reversed)),
+            ["axis value for wght=400 renamed from 'name ID 257' to 'name ID 258'"]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use font_types::NameId;
+use read_fonts::{
+    tables::{head::MacStyle, os2::SelectionFlags},
+    FontRef, TableProvider,
+};
+use skrifa::MetadataProvider;
+
+/// Whether a font is bold and italic, as one of the places which record
+/// it says
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+}
+
+impl Style {
+    fn describe(&self) -> &'static str {
+        match (self.bold, self.italic) {
+            (false, false) => "Regular",
+            (true, false) => "Bold",
+            (false, true) => "Italic",
+            (true, true) => "Bold Italic",
+        }
+    }
+}
+
+/// The style recorded by `head.macStyle`, `OS/2.fsSelection` and the
+/// subfamily name (ID 2), for those the font has, with a description of
+/// each record
+///
+/// The subfamily name is taken to be bold if it contains the word "Bold",
+/// and italic if it contains "Italic" or "Oblique".
+fn recorded_styles(font: &FontRef) -> Vec<(&'static str, String, Style)> {
+    let mut styles = vec![];
+    if let Ok(head) = font.head() {
+        let mac_style = head.mac_style();
+        styles.push((
+            "head.macStyle",
+            "head.macStyle".to_string(),
+            Style {
+                bold: mac_style.contains(MacStyle::BOLD),
+                italic: mac_style.contains(MacStyle::ITALIC),
+            },
+        ));
+    }
+    if let Ok(os2) = font.os2() {
+        let fs_selection = os2.fs_selection();
+        styles.push((
+            "OS/2.fsSelection",
+            "OS/2.fsSelection".to_string(),
+            Style {
+                bold: fs_selection.contains(SelectionFlags::BOLD),
+                italic: fs_selection.contains(SelectionFlags::ITALIC),
+            },
+        ));
+    }
+    if let Some(subfamily) = font
+        .localized_strings(NameId::SUBFAMILY_NAME)
+        .english_or_first()
+    {
+        let subfamily: String = subfamily.chars().collect();
+        let has_word = |word: &str| subfamily.split_whitespace().any(|w| w == word);
+        styles.push((
+            "subfamily name",
+            format!("subfamily name '{}'", subfamily),
+            Style {
+                bold: has_word("Bold"),
+                italic: has_word("Italic") || has_word("Oblique"),
+            },
+        ));
+    }
+    styles
+}
+
+/// The ways in which a font's records of its style disagree
+fn inconsistencies(font: &FontRef) -> Vec<String> {
+    let styles = recorded_styles(font);
+    let mut problems = vec![];
+    if styles.windows(2).any(|pair| pair[0].2 != pair[1].2) {
+        problems.push(
+            styles
+                .iter()
+                .map(|(_, record, style)| format!("{} says {}", record, style.describe()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+    if let Ok(os2) = font.os2() {
+        let fs_selection = os2.fs_selection();
+        if fs_selection.contains(SelectionFlags::REGULAR)
+            && fs_selection.intersects(SelectionFlags::BOLD | SelectionFlags::ITALIC)
+        {
+            problems.push("OS/2.fsSelection sets REGULAR along with BOLD or ITALIC".to_string());
+        }
+    }
+    problems
+}
+
+/// Check that the bold and italic bits of `head.macStyle` and
+/// `OS/2.fsSelection` and the subfamily name agree, in and between two
+/// fonts
+///
+/// Reports the places whose style differs between the fonts, then the
+/// disagreements within either font or both.
+pub fn style_bits_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<String> {
+    let mut changes = vec![];
+    let styles_b = recorded_styles(font_b);
+    for (place, _, old) in recorded_styles(font_a) {
+        if let Some((_, _, new)) = styles_b.iter().find(|(p, _, _)| *p == place) {
+            if old != *new {
+                changes.push(format!(
+                    "{} changed from {} to {}",
+                    place,
+                    old.describe(),
+                    new.describe()
+                ));
+            }
+        }
+    }
+
+    let problems_a = inconsistencies(font_a);
+    let problems_b = inconsistencies(font_b);
+    for problem in &problems_a {
+        let which = if problems_b.contains(problem) {
+            "both fonts"
+        } else {
+            "old font"
+        };
+        changes.push(format!("{}: {}", which, problem));
+    }
+    for problem in problems_b.iter().filter(|p| !problems_a.contains(p)) {
+        changes.push(format!("new font: {}", problem));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{font, head, name, os2};
+
+    /// A font whose `head.macStyle`, `OS/2.fsSelection` and subfamily
+    /// name are as given
+    fn style_font(mac_style: u16, fs_selection: u16, subfamily: &str) -> Vec<u8> {
+        let mut head = head(1000);
+        head[44..46].copy_from_slice(&mac_style.to_be_bytes());
+        font([
+            (b"OS/2", os2(&[], fs_selection)),
+            (b"head", head),
+            (b"name", name(&[(2, subfamily)])),
+        ])
+    }
+
+    #[test]
+    fn test_style_bits_diff() {
+        let bold = style_font(1, 0x20, "Bold");
+        let broken = style_font(1, 0x40, "Bold");
+        let (bold, broken) = (FontRef::new(&bold).unwrap(), FontRef::new(&broken).unwrap());
+        assert!(style_bits_diff(&bold, &bold).is_empty());
+        assert_eq!(
+            style_bits_diff(&bold, &broken),
+            vec![
+                "OS/2.fsSelection changed from Bold to Regular",
+                "new font: head.macStyle says Bold, OS/2.fsSelection says Regular, subfamily name 'Bold' says Bold",
+            ]
+        );
+
+        let regular_and_italic = style_font(2, 0x41, "Italic");
+        assert_eq!(
+            style_bits_diff(&FontRef::new(&regular_and_italic).unwrap(), &broken),
+            vec![
+                "head.macStyle changed from Italic to Bold",
+                "OS/2.fsSelection changed from Italic to Regular",
+                "subfamily name changed from Italic to Bold",
+                "old font: OS/2.fsSelection sets REGULAR along with BOLD or ITALIC",
+                "new font: head.macStyle says Bold, OS/2.fsSelection says Regular, subfamily name 'Bold' says Bold",
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use read_fonts::{
+    tables::gsub::{ExtensionSubtable, SingleSubst, SubstitutionLookup},
+    FontRef, TableProvider,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use skrifa::GlyphId;
+
+use super::{glyph_name, glyph_names};
+
+/// A glyph whose single substitution under a GSUB feature differs between
+/// two fonts
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubstitutionDiff {
+    pub feature: String,
+    pub glyph: String,
+    /// The glyph substituted in the old font, if any
+    pub old: Option<String>,
+    /// The glyph substituted in the new font, if any
+    pub new: Option<String>,
+}
+
+/// The targets of the single substitutions (GSUB lookup type 1) reachable
+/// from each feature, keyed by feature tag and input glyph name
+///
+/// Lookups wrapped in extension lookups (GSUB type 7) are included. Within
+/// a lookup only the first subtable which covers a glyph applies. If the
+/// feature's lookups substitute a glyph with different glyphs, all of them
+/// are collected.
+fn single_substitutions(font: &FontRef) -> BTreeMap<(String, String), BTreeSet<String>> {
+    let mut substitutions: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    let Ok(gsub) = font.gsub() else {
+        return substitutions;
+    };
+    let (Ok(feature_list), Ok(lookup_list)) = (gsub.feature_list(), gsub.lookup_list()) else {
+        return substitutions;
+    };
+    let mut feature_lookups: BTreeMap<String, BTreeSet<u16>> = BTreeMap::new();
+    for record in feature_list.feature_records() {
+        let Ok(feature) = record.feature(feature_list.offset_data()) else {
+            continue;
+        };
+        feature_lookups
+            .entry(record.feature_tag().to_string())
+            .or_default()
+            .extend(feature.lookup_list_indices().iter().map(|ix| ix.get()));
+    }
+    let lookups = lookup_list.lookups();
+    let names = glyph_names(font);
+    for (feature, indices) in feature_lookups {
+        for index in indices {
+            let Ok(lookup) = lookups.get(index as usize) else {
+                continue;
+            };
+            let mut seen: HashSet<GlyphId> = HashSet::new();
+            for subtable in single_subtables(&lookup) {
+                for (input, output) in subtable_mappings(&subtable) {
+                    if seen.insert(input) {
+                        substitutions
+                            .entry((feature.clone(), glyph_name(&names, input)))
+                            .or_default()
+                            .insert(glyph_name(&names, output));
+                    }
+                }
+            }
+        }
+    }
+    substitutions
+}
+
+/// The single substitution subtables of a lookup, unwrapping them from an
+/// extension lookup
+fn single_subtables<'a>(lookup: &SubstitutionLookup<'a>) -> Vec<SingleSubst<'a>> {
+    match lookup {
+        SubstitutionLookup::Single(lookup) => lookup.subtables().iter().flatten().collect(),
+        SubstitutionLookup::Extension(lookup) => lookup
+            .subtables()
+            .iter()
+            .flatten()
+            .filter_map(|subtable| match subtable {
+                ExtensionSubtable::Single(extension) => extension.extension().ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn subtable_mappings(subtable: &SingleSubst) -> Vec<(GlyphId, GlyphId)> {
+    match subtable {
+        SingleSubst::Format1(format1) => {
+            let delta = format1.delta_glyph_id();
+            format1.coverage().map_or(vec![], |coverage| {
+                coverage
+                    .iter()
+                    .map(|gid| {
+                        let output = (gid.to_u16() as i32 + delta as i32).rem_euclid(65536);
+                        (gid, GlyphId::new(output as u16))
+                    })
+                    .collect()
+            })
+        }
+        SingleSubst::Format2(format2) => format2.coverage().map_or(vec![], |coverage| {
+            coverage
+                .iter()
+                .zip(format2.substitute_glyph_ids().iter().map(|gid| gid.get()))
+                .collect()
+        }),
+    }
+}
+
+/// Compare the single substitutions under each GSUB feature of two fonts
+///
+/// Glyphs are matched by name, so a change in glyph order isn't reported,
+/// but a substitution which now points at a different glyph is. Glyphs
+/// substituted with several glyphs by one feature are shown with the
+/// alternatives separated by " or ".
+pub fn substitution_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<SubstitutionDiff> {
+    let substitutions_a = single_substitutions(font_a);
+    let substitutions_b = single_substitutions(font_b);
+    let keys: BTreeSet<&(String, String)> = substitutions_a
+        .keys()
+        .chain(substitutions_b.keys())
+        .collect();
+    let targets = |targets: Option<&BTreeSet<String>>| {
+        targets.map(|targets| targets.iter().cloned().collect::<Vec<_>>().join(" or "))
+    };
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = targets(substitutions_a.get(key));
+            let new = targets(substitutions_b.get(key));
+            (old != new).then(|| SubstitutionDiff {
+                feature: key.0.clone(),
+                glyph: key.1.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{be16, coverage, extension, font, layout, maxp, post};
+
+    const GLYPHS: [&str; 6] = [".notdef", "a", "b", "a.sc", "b.sc", "a.alt"];
+
+    /// A font whose `smcp` feature uses the (lookup type, subtables) lookup
+    fn smcp_font(lookup: (u16, Vec<Vec<u8>>)) -> Vec<u8> {
+        font([
+            (b"GSUB", layout(&[b"smcp"], &[lookup])),
+            (b"maxp", maxp(GLYPHS.len() as u16)),
+            (b"post", post(&GLYPHS)),
+        ])
+    }
+
+    /// A format 1 single substitution adding `delta` to each glyph ID
+    fn single_subst_1(glyphs: &[u16], delta: i32) -> Vec<u8> {
+        let mut subtable = be16(&[1, 6, delta]);
+        subtable.extend(coverage(glyphs));
+        subtable
+    }
+
+    /// A format 2 single substitution of each (glyph, substitute)
+    fn single_subst_2(mappings: &[(u16, u16)]) -> Vec<u8> {
+        let count = mappings.len() as i32;
+        let mut subtable = be16(&[2, 6 + 2 * count, count]);
+        subtable.extend(be16(
+            &mappings
+                .iter()
+                .map(|(_, to)| *to as i32)
+                .collect::<Vec<_>>(),
+        ));
+        let glyphs: Vec<u16> = mappings.iter().map(|(from, _)| *from).collect();
+        subtable.extend(coverage(&glyphs));
+        subtable
+    }
+
+    fn diff(old: &[u8], new: &[u8]) -> Vec<(String, Option<String>, Option<String>)> {
+        substitution_diff(&FontRef::new(old).unwrap(), &FontRef::new(new).unwrap())
+            .into_iter()
+            .map(|diff| {
+                assert_eq!(diff.feature, "smcp");
+                (diff.glyph, diff.old, diff.new)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_substitution_diff() {
+        let old = smcp_font((1, vec![single_subst_2(&[(1, 3)])]));
+        let new = smcp_font((1, vec![single_subst_2(&[(1, 5), (2, 4)])]));
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                (
+                    "a".to_string(),
+                    Some("a.sc".to_string()),
+                    Some("a.alt".to_string())
+                ),
+                ("b".to_string(), None, Some("b.sc".to_string())),
+            ]
+        );
+        // The same substitutions in either format are no change
+        let delta = smcp_font((1, vec![single_subst_1(&[1, 2], 2)]));
+        let listed = smcp_font((1, vec![single_subst_2(&[(1, 3), (2, 4)])]));
+        assert!(diff(&delta, &listed).is_empty());
+    }
+
+    #[test]
+    fn test_extension_lookup() {
+        // A substitution wrapped in an extension lookup is still seen, so
+        // pointing it at another glyph is reported
+        let old = smcp_font((1, vec![single_subst_2(&[(1, 3)])]));
+        let wrapped = smcp_font((7, vec![extension(1, &single_subst_2(&[(1, 3)]))]));
+        assert!(diff(&old, &wrapped).is_empty());
+
+        let new = smcp_font((7, vec![extension(1, &single_subst_2(&[(1, 5)]))]));
+        assert_eq!(
+            diff(&old, &new),
+            vec![(
+                "a".to_string(),
+                Some("a.sc".to_string()),
+                Some("a.alt".to_string())
+            )]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::BTreeSet;
+
+use read_fonts::{FontRef, TableProvider};
+use skrifa::MetadataProvider;
+
+/// An OS/2 `ulUnicodeRange` bit, its name, and the (inclusive) ranges of
+/// codepoints it covers
+type UnicodeRange = (u32, &'static str, &'static [(u32, u32)]);
+
+/// The OS/2 `ulUnicodeRange` bits defined by the OpenType specification
+const UNICODE_RANGES: &[UnicodeRange] = &[
+    (0, "Basic Latin", &[(0x0000, 0x007F)]),
+    (1, "Latin-1 Supplement", &[(0x0080, 0x00FF)]),
+    (2, "Latin Extended-A", &[(0x0100, 0x017F)]),
+    (3, "Latin Extended-B", &[(0x0180, 0x024F)]),
+    (
+        4,
+        "IPA Extensions",
+        &[(0x0250, 0x02AF), (0x1D00, 0x1D7F), (0x1D80, 0x1DBF)],
+    ),
+    (
+        5,
+        "Spacing Modifier Letters",
+        &[(0x02B0, 0x02FF), (0xA700, 0xA71F)],
+    ),
+    (
+        6,
+        "Combining Diacritical Marks",
+        &[(0x0300, 0x036F), (0x1DC0, 0x1DFF)],
+    ),
+    (7, "Greek and Coptic", &[(0x0370, 0x03FF)]),
+    (8, "Coptic", &[(0x2C80, 0x2CFF)]),
+    (
+        9,
+        "Cyrillic",
+        &[
+            (0x0400, 0x04FF),
+            (0x0500, 0x052F),
+            (0x2DE0, 0x2DFF),
+            (0xA640, 0xA69F),
+        ],
+    ),
+    (10, "Armenian", &[(0x0530, 0x058F)]),
+    (11, "Hebrew", &[(0x0590, 0x05FF)]),
+    (12, "Vai", &[(0xA500, 0xA63F)]),
+    (13, "Arabic", &[(0x0600, 0x06FF), (0x0750, 0x077F)]),
+    (14, "NKo", &[(0x07C0, 0x07FF)]),
+    (15, "Devanagari", &[(0x0900, 0x097F)]),
+    (16, "Bengali", &[(0x0980, 0x09FF)]),
+    (17, "Gurmukhi", &[(0x0A00, 0x0A7F)]),
+    (18, "Gujarati", &[(0x0A80, 0x0AFF)]),
+    (19, "Oriya", &[(0x0B00, 0x0B7F)]),
+    (20, "Tamil", &[(0x0B80, 0x0BFF)]),
+    (21, "Telugu", &[(0x0C00, 0x0C7F)]),
+    (22, "Kannada", &[(0x0C80, 0x0CFF)]),
+    (23, "Malayalam", &[(0x0D00, 0x0D7F)]),
+    (24, "Thai", &[(0x0E00, 0x0E7F)]),
+    (25, "Lao", &[(0x0E80, 0x0EFF)]),
+    (26, "Georgian", &[(0x10A0, 0x10FF), (0x2D00, 0x2D2F)]),
+    (27, "Balinese", &[(0x1B00, 0x1B7F)]),
+    (28, "Hangul Jamo", &[(0x1100, 0x11FF)]),
+    (
+        29,
+        "Latin Extended Additional",
+        &[(0x1E00, 0x1EFF), (0x2C60, 0x2C7F), (0xA720, 0xA7FF)],
+    ),
+    (30, "Greek Extended", &[(0x1F00, 0x1FFF)]),
+    (
+        31,
+        "General Punctuation",
+        &[(0x2000, 0x206F), (0x2E00, 0x2E7F)],
+    ),
+    (32, "Superscripts And Subscripts", &[(0x2070, 0x209F)]),
+    (33, "Currency Symbols", &[(0x20A0, 0x20CF)]),
+    (
+        34,
+        "Combining Diacritical Marks For Symbols",
+        &[(0x20D0, 0x20FF)],
+    ),
+    (35, "Letterlike Symbols", &[(0x2100, 0x214F)]),
+    (36, "Number Forms", &[(0x2150, 0x218F)]),
+    (
+        37,
+        "Arrows",
+        &[
+            (0x2190, 0x21FF),
+            (0x27F0, 0x27FF),
+            (0x2900, 0x297F),
+            (0x2B00, 0x2BFF),
+        ],
+    ),
+    (
+        38,
+        "Mathematical Operators",
+        &[
+            (0x2200, 0x22FF),
+            (0x2A00, 0x2AFF),
+            (0x27C0, 0x27EF),
+            (0x2980, 0x29FF),
+        ],
+    ),
+    (39, "Miscellaneous Technical", &[(0x2300, 0x23FF)]),
+    (40, "Control Pictures", &[(0x2400, 0x243F)]),
+    (41, "Optical Character Recognition", &[(0x2440, 0x245F)]),
+    (42, "Enclosed Alphanumerics", &[(0x2460, 0x24FF)]),
+    (43, "Box Drawing", &[(0x2500, 0x257F)]),
+    (44, "Block Elements", &[(0x2580, 0x259F)]),
+    (45, "Geometric Shapes", &[(0x25A0, 0x25FF)]),
+    (46, "Miscellaneous Symbols", &[(0x2600, 0x26FF)]),
+    (47, "Dingbats", &[(0x2700, 0x27BF)]),
+    (48, "CJK Symbols And Punctuation", &[(0x3000, 0x303F)]),
+    (49, "Hiragana", &[(0x3040, 0x309F)]),
+    (50, "Katakana", &[(0x30A0, 0x30FF), (0x31F0, 0x31FF)]),
+    (51, "Bopomofo", &[(0x3100, 0x312F), (0x31A0, 0x31BF)]),
+    (52, "Hangul Compatibility Jamo", &[(0x3130, 0x318F)]),
+    (53, "Phags-pa", &[(0xA840, 0xA87F)]),
+    (54, "Enclosed CJK Letters And Months", &[(0x3200, 0x32FF)]),
+    (55, "CJK Compatibility", &[(0x3300, 0x33FF)]),
+    (56, "Hangul Syllables", &[(0xAC00, 0xD7AF)]),
+    // Set for any character outside the Basic Multilingual Plane
+    (57, "Non-Plane 0", &[(0x10000, 0x10FFFF)]),
+    (58, "Phoenician", &[(0x10900, 0x1091F)]),
+    (
+        59,
+        "CJK Unified Ideographs",
+        &[
+            (0x4E00, 0x9FFF),
+            (0x2E80, 0x2EFF),
+            (0x2F00, 0x2FDF),
+            (0x2FF0, 0x2FFF),
+            (0x3400, 0x4DBF),
+            (0x20000, 0x2A6DF),
+            (0x3190, 0x319F),
+        ],
+    ),
+    (60, "Private Use Area (plane 0)", &[(0xE000, 0xF8FF)]),
+    (
+        61,
+        "CJK Strokes",
+        &[(0x31C0, 0x31EF), (0xF900, 0xFAFF), (0x2F800, 0x2FA1F)],
+    ),
+    (62, "Alphabetic Presentation Forms", &[(0xFB00, 0xFB4F)]),
+    (63, "Arabic Presentation Forms-A", &[(0xFB50, 0xFDFF)]),
+    (64, "Combining Half Marks", &[(0xFE20, 0xFE2F)]),
+    (65, "Vertical Forms", &[(0xFE10, 0xFE1F), (0xFE30, 0xFE4F)]),
+    (66, "Small Form Variants", &[(0xFE50, 0xFE6F)]),
+    (67, "Arabic Presentation Forms-B", &[(0xFE70, 0xFEFF)]),
+    (68, "Halfwidth And Fullwidth Forms", &[(0xFF00, 0xFFEF)]),
+    (69, "Specials", &[(0xFFF0, 0xFFFF)]),
+    (70, "Tibetan", &[(0x0F00, 0x0FFF)]),
+    (71, "Syriac", &[(0x0700, 0x074F)]),
+    (72, "Thaana", &[(0x0780, 0x07BF)]),
+    (73, "Sinhala", &[(0x0D80, 0x0DFF)]),
+    (74, "Myanmar", &[(0x1000, 0x109F)]),
+    (
+        75,
+        "Ethiopic",
+        &[(0x1200, 0x137F), (0x1380, 0x139F), (0x2D80, 0x2DDF)],
+    ),
+    (76, "Cherokee", &[(0x13A0, 0x13FF)]),
+    (
+        77,
+        "Unified Canadian Aboriginal Syllabics",
+        &[(0x1400, 0x167F)],
+    ),
+    (78, "Ogham", &[(0x1680, 0x169F)]),
+    (79, "Runic", &[(0x16A0, 0x16FF)]),
+    (80, "Khmer", &[(0x1780, 0x17FF), (0x19E0, 0x19FF)]),
+    (81, "Mongolian", &[(0x1800, 0x18AF)]),
+    (82, "Braille Patterns", &[(0x2800, 0x28FF)]),
+    (83, "Yi Syllables", &[(0xA000, 0xA48F), (0xA490, 0xA4CF)]),
+    (84, "Tagalog", &[(0x1700, 0x177F)]),
+    (85, "Old Italic", &[(0x10300, 0x1032F)]),
+    (86, "Gothic", &[(0x10330, 0x1034F)]),
+    (87, "Deseret", &[(0x10400, 0x1044F)]),
+    (88, "Musical Symbols", &[(0x1D000, 0x1D24F)]),
+    (
+        89,
+        "Mathematical Alphanumeric Symbols",
+        &[(0x1D400, 0x1D7FF)],
+    ),
+    (
+        90,
+        "Private Use (planes 15 and 16)",
+        &[(0xF0000, 0xFFFFD), (0x100000, 0x10FFFD)],
+    ),
+    (
+        91,
+        "Variation Selectors",
+        &[(0xFE00, 0xFE0F), (0xE0100, 0xE01EF)],
+    ),
+    (92, "Tags", &[(0xE0000, 0xE007F)]),
+    (93, "Limbu", &[(0x1900, 0x194F)]),
+    (94, "Tai Le", &[(0x1950, 0x197F)]),
+    (95, "New Tai Lue", &[(0x1980, 0x19DF)]),
+    (96, "Buginese", &[(0x1A00, 0x1A1F)]),
+    (97, "Glagolitic", &[(0x2C00, 0x2C5F)]),
+    (98, "Tifinagh", &[(0x2D30, 0x2D7F)]),
+    (99, "Yijing Hexagram Symbols", &[(0x4DC0, 0x4DFF)]),
+    (100, "Syloti Nagri", &[(0xA800, 0xA82F)]),
+    (101, "Linear B Syllabary", &[(0x10000, 0x1013F)]),
+    (102, "Ancient Greek Numbers", &[(0x10140, 0x1018F)]),
+    (103, "Ugaritic", &[(0x10380, 0x1039F)]),
+    (104, "Old Persian", &[(0x103A0, 0x103DF)]),
+    (105, "Shavian", &[(0x10450, 0x1047F)]),
+    (106, "Osmanya", &[(0x10480, 0x104AF)]),
+    (107, "Cypriot Syllabary", &[(0x10800, 0x1083F)]),
+    (108, "Kharoshthi", &[(0x10A00, 0x10A5F)]),
+    (109, "Tai Xuan Jing Symbols", &[(0x1D300, 0x1D35F)]),
+    (110, "Cuneiform", &[(0x12000, 0x1247F)]),
+    (111, "Counting Rod Numerals", &[(0x1D360, 0x1D37F)]),
+    (112, "Sundanese", &[(0x1B80, 0x1BBF)]),
+    (113, "Lepcha", &[(0x1C00, 0x1C4F)]),
+    (114, "Ol Chiki", &[(0x1C50, 0x1C7F)]),
+    (115, "Saurashtra", &[(0xA880, 0xA8DF)]),
+    (116, "Kayah Li", &[(0xA900, 0xA92F)]),
+    (117, "Rejang", &[(0xA930, 0xA95F)]),
+    (118, "Cham", &[(0xAA00, 0xAA5F)]),
+    (119, "Ancient Symbols", &[(0x10190, 0x101CF)]),
+    (120, "Phaistos Disc", &[(0x101D0, 0x101FF)]),
+    (
+        121,
+        "Carian",
+        &[(0x102A0, 0x102DF), (0x10280, 0x1029F), (0x10920, 0x1093F)],
+    ),
+    (122, "Domino Tiles", &[(0x1F000, 0x1F09F)]),
+];
+
+/// The `ulUnicodeRange` bits set in the font's OS/2 table
+fn declared_bits(font: &FontRef) -> Option<BTreeSet<u32>> {
+    let os2 = font.os2().ok()?;
+    let words = [
+        os2.ul_unicode_range_1(),
+        os2.ul_unicode_range_2(),
+        os2.ul_unicode_range_3(),
+        os2.ul_unicode_range_4(),
+    ];
+    Some(
+        UNICODE_RANGES
+            .iter()
+            .map(|(bit, _, _)| *bit)
+            .filter(|bit| words[(*bit / 32) as usize] & (1 << (bit % 32)) != 0)
+            .collect(),
+    )
+}
+
+/// The `ulUnicodeRange` bits for which the font maps at least one
+/// character
+fn covered_bits(font: &FontRef) -> BTreeSet<u32> {
+    let codepoints: Vec<u32> = font.charmap().mappings().map(|(cp, _)| cp).collect();
+    UNICODE_RANGES
+        .iter()
+        .filter(|(_, _, ranges)| {
+            codepoints.iter().any(|cp| {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (start..=end).contains(&cp))
+            })
+        })
+        .map(|(bit, _, _)| *bit)
+        .collect()
+}
+
+fn describe(bit: u32) -> String {
+    let name = UNICODE_RANGES
+        .iter()
+        .find(|(b, _, _)| *b == bit)
+        .map_or("reserved", |(_, name, _)| name);
+    format!("bit {} ({})", bit, name)
+}
+
+/// The bits which disagree with the cmap, and whether each is set
+fn mismatches(declared: &BTreeSet<u32>, covered: &BTreeSet<u32>) -> BTreeSet<(u32, bool)> {
+    declared
+        .symmetric_difference(covered)
+        .map(|bit| (*bit, declared.contains(bit)))
+        .collect()
+}
+
+/// Check the OS/2 `ulUnicodeRange` bits of two fonts against their cmaps
+///
+/// A bit is expected to be set if the font maps any character in the
+/// range. Reports the bits which differ between the fonts, then the bits
+/// which disagree with the cmap, in either font or both. (`ulCodePageRange`
+/// is not checked, as code page support can't be judged reliably from the
+/// cmap alone.) Fonts without an OS/2 table are not compared.
+pub fn unicode_range_diff(font_a: &FontRef, font_b: &FontRef) -> Vec<String> {
+    let (Some(declared_a), Some(declared_b)) = (declared_bits(font_a), declared_bits(font_b))
+    else {
+        return vec![];
+    };
+    let mut changes = vec![];
+    for bit in declared_b.difference(&declared_a) {
+        changes.push(format!("{} is now set", describe(*bit)));
+    }
+    for bit in declared_a.difference(&declared_b) {
+        changes.push(format!("{} is no longer set", describe(*bit)));
+    }
+
+    let mismatches_a = mismatches(&declared_a, &covered_bits(font_a));
+    let mismatches_b = mismatches(&declared_b, &covered_bits(font_b));
+    let mut all_mismatches: Vec<_> = mismatches_a.union(&mismatches_b).collect();
+    all_mismatches.sort();
+    for mismatch @ (bit, set) in all_mismatches {
+        let which = match (
+            mismatches_a.contains(mismatch),
+            mismatches_b.contains(mismatch),
+        ) {
+            (true, true) => "both fonts",
+            (true, false) => "old font",
+            _ => "new font",
+        };
+        changes.push(if *set {
+            format!(
+                "{}: {} is set, but no characters in it are mapped",
+                which,
+                describe(*bit)
+            )
+        } else {
+            format!(
+                "{}: {} is not set, but characters in it are mapped",
+                which,
+                describe(*bit)
+            )
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fonts::{cmap, font, os2};
+
+    /// Build a font with a version 0 OS/2 table declaring `bits`, and a
+    /// cmap mapping each of `codepoints` to glyph 1
+    fn range_font(bits: &[u32], codepoints: &[u16]) -> Vec<u8> {
+        let mappings: Vec<(u16, u16)> = codepoints.iter().map(|cp| (*cp, 1)).collect();
+        font([(b"OS/2", os2(bits, 0)), (b"cmap", cmap(&mappings))])
+    }
+
+    #[test]
+    fn test_range_table() {
+        // Each bit is listed once, in order, with well-formed ranges
+        assert!(UNICODE_RANGES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(UNICODE_RANGES
+            .iter()
+            .all(|(_, _, ranges)| ranges.iter().all(|(start, end)| start <= end)));
+        assert_eq!(describe(57), "bit 57 (Non-Plane 0)");
+        assert_eq!(describe(123), "bit 123 (reserved)");
+    }
+
+    #[test]
+    fn test_declared_and_covered_bits() {
+        let data = range_font(&[0, 7, 64], &[0x41, 0xE9]);
+        let font = FontRef::new(&data).unwrap();
+        assert_eq!(declared_bits(&font), Some(BTreeSet::from([0, 7, 64])));
+        assert_eq!(covered_bits(&font), BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn test_unicode_range_diff() {
+        // The old font leaves Latin-1 unset and sets Greek without mapping
+        // any; the new font sets Latin-1 without mapping any
+        let old = range_font(&[0, 7], &[0x41, 0xE9]);
+        let new = range_font(&[0, 1], &[0x41]);
+        let changes =
+            unicode_range_diff(&FontRef::new(&old).unwrap(), &FontRef::new(&new).unwrap());
+        assert_eq!(
+            changes,
+            [
+                "bit 1 (Latin-1 Supplement) is now set",
+                "bit 7 (Greek and Coptic) is no longer set",
+                "old font: bit 1 (Latin-1 Supplement) is not set, but characters in it are mapped",
+                "new font: bit 1 (Latin-1 Supplement) is set, but no characters in it are mapped",
+                "old font: bit 7 (Greek and Coptic) is set, but no characters in it are mapped",
+            ]
+        );
+        assert!(
+            unicode_range_diff(&FontRef::new(&new).unwrap(), &FontRef::new(&new).unwrap())
+                .iter()
+                .all(|change| change.starts_with("both fonts"))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use read_fonts::{FontRef, TableProvider};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// A change to the units per em
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UpmDiff {
+    pub old: u16,
+    pub new: u16,
+    /// A warning that values in font units are scaled to be compared
+    pub warning: String,
+}
+
+fn units_per_em(font: &FontRef) -> u16 {
+    font.head().map_or(0, |head| head.units_per_em())
+}
+
+/// The factor which converts values in `font_b`'s units to `font_a`'s
+///
+/// This is 1 if the fonts have the same units per em, or if either font
+/// has no (or a zero) unitsPerEm.
+pub fn upm_scale(font_a: &FontRef, font_b: &FontRef) -> f32 {
+    match (units_per_em(font_a), units_per_em(font_b)) {
+        (0, _) | (_, 0) => 1.0,
+        (old, new) => old as f32 / new as f32,
+    }
+}
+
+/// Convert a value in `font_b`'s units to `font_a`'s, given the factor
+/// from [upm_scale], rounding to whole units
+///
+/// Scaling can take a value outside the range of its type in the table,
+/// so the result is an `i32` rather than being clamped.
+pub fn scale_units(value: impl Into<i32>, scale: f32) -> i32 {
+    (value.into() as f64 * scale as f64).round() as i32
+}
+
+/// Compare the units per em of two fonts
+///
+/// Returns `None` if they are the same. Otherwise, the metrics, kerning,
+/// anchor and MVAR comparisons scale the new font's values to the old font's
+/// units, and the diff carries a warning saying so.
+pub fn upm_diff(font_a: &FontRef, font_b: &FontRef) -> Option<UpmDiff> {
+    let old = units_per_em(font_a);
+    let new = units_per_em(font_b);
+    (old != new).then(|| UpmDiff {
+        old,
+        new,
+        warning: format!(
+            "Units per em changed from {} to {}; the new font's metrics, kerning, anchor and MVAR values are scaled to {} units per em to be compared, but other values in font units are not",
+            old, new, old
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_fonts::{font, outline_tables},
+        ttj::metrics::metrics_diff,
+    };
+
+    #[test]
+    fn test_scaled_metrics_are_unchanged() {
+        // The same glyph at 1000 and 2048 units per em
+        let old = font(outline_tables(
+            1000,
+            &[(0x41, &[(125, 0), (125, 500), (375, 0)])],
+        ));
+        let new = font(outline_tables(
+            2048,
+            &[(0x41, &[(256, 0), (256, 1024), (768, 0)])],
+        ));
+        let (old, new) = (FontRef::new(&old).unwrap(), FontRef::new(&new).unwrap());
+
+        let upm = upm_diff(&old, &new).unwrap();
+        assert_eq!((upm.old, upm.new), (1000, 2048));
+        assert!(upm_diff(&old, &old).is_none());
+        assert_eq!(scale_units(1024, upm_scale(&old, &new)), 500);
+        assert!(metrics_diff(&old, &new, 0).is_empty());
+
+        // A side bearing which doesn't scale to the same value is reported
+        let moved = font(outline_tables(
+            2048,
+            &[(0x41, &[(300, 0), (300, 1024), (768, 0)])],
+        ));
+        let diffs = metrics_diff(&old, &FontRef::new(&moved).unwrap(), 0);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(
+            (diffs[0].old_side_bearing, diffs[0].new_side_bearing),
+            (125, 146)
+        );
+    }
+}
@@ -1,13 +1,144 @@
-use std::error::Error;
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-pub(crate) fn die(doing: &str, err: impl Error) -> ! {
-    eprintln!("Error {}: {}", doing, err);
-    eprintln!();
-    eprintln!("Caused by:");
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+pub use crate::dfont::DEFAULT_MAX_FONT_SIZE_MB;
+use crate::woff;
+
+static ASCII_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Only write ASCII to the terminal: turn off colors, and escape other
+/// characters in reports and log messages
+pub fn set_ascii_only(ascii_only: bool) {
+    ASCII_ONLY.store(ascii_only, Ordering::Relaxed);
+    if ascii_only {
+        colored::control::set_override(false);
+    }
+}
+
+/// Escape non-ASCII characters as `\u{...}` if only ASCII is to be written
+pub fn terminal_safe(text: &str) -> Cow<'_, str> {
+    if !ASCII_ONLY.load(Ordering::Relaxed) || text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| {
+                if c.is_ascii() {
+                    c.to_string()
+                } else {
+                    c.escape_unicode().to_string()
+                }
+            })
+            .collect(),
+    )
+}
+
+pub fn die(doing: &str, err: impl Error) -> ! {
+    fail(&error_message(doing, err))
+}
+
+/// Describe an error, and the chain of errors which caused it
+pub fn error_message(doing: &str, err: impl Error) -> String {
+    let mut message = format!("Error {}: {}", doing, err);
     if let Some(cause) = err.source() {
+        message.push_str("\n\nCaused by:");
         for (i, e) in std::iter::successors(Some(cause), |e| (*e).source()).enumerate() {
-            eprintln!("   {}: {}", i, e);
+            message.push_str(&format!("\n   {}: {}", i, e));
         }
     }
+    message
+}
+
+/// Log an error message and exit with status 1
+pub fn fail(message: &str) -> ! {
+    log::error!("{}", message);
     std::process::exit(1);
 }
+
+/// Writes log messages to stderr, leaving stdout for the report
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let message = terminal_safe(&message);
+        match record.level() {
+            Level::Info | Level::Error => eprintln!("{}", message),
+            Level::Warn => eprintln!("warning: {}", message),
+            level => eprintln!("{}: {}", level.as_str().to_lowercase(), message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Send log messages to stderr
+///
+/// By default, progress messages ("Testing ...") and warnings are shown.
+/// Each `verbose` step adds debug and then trace messages; `quiet` shows
+/// only errors.
+pub fn init_logging(verbose: u8, quiet: bool) {
+    let level = if quiet {
+        LevelFilter::Error
+    } else {
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    // Only fails if a logger is already set, in which case keep it
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Check that font data is no larger than `max_size` bytes, and that, if
+/// it is WOFF or WOFF2, it doesn't claim to decompress to anything larger
+///
+/// This only trusts the size the data declares, to reject fonts early;
+/// [`crate::dfont::DFont::with_max_size`] enforces the limit as the font
+/// is decompressed.
+pub fn check_font_size(data: &[u8], max_size: u64) -> io::Result<()> {
+    let too_large = |what: &str| {
+        Err(io::Error::other(format!(
+            "{} is larger than the maximum of {} bytes (see --max-font-size)",
+            what, max_size
+        )))
+    };
+    if data.len() as u64 > max_size {
+        return too_large("font");
+    }
+    if woff::decompressed_size(data).is_some_and(|size| size as u64 > max_size) {
+        return too_large("decompressed font");
+    }
+    Ok(())
+}
+
+/// Read a font file of at most `max_size` bytes
+///
+/// Larger files are rejected without being read in full.
+pub fn read_font_file(path: &Path, max_size: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    // Read one byte more than allowed, to tell whether there is more
+    File::open(path)?
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut data)?;
+    check_font_size(&data, max_size)?;
+    Ok(data)
+}
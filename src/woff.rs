@@ -0,0 +1,875 @@
+//! Decompression of WOFF and WOFF2 fonts to SFNT
+
+use std::{borrow::Cow, io::Read};
+
+use read_fonts::ReadError;
+
+/// Decompress WOFF or WOFF2 data to an SFNT font of at most `max_size`
+/// bytes
+///
+/// Fonts whose tables add up to more than `max_size` are rejected before
+/// they are inflated, and no table is inflated past the length it
+/// declares, so a font which lies about its size can't exhaust memory.
+/// Any other data is returned unchanged.
+pub fn decompress(data: &[u8], max_size: u64) -> Result<Cow<'_, [u8]>, ReadError> {
+    let font = match data.get(0..4) {
+        Some(b"wOFF") => woff(data, max_size)?,
+        Some(b"wOF2") => woff2(data, max_size)?,
+        _ => return Ok(Cow::Borrowed(data)),
+    };
+    // Rebuilding the glyf table can make it larger than it was stored
+    if font.len() as u64 > max_size {
+        return Err(TOO_LARGE);
+    }
+    Ok(Cow::Owned(font))
+}
+
+const TOO_LARGE: ReadError =
+    ReadError::MalformedData("font decompresses to more than the maximum size");
+
+/// The size of the SFNT font which WOFF or WOFF2 data declares it will
+/// decompress to, or `None` for any other data
+pub fn decompressed_size(data: &[u8]) -> Option<u32> {
+    match data.get(0..4)? {
+        b"wOFF" | b"wOF2" => {
+            let mut header = Reader::new(data);
+            header.pos = 16;
+            header.u32().ok()
+        }
+        _ => None,
+    }
+}
+
+/// A big-endian reader over a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
+        let end = self.pos.checked_add(len).ok_or(ReadError::OutOfBounds)?;
+        let bytes = self.data.get(self.pos..end).ok_or(ReadError::OutOfBounds)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.read::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_be_bytes(self.read()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, ReadError> {
+        Ok(i16::from_be_bytes(self.read()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_be_bytes(self.read()?))
+    }
+
+    /// A WOFF2 `UIntBase128`
+    fn base128(&mut self) -> Result<u32, ReadError> {
+        let mut value: u32 = 0;
+        for _ in 0..5 {
+            let byte = self.u8()?;
+            if value & 0xFE00_0000 != 0 {
+                return Err(ReadError::MalformedData("UIntBase128 overflow"));
+            }
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReadError::MalformedData("UIntBase128 is too long"))
+    }
+
+    /// A WOFF2 `255UInt16`
+    fn u16_255(&mut self) -> Result<u16, ReadError> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => Ok(self.u8()? as u16 + 506),
+            255 => Ok(self.u8()? as u16 + 253),
+            code => Ok(code as u16),
+        }
+    }
+}
+
+/// An SFNT table, by its tag
+pub(crate) struct Table {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn pad(data: &mut Vec<u8>) {
+    data.resize(data.len().next_multiple_of(4), 0);
+}
+
+/// Assemble an SFNT font from its tables
+pub(crate) fn build_sfnt(flavor: [u8; 4], mut tables: Vec<Table>) -> Result<Vec<u8>, ReadError> {
+    tables.sort_by_key(|table| table.tag);
+    // The table directory's rangeShift, numTables * 16, must fit in 16 bits
+    let num_tables = u16::try_from(tables.len())
+        .ok()
+        .filter(|n| *n < 4096)
+        .ok_or(ReadError::MalformedData("too many tables"))?;
+    let entry_selector = num_tables.checked_ilog2().unwrap_or(0) as u16;
+    let search_range = (1 << entry_selector) * 16;
+    let mut font = flavor.to_vec();
+    for value in [
+        num_tables,
+        search_range,
+        entry_selector,
+        (num_tables * 16).saturating_sub(search_range),
+    ] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    for table in tables.iter() {
+        font.extend(table.tag);
+        font.extend(checksum(&table.data).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((table.data.len() as u32).to_be_bytes());
+        offset += table.data.len().next_multiple_of(4);
+    }
+    for table in tables {
+        font.extend(table.data);
+        pad(&mut font);
+    }
+    Ok(font)
+}
+
+fn woff(data: &[u8], max_size: u64) -> Result<Vec<u8>, ReadError> {
+    let mut header = Reader::new(data);
+    header.take(4)?;
+    let flavor = header.read()?;
+    header.take(4)?;
+    let num_tables = header.u16()?;
+    header.pos = 44;
+    let mut tables = vec![];
+    let mut total_length = 0;
+    for _ in 0..num_tables {
+        let tag = header.read()?;
+        let offset = header.u32()? as usize;
+        let compressed_length = header.u32()? as usize;
+        let length = header.u32()? as usize;
+        header.take(4)?;
+        total_length += length as u64;
+        if total_length > max_size {
+            return Err(TOO_LARGE);
+        }
+        let compressed = offset
+            .checked_add(compressed_length)
+            .and_then(|end| data.get(offset..end))
+            .ok_or(ReadError::OutOfBounds)?;
+        let table = if compressed_length < length {
+            // Read at most one byte more than declared, to catch tables
+            // which inflate to more without inflating all of them
+            let mut table = vec![];
+            flate2::read::ZlibDecoder::new(compressed)
+                .take(length as u64 + 1)
+                .read_to_end(&mut table)
+                .map_err(|_| ReadError::MalformedData("couldn't decompress WOFF table"))?;
+            table
+        } else {
+            compressed.to_vec()
+        };
+        if table.len() != length {
+            return Err(ReadError::MalformedData("WOFF table has the wrong length"));
+        }
+        tables.push(Table { tag, data: table });
+    }
+    build_sfnt(flavor, tables)
+}
+
+/// The tags which WOFF2 table directory entries can refer to by index
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+fn woff2(data: &[u8], max_size: u64) -> Result<Vec<u8>, ReadError> {
+    let mut header = Reader::new(data);
+    header.take(4)?;
+    let flavor = header.read()?;
+    if &flavor == b"ttcf" {
+        return Err(ReadError::MalformedData(
+            "WOFF2 font collections are not supported",
+        ));
+    }
+    header.take(4)?;
+    let num_tables = header.u16()?;
+    header.take(6)?;
+    let compressed_length = header.u32()? as usize;
+    header.pos = 48;
+
+    // (tag, transformed, length in the decompressed stream)
+    let mut entries = vec![];
+    for _ in 0..num_tables {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3F {
+            63 => header.read()?,
+            index => *KNOWN_TAGS[index as usize],
+        };
+        let transform_version = flags >> 6;
+        let length = header.base128()? as usize;
+        // The null transform is version 3 for glyf and loca, but version 0
+        // for other tables
+        let transformed = if &tag == b"glyf" || &tag == b"loca" {
+            transform_version != 3
+        } else {
+            transform_version != 0
+        };
+        let length = if transformed {
+            header.base128()? as usize
+        } else {
+            length
+        };
+        entries.push((tag, transformed, length));
+    }
+
+    // As for WOFF, read at most one byte more than the tables' lengths
+    let stream_length: u64 = entries.iter().map(|(_, _, length)| *length as u64).sum();
+    if stream_length > max_size {
+        return Err(TOO_LARGE);
+    }
+    let mut stream = vec![];
+    brotli::Decompressor::new(header.take(compressed_length)?, 4096)
+        .take(stream_length + 1)
+        .read_to_end(&mut stream)
+        .map_err(|_| ReadError::MalformedData("couldn't decompress WOFF2 data"))?;
+    if stream.len() as u64 != stream_length {
+        return Err(ReadError::MalformedData("WOFF2 data has the wrong length"));
+    }
+    let mut stream = Reader::new(&stream);
+    let mut tables = vec![];
+    let mut transformed_hmtx = None;
+    for (tag, transformed, length) in entries {
+        let data = stream.take(length)?;
+        match (&tag, transformed) {
+            (b"glyf", true) => {
+                let (glyf, loca) = reconstruct_glyf(data)?;
+                tables.push(Table {
+                    tag: *b"glyf",
+                    data: glyf,
+                });
+                tables.push(Table {
+                    tag: *b"loca",
+                    data: loca,
+                });
+            }
+            // Rebuilt along with glyf
+            (b"loca", true) => {}
+            (b"hmtx", true) => transformed_hmtx = Some(data),
+            (_, true) => return Err(ReadError::MalformedData("unknown WOFF2 table transform")),
+            _ => tables.push(Table {
+                tag,
+                data: data.to_vec(),
+            }),
+        }
+    }
+    if let Some(hmtx) = transformed_hmtx {
+        let hmtx = reconstruct_hmtx(hmtx, &tables)?;
+        tables.push(Table {
+            tag: *b"hmtx",
+            data: hmtx,
+        });
+    }
+    build_sfnt(flavor, tables)
+}
+
+/// Read the glyph-data of a composite glyph, returning it and whether
+/// the glyph has instructions
+fn read_composite<'a>(composite: &mut Reader<'a>) -> Result<(&'a [u8], bool), ReadError> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+    const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+    let start = composite.pos;
+    let mut has_instructions = false;
+    loop {
+        let flags = composite.u16()?;
+        has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+        let mut length = 2;
+        length += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            length += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            length += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            length += 8;
+        }
+        composite.take(length)?;
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    Ok((&composite.data[start..composite.pos], has_instructions))
+}
+
+/// Decode a point's coordinate deltas from the glyph stream
+fn read_triplet(flag: u8, glyphs: &mut Reader) -> Result<(i32, i32), ReadError> {
+    let with_sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+    let flag_i = flag as i32;
+    Ok(match flag {
+        0..=9 => (
+            0,
+            with_sign(flag, ((flag_i & 14) << 7) + glyphs.u8()? as i32),
+        ),
+        10..=19 => (
+            with_sign(flag, (((flag_i - 10) & 14) << 7) + glyphs.u8()? as i32),
+            0,
+        ),
+        20..=83 => {
+            let b0 = flag_i - 20;
+            let b1 = glyphs.u8()? as i32;
+            (
+                with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+                with_sign(flag >> 1, 1 + ((b0 & 0x0C) << 2) + (b1 & 0x0F)),
+            )
+        }
+        84..=119 => {
+            let b0 = flag_i - 84;
+            let [b1, b2] = glyphs.read()?;
+            (
+                with_sign(flag, 1 + ((b0 / 12) << 8) + b1 as i32),
+                with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2 as i32),
+            )
+        }
+        120..=123 => {
+            let [b1, b2, b3] = glyphs.read()?.map(|b: u8| b as i32);
+            (
+                with_sign(flag, (b1 << 4) + (b2 >> 4)),
+                with_sign(flag >> 1, ((b2 & 0x0F) << 8) + b3),
+            )
+        }
+        _ => {
+            let [b1, b2, b3, b4] = glyphs.read()?.map(|b: u8| b as i32);
+            (
+                with_sign(flag, (b1 << 8) + b2),
+                with_sign(flag >> 1, (b3 << 8) + b4),
+            )
+        }
+    })
+}
+
+/// Rebuild the `glyf` and `loca` tables from the WOFF2 transformed `glyf`
+/// table
+fn reconstruct_glyf(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), ReadError> {
+    const OVERLAP_SIMPLE: u8 = 0x40;
+    let mut header = Reader::new(data);
+    header.take(2)?;
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut streams = vec![];
+    for _ in 0..7 {
+        streams.push(header.u32()? as usize);
+    }
+    let mut stream = |length: usize| header.take(length).map(Reader::new);
+    let mut n_contours = stream(streams[0])?;
+    let mut n_points = stream(streams[1])?;
+    let mut flags = stream(streams[2])?;
+    let mut glyphs = stream(streams[3])?;
+    let mut composites = stream(streams[4])?;
+    let mut bboxes = stream(streams[5])?;
+    let mut instructions = stream(streams[6])?;
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        Some(stream(num_glyphs.div_ceil(8))?.data)
+    } else {
+        None
+    };
+    let bit_set = |bitmap: &[u8], index: usize| bitmap[index >> 3] & (0x80 >> (index & 7)) != 0;
+    let bbox_bitmap = bboxes.take(num_glyphs.div_ceil(32) * 4)?;
+
+    let mut glyf = vec![];
+    let mut offsets = vec![];
+    for glyph_id in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let has_bbox = bit_set(bbox_bitmap, glyph_id);
+        let contours = n_contours.i16()?;
+        if contours == 0 {
+            if has_bbox {
+                return Err(ReadError::MalformedData("empty WOFF2 glyph has a bbox"));
+            }
+            continue;
+        }
+        glyf.extend(contours.to_be_bytes());
+        if contours < 0 {
+            if !has_bbox {
+                return Err(ReadError::MalformedData(
+                    "composite WOFF2 glyph has no bbox",
+                ));
+            }
+            glyf.extend(bboxes.take(8)?);
+            let (components, has_instructions) = read_composite(&mut composites)?;
+            glyf.extend(components);
+            if has_instructions {
+                let length = glyphs.u16_255()?;
+                glyf.extend(length.to_be_bytes());
+                glyf.extend(instructions.take(length as usize)?);
+            }
+        } else {
+            let mut end_points = vec![];
+            let mut total_points = 0u16;
+            for _ in 0..contours {
+                total_points = total_points
+                    .checked_add(n_points.u16_255()?)
+                    .ok_or(ReadError::MalformedData("WOFF2 glyph has too many points"))?;
+                end_points.push(
+                    total_points
+                        .checked_sub(1)
+                        .ok_or(ReadError::MalformedData("WOFF2 contour has no points"))?,
+                );
+            }
+            let (mut x, mut y) = (0i32, 0i32);
+            let mut points = vec![];
+            for _ in 0..total_points {
+                let flag = flags.u8()?;
+                let (dx, dy) = read_triplet(flag & 0x7F, &mut glyphs)?;
+                let overflow = || ReadError::MalformedData("WOFF2 coordinate overflow");
+                x = x.checked_add(dx).ok_or_else(overflow)?;
+                y = y.checked_add(dy).ok_or_else(overflow)?;
+                points.push((x, y, flag & 0x80 == 0));
+            }
+            let instruction_length = glyphs.u16_255()?;
+            if has_bbox {
+                glyf.extend(bboxes.take(8)?);
+            } else {
+                let x_min = points.iter().map(|p| p.0).min().unwrap_or(0);
+                let y_min = points.iter().map(|p| p.1).min().unwrap_or(0);
+                let x_max = points.iter().map(|p| p.0).max().unwrap_or(0);
+                let y_max = points.iter().map(|p| p.1).max().unwrap_or(0);
+                for value in [x_min, y_min, x_max, y_max] {
+                    glyf.extend((value as i16).to_be_bytes());
+                }
+            }
+            for end_point in end_points {
+                glyf.extend(end_point.to_be_bytes());
+            }
+            glyf.extend(instruction_length.to_be_bytes());
+            glyf.extend(instructions.take(instruction_length as usize)?);
+            // Write every coordinate as a full 16-bit delta, for simplicity
+            let overlap = overlap_bitmap.is_some_and(|bitmap| bit_set(bitmap, glyph_id));
+            for (index, (_, _, on_curve)) in points.iter().enumerate() {
+                let mut flag = *on_curve as u8;
+                if index == 0 && overlap {
+                    flag |= OVERLAP_SIMPLE;
+                }
+                glyf.push(flag);
+            }
+            for coordinate in [0, 1] {
+                let mut previous = 0;
+                for point in points.iter() {
+                    let value = if coordinate == 0 { point.0 } else { point.1 };
+                    glyf.extend(((value - previous) as i16).to_be_bytes());
+                    previous = value;
+                }
+            }
+        }
+        pad(&mut glyf);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = vec![];
+    for offset in offsets {
+        if index_format == 0 {
+            let offset = u16::try_from(offset / 2).map_err(|_| {
+                ReadError::MalformedData("glyf is too large for short loca offsets")
+            })?;
+            loca.extend(offset.to_be_bytes());
+        } else {
+            loca.extend((offset as u32).to_be_bytes());
+        }
+    }
+    Ok((glyf, loca))
+}
+
+/// Rebuild the `hmtx` table from the WOFF2 transformed `hmtx` table,
+/// taking any omitted side bearings from the glyphs' bounding boxes
+fn reconstruct_hmtx(data: &[u8], tables: &[Table]) -> Result<Vec<u8>, ReadError> {
+    let table = |tag: &[u8; 4]| {
+        tables
+            .iter()
+            .find(|table| &table.tag == tag)
+            .map(|table| Reader::new(&table.data))
+            .ok_or(ReadError::MalformedData(
+                "transformed hmtx needs glyf, loca, hhea and maxp",
+            ))
+    };
+    let mut maxp = table(b"maxp")?;
+    maxp.take(4)?;
+    let num_glyphs = maxp.u16()? as usize;
+    let mut hhea = table(b"hhea")?;
+    hhea.take(34)?;
+    let num_h_metrics = hhea.u16()? as usize;
+    let mut head = table(b"head")?;
+    head.take(50)?;
+    let long_offsets = head.i16()? == 1;
+    let glyf = table(b"glyf")?.data;
+    let mut loca = table(b"loca")?;
+    let mut offsets = vec![];
+    for _ in 0..=num_glyphs {
+        offsets.push(if long_offsets {
+            loca.u32()? as usize
+        } else {
+            loca.u16()? as usize * 2
+        });
+    }
+    let x_mins = offsets
+        .windows(2)
+        .map(|range| {
+            if range[0] == range[1] {
+                return Ok(0);
+            }
+            let mut glyph = Reader::new(glyf.get(range[0]..range[1]).unwrap_or_default());
+            glyph.take(2)?;
+            glyph.i16()
+        })
+        .collect::<Result<Vec<i16>, ReadError>>()?;
+
+    let mut hmtx = Reader::new(data);
+    let flags = hmtx.u8()?;
+    let mut advances = vec![];
+    for _ in 0..num_h_metrics {
+        advances.push(hmtx.u16()?);
+    }
+    let mut side_bearings = vec![];
+    for (glyph_id, x_min) in x_mins.into_iter().enumerate() {
+        // Bit 0 means the proportional glyphs' bearings are omitted, and
+        // bit 1 the monospaced glyphs'
+        let omitted = if glyph_id < num_h_metrics {
+            flags & 1 != 0
+        } else {
+            flags & 2 != 0
+        };
+        side_bearings.push(if omitted { x_min } else { hmtx.i16()? });
+    }
+    let mut output = vec![];
+    for (glyph_id, side_bearing) in side_bearings.into_iter().enumerate() {
+        if let Some(advance) = advances.get(glyph_id) {
+            output.extend(advance.to_be_bytes());
+        }
+        output.extend(side_bearing.to_be_bytes());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read_fonts::{tables::glyf::Glyph, FontRef, TableProvider};
+    use skrifa::{
+        instance::{LocationRef, Size},
+        outline::{DrawSettings, OutlinePen},
+        GlyphId, MetadataProvider, Tag,
+    };
+    use std::io::Write;
+
+    use crate::test_fonts::{be16, be32};
+
+    const SFNT: &[u8] = include_bytes!("../www/AND-Regular.ttf");
+
+    /// The tables of an SFNT font, as (tag, data)
+    fn sfnt_tables(sfnt: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let font = FontRef::new(sfnt).unwrap();
+        font.table_directory
+            .table_records()
+            .iter()
+            .map(|record| {
+                let tag = record.tag().into_bytes();
+                (tag, font.table_data(record.tag()).unwrap().as_bytes())
+            })
+            .collect()
+    }
+
+    /// Pack (tag, original length, stored data) tables into a WOFF file
+    fn build_woff(tables: &[([u8; 4], u32, Vec<u8>)]) -> Vec<u8> {
+        let mut directory = vec![];
+        let mut data = vec![];
+        let mut offset = 44 + 20 * tables.len();
+        for (tag, length, stored) in tables {
+            directory.extend(tag);
+            directory.extend(be32(&[offset as u32, stored.len() as u32, *length, 0]));
+            data.extend(stored);
+            pad(&mut data);
+            offset += stored.len().next_multiple_of(4);
+        }
+        let mut woff = b"wOFF\x00\x01\x00\x00".to_vec();
+        woff.extend(be32(&[offset as u32]));
+        woff.extend(be16(&[tables.len() as i32, 0]));
+        woff.extend(be32(&[SFNT.len() as u32]));
+        woff.extend([0; 24]);
+        woff.extend(directory);
+        woff.extend(data);
+        woff
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn woff_from_sfnt(sfnt: &[u8]) -> Vec<u8> {
+        let tables: Vec<_> = sfnt_tables(sfnt)
+            .into_iter()
+            .map(|(tag, data)| {
+                // Tables which don't shrink are stored uncompressed
+                let compressed = zlib(data);
+                let stored = if compressed.len() < data.len() {
+                    compressed
+                } else {
+                    data.to_vec()
+                };
+                (tag, data.len() as u32, stored)
+            })
+            .collect();
+        build_woff(&tables)
+    }
+
+    /// A WOFF2 file of an SFNT font's tables, none of them transformed
+    fn woff2_from_sfnt(sfnt: &[u8]) -> Vec<u8> {
+        let mut directory = vec![];
+        let mut stream = vec![];
+        let tables = sfnt_tables(sfnt);
+        for (tag, data) in &tables {
+            // glyf and loca are left untransformed by transform version 3
+            let version = if tag == b"glyf" || tag == b"loca" {
+                3
+            } else {
+                0
+            };
+            directory.push(version << 6 | 63);
+            directory.extend(tag);
+            let mut length = data.len() as u32;
+            let mut base128 = vec![(length & 0x7F) as u8];
+            while length > 0x7F {
+                length >>= 7;
+                base128.insert(0, (length & 0x7F) as u8 | 0x80);
+            }
+            directory.extend(base128);
+            stream.extend(*data);
+        }
+        let mut compressed = vec![];
+        brotli::BrotliCompress(&mut stream.as_slice(), &mut compressed, &Default::default())
+            .unwrap();
+        let mut woff2 = b"wOF2\x00\x01\x00\x00".to_vec();
+        woff2.extend(be32(&[(48 + directory.len() + compressed.len()) as u32]));
+        woff2.extend(be16(&[tables.len() as i32, 0]));
+        woff2.extend(be32(&[SFNT.len() as u32, compressed.len() as u32]));
+        woff2.extend([0; 24]);
+        woff2.extend(directory);
+        woff2.extend(compressed);
+        woff2
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for packed in [woff_from_sfnt(SFNT), woff2_from_sfnt(SFNT)] {
+            let sfnt = decompress(&packed, SFNT.len() as u64).unwrap();
+            assert_eq!(sfnt_tables(&sfnt), sfnt_tables(SFNT));
+            assert!(decompress(&packed, SFNT.len() as u64 / 2).is_err());
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        for packed in [woff_from_sfnt(SFNT), woff2_from_sfnt(SFNT)] {
+            for length in [8, 40, 100, packed.len() / 2, packed.len() - 8] {
+                let truncated = &packed[..length];
+                assert!(
+                    decompress(truncated, u64::MAX).is_err(),
+                    "length {}",
+                    length
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_oversized_length() {
+        // A table which claims to inflate to 4GB
+        let woff = build_woff(&[(*b"name", u32::MAX, zlib(b"name"))]);
+        assert!(decompress(&woff, u64::MAX).is_err());
+    }
+
+    /// A transformed glyf table of a single simple glyph, with one contour
+    /// of the given number of points, each (1, 1) on from the last
+    fn transformed_glyf(n_points: u8) -> Vec<u8> {
+        let streams: [&[u8]; 7] = [
+            &[0, 1],
+            &[n_points],
+            &vec![23; n_points as usize],
+            &[vec![0; n_points as usize], vec![0]].concat(),
+            &[],
+            &[0; 4],
+            &[],
+        ];
+        let mut glyf = be16(&[0, 0, 1, 0]);
+        for stream in streams {
+            glyf.extend(be32(&[stream.len() as u32]));
+        }
+        for stream in streams {
+            glyf.extend(stream);
+        }
+        glyf
+    }
+
+    #[test]
+    fn test_reconstruct_glyf() {
+        let (glyf, loca) = reconstruct_glyf(&transformed_glyf(3)).unwrap();
+        let expected: Vec<u8> =
+            [be16(&[1, 1, 1, 3, 3, 2, 0]), vec![1, 1, 1], be16(&[1; 6])].concat();
+        assert_eq!(&glyf[..expected.len()], expected);
+        assert_eq!(glyf.len(), expected.len().next_multiple_of(4));
+        assert_eq!(loca, [0, 0, 0, (glyf.len() / 2) as u8]);
+    }
+
+    #[test]
+    fn test_zero_point_contour() {
+        assert!(matches!(
+            reconstruct_glyf(&transformed_glyf(0)),
+            Err(ReadError::MalformedData(_))
+        ));
+    }
+
+    struct NullPen;
+
+    impl OutlinePen for NullPen {
+        fn move_to(&mut self, _x: f32, _y: f32) {}
+        fn line_to(&mut self, _x: f32, _y: f32) {}
+        fn quad_to(&mut self, _cx0: f32, _cy0: f32, _x: f32, _y: f32) {}
+        fn curve_to(&mut self, _cx0: f32, _cy0: f32, _cx1: f32, _cy1: f32, _x: f32, _y: f32) {}
+        fn close(&mut self) {}
+    }
+
+    /// A WOFF2 font made by a WOFF2 encoder rather than by these tests, with
+    /// transformed glyf and loca tables and composite glyphs (Source Code
+    /// Pro Italic; see SourceCodePro-LICENSE.txt)
+    const REAL_WOFF2: &[u8] = include_bytes!("../test-data/SourceCodePro-It.ttf.woff2");
+
+    #[test]
+    fn test_real_woff2() {
+        let sfnt = decompress(REAL_WOFF2, u64::MAX).unwrap();
+        let font = FontRef::new(&sfnt).unwrap();
+        let (glyf, loca, maxp) = (
+            font.glyf().unwrap(),
+            font.loca(None).unwrap(),
+            font.maxp().unwrap(),
+        );
+        let hmtx = font.hmtx().unwrap();
+        let (mut max_points, mut max_contours, mut max_components) = (0, 0, 0);
+        let mut composites = 0;
+        for gid in 0..maxp.num_glyphs() {
+            let gid = GlyphId::new(gid);
+            let Some(glyph) = loca.get_glyf(gid, &glyf).unwrap() else {
+                continue;
+            };
+            // The side bearings are stored untransformed, so check the
+            // rebuilt bounding boxes against them
+            let lsb = hmtx.side_bearing(gid).unwrap();
+            assert_eq!(glyph.x_min(), lsb, "{}", gid);
+            match glyph {
+                Glyph::Simple(simple) => {
+                    let points: Vec<_> = simple.points().collect();
+                    let x_min = points.iter().map(|p| p.x).min().unwrap();
+                    let y_max = points.iter().map(|p| p.y).max().unwrap();
+                    assert_eq!((simple.x_min(), simple.y_max()), (x_min, y_max), "{}", gid);
+                    max_points = max_points.max(points.len());
+                    max_contours = max_contours.max(simple.end_pts_of_contours().len());
+                }
+                Glyph::Composite(composite) => {
+                    composites += 1;
+                    max_components = max_components.max(composite.components().count());
+                }
+            }
+        }
+        assert!(composites > 0);
+        // The font's maxp was compiled from the original glyphs
+        assert_eq!(maxp.max_points(), Some(max_points as u16));
+        assert_eq!(maxp.max_contours(), Some(max_contours as u16));
+        assert_eq!(maxp.max_component_elements(), Some(max_components as u16));
+
+        let outlines = font.outline_glyphs();
+        for gid in 0..maxp.num_glyphs() {
+            let outline = outlines.get(GlyphId::new(gid)).unwrap();
+            let settings = DrawSettings::unhinted(Size::unscaled(), LocationRef::default());
+            outline.draw(settings, &mut NullPen).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_hmtx() {
+        // No encoder to hand transforms hmtx, so transform the real font's
+        // hmtx as the WOFF2 spec describes and check it's rebuilt
+        let sfnt = decompress(REAL_WOFF2, u64::MAX).unwrap();
+        let font = FontRef::new(&sfnt).unwrap();
+        let tables: Vec<Table> = sfnt_tables(&sfnt)
+            .into_iter()
+            .map(|(tag, data)| Table {
+                tag,
+                data: data.to_vec(),
+            })
+            .collect();
+        let hmtx = font.hmtx().unwrap();
+        let advances: Vec<i32> = hmtx
+            .h_metrics()
+            .iter()
+            .map(|m| m.advance() as i32)
+            .collect();
+        let bearings: Vec<i32> = hmtx
+            .left_side_bearings()
+            .iter()
+            .map(|b| b.get() as i32)
+            .collect();
+        assert!(!bearings.is_empty(), "the font has monospaced glyphs");
+        let original = font.table_data(Tag::new(b"hmtx")).unwrap();
+
+        // Both arrays of side bearings omitted
+        let mut transformed = vec![3];
+        transformed.extend(be16(&advances));
+        assert_eq!(
+            reconstruct_hmtx(&transformed, &tables).unwrap(),
+            original.as_bytes()
+        );
+
+        // Only the proportional glyphs' side bearings omitted
+        let mut transformed = vec![1];
+        transformed.extend(be16(&advances));
+        transformed.extend(be16(&bearings));
+        assert_eq!(
+            reconstruct_hmtx(&transformed, &tables).unwrap(),
+            original.as_bytes()
+        );
+    }
+}